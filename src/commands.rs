@@ -0,0 +1,660 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use clap::{ArgMatches, ValueEnum};
+use log::{error, info};
+use semver::Version;
+use serde::Serialize;
+
+use crate::database::Database;
+use crate::extensions::{
+    self, ExtensionManager, ExtensionManagerConfig, LoadReport, Severity, ValidationReport,
+};
+use crate::models::common::{
+    Device, DeviceCategoryUniqueID, DeviceUniqueID, DeviceVariant, InventoryExtensionMetadata,
+    InventoryExtensionUniqueID as ExtensionID, PartKind, PartUniqueID, UniqueID,
+};
+use crate::plugins::{Catalog, PluginManager, PluginManagerConfig};
+
+#[cfg(test)]
+mod tests;
+
+/// The format used to print the results of a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text.
+    Text,
+    /// Machine-readable JSON, for use in scripts and CI.
+    Json,
+}
+
+/// Runs one of the `extension` subcommands, returning the exit code of the process.
+pub async fn extension(args: &ArgMatches, config: ExtensionManagerConfig) -> anyhow::Result<i32> {
+    match args.subcommand() {
+        Some(("lint", args)) => {
+            let files = args.get_many::<PathBuf>("files").unwrap();
+            let format = *args.get_one::<OutputFormat>("format").unwrap();
+            lint(files, format, config, &mut std::io::stdout())
+        }
+        Some(("export", args)) => export(args).await,
+        Some(("enable", args)) => set_enabled(args, true).await,
+        Some(("disable", args)) => set_enabled(args, false).await,
+        Some(("status", args)) => status(*args.get_one::<OutputFormat>("format").unwrap()).await,
+        Some(("sign", args)) => sign(args),
+        Some(("upgrade", args)) => {
+            let files = args.get_many::<PathBuf>("files").unwrap();
+            upgrade(files, config)
+        }
+        // * Clap rejects missing or unknown subcommands before this point.
+        _ => unreachable!(),
+    }
+}
+
+/// Runs one of the `device` subcommands, returning the exit code of the process.
+pub async fn device(args: &ArgMatches) -> anyhow::Result<i32> {
+    match args.subcommand() {
+        Some(("lookup", args)) => {
+            let identifier = args.get_one::<String>("identifier").unwrap();
+            lookup(identifier, *args.get_one::<OutputFormat>("format").unwrap()).await
+        }
+        Some(("parts", args)) => {
+            let device = DeviceUniqueID::new(args.get_one::<String>("device").unwrap());
+            compatible_parts(&device, *args.get_one::<OutputFormat>("format").unwrap()).await
+        }
+        Some(("prices", args)) => {
+            let device = DeviceUniqueID::new(args.get_one::<String>("device").unwrap());
+            price_list(&device, *args.get_one::<OutputFormat>("format").unwrap()).await
+        }
+        // * Clap rejects missing or unknown subcommands before this point.
+        _ => unreachable!(),
+    }
+}
+
+/// Runs one of the `part` subcommands, returning the exit code of the process.
+pub async fn part(args: &ArgMatches) -> anyhow::Result<i32> {
+    match args.subcommand() {
+        Some(("devices", args)) => {
+            let part = PartUniqueID::new(args.get_one::<String>("part").unwrap());
+            compatible_devices(&part, *args.get_one::<OutputFormat>("format").unwrap()).await
+        }
+        // * Clap rejects missing or unknown subcommands before this point.
+        _ => unreachable!(),
+    }
+}
+
+/// Runs one of the `category` subcommands, returning the exit code of the process.
+pub async fn category(args: &ArgMatches) -> anyhow::Result<i32> {
+    match args.subcommand() {
+        Some(("devices", args)) => {
+            let category = DeviceCategoryUniqueID::new(args.get_one::<String>("category").unwrap());
+            category_devices(&category, *args.get_one::<OutputFormat>("format").unwrap()).await
+        }
+        // * Clap rejects missing or unknown subcommands before this point.
+        _ => unreachable!(),
+    }
+}
+
+/// Runs one of the `plugin` subcommands, returning the exit code of the process.
+pub async fn plugin(args: &ArgMatches, config: PluginManagerConfig) -> anyhow::Result<i32> {
+    let db = Database::connect().await;
+    // * Listing plugins or running a hook only inspects them, so neither the schema nor the loaded
+    // * versions are written.
+    let (mut host, result, _) = PluginManager::new(config).load_into_host(
+        &Catalog::read(&db).await?,
+        &db.list_plugins().await?,
+        &db.list_plugin_hashes().await?,
+    )?;
+    let code = match result.failures.is_empty() {
+        true => 0,
+        false => 1,
+    };
+
+    match args.subcommand() {
+        Some(("list", _)) => {
+            for (metadata, hooks) in host.plugins() {
+                println!(
+                    "{} v{}: {}",
+                    metadata.id.unnamespaced(),
+                    metadata.version,
+                    hooks.join(", ")
+                );
+            }
+            Ok(code)
+        }
+        Some(("run", args)) => {
+            let hook = args.get_one::<String>("hook").unwrap();
+            let input = match args.get_one::<String>("input") {
+                Some(input) => serde_json::from_str(input)?,
+                None => serde_json::Value::Null,
+            };
+            let outputs = host.run_hook(hook, &input)?;
+            println!("{}", serde_json::to_string_pretty(&outputs)?);
+
+            match outputs.iter().any(|o| o.error.is_some()) {
+                true => Ok(1),
+                false => Ok(code),
+            }
+        }
+        // * Clap rejects missing or unknown subcommands before this point.
+        _ => unreachable!(),
+    }
+}
+
+/// Parses and validates extension files without connecting to the database, writing the results
+/// to the given output. Exits with a non-zero code if any file contains errors.
+fn lint<'a>(
+    files: impl IntoIterator<Item = &'a PathBuf>,
+    format: OutputFormat,
+    config: ExtensionManagerConfig,
+    out: &mut impl Write,
+) -> anyhow::Result<i32> {
+    let manager = ExtensionManager::base_with_context(config);
+    let reports = files
+        .into_iter()
+        .map(|file| match manager.parse_extension(file) {
+            Ok((_, report)) => report,
+            Err(report) => report,
+        })
+        .collect::<Vec<ValidationReport>>();
+
+    match format {
+        OutputFormat::Text => {
+            for report in &reports {
+                for diagnostic in &report.diagnostics {
+                    writeln!(out, "{}", report.describe(diagnostic))?;
+                }
+            }
+
+            let errors = reports
+                .iter()
+                .map(|r| r.count(Severity::Error))
+                .sum::<usize>();
+            let warnings = reports
+                .iter()
+                .map(|r| r.count(Severity::Warning))
+                .sum::<usize>();
+            writeln!(
+                out,
+                "Checked {} extension file(s): {errors} error(s), {warnings} warning(s).",
+                reports.len()
+            )?;
+        }
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&reports)?)?,
+    }
+
+    match reports.iter().any(ValidationReport::has_errors) {
+        true => Ok(1),
+        false => Ok(0),
+    }
+}
+
+/// Rewrites extension files in the newest format version.
+/// Exits with a non-zero code if any file could not be upgraded.
+fn upgrade<'a>(
+    files: impl IntoIterator<Item = &'a PathBuf>,
+    config: ExtensionManagerConfig,
+) -> anyhow::Result<i32> {
+    let manager = ExtensionManager::base_with_context(config);
+    let mut code = 0;
+    for file in files {
+        match manager.upgrade_extension(file) {
+            Ok(Some(source)) => {
+                std::fs::write(file, source)?;
+                info!("Upgraded {}.", file.display());
+            }
+            Ok(None) => info!("{} is already up to date.", file.display()),
+            Err(report) => {
+                report.log();
+                code = 1;
+            }
+        }
+    }
+
+    Ok(code)
+}
+
+/// Exports the contents of the database as a TOML extension.
+/// The metadata of the exported extension defaults to that of the extension being exported, if
+/// the export is limited to one extension.
+async fn export(args: &ArgMatches) -> anyhow::Result<i32> {
+    let owner = args.get_one::<String>("extension").map(ExtensionID::new);
+
+    let db = Database::connect().await;
+    let loaded = match &owner {
+        Some(owner) => {
+            let loaded = db
+                .list_extensions()
+                .await?
+                .into_all()
+                .into_iter()
+                .find(|metadata| &metadata.id == owner);
+            if loaded.is_none() {
+                error!("Extension '{}' is not loaded.", owner.unnamespaced());
+                return Ok(1);
+            }
+            loaded
+        }
+        None => None,
+    };
+
+    let metadata = InventoryExtensionMetadata {
+        id: match args.get_one::<String>("id") {
+            Some(id) => ExtensionID::new(id),
+            None => loaded
+                .as_ref()
+                .map_or(ExtensionID::new("database_export"), |l| l.id.clone()),
+        },
+        display_name: match args.get_one::<String>("name") {
+            Some(name) => name.clone(),
+            None => loaded
+                .as_ref()
+                .map_or("Database Export".to_owned(), |l| l.display_name.clone()),
+        },
+        version: match args.get_one::<Version>("version") {
+            Some(version) => version.clone(),
+            None => loaded
+                .as_ref()
+                .map_or(Version::new(1, 0, 0), |l| l.version.clone()),
+        },
+        // * The rest of the manifest is kept when re-exporting a loaded extension.
+        author: loaded.as_ref().and_then(|l| l.author.clone()),
+        license: loaded.as_ref().and_then(|l| l.license.clone()),
+        description: loaded.as_ref().and_then(|l| l.description.clone()),
+        homepage: loaded.as_ref().and_then(|l| l.homepage.clone()),
+        techtriage: loaded.as_ref().and_then(|l| l.techtriage.clone()),
+    };
+
+    let toml = extensions::export(&db, owner.as_ref(), &metadata).await?;
+    match args.get_one::<PathBuf>("output") {
+        Some(path) => {
+            std::fs::write(path, toml)?;
+            info!("Exported extension to {}.", path.display());
+        }
+        None => print!("{toml}"),
+    }
+
+    Ok(0)
+}
+
+/// The status of a loaded extension, as printed by the `extension status` command.
+#[derive(Debug, Serialize)]
+struct ExtensionStatus {
+    id: String,
+    display_name: String,
+    version: String,
+    /// Either `base` or `override`.
+    kind: &'static str,
+    enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    homepage: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    techtriage: Option<String>,
+}
+
+/// Enables or disables loaded extensions without unloading their contents.
+/// Exits with a non-zero code if any extension is not loaded.
+async fn set_enabled(args: &ArgMatches, enabled: bool) -> anyhow::Result<i32> {
+    let db = Database::connect().await;
+    // * Databases set up before extensions could be disabled need the new field.
+    db.setup_tables().await?;
+
+    let mut code = 0;
+    for id in args.get_many::<String>("extensions").unwrap() {
+        let id = ExtensionID::new(id);
+        if !db.set_extension_enabled(&id, enabled).await? {
+            error!("Extension '{}' is not loaded.", id.unnamespaced());
+            code = 1;
+            continue;
+        }
+        match enabled {
+            true => info!("Enabled extension '{}'.", id.unnamespaced()),
+            false => info!("Disabled extension '{}'.", id.unnamespaced()),
+        }
+    }
+
+    Ok(code)
+}
+
+/// Prints every loaded extension, along with its kind and whether it is enabled.
+async fn status(format: OutputFormat) -> anyhow::Result<i32> {
+    let db = Database::connect().await;
+    let loaded = db.list_extensions().await?;
+    let disabled = db.list_disabled_extensions().await?;
+
+    let mut statuses = loaded
+        .base
+        .into_iter()
+        .map(|metadata| (metadata, "base"))
+        .chain(
+            loaded
+                .overrides
+                .into_iter()
+                .map(|metadata| (metadata, "override")),
+        )
+        .map(|(metadata, kind)| ExtensionStatus {
+            enabled: !disabled.contains(&metadata.id),
+            id: metadata.id.unnamespaced().to_owned(),
+            display_name: metadata.display_name,
+            version: metadata.version.to_string(),
+            kind,
+            author: metadata.author,
+            license: metadata.license,
+            description: metadata.description,
+            homepage: metadata.homepage,
+            techtriage: metadata.techtriage.map(|r| r.to_string()),
+        })
+        .collect::<Vec<_>>();
+    statuses.sort_by(|a, b| a.id.cmp(&b.id));
+
+    match format {
+        OutputFormat::Text => {
+            for status in &statuses {
+                println!(
+                    "{} v{} ({}): {}",
+                    status.id,
+                    status.version,
+                    status.kind,
+                    match status.enabled {
+                        true => "enabled",
+                        false => "disabled",
+                    }
+                );
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&statuses)?),
+    }
+
+    Ok(0)
+}
+
+/// A device found by model identifier, as printed by the `device lookup` command.
+#[derive(Debug, Serialize)]
+struct LookupResult<'a> {
+    id: &'a str,
+    display_name: &'a str,
+    manufacturer: &'a str,
+    category: &'a str,
+    /// The variant the identifier belongs to, if it is not one of the device's own identifiers.
+    variant: Option<&'a DeviceVariant>,
+}
+
+/// Prints the device and variant which a model identifier refers to.
+/// Exits with a non-zero code if no device has the identifier.
+async fn lookup(identifier: &str, format: OutputFormat) -> anyhow::Result<i32> {
+    let db = Database::connect().await;
+    let Some(found) = db.find_device_by_identifier(identifier).await? else {
+        error!("No device has model identifier '{identifier}'.");
+        return Ok(1);
+    };
+
+    let device = &found.device;
+    let result = LookupResult {
+        id: device.id.unnamespaced(),
+        display_name: &device.display_name,
+        manufacturer: device.manufacturer.unnamespaced(),
+        category: device.category.unnamespaced(),
+        variant: found.variant.as_ref(),
+    };
+    match format {
+        OutputFormat::Text => match result.variant {
+            Some(variant) => println!(
+                "{} ({}), variant {}: {variant}",
+                result.display_name, result.id, variant.id
+            ),
+            None => println!("{} ({})", result.display_name, result.id),
+        },
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&result)?),
+    }
+
+    Ok(0)
+}
+
+/// A part which fits a device, as printed by the `device parts` command.
+#[derive(Debug, Serialize)]
+struct PartListing<'a> {
+    id: &'a str,
+    display_name: &'a str,
+    kind: PartKind,
+    supplier_skus: &'a [String],
+}
+
+/// A device which a part fits, or which belongs to a category, as printed by the `part devices` and
+/// `category devices` commands.
+#[derive(Debug, Serialize)]
+struct DeviceListing<'a> {
+    id: &'a str,
+    display_name: &'a str,
+    manufacturer: &'a str,
+    category: &'a str,
+}
+
+/// Prints the parts which fit a device, sorted by ID.
+/// Exits with a non-zero code if no parts fit it.
+async fn compatible_parts(device: &DeviceUniqueID, format: OutputFormat) -> anyhow::Result<i32> {
+    let db = Database::connect().await;
+    let mut parts = db.list_compatible_parts(device).await?;
+    if parts.is_empty() {
+        error!("No parts fit device '{}'.", device.unnamespaced());
+        return Ok(1);
+    }
+
+    parts.sort_by(|a, b| a.id.cmp(&b.id));
+    let listings = parts
+        .iter()
+        .map(|p| PartListing {
+            id: p.id.unnamespaced(),
+            display_name: &p.display_name,
+            kind: p.kind,
+            supplier_skus: &p.supplier_skus,
+        })
+        .collect::<Vec<_>>();
+    match format {
+        OutputFormat::Text => {
+            for listing in &listings {
+                println!(
+                    "{} ({}): {}",
+                    listing.display_name,
+                    listing.id,
+                    listing.kind.name()
+                );
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&listings)?),
+    }
+
+    Ok(0)
+}
+
+/// Prints the devices which a part fits, sorted by ID.
+/// Exits with a non-zero code if the part does not exist or fits no devices.
+async fn compatible_devices(part: &PartUniqueID, format: OutputFormat) -> anyhow::Result<i32> {
+    let db = Database::connect().await;
+    let devices = db.list_compatible_devices(part).await?;
+    if devices.is_empty() {
+        error!("Part '{}' does not fit any devices.", part.unnamespaced());
+        return Ok(1);
+    }
+
+    print_devices(devices, format)?;
+    Ok(0)
+}
+
+/// Prints the devices in a category or any category below it, sorted by ID.
+/// Exits with a non-zero code if there are no such devices.
+async fn category_devices(
+    category: &DeviceCategoryUniqueID,
+    format: OutputFormat,
+) -> anyhow::Result<i32> {
+    let db = Database::connect().await;
+    let devices = db.list_devices_in_category(category).await?;
+    if devices.is_empty() {
+        error!(
+            "No devices belong to category '{}'.",
+            category.unnamespaced()
+        );
+        return Ok(1);
+    }
+
+    print_devices(devices, format)?;
+    Ok(0)
+}
+
+/// Prints a list of devices, sorted by ID.
+fn print_devices(mut devices: Vec<Device>, format: OutputFormat) -> anyhow::Result<()> {
+    devices.sort_by(|a, b| a.id.cmp(&b.id));
+    let listings = devices
+        .iter()
+        .map(|d| DeviceListing {
+            id: d.id.unnamespaced(),
+            display_name: &d.display_name,
+            manufacturer: d.manufacturer.unnamespaced(),
+            category: d.category.unnamespaced(),
+        })
+        .collect::<Vec<_>>();
+    match format {
+        OutputFormat::Text => {
+            for listing in &listings {
+                println!("{} ({})", listing.display_name, listing.id);
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&listings)?),
+    }
+
+    Ok(())
+}
+
+/// A repair service in a device's price list, as printed by the `device prices` command.
+#[derive(Debug, Serialize)]
+struct PriceListing<'a> {
+    id: &'a str,
+    display_name: &'a str,
+    default_labor_minutes: u32,
+    /// In the smallest unit of the shop's currency, such as cents.
+    default_price: u64,
+    /// The IDs of the parts which fit the device and are used by the service.
+    parts: Vec<&'a str>,
+    /// The part kinds which the service needs, but which no part in the catalog provides for the
+    /// device.
+    missing_part_kinds: Vec<PartKind>,
+}
+
+/// Prints the repair services which apply to a device, with their default prices and labor times.
+/// Exits with a non-zero code if the device does not exist.
+async fn price_list(device: &DeviceUniqueID, format: OutputFormat) -> anyhow::Result<i32> {
+    let db = Database::connect().await;
+    let Some(price_list) = db.price_list(device).await? else {
+        error!("Device '{}' does not exist.", device.unnamespaced());
+        return Ok(1);
+    };
+
+    let listings = price_list
+        .entries
+        .iter()
+        .map(|entry| PriceListing {
+            id: entry.service.id.unnamespaced(),
+            display_name: &entry.service.display_name,
+            default_labor_minutes: entry.service.default_labor_minutes,
+            default_price: entry.service.default_price,
+            parts: entry.parts.iter().map(|p| p.id.unnamespaced()).collect(),
+            missing_part_kinds: entry.missing_part_kinds(),
+        })
+        .collect::<Vec<_>>();
+    match format {
+        OutputFormat::Text => {
+            println!(
+                "Price list for {} ({}):",
+                price_list.device.display_name,
+                price_list.device.id.unnamespaced()
+            );
+            if listings.is_empty() {
+                println!("  (no repair services)");
+            }
+            for listing in &listings {
+                // * Prices are printed with two decimal places, which suits most currencies.
+                print!(
+                    "  {} ({}): {}.{:02}, {} min",
+                    listing.display_name,
+                    listing.id,
+                    listing.default_price / 100,
+                    listing.default_price % 100,
+                    listing.default_labor_minutes
+                );
+                if !listing.missing_part_kinds.is_empty() {
+                    let kinds = listing
+                        .missing_part_kinds
+                        .iter()
+                        .map(PartKind::name)
+                        .collect::<Vec<_>>();
+                    print!(" (no parts for: {})", kinds.join(", "));
+                }
+                println!();
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&listings)?),
+    }
+
+    Ok(0)
+}
+
+/// Signs an extension bundle with a secret key, so it can be loaded by anyone who trusts the
+/// matching public key. Prints the public key, which goes in the trusted keys directory.
+fn sign(args: &ArgMatches) -> anyhow::Result<i32> {
+    let bundle = args.get_one::<PathBuf>("bundle").unwrap();
+    let key = args.get_one::<PathBuf>("key").unwrap();
+    let extension_file = args.get_one::<String>("extension file");
+
+    let signing_key = extensions::parse_signing_key(&std::fs::read_to_string(key)?)?;
+    let signed = extensions::sign_bundle(bundle, extension_file.map(String::as_str), &signing_key)?;
+    info!("Signed {signed} in bundle {}.", bundle.display());
+    println!("{}", hex::encode(signing_key.verifying_key().to_bytes()));
+
+    Ok(0)
+}
+
+/// Prints the outcome of loading the staged extensions.
+/// Text reports are logged as a summary, while JSON reports are printed for use in scripts.
+pub fn load_report(result: &LoadReport, format: OutputFormat) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Text => result.log_summary(),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(result)?),
+    }
+
+    Ok(())
+}
+
+/// Prints the changes which a dry run found for each staged extension.
+/// Returns a non-zero exit code if any extension would fail to load.
+pub fn dry_run(result: &LoadReport, format: OutputFormat) -> anyhow::Result<i32> {
+    match format {
+        OutputFormat::Text => {
+            for diff in &result.diffs {
+                print!("{diff}");
+            }
+            for failure in &result.failures {
+                println!(
+                    "Extension '{}' would fail to load: {}",
+                    failure.id.unnamespaced(),
+                    failure.reason
+                );
+            }
+            for orphan in &result.orphans {
+                println!(
+                    "Extension '{}' is loaded, but no longer has an extension file.",
+                    orphan.unnamespaced()
+                );
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(result)?),
+    }
+
+    match result.failures.is_empty() {
+        true => Ok(0),
+        false => Ok(1),
+    }
+}
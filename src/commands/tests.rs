@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use super::{lint, OutputFormat};
+
+/// An extension file which passes validation.
+const VALID_EXTENSION: &str = r#"
+format_version = 2
+
+[extension]
+id = "valid"
+display_name = "Valid"
+version = "1.0.0"
+
+[[device_manufacturers]]
+id = "apple"
+display_name = "Apple"
+
+[[device_categories]]
+id = "phone"
+display_name = "Phone"
+
+[[devices]]
+id = "iphone_8"
+display_name = "iPhone 8"
+manufacturer = "apple"
+category = "phone"
+primary_model_identifiers = ["A1863"]
+extended_model_identifiers = []
+"#;
+
+/// Tests that linting exits with a non-zero code only if a file has errors, and that the JSON
+/// output lists every file with its diagnostics.
+#[test]
+fn lint_output() {
+    let directory = tempfile::tempdir().unwrap();
+    let valid = directory.path().join("valid.toml");
+    let invalid = directory.path().join("invalid.toml");
+    std::fs::write(&valid, VALID_EXTENSION).unwrap();
+    std::fs::write(&invalid, VALID_EXTENSION.replace("\"Valid\"", "\"\"")).unwrap();
+
+    let run = |files: &[&PathBuf], format: OutputFormat| {
+        let mut out = Vec::new();
+        let code = lint(files.iter().copied(), format, Default::default(), &mut out).unwrap();
+        (code, String::from_utf8(out).unwrap())
+    };
+
+    // Make sure a valid file passes, and the summary is printed as text
+    let (code, text) = run(&[&valid], OutputFormat::Text);
+    assert_eq!(code, 0);
+    assert!(text.contains("Checked 1 extension file(s): 0 error(s), 0 warning(s)."));
+
+    // Make sure an invalid file fails, and its error is located in the JSON output
+    let (code, json) = run(&[&valid, &invalid], OutputFormat::Json);
+    assert_eq!(code, 1);
+    let reports = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+    let reports = reports.as_array().unwrap();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0]["path"], valid.to_str().unwrap());
+    assert!(reports[0]["diagnostics"].as_array().unwrap().is_empty());
+    let diagnostic = &reports[1]["diagnostics"][0];
+    assert_eq!(diagnostic["severity"], "error");
+    assert_eq!(diagnostic["field"], "extension.display_name");
+    assert_eq!(diagnostic["location"]["line"], 6);
+    assert!(diagnostic["message"].is_string());
+}
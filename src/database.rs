@@ -1,20 +1,34 @@
+use std::collections::{HashMap, HashSet};
 use std::net::{Ipv4Addr, SocketAddr};
 
-use futures_util::future;
 use log::{debug, error, info};
+use semver::VersionReq;
+use serde::Serialize;
 use surrealdb::engine::remote::ws::{Client, Ws};
+use surrealdb::method::Query;
 use surrealdb::opt::auth::Root;
+use surrealdb::sql::Thing;
 use surrealdb::Surreal;
 
-use crate::extensions::InventoryExtension;
+use crate::extensions::{
+    apply_overrides, FieldConflict, InventoryExtension, MergeConflictError, Merger, RecordCounts,
+    Resolution,
+};
 use crate::models::common::{
-    Device, DeviceCategory, DeviceCategoryUniqueID, DeviceManufacturer, DeviceManufacturerUniqueID,
-    DeviceUniqueID, InventoryExtensionMetadata, InventoryExtensionUniqueID, UniqueID,
+    withdraw_contribution, CategoryTree, Combined, Contributions, CustomFieldDefinition,
+    CustomFieldTarget, CustomFieldType, Dependencies, Device, DeviceCategory,
+    DeviceCategoryUniqueID, DeviceManufacturer, DeviceManufacturerUniqueID, DeviceUniqueID,
+    IdentifierMatch, InventoryExtensionMetadata, InventoryExtensionUniqueID, LoadedExtensions,
+    Override, Part, PartUniqueID, PluginMetadata, PluginUniqueID, PriceList, RepairService,
+    RepairServiceUniqueID, UniqueID,
 };
 use crate::models::database::{
+    ContributionPullRecord, ContributionPushRecord, CustomFieldPullRecord, CustomFieldPushRecord,
     DeviceCategoryPullRecord, DeviceCategoryPushRecord, DeviceManufacturerPullRecord,
-    DeviceManufacturerPushRecord, DevicePullRecord, DevicePushRecord, GenericPullRecord,
-    InventoryExtensionMetadataPullRecord, InventoryExtensionMetadataPushRecord,
+    DeviceManufacturerPushRecord, DevicePullRecord, DevicePushRecord,
+    InventoryExtensionMetadataPullRecord, InventoryExtensionMetadataPushRecord, OverridePullRecord,
+    OverridePushRecord, PartPullRecord, PartPushRecord, PluginMetadataPullRecord,
+    PluginMetadataPushRecord, RepairServicePullRecord, RepairServicePushRecord,
 };
 use crate::stop;
 
@@ -22,6 +36,12 @@ pub const EXTENSION_TABLE_NAME: &str = "extensions";
 pub const DEVICE_MANUFACTURER_TABLE_NAME: &str = "device_manufacturers";
 pub const DEVICE_CATEGORY_TABLE_NAME: &str = "device_categories";
 pub const DEVICE_TABLE_NAME: &str = "devices";
+pub const OVERRIDE_TABLE_NAME: &str = "overrides";
+pub const PLUGIN_TABLE_NAME: &str = "plugins";
+pub const CUSTOM_FIELD_TABLE_NAME: &str = "custom_fields";
+pub const PART_TABLE_NAME: &str = "parts";
+pub const REPAIR_SERVICE_TABLE_NAME: &str = "repair_services";
+pub const CONTRIBUTION_TABLE_NAME: &str = "contributions";
 
 /// Wrapper type for a SurrealDB connection.
 pub struct Database {
@@ -117,16 +137,29 @@ impl Database {
                 DEFINE TABLE {EXTENSION_TABLE_NAME} SCHEMAFUL;
                 DEFINE FIELD display_name ON TABLE {EXTENSION_TABLE_NAME} TYPE string;
                 DEFINE FIELD version ON TABLE {EXTENSION_TABLE_NAME} TYPE string;
+                DEFINE FIELD content_hash ON TABLE {EXTENSION_TABLE_NAME} TYPE option<string>;
+                DEFINE FIELD enabled ON TABLE {EXTENSION_TABLE_NAME} TYPE option<bool>;
+                DEFINE FIELD author ON TABLE {EXTENSION_TABLE_NAME} TYPE option<string>;
+                DEFINE FIELD license ON TABLE {EXTENSION_TABLE_NAME} TYPE option<string>;
+                DEFINE FIELD description ON TABLE {EXTENSION_TABLE_NAME} TYPE option<string>;
+                DEFINE FIELD homepage ON TABLE {EXTENSION_TABLE_NAME} TYPE option<string>;
+                DEFINE FIELD techtriage ON TABLE {EXTENSION_TABLE_NAME} TYPE option<string>;
+                DEFINE FIELD dependencies ON TABLE {EXTENSION_TABLE_NAME} TYPE option<array<object>>;
+                DEFINE FIELD dependencies.*.extension ON TABLE {EXTENSION_TABLE_NAME} TYPE record({EXTENSION_TABLE_NAME});
+                DEFINE FIELD dependencies.*.requirement ON TABLE {EXTENSION_TABLE_NAME} TYPE string;
 
                 DEFINE TABLE {DEVICE_MANUFACTURER_TABLE_NAME} SCHEMAFUL;
                 DEFINE FIELD display_name ON TABLE {DEVICE_MANUFACTURER_TABLE_NAME} TYPE string;
                 DEFINE FIELD extensions ON TABLE {DEVICE_MANUFACTURER_TABLE_NAME} TYPE array<record({EXTENSION_TABLE_NAME})>;
                 DEFINE FIELD extensions.* ON TABLE {DEVICE_MANUFACTURER_TABLE_NAME} TYPE record({EXTENSION_TABLE_NAME});
+                DEFINE FIELD custom_fields ON TABLE {DEVICE_MANUFACTURER_TABLE_NAME} TYPE option<object>;
 
                 DEFINE TABLE {DEVICE_CATEGORY_TABLE_NAME} SCHEMAFUL;
                 DEFINE FIELD display_name ON TABLE {DEVICE_CATEGORY_TABLE_NAME} TYPE string;
+                DEFINE FIELD parent ON TABLE {DEVICE_CATEGORY_TABLE_NAME} TYPE option<record({DEVICE_CATEGORY_TABLE_NAME})>;
                 DEFINE FIELD extensions ON TABLE {DEVICE_CATEGORY_TABLE_NAME} TYPE array<record({EXTENSION_TABLE_NAME})>;
                 DEFINE FIELD extensions.* ON TABLE {DEVICE_CATEGORY_TABLE_NAME} TYPE record({EXTENSION_TABLE_NAME});
+                DEFINE FIELD custom_fields ON TABLE {DEVICE_CATEGORY_TABLE_NAME} TYPE option<object>;
 
                 DEFINE TABLE {DEVICE_TABLE_NAME} SCHEMAFUL;
                 DEFINE FIELD display_name ON TABLE {DEVICE_TABLE_NAME} TYPE string;
@@ -138,6 +171,68 @@ impl Database {
                 DEFINE FIELD primary_model_identifiers.* ON TABLE {DEVICE_TABLE_NAME} TYPE string;
                 DEFINE FIELD extended_model_identifiers ON TABLE {DEVICE_TABLE_NAME} TYPE array<string>;
                 DEFINE FIELD extended_model_identifiers.* ON TABLE {DEVICE_TABLE_NAME} TYPE string;
+                DEFINE FIELD variants ON TABLE {DEVICE_TABLE_NAME} TYPE option<array<object>>;
+                DEFINE FIELD variants.*.id ON TABLE {DEVICE_TABLE_NAME} TYPE string;
+                DEFINE FIELD variants.*.capacity ON TABLE {DEVICE_TABLE_NAME} TYPE option<string>;
+                DEFINE FIELD variants.*.color ON TABLE {DEVICE_TABLE_NAME} TYPE option<string>;
+                DEFINE FIELD variants.*.carrier ON TABLE {DEVICE_TABLE_NAME} TYPE option<string>;
+                DEFINE FIELD variants.*.region ON TABLE {DEVICE_TABLE_NAME} TYPE option<string>;
+                DEFINE FIELD variants.*.model_identifiers ON TABLE {DEVICE_TABLE_NAME} TYPE array<string>;
+                DEFINE FIELD variants.*.model_identifiers.* ON TABLE {DEVICE_TABLE_NAME} TYPE string;
+                DEFINE FIELD custom_fields ON TABLE {DEVICE_TABLE_NAME} TYPE option<object>;
+
+                DEFINE TABLE {OVERRIDE_TABLE_NAME} SCHEMAFUL;
+                DEFINE FIELD extension ON TABLE {OVERRIDE_TABLE_NAME} TYPE record({EXTENSION_TABLE_NAME});
+                DEFINE FIELD position ON TABLE {OVERRIDE_TABLE_NAME} TYPE int;
+                DEFINE FIELD target ON TABLE {OVERRIDE_TABLE_NAME} TYPE record({DEVICE_MANUFACTURER_TABLE_NAME} | {DEVICE_CATEGORY_TABLE_NAME} | {DEVICE_TABLE_NAME});
+                DEFINE FIELD operation ON TABLE {OVERRIDE_TABLE_NAME} TYPE string;
+                DEFINE FIELD field ON TABLE {OVERRIDE_TABLE_NAME} TYPE option<string>;
+                DEFINE FIELD value ON TABLE {OVERRIDE_TABLE_NAME} TYPE option<string>;
+
+                DEFINE TABLE {PLUGIN_TABLE_NAME} SCHEMAFUL;
+                DEFINE FIELD display_name ON TABLE {PLUGIN_TABLE_NAME} TYPE string;
+                DEFINE FIELD version ON TABLE {PLUGIN_TABLE_NAME} TYPE string;
+                DEFINE FIELD content_hash ON TABLE {PLUGIN_TABLE_NAME} TYPE string;
+
+                DEFINE TABLE {CUSTOM_FIELD_TABLE_NAME} SCHEMAFUL;
+                DEFINE FIELD extension ON TABLE {CUSTOM_FIELD_TABLE_NAME} TYPE record({EXTENSION_TABLE_NAME});
+                DEFINE FIELD target ON TABLE {CUSTOM_FIELD_TABLE_NAME} TYPE string;
+                DEFINE FIELD field ON TABLE {CUSTOM_FIELD_TABLE_NAME} TYPE string;
+                DEFINE FIELD display_name ON TABLE {CUSTOM_FIELD_TABLE_NAME} TYPE string;
+                DEFINE FIELD type ON TABLE {CUSTOM_FIELD_TABLE_NAME} TYPE string;
+                DEFINE FIELD values ON TABLE {CUSTOM_FIELD_TABLE_NAME} TYPE option<array<string>>;
+                DEFINE FIELD values.* ON TABLE {CUSTOM_FIELD_TABLE_NAME} TYPE string;
+                DEFINE FIELD required ON TABLE {CUSTOM_FIELD_TABLE_NAME} TYPE bool;
+
+                DEFINE TABLE {PART_TABLE_NAME} SCHEMAFUL;
+                DEFINE FIELD display_name ON TABLE {PART_TABLE_NAME} TYPE string;
+                DEFINE FIELD kind ON TABLE {PART_TABLE_NAME} TYPE string;
+                DEFINE FIELD supplier_skus ON TABLE {PART_TABLE_NAME} TYPE array<string>;
+                DEFINE FIELD supplier_skus.* ON TABLE {PART_TABLE_NAME} TYPE string;
+                DEFINE FIELD compatible_devices ON TABLE {PART_TABLE_NAME} TYPE array<record({DEVICE_TABLE_NAME})>;
+                DEFINE FIELD compatible_devices.* ON TABLE {PART_TABLE_NAME} TYPE record({DEVICE_TABLE_NAME});
+                DEFINE FIELD extensions ON TABLE {PART_TABLE_NAME} TYPE array<record({EXTENSION_TABLE_NAME})>;
+                DEFINE FIELD extensions.* ON TABLE {PART_TABLE_NAME} TYPE record({EXTENSION_TABLE_NAME});
+
+                DEFINE TABLE {REPAIR_SERVICE_TABLE_NAME} SCHEMAFUL;
+                DEFINE FIELD display_name ON TABLE {REPAIR_SERVICE_TABLE_NAME} TYPE string;
+                DEFINE FIELD default_labor_minutes ON TABLE {REPAIR_SERVICE_TABLE_NAME} TYPE int;
+                DEFINE FIELD default_price ON TABLE {REPAIR_SERVICE_TABLE_NAME} TYPE int;
+                DEFINE FIELD required_part_kinds ON TABLE {REPAIR_SERVICE_TABLE_NAME} TYPE array<string>;
+                DEFINE FIELD required_part_kinds.* ON TABLE {REPAIR_SERVICE_TABLE_NAME} TYPE string;
+                DEFINE FIELD device_categories ON TABLE {REPAIR_SERVICE_TABLE_NAME} TYPE array<record({DEVICE_CATEGORY_TABLE_NAME})>;
+                DEFINE FIELD device_categories.* ON TABLE {REPAIR_SERVICE_TABLE_NAME} TYPE record({DEVICE_CATEGORY_TABLE_NAME});
+                DEFINE FIELD devices ON TABLE {REPAIR_SERVICE_TABLE_NAME} TYPE array<record({DEVICE_TABLE_NAME})>;
+                DEFINE FIELD devices.* ON TABLE {REPAIR_SERVICE_TABLE_NAME} TYPE record({DEVICE_TABLE_NAME});
+                DEFINE FIELD extensions ON TABLE {REPAIR_SERVICE_TABLE_NAME} TYPE array<record({EXTENSION_TABLE_NAME})>;
+                DEFINE FIELD extensions.* ON TABLE {REPAIR_SERVICE_TABLE_NAME} TYPE record({EXTENSION_TABLE_NAME});
+
+                DEFINE TABLE {CONTRIBUTION_TABLE_NAME} SCHEMAFUL;
+                DEFINE FIELD extension ON TABLE {CONTRIBUTION_TABLE_NAME} TYPE record({EXTENSION_TABLE_NAME});
+                DEFINE FIELD item ON TABLE {CONTRIBUTION_TABLE_NAME} TYPE string;
+                DEFINE FIELD field ON TABLE {CONTRIBUTION_TABLE_NAME} TYPE string;
+                DEFINE FIELD values ON TABLE {CONTRIBUTION_TABLE_NAME} TYPE array<string>;
+                DEFINE FIELD values.* ON TABLE {CONTRIBUTION_TABLE_NAME} TYPE string;
                 ",
             ))
             .await
@@ -160,85 +255,580 @@ impl Database {
     }
 
     /// Loads the contents of an inventory extension into the database.
-    pub async fn load_extension(&self, extension: InventoryExtension) -> surrealdb::Result<()> {
-        self.connection
-            .create::<Vec<GenericPullRecord>>(EXTENSION_TABLE_NAME)
-            .content(InventoryExtensionMetadataPushRecord::from(
-                &extension.metadata,
-            ))
+    /// Items which already exist are merged with the extension's items, and the number of
+    /// records created and merged is returned along with any metadata conflicts. If any part of
+    /// the extension fails to load, the database is left unchanged.
+    pub async fn load_extension(
+        &self,
+        extension: InventoryExtension,
+        merger: &Merger<'_>,
+    ) -> anyhow::Result<(RecordCounts, Vec<FieldConflict>)> {
+        let transaction = Transaction::begin(&self.connection);
+        let (transaction, records, conflicts) = self
+            .add_extension(transaction, &extension, true, merger)
             .await?;
+        transaction.commit().await?;
+        Ok((records, conflicts))
+    }
 
-        let mut futures = Vec::new();
-        for category in extension.device_categories {
-            futures.push(self.add_device_category(category));
+    /// Removes an extension and its contents from the database.
+    /// If any part of the extension fails to unload, the database is left unchanged.
+    pub async fn unload_extension(
+        &self,
+        extension_id: &InventoryExtensionUniqueID,
+    ) -> anyhow::Result<()> {
+        let transaction = Transaction::begin(&self.connection);
+        self.remove_extension(transaction, extension_id)
+            .await?
+            .commit()
+            .await
+    }
+
+    /// Removes the extension corresponding to the ID of the given extension, and loads the given
+    /// extension in its place.
+    /// Both steps happen in a single transaction, so the original extension is kept if the new
+    /// one fails to load. A disabled extension stays disabled.
+    pub async fn reload_extension(
+        &self,
+        extension: InventoryExtension,
+        merger: &Merger<'_>,
+    ) -> anyhow::Result<(RecordCounts, Vec<FieldConflict>)> {
+        let enabled = !self
+            .list_disabled_extensions()
+            .await?
+            .contains(&extension.metadata.id);
+        let transaction = Transaction::begin(&self.connection);
+        let transaction = self
+            .remove_extension(transaction, &extension.metadata.id)
+            .await?;
+        let (transaction, records, conflicts) = self
+            .add_extension(transaction, &extension, enabled, merger)
+            .await?;
+        transaction.commit().await?;
+        Ok((records, conflicts))
+    }
+
+    /// Adds the statements needed to load an extension to a transaction.
+    /// Existing records are read beforehand so they can be merged with the extension's contents.
+    /// If the extension is being reloaded, the values it previously contributed to records it
+    /// shares with other extensions are removed before merging, so it can drop them.
+    /// Fails with a [`MergeConflictError`] if the merge policy refuses any metadata conflicts.
+    // * The existing records are read outside of the transaction, since it is only sent once every
+    // * statement has been added. This assumes nothing else changes the extension's items between
+    // * the read and the commit, which holds because extensions are only loaded by one manager or
+    // * watcher at a time, and each loads its extensions one after another.
+    async fn add_extension<'a>(
+        &self,
+        mut transaction: Transaction<'a>,
+        extension: &InventoryExtension,
+        enabled: bool,
+        merger: &Merger<'_>,
+    ) -> anyhow::Result<(Transaction<'a>, RecordCounts, Vec<FieldConflict>)> {
+        let mut conflicts = Vec::new();
+        let mut records = RecordCounts::default();
+        // * Records which only belonged to the extension are replaced when it is reloaded, so
+        // * they count as created rather than merged.
+        let is_shared = |extensions: &HashSet<InventoryExtensionUniqueID>| {
+            extensions.iter().any(|id| *id != extension.metadata.id)
+        };
+        let contributions = self.list_contributions().await?;
+        transaction = transaction.statement(
+            &format!("CREATE {EXTENSION_TABLE_NAME} CONTENT $value;"),
+            InventoryExtensionMetadataPushRecord {
+                enabled,
+                ..InventoryExtensionMetadataPushRecord::from(extension)
+            },
+        );
+
+        // * The schema is extended before any items are created, so their custom field values
+        // * are kept by the schemaful tables.
+        for field in &extension.custom_fields {
+            transaction = transaction
+                .statement(
+                    &format!("CREATE {CUSTOM_FIELD_TABLE_NAME} CONTENT $value;"),
+                    CustomFieldPushRecord::new(&extension.metadata.id, field),
+                )
+                .schema(&custom_field_schema(field)?);
+            records.custom_fields.created += 1;
+        }
+
+        // * Overrides are kept apart from the records they target, so they are not lost when the
+        // * targeted extensions are reloaded.
+        for (position, o) in extension.overrides.iter().enumerate() {
+            transaction = transaction.statement(
+                &format!("CREATE {OVERRIDE_TABLE_NAME} CONTENT $value;"),
+                OverridePushRecord::new(&extension.metadata.id, position, o),
+            );
+            records.overrides.created += 1;
         }
-        future::join_all(futures).await;
 
-        let mut futures = Vec::new();
-        for manufacturer in extension.device_manufacturers {
-            futures.push(self.add_device_manufacturer(manufacturer));
+        for mut category in extension.device_categories.iter().cloned() {
+            transaction = Self::record_contribution(transaction, &extension.metadata.id, &category);
+            let mut merged = false;
+            if let Some(existing_record) = self.get_device_category(&category.id).await? {
+                let mut existing = DeviceCategory::try_from(existing_record)?;
+                withdraw_contribution(&mut existing, &extension.metadata.id, &contributions);
+                merged = is_shared(&existing.extensions);
+                conflicts.extend(merger.device_category(&mut category, existing));
+                transaction = transaction.statement("DELETE $value;", Thing::from(&category.id));
+            }
+            records.device_categories.record(merged);
+
+            transaction = transaction.statement(
+                &format!("CREATE {DEVICE_CATEGORY_TABLE_NAME} CONTENT $value;"),
+                DeviceCategoryPushRecord::from(&category),
+            );
         }
-        future::join_all(futures).await;
 
-        let mut futures = Vec::new();
-        for device in extension.devices {
-            futures.push(self.add_device(device));
+        for mut manufacturer in extension.device_manufacturers.iter().cloned() {
+            transaction =
+                Self::record_contribution(transaction, &extension.metadata.id, &manufacturer);
+            let mut merged = false;
+            if let Some(existing_record) = self.get_device_manufacturer(&manufacturer.id).await? {
+                let mut existing = DeviceManufacturer::try_from(existing_record)?;
+                withdraw_contribution(&mut existing, &extension.metadata.id, &contributions);
+                merged = is_shared(&existing.extensions);
+                conflicts.extend(merger.device_manufacturer(&mut manufacturer, existing));
+                transaction =
+                    transaction.statement("DELETE $value;", Thing::from(&manufacturer.id));
+            }
+            records.device_manufacturers.record(merged);
+
+            transaction = transaction.statement(
+                &format!("CREATE {DEVICE_MANUFACTURER_TABLE_NAME} CONTENT $value;"),
+                DeviceManufacturerPushRecord::from(&manufacturer),
+            );
         }
-        future::join_all(futures).await;
 
-        Ok(())
+        for mut device in extension.devices.iter().cloned() {
+            transaction = Self::record_contribution(transaction, &extension.metadata.id, &device);
+            let mut merged = false;
+            if let Some(existing_record) = self.get_device(&device.id).await? {
+                let mut existing = Device::try_from(existing_record)?;
+                withdraw_contribution(&mut existing, &extension.metadata.id, &contributions);
+                merged = is_shared(&existing.extensions);
+                conflicts.extend(merger.device(&mut device, existing));
+                transaction = transaction.statement("DELETE $value;", Thing::from(&device.id));
+            }
+            records.devices.record(merged);
+
+            transaction = transaction.statement(
+                &format!("CREATE {DEVICE_TABLE_NAME} CONTENT $value;"),
+                DevicePushRecord::from(&device),
+            );
+        }
+
+        for mut part in extension.parts.iter().cloned() {
+            transaction = Self::record_contribution(transaction, &extension.metadata.id, &part);
+            let mut merged = false;
+            if let Some(existing_record) = self.get_part(&part.id).await? {
+                let mut existing = Part::try_from(existing_record)?;
+                withdraw_contribution(&mut existing, &extension.metadata.id, &contributions);
+                merged = is_shared(&existing.extensions);
+                conflicts.extend(merger.part(&mut part, existing));
+                transaction = transaction.statement("DELETE $value;", Thing::from(&part.id));
+            }
+            records.parts.record(merged);
+
+            transaction = transaction.statement(
+                &format!("CREATE {PART_TABLE_NAME} CONTENT $value;"),
+                PartPushRecord::from(&part),
+            );
+        }
+
+        for mut service in extension.repair_services.iter().cloned() {
+            transaction = Self::record_contribution(transaction, &extension.metadata.id, &service);
+            let mut merged = false;
+            if let Some(existing_record) = self.get_repair_service(&service.id).await? {
+                let mut existing = RepairService::try_from(existing_record)?;
+                withdraw_contribution(&mut existing, &extension.metadata.id, &contributions);
+                merged = is_shared(&existing.extensions);
+                conflicts.extend(merger.repair_service(&mut service, existing));
+                transaction = transaction.statement("DELETE $value;", Thing::from(&service.id));
+            }
+            records.repair_services.record(merged);
+
+            transaction = transaction.statement(
+                &format!("CREATE {REPAIR_SERVICE_TABLE_NAME} CONTENT $value;"),
+                RepairServicePushRecord::from(&service),
+            );
+        }
+
+        if conflicts
+            .iter()
+            .any(|conflict| conflict.resolution == Resolution::Refused)
+        {
+            return Err(MergeConflictError(conflicts).into());
+        }
+
+        Ok((transaction, records, conflicts))
     }
 
-    /// Removes an extension and its contents from the database.
-    pub async fn unload_extension(
+    /// Adds the statements needed to record the values an extension gives to the combined lists
+    /// of an item to a transaction.
+    fn record_contribution<'a>(
+        mut transaction: Transaction<'a>,
+        extension_id: &InventoryExtensionUniqueID,
+        item: &impl Combined,
+    ) -> Transaction<'a> {
+        for (field, values) in &item.contribution() {
+            transaction = transaction.statement(
+                &format!("CREATE {CONTRIBUTION_TABLE_NAME} CONTENT $value;"),
+                ContributionPushRecord {
+                    extension: Thing::from(extension_id),
+                    item: item.key(),
+                    field,
+                    values,
+                },
+            );
+        }
+        transaction
+    }
+
+    /// Adds the statements needed to unload an extension to a transaction.
+    /// Records which only belong to the extension are deleted, and the extension is removed from
+    /// all other records, along with the values it contributed to them which no other provider
+    /// contributed as well.
+    async fn remove_extension<'a>(
         &self,
+        mut transaction: Transaction<'a>,
         extension_id: &InventoryExtensionUniqueID,
-    ) -> anyhow::Result<()> {
-        self.connection
-            .query(&format!(
+    ) -> anyhow::Result<Transaction<'a>> {
+        // * Shared records are rewritten before the extension is removed from them, so the
+        // * rewritten records do not list it again.
+        let contributions = self.list_contributions().await?;
+        for category in withdrawn(
+            self.list_base_device_categories().await?,
+            extension_id,
+            &contributions,
+        ) {
+            transaction = transaction
+                .statement("DELETE $value;", Thing::from(&category.id))
+                .statement(
+                    &format!("CREATE {DEVICE_CATEGORY_TABLE_NAME} CONTENT $value;"),
+                    DeviceCategoryPushRecord::from(&category),
+                );
+        }
+        for manufacturer in withdrawn(
+            self.list_base_device_manufacturers().await?,
+            extension_id,
+            &contributions,
+        ) {
+            transaction = transaction
+                .statement("DELETE $value;", Thing::from(&manufacturer.id))
+                .statement(
+                    &format!("CREATE {DEVICE_MANUFACTURER_TABLE_NAME} CONTENT $value;"),
+                    DeviceManufacturerPushRecord::from(&manufacturer),
+                );
+        }
+        for device in withdrawn(
+            self.list_base_devices().await?,
+            extension_id,
+            &contributions,
+        ) {
+            transaction = transaction
+                .statement("DELETE $value;", Thing::from(&device.id))
+                .statement(
+                    &format!("CREATE {DEVICE_TABLE_NAME} CONTENT $value;"),
+                    DevicePushRecord::from(&device),
+                );
+        }
+        for part in withdrawn(self.list_base_parts().await?, extension_id, &contributions) {
+            transaction = transaction
+                .statement("DELETE $value;", Thing::from(&part.id))
+                .statement(
+                    &format!("CREATE {PART_TABLE_NAME} CONTENT $value;"),
+                    PartPushRecord::from(&part),
+                );
+        }
+        for service in withdrawn(
+            self.list_base_repair_services().await?,
+            extension_id,
+            &contributions,
+        ) {
+            transaction = transaction
+                .statement("DELETE $value;", Thing::from(&service.id))
+                .statement(
+                    &format!("CREATE {REPAIR_SERVICE_TABLE_NAME} CONTENT $value;"),
+                    RepairServicePushRecord::from(&service),
+                );
+        }
+
+        Ok(transaction.statement(
+            &format!(
                 "
-                DELETE {DEVICE_MANUFACTURER_TABLE_NAME} WHERE extensions = [\"{0}\"];
-                DELETE {DEVICE_CATEGORY_TABLE_NAME} WHERE extensions = [\"{0}\"];
-                DELETE {DEVICE_TABLE_NAME} WHERE extensions = [\"{0}\"];
-                DELETE {EXTENSION_TABLE_NAME} WHERE id = \"{0}\";
-                
-                UPDATE {DEVICE_MANUFACTURER_TABLE_NAME} SET extensions -= [\"{0}\"];
-                UPDATE {DEVICE_CATEGORY_TABLE_NAME} SET extensions -= [\"{0}\"];
-                UPDATE {DEVICE_TABLE_NAME} SET extensions -= [\"{0}\"];
-                ",
-                extension_id.namespaced()
+                DELETE {DEVICE_MANUFACTURER_TABLE_NAME} WHERE extensions = [$value];
+                DELETE {DEVICE_CATEGORY_TABLE_NAME} WHERE extensions = [$value];
+                DELETE {DEVICE_TABLE_NAME} WHERE extensions = [$value];
+                DELETE {PART_TABLE_NAME} WHERE extensions = [$value];
+                DELETE {REPAIR_SERVICE_TABLE_NAME} WHERE extensions = [$value];
+                DELETE {OVERRIDE_TABLE_NAME} WHERE extension = $value;
+                DELETE {CUSTOM_FIELD_TABLE_NAME} WHERE extension = $value;
+                DELETE {CONTRIBUTION_TABLE_NAME} WHERE extension = $value;
+                DELETE {EXTENSION_TABLE_NAME} WHERE id = $value;
+
+                UPDATE {DEVICE_MANUFACTURER_TABLE_NAME} SET extensions -= [$value];
+                UPDATE {DEVICE_CATEGORY_TABLE_NAME} SET extensions -= [$value];
+                UPDATE {DEVICE_TABLE_NAME} SET extensions -= [$value];
+                UPDATE {PART_TABLE_NAME} SET extensions -= [$value];
+                UPDATE {REPAIR_SERVICE_TABLE_NAME} SET extensions -= [$value];
+                "
+            ),
+            Thing::from(extension_id),
+        ))
+    }
+
+    /// Lists all currently-loaded extensions in the database.
+    /// Override extensions are listed separately from the base extensions whose items they patch.
+    pub async fn list_extensions(&self) -> anyhow::Result<LoadedExtensions> {
+        let pull_records = self
+            .connection
+            .select::<Vec<InventoryExtensionMetadataPullRecord>>(EXTENSION_TABLE_NAME)
+            .await?;
+        let override_extensions = self
+            .connection
+            .select::<Vec<OverridePullRecord>>(OVERRIDE_TABLE_NAME)
+            .await?
+            .into_iter()
+            .map(|record| InventoryExtensionUniqueID::try_from(record.extension))
+            .collect::<anyhow::Result<HashSet<_>>>()?;
+
+        let mut extensions = LoadedExtensions::default();
+        for record in pull_records {
+            let metadata = InventoryExtensionMetadata::try_from(record)?;
+            match override_extensions.contains(&metadata.id) {
+                true => extensions.overrides.push(metadata),
+                false => extensions.base.push(metadata),
+            }
+        }
+
+        Ok(extensions)
+    }
+
+    /// Lists the custom fields declared by every loaded extension, along with the extension which
+    /// declared each one.
+    pub async fn list_custom_fields(
+        &self,
+    ) -> anyhow::Result<Vec<(InventoryExtensionUniqueID, CustomFieldDefinition)>> {
+        let pull_records = self
+            .connection
+            .select::<Vec<CustomFieldPullRecord>>(CUSTOM_FIELD_TABLE_NAME)
+            .await?;
+
+        let mut fields = Vec::new();
+        for record in pull_records {
+            let extension = InventoryExtensionUniqueID::try_from(record.extension.clone())?;
+            fields.push((extension, CustomFieldDefinition::try_from(record)?));
+        }
+
+        Ok(fields)
+    }
+
+    /// Lists the values which each extension gave to the combined lists of the items it provides.
+    pub async fn list_contributions(&self) -> anyhow::Result<Contributions> {
+        let pull_records = self
+            .connection
+            .select::<Vec<ContributionPullRecord>>(CONTRIBUTION_TABLE_NAME)
+            .await?;
+
+        let mut contributions = Contributions::new();
+        for record in pull_records {
+            contributions
+                .entry(record.item)
+                .or_default()
+                .entry(InventoryExtensionUniqueID::try_from(record.extension)?)
+                .or_default()
+                .insert(record.field, record.values);
+        }
+
+        Ok(contributions)
+    }
+
+    /// Records the version of a plugin which was loaded, replacing any previous record.
+    pub async fn record_plugin(
+        &self,
+        plugin: &PluginMetadata,
+        content_hash: &str,
+    ) -> anyhow::Result<()> {
+        Transaction::begin(&self.connection)
+            .statement("DELETE $value;", Thing::from(&plugin.id))
+            .statement(
+                &format!("CREATE {PLUGIN_TABLE_NAME} CONTENT $value;"),
+                PluginMetadataPushRecord::new(plugin, content_hash),
+            )
+            .commit()
+            .await
+    }
+
+    /// Lists every plugin which has been loaded.
+    pub async fn list_plugins(&self) -> anyhow::Result<Vec<PluginMetadata>> {
+        let pull_records = self
+            .connection
+            .select::<Vec<PluginMetadataPullRecord>>(PLUGIN_TABLE_NAME)
+            .await?;
+
+        let mut plugins = Vec::new();
+        for record in pull_records {
+            plugins.push(PluginMetadata::try_from(record)?);
+        }
+
+        Ok(plugins)
+    }
+
+    /// Lists the hash of the module of each plugin which has been loaded.
+    pub async fn list_plugin_hashes(&self) -> anyhow::Result<HashMap<PluginUniqueID, String>> {
+        let pull_records = self
+            .connection
+            .select::<Vec<PluginMetadataPullRecord>>(PLUGIN_TABLE_NAME)
+            .await?;
+
+        let mut hashes = HashMap::new();
+        for record in pull_records {
+            hashes.insert(PluginUniqueID::try_from(record.id)?, record.content_hash);
+        }
+
+        Ok(hashes)
+    }
+
+    /// Lists the IDs of loaded extensions which are disabled.
+    pub async fn list_disabled_extensions(
+        &self,
+    ) -> anyhow::Result<HashSet<InventoryExtensionUniqueID>> {
+        let pull_records = self
+            .connection
+            .select::<Vec<InventoryExtensionMetadataPullRecord>>(EXTENSION_TABLE_NAME)
+            .await?;
+
+        let mut disabled = HashSet::new();
+        for record in pull_records {
+            if record.enabled == Some(false) {
+                disabled.insert(InventoryExtensionUniqueID::try_from(record.id)?);
+            }
+        }
+
+        Ok(disabled)
+    }
+
+    /// Enables or disables a loaded extension, without changing its contents.
+    /// Items which are only provided by disabled extensions are kept in the database, but hidden
+    /// from listings. Returns `false` if the extension is not loaded.
+    pub async fn set_extension_enabled(
+        &self,
+        extension_id: &InventoryExtensionUniqueID,
+        enabled: bool,
+    ) -> anyhow::Result<bool> {
+        // * Updating a record which does not exist would create it.
+        let existing = self
+            .connection
+            .select::<Option<InventoryExtensionMetadataPullRecord>>((
+                EXTENSION_TABLE_NAME,
+                extension_id.unnamespaced(),
             ))
             .await?;
+        if existing.is_none() {
+            return Ok(false);
+        }
 
-        Ok(())
+        self.connection
+            .query("UPDATE $extension SET enabled = $enabled;")
+            .bind(("extension", Thing::from(extension_id)))
+            .bind(("enabled", enabled))
+            .await?
+            .check()?;
+
+        Ok(true)
     }
 
-    /// Removes the extension corresponding to the ID of the given extension, and loads the given
-    /// extension in its place.
-    pub async fn reload_extension(&self, extension: InventoryExtension) -> anyhow::Result<()> {
-        self.unload_extension(&extension.metadata.id).await?;
-        self.load_extension(extension).await?;
-        Ok(())
+    /// Lists the overrides of all enabled override extensions, in the order they are applied.
+    /// Overrides are applied by extension ID, then in the order they are listed within their
+    /// extension.
+    pub async fn list_overrides(&self) -> anyhow::Result<Vec<Override>> {
+        let disabled = self.list_disabled_extensions().await?;
+        let mut pull_records = self
+            .connection
+            .select::<Vec<OverridePullRecord>>(OVERRIDE_TABLE_NAME)
+            .await?;
+        pull_records.retain(|record| {
+            InventoryExtensionUniqueID::try_from(record.extension.clone())
+                .map_or(true, |id| !disabled.contains(&id))
+        });
+        pull_records.sort_by_cached_key(|record| (record.extension.to_string(), record.position));
+
+        let mut overrides = Vec::new();
+        for record in pull_records {
+            overrides.push(Override::try_from(record)?);
+        }
+
+        Ok(overrides)
     }
 
-    /// Lists all currently-loaded extensions in the database.
-    pub async fn list_extensions(&self) -> anyhow::Result<Vec<InventoryExtensionMetadata>> {
+    /// Lists the extensions which each currently-loaded extension depends on, along with the
+    /// versions it requires.
+    /// Extensions which were loaded before dependencies were recorded are listed without any.
+    pub async fn list_extension_dependencies(
+        &self,
+    ) -> anyhow::Result<HashMap<InventoryExtensionUniqueID, Dependencies>> {
         let pull_records = self
             .connection
             .select::<Vec<InventoryExtensionMetadataPullRecord>>(EXTENSION_TABLE_NAME)
             .await?;
 
-        let mut extensions = Vec::new();
+        let mut dependencies = HashMap::new();
         for record in pull_records {
-            extensions.push(InventoryExtensionMetadata::try_from(record)?);
+            let mut extension_dependencies = Dependencies::new();
+            for dependency in record.dependencies.unwrap_or_default() {
+                extension_dependencies.insert(
+                    InventoryExtensionUniqueID::try_from(dependency.extension)?,
+                    VersionReq::parse(&dependency.requirement)?,
+                );
+            }
+            dependencies.insert(
+                InventoryExtensionUniqueID::try_from(record.id)?,
+                extension_dependencies,
+            );
         }
 
-        Ok(extensions)
+        Ok(dependencies)
     }
 
-    /// Lists all the device manufacturers in the database.
-    #[allow(dead_code)]
+    /// Lists the content hash of each currently-loaded extension.
+    /// Extensions which were loaded without a content hash are omitted.
+    pub async fn list_extension_hashes(
+        &self,
+    ) -> anyhow::Result<HashMap<InventoryExtensionUniqueID, String>> {
+        let pull_records = self
+            .connection
+            .select::<Vec<InventoryExtensionMetadataPullRecord>>(EXTENSION_TABLE_NAME)
+            .await?;
+
+        let mut hashes = HashMap::new();
+        for record in pull_records {
+            if let Some(content_hash) = record.content_hash {
+                hashes.insert(
+                    InventoryExtensionUniqueID::try_from(record.id)?,
+                    content_hash,
+                );
+            }
+        }
+
+        Ok(hashes)
+    }
+
+    /// Lists all the device manufacturers in the database, with overrides applied.
+    /// Items which are only provided by disabled extensions are hidden.
     pub async fn list_device_manufacturers(&self) -> anyhow::Result<Vec<DeviceManufacturer>> {
+        let disabled = self.list_disabled_extensions().await?;
+        let mut device_manufacturers = self.list_base_device_manufacturers().await?;
+        device_manufacturers.retain(|m| is_enabled(&m.extensions, &disabled));
+        Ok(apply_overrides(
+            device_manufacturers,
+            &self.list_overrides().await?,
+        ))
+    }
+
+    /// Lists all the device manufacturers in the database as provided by their extensions, without
+    /// overrides applied.
+    pub async fn list_base_device_manufacturers(&self) -> anyhow::Result<Vec<DeviceManufacturer>> {
         let pull_records = self
             .connection
             .select::<Vec<DeviceManufacturerPullRecord>>(DEVICE_MANUFACTURER_TABLE_NAME)
@@ -252,9 +842,21 @@ impl Database {
         Ok(manufacturers)
     }
 
-    /// Lists all the device categories in the database.
-    #[allow(dead_code)]
+    /// Lists all the device categories in the database, with overrides applied.
+    /// Items which are only provided by disabled extensions are hidden.
     pub async fn list_device_categories(&self) -> anyhow::Result<Vec<DeviceCategory>> {
+        let disabled = self.list_disabled_extensions().await?;
+        let mut device_categories = self.list_base_device_categories().await?;
+        device_categories.retain(|c| is_enabled(&c.extensions, &disabled));
+        Ok(apply_overrides(
+            device_categories,
+            &self.list_overrides().await?,
+        ))
+    }
+
+    /// Lists all the device categories in the database as provided by their extensions, without
+    /// overrides applied.
+    pub async fn list_base_device_categories(&self) -> anyhow::Result<Vec<DeviceCategory>> {
         let pull_records = self
             .connection
             .select::<Vec<DeviceCategoryPullRecord>>(DEVICE_CATEGORY_TABLE_NAME)
@@ -268,8 +870,30 @@ impl Database {
         Ok(categories)
     }
 
-    /// Lists all the devices in the database.
+    /// Lists all the devices in the database, with overrides applied.
+    /// Items which are only provided by disabled extensions are hidden.
     pub async fn list_devices(&self) -> anyhow::Result<Vec<Device>> {
+        let disabled = self.list_disabled_extensions().await?;
+        let mut devices = self.list_base_devices().await?;
+        devices.retain(|d| is_enabled(&d.extensions, &disabled));
+        Ok(apply_overrides(devices, &self.list_overrides().await?))
+    }
+
+    /// Finds the device which a model identifier refers to, along with the variant the identifier
+    /// belongs to, if any. Devices are searched as listed, with overrides applied.
+    pub async fn find_device_by_identifier(
+        &self,
+        identifier: &str,
+    ) -> anyhow::Result<Option<IdentifierMatch>> {
+        Ok(self.list_devices().await?.into_iter().find_map(|device| {
+            let variant = device.match_identifier(identifier)?.cloned();
+            Some(IdentifierMatch { device, variant })
+        }))
+    }
+
+    /// Lists all the devices in the database as provided by their extensions, without
+    /// overrides applied.
+    pub async fn list_base_devices(&self) -> anyhow::Result<Vec<Device>> {
         let pull_records = self
             .connection
             .select::<Vec<DevicePullRecord>>(DEVICE_TABLE_NAME)
@@ -283,83 +907,110 @@ impl Database {
         Ok(devices)
     }
 
-    /// Adds a deivice manufacturer to the database, merging it with an existing record if needed.
-    pub async fn add_device_manufacturer(
+    /// Lists the devices in the given category or any category below it, with overrides applied.
+    pub async fn list_devices_in_category(
         &self,
-        mut manufacturer: DeviceManufacturer,
-    ) -> anyhow::Result<()> {
-        if let Some(existing_record) = self.get_device_manufacturer(&manufacturer.id).await? {
-            manufacturer.merge(existing_record.try_into()?);
-            self.remove_device_manufacturer(&manufacturer.id).await?;
-        }
-
-        self.connection
-            .create::<Vec<GenericPullRecord>>(DEVICE_MANUFACTURER_TABLE_NAME)
-            .content(DeviceManufacturerPushRecord::from(&manufacturer))
-            .await?;
-
-        Ok(())
+        category: &DeviceCategoryUniqueID,
+    ) -> anyhow::Result<Vec<Device>> {
+        let tree = CategoryTree::new(&self.list_device_categories().await?);
+        let mut devices = self.list_devices().await?;
+        devices.retain(|device| tree.is_within(&device.category, category));
+        Ok(devices)
     }
 
-    /// Adds a device category to the database, merging it with an existing record if needed.
-    async fn add_device_category(&self, mut category: DeviceCategory) -> anyhow::Result<()> {
-        if let Some(existing_record) = self.get_device_category(&category.id).await? {
-            category.merge(existing_record.try_into()?);
-            self.remove_device_category(&category.id).await?;
-        }
+    /// Lists all the parts in the database.
+    /// Items which are only provided by disabled extensions are hidden.
+    pub async fn list_parts(&self) -> anyhow::Result<Vec<Part>> {
+        let disabled = self.list_disabled_extensions().await?;
+        let mut parts = self.list_base_parts().await?;
+        parts.retain(|p| is_enabled(&p.extensions, &disabled));
+        Ok(parts)
+    }
 
-        self.connection
-            .create::<Vec<GenericPullRecord>>(DEVICE_CATEGORY_TABLE_NAME)
-            .content(DeviceCategoryPushRecord::from(&category))
+    /// Lists all the parts in the database as provided by their extensions, including those of
+    /// disabled extensions.
+    pub async fn list_base_parts(&self) -> anyhow::Result<Vec<Part>> {
+        let pull_records = self
+            .connection
+            .select::<Vec<PartPullRecord>>(PART_TABLE_NAME)
             .await?;
 
-        Ok(())
-    }
-
-    /// Adds a device to the database, merging it with an existing record if needed.
-    async fn add_device(&self, mut device: Device) -> anyhow::Result<()> {
-        if let Some(existing_record) = self.get_device(&device.id).await? {
-            device.merge(existing_record.try_into()?);
-            self.remove_device(&device.id).await?;
+        let mut parts = Vec::new();
+        for record in pull_records {
+            parts.push(Part::try_from(record)?);
         }
 
-        self.connection
-            .create::<Vec<GenericPullRecord>>(DEVICE_TABLE_NAME)
-            .content(DevicePushRecord::from(&device))
-            .await?;
+        Ok(parts)
+    }
 
-        Ok(())
+    /// Lists the parts which fit the given device.
+    pub async fn list_compatible_parts(
+        &self,
+        device: &DeviceUniqueID,
+    ) -> anyhow::Result<Vec<Part>> {
+        let mut parts = self.list_parts().await?;
+        parts.retain(|part| part.is_compatible_with(device));
+        Ok(parts)
     }
 
-    /// Removes a single device manufacturer from the database.
-    // TODO: Any way to consolidate these 3 methods?
-    pub async fn remove_device_manufacturer(
+    /// Lists the devices which the given part fits, with overrides applied.
+    /// Devices which are hidden, or which no longer exist, are left out.
+    pub async fn list_compatible_devices(
         &self,
-        id: &DeviceManufacturerUniqueID,
-    ) -> anyhow::Result<()> {
-        self.connection
-            .query(&format!("DELETE {}", id.namespaced()))
-            .await?;
+        part: &PartUniqueID,
+    ) -> anyhow::Result<Vec<Device>> {
+        let Some(part) = self.list_parts().await?.into_iter().find(|p| p.id == *part) else {
+            return Ok(Vec::new());
+        };
 
-        Ok(())
+        let mut devices = self.list_devices().await?;
+        devices.retain(|device| part.is_compatible_with(&device.id));
+        Ok(devices)
     }
 
-    /// Removes a single device category from the database.
-    pub async fn remove_device_category(&self, id: &DeviceCategoryUniqueID) -> anyhow::Result<()> {
-        self.connection
-            .query(&format!("DELETE {}", id.namespaced()))
+    /// Lists all the repair services in the database.
+    /// Items which are only provided by disabled extensions are hidden.
+    pub async fn list_repair_services(&self) -> anyhow::Result<Vec<RepairService>> {
+        let disabled = self.list_disabled_extensions().await?;
+        let mut services = self.list_base_repair_services().await?;
+        services.retain(|s| is_enabled(&s.extensions, &disabled));
+        Ok(services)
+    }
+
+    /// Lists all the repair services in the database as provided by their extensions, including
+    /// those of disabled extensions.
+    pub async fn list_base_repair_services(&self) -> anyhow::Result<Vec<RepairService>> {
+        let pull_records = self
+            .connection
+            .select::<Vec<RepairServicePullRecord>>(REPAIR_SERVICE_TABLE_NAME)
             .await?;
 
-        Ok(())
+        let mut services = Vec::new();
+        for record in pull_records {
+            services.push(RepairService::try_from(record)?);
+        }
+
+        Ok(services)
     }
 
-    /// Removes a single device from the database.
-    pub async fn remove_device(&self, id: &DeviceUniqueID) -> anyhow::Result<()> {
-        self.connection
-            .query(&format!("DELETE {}", id.namespaced()))
-            .await?;
+    /// Builds the price list of a device, with the repair services which apply to it and the
+    /// parts each service needs. Returns `None` if the device does not exist or is hidden.
+    pub async fn price_list(&self, device: &DeviceUniqueID) -> anyhow::Result<Option<PriceList>> {
+        let Some(device) = self
+            .list_devices()
+            .await?
+            .into_iter()
+            .find(|d| d.id == *device)
+        else {
+            return Ok(None);
+        };
 
-        Ok(())
+        Ok(Some(PriceList::new(
+            device,
+            self.list_repair_services().await?,
+            &self.list_parts().await?,
+            &CategoryTree::new(&self.list_device_categories().await?),
+        )))
     }
 
     // ? Can this be combined with `get_device_category()` into a single function?
@@ -399,11 +1050,33 @@ impl Database {
             .await?)
     }
 
+    /// Gets a part from the database, if it exists.
+    async fn get_part(&self, id: &PartUniqueID) -> anyhow::Result<Option<PartPullRecord>> {
+        Ok(self
+            .connection
+            .select::<Option<PartPullRecord>>((PART_TABLE_NAME, id.unnamespaced()))
+            .await?)
+    }
+
+    /// Gets a repair service from the database, if it exists.
+    async fn get_repair_service(
+        &self,
+        id: &RepairServiceUniqueID,
+    ) -> anyhow::Result<Option<RepairServicePullRecord>> {
+        Ok(self
+            .connection
+            .select::<Option<RepairServicePullRecord>>((
+                REPAIR_SERVICE_TABLE_NAME,
+                id.unnamespaced(),
+            ))
+            .await?)
+    }
+
     /// Checks that the database contains the given extension and its contents.
     /// Used for testing purposes.
     #[cfg(test)]
     pub async fn contains(&self, extension: &InventoryExtension, exclusive: bool) {
-        let loaded_extensions = self.list_extensions().await.unwrap();
+        let loaded_extensions = self.list_extensions().await.unwrap().into_all();
 
         if exclusive {
             assert_eq!(loaded_extensions.len(), 1);
@@ -480,5 +1153,141 @@ impl Database {
 
             panic!("Device not found");
         }
+
+        let loaded_parts = self.list_parts().await.unwrap();
+        for extension_part in &extension.parts {
+            let found = loaded_parts.iter().any(|loaded_part| {
+                loaded_part.id == extension_part.id
+                    && loaded_part.display_name == extension_part.display_name
+                    && loaded_part.kind == extension_part.kind
+                    && loaded_part.extensions.contains(&extension.metadata.id)
+                    && (!exclusive || loaded_part.extensions.len() == 1)
+            });
+            assert!(found, "Part not found");
+        }
+
+        let loaded_services = self.list_repair_services().await.unwrap();
+        for extension_service in &extension.repair_services {
+            let found = loaded_services.iter().any(|loaded_service| {
+                loaded_service.id == extension_service.id
+                    && loaded_service.display_name == extension_service.display_name
+                    && loaded_service.extensions.contains(&extension.metadata.id)
+                    && (!exclusive || loaded_service.extensions.len() == 1)
+            });
+            assert!(found, "Repair service not found");
+        }
+    }
+}
+
+/// Checks whether an item is provided by at least one extension which is not disabled.
+fn is_enabled(
+    extensions: &HashSet<InventoryExtensionUniqueID>,
+    disabled: &HashSet<InventoryExtensionUniqueID>,
+) -> bool {
+    extensions
+        .iter()
+        .any(|extension| !disabled.contains(extension))
+}
+
+/// Withdraws the values an extension contributed from the items it shares with other extensions,
+/// returning only the items which changed.
+// * Items which only belong to the extension are deleted along with it, so they are skipped.
+fn withdrawn<T: Combined>(
+    items: Vec<T>,
+    extension: &InventoryExtensionUniqueID,
+    contributions: &Contributions,
+) -> Vec<T> {
+    items
+        .into_iter()
+        .filter(|item| item.providers().len() > 1)
+        .filter_map(|mut item| {
+            let contribution = item.contribution();
+            withdraw_contribution(&mut item, extension, contributions);
+            (item.contribution() != contribution).then_some(item)
+        })
+        .collect()
+}
+
+/// Builds the statement which adds a custom field to the schema of the table it targets.
+/// Redefining a field replaces its type, so reloading an extension updates its fields. Fields are
+/// kept when their extension is unloaded, since items from other extensions may still use them.
+fn custom_field_schema(field: &CustomFieldDefinition) -> anyhow::Result<String> {
+    // * Field IDs are validated when extensions are parsed, since they cannot be bound as
+    // * parameters in schema statements.
+    if !crate::extensions::is_valid_custom_field_id(&field.id) {
+        anyhow::bail!("Custom field ID '{}' is not valid", field.id);
+    }
+
+    let table = match field.target {
+        CustomFieldTarget::DeviceManufacturer => DEVICE_MANUFACTURER_TABLE_NAME,
+        CustomFieldTarget::DeviceCategory => DEVICE_CATEGORY_TABLE_NAME,
+        CustomFieldTarget::Device => DEVICE_TABLE_NAME,
+    };
+    let id = &field.id;
+    Ok(match &field.field_type {
+        CustomFieldType::String => {
+            format!("DEFINE FIELD custom_fields.{id} ON TABLE {table} TYPE option<string>;")
+        }
+        CustomFieldType::Number => {
+            format!("DEFINE FIELD custom_fields.{id} ON TABLE {table} TYPE option<number>;")
+        }
+        CustomFieldType::Bool => {
+            format!("DEFINE FIELD custom_fields.{id} ON TABLE {table} TYPE option<bool>;")
+        }
+        // * JSON strings are also valid SurrealQL strings, so the values are escaped as JSON.
+        CustomFieldType::Enum(values) => format!(
+            "DEFINE FIELD custom_fields.{id} ON TABLE {table} TYPE option<string> \
+            ASSERT $value = NONE OR $value INSIDE {};",
+            serde_json::to_string(values)?
+        ),
+        CustomFieldType::List => format!(
+            "
+            DEFINE FIELD custom_fields.{id} ON TABLE {table} TYPE option<array<string>>;
+            DEFINE FIELD custom_fields.{id}.* ON TABLE {table} TYPE string;
+            "
+        ),
+    })
+}
+
+/// A set of statements which are sent to the database as a single query and either all succeed or
+/// all fail.
+struct Transaction<'a> {
+    query: Query<'a, Client>,
+    parameters: usize,
+}
+
+impl<'a> Transaction<'a> {
+    /// Starts building a new transaction.
+    fn begin(connection: &'a Surreal<Client>) -> Self {
+        Self {
+            query: connection.query("BEGIN TRANSACTION;"),
+            parameters: 0,
+        }
+    }
+
+    /// Adds one or more statements to the transaction.
+    /// Each occurrence of `$value` in the statements refers to the given value.
+    fn statement(mut self, statement: &str, value: impl Serialize) -> Self {
+        // * Every value needs its own parameter, since all statements share one query.
+        let parameter = format!("value_{}", self.parameters);
+        self.parameters += 1;
+        self.query = self
+            .query
+            .query(statement.replace("$value", &format!("${parameter}")))
+            .bind((parameter, value));
+        self
+    }
+
+    /// Adds one or more statements which do not take a value, such as schema definitions.
+    /// Occurrences of `$value` are left as they are.
+    fn schema(mut self, statement: &str) -> Self {
+        self.query = self.query.query(statement);
+        self
+    }
+
+    /// Runs the transaction, returning the first error if any statement failed.
+    async fn commit(self) -> anyhow::Result<()> {
+        self.query.query("COMMIT TRANSACTION;").await?.check()?;
+        Ok(())
     }
 }
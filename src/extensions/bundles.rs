@@ -0,0 +1,227 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context};
+use clap::ValueEnum;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::formats;
+
+/// The file extension of bundle directories.
+pub const BUNDLE_EXTENSION: &str = "bundle";
+/// The name of the manifest within a bundle.
+pub const MANIFEST_FILE: &str = "manifest.toml";
+/// The name of the detached signature of the manifest within a bundle.
+pub const SIGNATURE_FILE: &str = "manifest.toml.sig";
+/// The directory which the public keys of trusted bundle signers are read from.
+pub const TRUSTED_KEYS_DIRECTORY: &str = "./trusted_keys";
+/// The file extension of public key files in the trusted keys directory.
+const PUBLIC_KEY_EXTENSION: &str = "pub";
+
+/// What to do with extension files which are not part of a signed bundle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum UnsignedPolicy {
+    /// Load unsigned extensions normally.
+    #[default]
+    Allow,
+    /// Load unsigned extensions, but log a warning.
+    Warn,
+    /// Refuse to load unsigned extensions.
+    Reject,
+}
+
+/// The manifest of a bundle, which is the part of the bundle that is actually signed.
+/// It pins the contents of the extension file with a hash, so the file cannot be changed without
+/// invalidating the signature.
+#[derive(Debug, Deserialize, Serialize)]
+struct Manifest {
+    /// The name of the extension file within the bundle.
+    extension: String,
+    /// The hex-encoded SHA-256 hash of the extension file.
+    sha256: String,
+}
+
+/// The public keys of signers whose bundles can be loaded.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedKeys(Vec<(String, VerifyingKey)>);
+
+impl TrustedKeys {
+    /// Reads every hex-encoded public key file in the given directory.
+    /// Each key is named after its file. If the directory does not exist, no keys are trusted.
+    pub fn load(directory: &Path) -> anyhow::Result<Self> {
+        let Ok(entries) = std::fs::read_dir(directory) else {
+            return Ok(Self::default());
+        };
+
+        let mut keys = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension() != Some(OsStr::new(PUBLIC_KEY_EXTENSION)) {
+                continue;
+            }
+
+            let name = path
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .unwrap_or_default()
+                .to_owned();
+            let key = parse_public_key(&std::fs::read_to_string(&path)?)
+                .with_context(|| format!("Invalid trusted key {}", path.display()))?;
+            keys.push((name, key));
+        }
+
+        keys.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(Self(keys))
+    }
+
+    /// Creates a set of trusted keys directly, with the given names.
+    #[cfg(test)]
+    pub fn from_keys(keys: impl IntoIterator<Item = (String, VerifyingKey)>) -> Self {
+        Self(keys.into_iter().collect())
+    }
+}
+
+/// Checks whether a path has the file extension of a bundle directory.
+pub fn is_bundle_path(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new(BUNDLE_EXTENSION))
+}
+
+/// Parses a hex-encoded ed25519 public key.
+pub fn parse_public_key(hex: &str) -> anyhow::Result<VerifyingKey> {
+    let bytes = decode_fixed::<32>(hex)?;
+    Ok(VerifyingKey::from_bytes(&bytes)?)
+}
+
+/// Parses a hex-encoded ed25519 secret key.
+pub fn parse_signing_key(hex: &str) -> anyhow::Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&decode_fixed::<32>(hex)?))
+}
+
+/// Decodes a hex string with a fixed number of bytes, ignoring surrounding whitespace.
+fn decode_fixed<const N: usize>(hex: &str) -> anyhow::Result<[u8; N]> {
+    let bytes = hex::decode(hex.trim())?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| anyhow!("Expected {N} bytes, found {}", bytes.len()))
+}
+
+/// The extension file of a bundle whose signature and hash have been checked.
+#[derive(Debug)]
+pub struct VerifiedBundle {
+    /// The path of the extension file within the bundle.
+    pub extension_file: PathBuf,
+    /// The contents of the extension file, exactly as they were hashed.
+    pub source: String,
+    /// The name of the key which signed the bundle.
+    pub signer: String,
+}
+
+/// Hashes some contents with SHA-256, returning the hex-encoded hash.
+fn hash(contents: &[u8]) -> String {
+    hex::encode(Sha256::digest(contents))
+}
+
+/// Reads the contents of a file within a bundle.
+fn read_file(path: &Path) -> anyhow::Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Verifies that a bundle was signed by a trusted key and has not been modified since.
+/// Returns the contents of the extension file within the bundle along with its path, and the name
+/// of the key which signed it.
+pub fn verify(bundle: &Path, trusted_keys: &TrustedKeys) -> anyhow::Result<VerifiedBundle> {
+    let manifest_source = std::fs::read(bundle.join(MANIFEST_FILE))
+        .with_context(|| format!("Failed to read {MANIFEST_FILE}"))?;
+    let signature = std::fs::read_to_string(bundle.join(SIGNATURE_FILE))
+        .with_context(|| format!("Failed to read {SIGNATURE_FILE}"))?;
+    let signature =
+        Signature::from_bytes(&decode_fixed::<64>(&signature).context("Invalid signature")?);
+
+    let Some(signer) = trusted_keys
+        .0
+        .iter()
+        .find(|(_, key)| key.verify(&manifest_source, &signature).is_ok())
+    else {
+        bail!("The manifest is not signed by any trusted key");
+    };
+
+    // * The manifest is only trusted once its signature has been checked.
+    let manifest = toml::from_str::<Manifest>(std::str::from_utf8(&manifest_source)?)
+        .context("Invalid manifest")?;
+    // * The file is read once, and the same contents are hashed and returned, so it cannot be
+    // * swapped out between being checked and being parsed.
+    let extension_file = extension_path(bundle, &manifest.extension)?;
+    let contents = read_file(&extension_file)?;
+    if hash(&contents) != manifest.sha256.to_lowercase() {
+        bail!(
+            "The hash of {} does not match the manifest",
+            manifest.extension
+        );
+    }
+    let source = String::from_utf8(contents)
+        .with_context(|| format!("{} is not valid UTF-8", manifest.extension))?;
+
+    Ok(VerifiedBundle {
+        extension_file,
+        source,
+        signer: signer.0.clone(),
+    })
+}
+
+/// Signs a bundle by writing a manifest for an extension file and a detached signature.
+/// If no extension file is given, the bundle must contain exactly one. Returns the name of the
+/// extension file which was signed.
+pub fn sign(
+    bundle: &Path,
+    extension: Option<&str>,
+    signing_key: &SigningKey,
+) -> anyhow::Result<String> {
+    let extension = match extension {
+        Some(extension) => extension.to_owned(),
+        None => find_extension_file(bundle)?,
+    };
+    let manifest = Manifest {
+        sha256: hash(&read_file(&extension_path(bundle, &extension)?)?),
+        extension,
+    };
+    let manifest_source = toml::to_string(&manifest)?;
+    let signature = signing_key.sign(manifest_source.as_bytes());
+
+    std::fs::write(bundle.join(MANIFEST_FILE), &manifest_source)?;
+    std::fs::write(
+        bundle.join(SIGNATURE_FILE),
+        hex::encode(signature.to_bytes()),
+    )?;
+
+    Ok(manifest.extension)
+}
+
+/// Finds the only extension file in a bundle.
+// * The manifest is TOML as well, so it is skipped for bundles which were already signed.
+fn find_extension_file(bundle: &Path) -> anyhow::Result<String> {
+    let mut files = std::fs::read_dir(bundle)
+        .with_context(|| format!("Failed to read {}", bundle.display()))?
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .filter(|entry| formats::for_path(&entry.path()).is_some())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name != MANIFEST_FILE)
+        .collect::<Vec<_>>();
+
+    match files.len() {
+        1 => Ok(files.remove(0)),
+        0 => bail!("The bundle does not contain an extension file"),
+        _ => bail!("The bundle contains more than one extension file; choose one to sign"),
+    }
+}
+
+/// Gets the path of an extension file within a bundle, making sure it cannot point outside it.
+fn extension_path(bundle: &Path, extension: &str) -> anyhow::Result<PathBuf> {
+    if Path::new(extension).file_name() != Some(OsStr::new(extension)) {
+        bail!("The extension file '{extension}' must be directly inside the bundle");
+    }
+
+    Ok(bundle.join(extension))
+}
@@ -1,15 +1,68 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use semver::Version;
+use serde::Serialize;
+
+use super::dependencies::DependencyError;
+use super::manager::ExtensionManagerConfig;
+use super::merging::{FieldConflict, MergeConflictError};
+use super::validation::Diagnostic;
 use super::{Extension, ExtensionID, Metadata};
 
 /// Indicator that the manager encountered an error when loading an extension.
 #[derive(Debug, PartialEq, Eq)]
 pub struct LoadConflict {
     pub id: ExtensionID,
-    pub same_version: bool,
+    pub kind: ConflictKind,
+    /// The version of the extension which is already loaded.
+    pub old_version: Version,
+    /// The version of the staged extension.
+    pub new_version: Version,
+}
+
+/// How a staged extension differs from the loaded extension with the same ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    /// The version and contents are the same.
+    Unchanged,
+    /// The version is the same, but the contents have changed.
+    /// This usually means the version was not bumped after editing the extension.
+    ContentDrift,
+    /// The staged version is newer than the loaded version.
+    Upgrade,
+    /// The staged version is older than the loaded version.
+    Downgrade,
+}
+
+/// Indicator that the manager refused to load an extension.
+#[derive(Debug, PartialEq)]
+pub struct LoadFailure {
+    pub id: ExtensionID,
+    pub reason: LoadFailureReason,
+}
+
+/// The reason an extension could not be loaded.
+#[derive(Debug, PartialEq)]
+pub enum LoadFailureReason {
+    /// The extension's dependencies could not be satisfied.
+    Dependency(DependencyError),
+    /// The extension is not compatible with the current contents of the database.
+    Invalid(Vec<Diagnostic>),
+    /// The staged version is older than the loaded version, and downgrades are not allowed.
+    Downgrade { loaded: Version, staged: Version },
+    /// The extension's metadata conflicts with existing records, and the merge policy refuses
+    /// conflicts.
+    MergeConflict(Vec<FieldConflict>),
 }
 
 impl LoadConflict {
     /// Checks whether a given staged extension conflicts with any of the given loaded extensions.
     /// If it does, the conflict is returned.
+    /// The content hashes of the loaded extensions are used to detect changes which were made
+    /// without bumping the version.
     // * Any staged extension can only logically have up to one conflict with a loaded
     // * extension, and vice versa, because of the following reasons:
     // * - Conflicts can only arise when a staged and a loaded extension share the same ID.
@@ -19,6 +72,7 @@ impl LoadConflict {
     pub fn new(
         staged_extension: &Extension,
         loaded_extensions: &mut Vec<Metadata>,
+        loaded_hashes: &HashMap<ExtensionID, String>,
     ) -> Option<Self> {
         let staged_extension_metadata = &staged_extension.metadata;
         for (i, loaded_extension_metadata) in loaded_extensions.iter().enumerate() {
@@ -26,10 +80,18 @@ impl LoadConflict {
                 continue;
             }
 
+            let kind = ConflictKind::classify(
+                &staged_extension_metadata.version,
+                &staged_extension.content_hash(),
+                &loaded_extension_metadata.version,
+                loaded_hashes.get(&loaded_extension_metadata.id),
+            );
+
             let conflict = LoadConflict {
                 id: loaded_extension_metadata.id.clone(),
-                same_version: staged_extension_metadata.version
-                    == loaded_extension_metadata.version,
+                kind,
+                old_version: loaded_extension_metadata.version.clone(),
+                new_version: staged_extension_metadata.version.clone(),
             };
 
             // Skip the conflicting extension in subsequent conflict checks for optimization.
@@ -41,7 +103,77 @@ impl LoadConflict {
     }
 
     /// Checks whether a conflict should be resolved by reloading the extension.
-    pub fn should_reload(&self) -> bool {
-        !self.same_version
+    /// Upgrades are always reloaded, while downgrades and extensions whose contents drifted are
+    /// only reloaded if requested. Force-reloading does not apply to downgrades.
+    pub fn should_reload(&self, config: &ExtensionManagerConfig) -> bool {
+        self.kind.should_reload(config)
+    }
+}
+
+impl ConflictKind {
+    /// Compares a staged version of an extension or plugin with the loaded version.
+    // * Items loaded before content hashes were stored have no hash, so they cannot be confirmed
+    // * to be unchanged.
+    pub fn classify(
+        staged_version: &Version,
+        staged_hash: &str,
+        loaded_version: &Version,
+        loaded_hash: Option<&String>,
+    ) -> Self {
+        match staged_version.cmp(loaded_version) {
+            Ordering::Greater => ConflictKind::Upgrade,
+            Ordering::Less => ConflictKind::Downgrade,
+            Ordering::Equal if loaded_hash.is_some_and(|hash| hash == staged_hash) => {
+                ConflictKind::Unchanged
+            }
+            Ordering::Equal => ConflictKind::ContentDrift,
+        }
+    }
+
+    /// Checks whether a conflict of this kind should be resolved by replacing the loaded version.
+    pub fn should_reload(self, config: &ExtensionManagerConfig) -> bool {
+        match self {
+            ConflictKind::Downgrade => config.allow_downgrade,
+            _ if config.auto_reload => true,
+            ConflictKind::Unchanged => false,
+            ConflictKind::ContentDrift => config.reload_on_drift,
+            ConflictKind::Upgrade => true,
+        }
+    }
+}
+
+impl fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConflictKind::Unchanged => write!(f, "unchanged"),
+            ConflictKind::ContentDrift => write!(f, "contents changed without a version bump"),
+            ConflictKind::Upgrade => write!(f, "upgrade"),
+            ConflictKind::Downgrade => write!(f, "downgrade"),
+        }
+    }
+}
+
+impl fmt::Display for LoadFailureReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadFailureReason::Dependency(error) => write!(f, "{error}"),
+            LoadFailureReason::Downgrade { loaded, staged } => write!(
+                f,
+                "Version {staged} is older than the loaded version {loaded}"
+            ),
+            LoadFailureReason::MergeConflict(conflicts) => {
+                write!(f, "{}", MergeConflictError(conflicts.clone()))
+            }
+            LoadFailureReason::Invalid(diagnostics) => write!(
+                f,
+                "{}",
+                diagnostics
+                    .iter()
+                    .filter(|d| d.is_error())
+                    .map(|d| d.to_string())
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+        }
     }
 }
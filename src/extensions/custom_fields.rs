@@ -0,0 +1,364 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::bail;
+
+use super::manager::{
+    CustomFieldTargetToml, CustomFieldToml, CustomFieldTypeToml, InventoryExtensionToml,
+};
+use super::validation::{Diagnostic, FieldPath};
+use super::{Extension, ExtensionID};
+use crate::models::common::{
+    CustomFieldDefinition, CustomFieldTarget, CustomFieldType, CustomFieldValue, CustomFields,
+    UniqueID,
+};
+
+/// Checks whether a custom field ID is valid.
+/// IDs become part of the database schema, so they are limited to lowercase letters, digits, and
+/// underscores, starting with a letter.
+pub fn is_valid_id(id: &str) -> bool {
+    id.starts_with(|c: char| c.is_ascii_lowercase())
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+impl From<CustomFieldTargetToml> for CustomFieldTarget {
+    fn from(target: CustomFieldTargetToml) -> Self {
+        match target {
+            CustomFieldTargetToml::DeviceManufacturer => CustomFieldTarget::DeviceManufacturer,
+            CustomFieldTargetToml::DeviceCategory => CustomFieldTarget::DeviceCategory,
+            CustomFieldTargetToml::Device => CustomFieldTarget::Device,
+        }
+    }
+}
+
+impl From<CustomFieldTarget> for CustomFieldTargetToml {
+    fn from(target: CustomFieldTarget) -> Self {
+        match target {
+            CustomFieldTarget::DeviceManufacturer => CustomFieldTargetToml::DeviceManufacturer,
+            CustomFieldTarget::DeviceCategory => CustomFieldTargetToml::DeviceCategory,
+            CustomFieldTarget::Device => CustomFieldTargetToml::Device,
+        }
+    }
+}
+
+impl TryFrom<CustomFieldToml> for CustomFieldDefinition {
+    type Error = anyhow::Error;
+    fn try_from(toml: CustomFieldToml) -> Result<Self, Self::Error> {
+        if !is_valid_id(&toml.id) {
+            bail!("Custom field ID '{}' is not valid", toml.id);
+        }
+        let field_type = match (toml.field_type, toml.values) {
+            (CustomFieldTypeToml::Enum, Some(values)) if !values.is_empty() => {
+                CustomFieldType::Enum(values)
+            }
+            (CustomFieldTypeToml::Enum, _) => bail!("Enum field '{}' has no values", toml.id),
+            (_, Some(_)) => bail!("Only enum fields can have values"),
+            (CustomFieldTypeToml::String, None) => CustomFieldType::String,
+            (CustomFieldTypeToml::Number, None) => CustomFieldType::Number,
+            (CustomFieldTypeToml::Bool, None) => CustomFieldType::Bool,
+            (CustomFieldTypeToml::List, None) => CustomFieldType::List,
+        };
+
+        Ok(CustomFieldDefinition {
+            id: toml.id,
+            target: toml.target.into(),
+            display_name: toml.display_name,
+            field_type,
+            required: toml.required,
+        })
+    }
+}
+
+impl From<&CustomFieldDefinition> for CustomFieldToml {
+    fn from(field: &CustomFieldDefinition) -> Self {
+        let (field_type, values) = match &field.field_type {
+            CustomFieldType::String => (CustomFieldTypeToml::String, None),
+            CustomFieldType::Number => (CustomFieldTypeToml::Number, None),
+            CustomFieldType::Bool => (CustomFieldTypeToml::Bool, None),
+            CustomFieldType::Enum(values) => (CustomFieldTypeToml::Enum, Some(values.clone())),
+            CustomFieldType::List => (CustomFieldTypeToml::List, None),
+        };
+
+        CustomFieldToml {
+            id: field.id.clone(),
+            target: field.target.into(),
+            display_name: field.display_name.clone(),
+            field_type,
+            values,
+            required: field.required,
+        }
+    }
+}
+
+/// Lists the custom field values of every item in a parsed extension file, along with the kind
+/// of item and the path to the item.
+fn toml_items(
+    extension: &InventoryExtensionToml,
+) -> impl Iterator<
+    Item = (
+        CustomFieldTarget,
+        FieldPath,
+        &BTreeMap<String, CustomFieldValue>,
+    ),
+> {
+    let manufacturers = extension
+        .device_manufacturers
+        .iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, m)| {
+            (
+                CustomFieldTarget::DeviceManufacturer,
+                FieldPath::element("device_manufacturers", i),
+                &m.custom_fields,
+            )
+        });
+    let categories = extension
+        .device_categories
+        .iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, c)| {
+            (
+                CustomFieldTarget::DeviceCategory,
+                FieldPath::element("device_categories", i),
+                &c.custom_fields,
+            )
+        });
+    let devices = extension.devices.iter().enumerate().map(|(i, d)| {
+        (
+            CustomFieldTarget::Device,
+            FieldPath::element("devices", i),
+            &d.custom_fields,
+        )
+    });
+
+    manufacturers.chain(categories).chain(devices)
+}
+
+/// Lists the custom field values of every item in an extension, along with the kind of item and
+/// the path to the item.
+fn extension_items(
+    extension: &Extension,
+) -> impl Iterator<Item = (CustomFieldTarget, FieldPath, &CustomFields)> {
+    let manufacturers = extension
+        .device_manufacturers
+        .iter()
+        .enumerate()
+        .map(|(i, m)| {
+            (
+                CustomFieldTarget::DeviceManufacturer,
+                FieldPath::element("device_manufacturers", i),
+                &m.custom_fields,
+            )
+        });
+    let categories = extension
+        .device_categories
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            (
+                CustomFieldTarget::DeviceCategory,
+                FieldPath::element("device_categories", i),
+                &c.custom_fields,
+            )
+        });
+    let devices = extension.devices.iter().enumerate().map(|(i, d)| {
+        (
+            CustomFieldTarget::Device,
+            FieldPath::element("devices", i),
+            &d.custom_fields,
+        )
+    });
+
+    manufacturers.chain(categories).chain(devices)
+}
+
+/// Describes a value which does not match the type of its field.
+fn type_mismatch(id: &str, field_type: &CustomFieldType, value: &CustomFieldValue) -> String {
+    format!("Custom field '{id}' must be {field_type}, but is {value}")
+}
+
+/// Checks the custom field declarations in a parsed extension file, along with every value given
+/// to a field which the extension declares.
+/// Values of fields declared by other extensions are checked against the database at load time.
+pub(super) fn validate(extension: &InventoryExtensionToml) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    let mut declared = HashMap::new();
+    for (i, field) in extension.custom_fields.iter().flatten().enumerate() {
+        let path = FieldPath::element("custom_fields", i);
+        if !is_valid_id(&field.id) {
+            diagnostics.push(Diagnostic::error(
+                path.clone().then("id"),
+                format!(
+                    "Custom field ID '{}' must start with a lowercase letter and only contain \
+                    lowercase letters, digits, and underscores",
+                    field.id
+                ),
+            ));
+        }
+        if field.display_name.trim().is_empty() {
+            diagnostics.push(Diagnostic::error(
+                path.clone().then("display_name"),
+                "Custom field display name must not be empty",
+            ));
+        }
+
+        match (&field.field_type, &field.values) {
+            (CustomFieldTypeToml::Enum, None) => diagnostics.push(Diagnostic::error(
+                path.clone().then("values"),
+                format!("Enum field '{}' must list its allowed values", field.id),
+            )),
+            (CustomFieldTypeToml::Enum, Some(values)) => {
+                if values.is_empty() {
+                    diagnostics.push(Diagnostic::error(
+                        path.clone().then("values"),
+                        format!("Enum field '{}' must have at least one value", field.id),
+                    ));
+                }
+                let mut seen = HashSet::new();
+                for value in values {
+                    if !seen.insert(value) {
+                        diagnostics.push(Diagnostic::warning(
+                            path.clone().then("values"),
+                            format!("Enum field '{}' lists '{value}' more than once", field.id),
+                        ));
+                    }
+                }
+            }
+            (_, Some(_)) => diagnostics.push(Diagnostic::error(
+                path.clone().then("values"),
+                format!(
+                    "Only enum fields can have values, but '{}' does not",
+                    field.id
+                ),
+            )),
+            (_, None) => {}
+        }
+
+        let target = CustomFieldTarget::from(field.target);
+        if declared
+            .insert((target, field.id.as_str()), field)
+            .is_some()
+        {
+            diagnostics.push(Diagnostic::error(
+                path.then("id"),
+                format!(
+                    "Custom field '{}' is declared more than once for {}",
+                    field.id,
+                    target.name()
+                ),
+            ));
+        }
+    }
+
+    for (target, path, values) in toml_items(extension) {
+        for (id, value) in values {
+            let field_path = path.clone().then("custom_fields").then(id);
+            let Some(field) = declared.get(&(target, id.as_str())) else {
+                if !is_valid_id(id) {
+                    diagnostics.push(Diagnostic::error(
+                        field_path,
+                        format!("Custom field ID '{id}' is not valid"),
+                    ));
+                }
+                continue;
+            };
+
+            // * Invalid declarations were reported above, so their values are not checked.
+            let Ok(definition) = CustomFieldDefinition::try_from((*field).clone()) else {
+                continue;
+            };
+            if !definition.field_type.accepts(value) {
+                diagnostics.push(Diagnostic::error(
+                    field_path,
+                    type_mismatch(id, &definition.field_type, value),
+                ));
+            }
+        }
+
+        for field in extension.custom_fields.iter().flatten() {
+            if field.required
+                && CustomFieldTarget::from(field.target) == target
+                && !values.contains_key(&field.id)
+            {
+                diagnostics.push(Diagnostic::error(
+                    path.clone(),
+                    format!("Required custom field '{}' is missing", field.id),
+                ));
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Checks an extension's custom fields against the fields which loaded extensions declare.
+/// Fields declared by more than one extension must have the same type, and every value must be
+/// given to a field which is declared by this extension or a loaded one.
+pub fn check_against_database(
+    extension: &Extension,
+    existing_fields: &[(ExtensionID, CustomFieldDefinition)],
+) -> Vec<Diagnostic> {
+    // * The extension's own fields are replaced when it is reloaded, so they are not compared.
+    let existing = existing_fields
+        .iter()
+        .filter(|(owner, _)| *owner != extension.metadata.id)
+        .map(|(owner, field)| ((field.target, field.id.as_str()), (owner, field)))
+        .collect::<HashMap<_, _>>();
+    let declared = extension
+        .custom_fields
+        .iter()
+        .map(|field| ((field.target, field.id.as_str()), field))
+        .collect::<HashMap<_, _>>();
+
+    let mut diagnostics = Vec::new();
+    for (i, field) in extension.custom_fields.iter().enumerate() {
+        let Some((owner, existing_field)) = existing.get(&(field.target, field.id.as_str())) else {
+            continue;
+        };
+        if existing_field.field_type != field.field_type {
+            diagnostics.push(Diagnostic::error(
+                FieldPath::element("custom_fields", i).then("type"),
+                format!(
+                    "Custom field '{}' is declared as {} here, but as {} by extension '{}'",
+                    field.id,
+                    field.field_type,
+                    existing_field.field_type,
+                    owner.unnamespaced()
+                ),
+            ));
+        }
+    }
+
+    for (target, path, values) in extension_items(extension) {
+        for (id, value) in values {
+            if declared.contains_key(&(target, id.as_str())) {
+                continue;
+            }
+
+            let field_path = path.clone().then("custom_fields").then(id);
+            match existing.get(&(target, id.as_str())) {
+                None => diagnostics.push(Diagnostic::error(
+                    field_path,
+                    format!(
+                        "Custom field '{id}' is not declared for {} by this extension or any \
+                        loaded extension",
+                        target.name()
+                    ),
+                )),
+                Some((_, field)) if !field.field_type.accepts(value) => {
+                    diagnostics.push(Diagnostic::error(
+                        field_path,
+                        type_mismatch(id, &field.field_type, value),
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    diagnostics
+}
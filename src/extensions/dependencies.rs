@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use semver::{Version, VersionReq};
+
+use super::{Extension, ExtensionID, Metadata};
+use crate::models::common::UniqueID;
+
+/// A problem with an extension's dependencies which prevents it from being loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DependencyError {
+    /// The dependency is neither staged nor loaded in the database.
+    Missing {
+        dependency: ExtensionID,
+        requirement: VersionReq,
+    },
+    /// The dependency is available, but its version does not satisfy the requirement.
+    Incompatible {
+        dependency: ExtensionID,
+        requirement: VersionReq,
+        found: Version,
+    },
+    /// The extension depends on itself through the given chain of extensions.
+    Cycle(Vec<ExtensionID>),
+    /// The dependency is staged, but could not be loaded itself.
+    Unavailable { dependency: ExtensionID },
+}
+
+/// The progress of resolving a single staged extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Unvisited,
+    Visiting,
+    Resolved,
+    Failed,
+}
+
+/// Orders staged extensions with a depth-first search of their dependencies.
+struct Resolver<'a> {
+    staged: &'a [Extension],
+    staged_indices: HashMap<&'a ExtensionID, usize>,
+    loaded_versions: HashMap<&'a ExtensionID, &'a Version>,
+    states: Vec<State>,
+    stack: Vec<usize>,
+    order: Vec<usize>,
+    failures: Vec<(usize, DependencyError)>,
+}
+
+/// Sorts staged extensions so that every extension comes after the extensions it depends on.
+/// Dependencies may be satisfied either by another staged extension or by an extension which is
+/// already loaded in the database. Staged extensions take precedence, since they will replace
+/// the loaded version if they are reloaded.
+/// Extensions whose dependencies cannot be satisfied are removed and returned with the reason.
+pub fn sort(
+    staged: Vec<Extension>,
+    loaded: &[Metadata],
+) -> (Vec<Extension>, Vec<(Extension, DependencyError)>) {
+    let mut resolver = Resolver {
+        staged: &staged,
+        staged_indices: staged
+            .iter()
+            .enumerate()
+            .map(|(i, extension)| (&extension.metadata.id, i))
+            .collect(),
+        loaded_versions: loaded
+            .iter()
+            .map(|metadata| (&metadata.id, &metadata.version))
+            .collect(),
+        states: vec![State::Unvisited; staged.len()],
+        stack: Vec::new(),
+        order: Vec::new(),
+        failures: Vec::new(),
+    };
+
+    for i in 0..staged.len() {
+        resolver.visit(i);
+    }
+
+    let (order, failures) = (resolver.order, resolver.failures);
+    let mut staged = staged.into_iter().map(Some).collect::<Vec<_>>();
+    let sorted = order.into_iter().filter_map(|i| staged[i].take()).collect();
+    let failed = failures
+        .into_iter()
+        .filter_map(|(i, error)| Some((staged[i].take()?, error)))
+        .collect();
+
+    (sorted, failed)
+}
+
+impl Resolver<'_> {
+    /// Resolves the dependencies of a staged extension before the extension itself.
+    /// Returns whether the extension can be loaded.
+    fn visit(&mut self, i: usize) -> bool {
+        match self.states[i] {
+            State::Resolved => return true,
+            State::Failed => return false,
+            // * Cycles are detected by the dependent before visiting, so this is unreachable.
+            State::Visiting => return false,
+            State::Unvisited => {}
+        }
+
+        self.states[i] = State::Visiting;
+        self.stack.push(i);
+
+        let mut error = None;
+        for (dependency, requirement) in &self.staged[i].dependencies {
+            if let Err(e) = self.check(dependency, requirement) {
+                error = Some(e);
+                break;
+            }
+        }
+
+        self.stack.pop();
+        match error {
+            None => {
+                self.states[i] = State::Resolved;
+                self.order.push(i);
+                true
+            }
+            Some(error) => {
+                self.states[i] = State::Failed;
+                self.failures.push((i, error));
+                false
+            }
+        }
+    }
+
+    /// Checks that a single dependency can be satisfied, resolving it first if it is staged.
+    fn check(
+        &mut self,
+        dependency: &ExtensionID,
+        requirement: &VersionReq,
+    ) -> Result<(), DependencyError> {
+        let (version, staged_index) = match self.staged_indices.get(dependency) {
+            Some(&j) => (&self.staged[j].metadata.version, Some(j)),
+            None => match self.loaded_versions.get(dependency) {
+                Some(&version) => (version, None),
+                None => {
+                    return Err(DependencyError::Missing {
+                        dependency: dependency.clone(),
+                        requirement: requirement.clone(),
+                    })
+                }
+            },
+        };
+
+        if !requirement.matches(version) {
+            return Err(DependencyError::Incompatible {
+                dependency: dependency.clone(),
+                requirement: requirement.clone(),
+                found: version.clone(),
+            });
+        }
+
+        let Some(j) = staged_index else {
+            return Ok(());
+        };
+
+        if self.states[j] == State::Visiting {
+            let start = self.stack.iter().position(|&k| k == j).unwrap_or(0);
+            let cycle = self.stack[start..]
+                .iter()
+                .chain([&j])
+                .map(|&k| self.staged[k].metadata.id.clone())
+                .collect();
+            return Err(DependencyError::Cycle(cycle));
+        }
+
+        match self.visit(j) {
+            true => Ok(()),
+            false => Err(DependencyError::Unavailable {
+                dependency: dependency.clone(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for DependencyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DependencyError::Missing {
+                dependency,
+                requirement,
+            } => write!(
+                f,
+                "Dependency '{}' ({requirement}) is not staged or loaded",
+                dependency.unnamespaced()
+            ),
+            DependencyError::Incompatible {
+                dependency,
+                requirement,
+                found,
+            } => write!(
+                f,
+                "Dependency '{}' requires version {requirement}, but version {found} is available",
+                dependency.unnamespaced()
+            ),
+            DependencyError::Cycle(cycle) => write!(
+                f,
+                "Dependency cycle detected: {}",
+                cycle
+                    .iter()
+                    .map(|id| id.unnamespaced())
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            ),
+            DependencyError::Unavailable { dependency } => write!(
+                f,
+                "Dependency '{}' could not be loaded",
+                dependency.unnamespaced()
+            ),
+        }
+    }
+}
@@ -0,0 +1,695 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+use semver::Version;
+use serde::Serialize;
+
+use super::merging::{FieldConflict, MergeConflictError, Merger, Resolution};
+use super::{Extension, ExtensionID};
+use crate::database::Database;
+use crate::models::common::{
+    withdraw_contribution, Combined, Contributions, CustomFieldDefinition, CustomFields, Device,
+    DeviceCategory, DeviceManufacturer, DeviceVariant, Part, PartKind, RepairService, UniqueID,
+};
+
+/// A preview of how loading a staged extension would change the database.
+#[derive(Debug, Serialize)]
+pub struct ExtensionDiff {
+    pub id: String,
+    pub action: PlannedAction,
+    pub old_version: Option<String>,
+    pub new_version: String,
+    pub device_manufacturers: Vec<ItemDiff>,
+    pub device_categories: Vec<ItemDiff>,
+    pub devices: Vec<ItemDiff>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<ItemDiff>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub repair_services: Vec<ItemDiff>,
+    pub added_model_identifiers: BTreeSet<String>,
+    pub removed_model_identifiers: BTreeSet<String>,
+}
+
+/// What the manager would do with a staged extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlannedAction {
+    Load,
+    Reload,
+    Skip,
+}
+
+/// A change to a single manufacturer, category, device, part, or repair service.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct ItemDiff {
+    pub id: String,
+    pub change: ItemChange,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldChange>,
+}
+
+/// The kind of change made to a single item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemChange {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// A change to a single field of a modified item.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+/// The contents of the database which staged extensions are compared against.
+/// In a dry run, each staged extension is applied to the snapshot in place of the database, so
+/// later extensions are checked against what earlier ones would have loaded.
+#[derive(Debug, Clone, Default)]
+pub struct DatabaseSnapshot {
+    pub(super) device_manufacturers: Vec<DeviceManufacturer>,
+    pub(super) device_categories: Vec<DeviceCategory>,
+    pub(super) devices: Vec<Device>,
+    pub(super) parts: Vec<Part>,
+    pub(super) repair_services: Vec<RepairService>,
+    pub(super) custom_fields: Vec<(ExtensionID, CustomFieldDefinition)>,
+    pub(super) contributions: Contributions,
+    /// The staged extensions which have been applied to the snapshot.
+    pub(super) staged: HashSet<ExtensionID>,
+}
+
+impl DatabaseSnapshot {
+    /// Reads the current contents of the database.
+    /// Items are read as their extensions provide them, without overrides applied and including
+    /// the items of disabled extensions, since those are the records staged extensions are merged
+    /// with.
+    pub async fn read(db: &Database) -> anyhow::Result<Self> {
+        Ok(Self {
+            device_manufacturers: db.list_base_device_manufacturers().await?,
+            device_categories: db.list_base_device_categories().await?,
+            devices: db.list_base_devices().await?,
+            parts: db.list_base_parts().await?,
+            repair_services: db.list_base_repair_services().await?,
+            custom_fields: db.list_custom_fields().await?,
+            contributions: db.list_contributions().await?,
+            staged: HashSet::new(),
+        })
+    }
+
+    /// Applies a staged extension to the snapshot the same way loading it would change the
+    /// database, replacing any version of the extension which is already loaded.
+    /// Returns the metadata conflicts found while merging its items. If the merge policy refuses
+    /// any of them, the snapshot is left unchanged.
+    pub fn apply(
+        &mut self,
+        extension: &Extension,
+        merger: &Merger<'_>,
+    ) -> Result<Vec<FieldConflict>, MergeConflictError> {
+        let id = &extension.metadata.id;
+        let mut applied = self.clone();
+        let contributions = &self.contributions;
+        remove_provider(&mut applied.device_manufacturers, id, contributions);
+        remove_provider(&mut applied.device_categories, id, contributions);
+        remove_provider(&mut applied.devices, id, contributions);
+        remove_provider(&mut applied.parts, id, contributions);
+        remove_provider(&mut applied.repair_services, id, contributions);
+        applied.custom_fields.retain(|(owner, _)| owner != id);
+        applied.custom_fields.extend(
+            extension
+                .custom_fields
+                .iter()
+                .map(|field| (id.clone(), field.clone())),
+        );
+
+        // * Items are merged in the same order as when the extension is loaded, so conflicts are
+        // * reported in the same order.
+        let mut conflicts = Vec::new();
+        conflicts.extend(merge_items(
+            &mut applied.device_categories,
+            &extension.device_categories,
+            |staged, existing| merger.device_category(staged, existing),
+        ));
+        conflicts.extend(merge_items(
+            &mut applied.device_manufacturers,
+            &extension.device_manufacturers,
+            |staged, existing| merger.device_manufacturer(staged, existing),
+        ));
+        conflicts.extend(merge_items(
+            &mut applied.devices,
+            &extension.devices,
+            |staged, existing| merger.device(staged, existing),
+        ));
+        conflicts.extend(merge_items(
+            &mut applied.parts,
+            &extension.parts,
+            |staged, existing| merger.part(staged, existing),
+        ));
+        conflicts.extend(merge_items(
+            &mut applied.repair_services,
+            &extension.repair_services,
+            |staged, existing| merger.repair_service(staged, existing),
+        ));
+
+        if conflicts
+            .iter()
+            .any(|conflict| conflict.resolution == Resolution::Refused)
+        {
+            return Err(MergeConflictError(conflicts));
+        }
+
+        for item_contributions in applied.contributions.values_mut() {
+            item_contributions.remove(id);
+        }
+        record_contributions(
+            &mut applied.contributions,
+            id,
+            &extension.device_manufacturers,
+        );
+        record_contributions(&mut applied.contributions, id, &extension.device_categories);
+        record_contributions(&mut applied.contributions, id, &extension.devices);
+        record_contributions(&mut applied.contributions, id, &extension.parts);
+        record_contributions(&mut applied.contributions, id, &extension.repair_services);
+
+        applied.staged.insert(id.clone());
+        *self = applied;
+        Ok(conflicts)
+    }
+
+    /// Lists the given items which an applied staged extension provides.
+    pub(super) fn staged_items<'a, T: Combined>(
+        &'a self,
+        items: &'a [T],
+    ) -> impl Iterator<Item = &'a T> {
+        items
+            .iter()
+            .filter(|item| item.providers().iter().any(|e| self.staged.contains(e)))
+    }
+}
+
+/// Removes an extension from the providers of each item, deleting the items which no other
+/// extension provides, as reloading it would. The values it contributed to the items which are
+/// kept are withdrawn.
+fn remove_provider<T: Diffable + Combined>(
+    items: &mut Vec<T>,
+    extension: &ExtensionID,
+    contributions: &Contributions,
+) {
+    items.retain_mut(|item| {
+        withdraw_contribution(item, extension, contributions);
+        let extensions = item.extensions_mut();
+        !(extensions.remove(extension) && extensions.is_empty())
+    });
+}
+
+/// Records the values which an extension gives to the combined lists of its items.
+fn record_contributions<T: Combined>(
+    contributions: &mut Contributions,
+    extension: &ExtensionID,
+    items: &[T],
+) {
+    for item in items {
+        contributions
+            .entry(item.key())
+            .or_default()
+            .insert(extension.clone(), item.contribution());
+    }
+}
+
+/// Merges staged items with the existing items of the same ID, adding the merged items in their
+/// place.
+fn merge_items<T: Diffable + Clone>(
+    items: &mut Vec<T>,
+    staged: &[T],
+    merge: impl Fn(&mut T, T) -> Vec<FieldConflict>,
+) -> Vec<FieldConflict> {
+    let mut conflicts = Vec::new();
+    for mut item in staged.iter().cloned() {
+        if let Some(position) = items.iter().position(|existing| existing.id() == item.id()) {
+            conflicts.extend(merge(&mut item, items.remove(position)));
+        }
+        items.push(item);
+    }
+    conflicts
+}
+
+/// An item which can be compared against the version of itself in the database.
+trait Diffable {
+    fn id(&self) -> &str;
+    fn extensions_mut(&mut self) -> &mut HashSet<ExtensionID>;
+    /// Whether the item belongs only to the given extension, and would be deleted along with it.
+    fn owned_only_by(&self, extension: &Extension) -> bool;
+    fn field_changes(&self, new: &Self) -> Vec<FieldChange>;
+}
+
+impl ExtensionDiff {
+    /// Compares a staged extension against the contents of the database.
+    /// Items are compared by ID against every record in the database, since records are shared
+    /// between extensions. Items are only reported as removed if no other extension provides
+    /// them, since otherwise they would be kept.
+    pub fn new(
+        extension: &Extension,
+        old_version: Option<&Version>,
+        action: PlannedAction,
+        snapshot: &DatabaseSnapshot,
+    ) -> Self {
+        let old_identifiers = snapshot
+            .devices
+            .iter()
+            .filter(|d| d.extensions.contains(&extension.metadata.id))
+            .flat_map(model_identifiers)
+            .collect::<BTreeSet<_>>();
+        let new_identifiers = extension
+            .devices
+            .iter()
+            .flat_map(model_identifiers)
+            .collect::<BTreeSet<_>>();
+
+        Self {
+            id: extension.metadata.id.unnamespaced().to_owned(),
+            action,
+            old_version: old_version.map(Version::to_string),
+            new_version: extension.metadata.version.to_string(),
+            device_manufacturers: diff_items(
+                extension,
+                &extension.device_manufacturers,
+                &snapshot.device_manufacturers,
+            ),
+            device_categories: diff_items(
+                extension,
+                &extension.device_categories,
+                &snapshot.device_categories,
+            ),
+            devices: diff_items(extension, &extension.devices, &snapshot.devices),
+            parts: diff_items(extension, &extension.parts, &snapshot.parts),
+            repair_services: diff_items(
+                extension,
+                &extension.repair_services,
+                &snapshot.repair_services,
+            ),
+            added_model_identifiers: new_identifiers
+                .difference(&old_identifiers)
+                .cloned()
+                .collect(),
+            removed_model_identifiers: old_identifiers
+                .difference(&new_identifiers)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Checks whether loading the extension would leave the contents of the database unchanged.
+    pub fn is_empty(&self) -> bool {
+        self.device_manufacturers.is_empty()
+            && self.device_categories.is_empty()
+            && self.devices.is_empty()
+            && self.parts.is_empty()
+            && self.repair_services.is_empty()
+            && self.added_model_identifiers.is_empty()
+            && self.removed_model_identifiers.is_empty()
+    }
+}
+
+/// Gets all model identifiers of a device, including those of its variants.
+fn model_identifiers(device: &Device) -> impl Iterator<Item = String> + '_ {
+    device.model_identifiers().cloned()
+}
+
+/// Compares the staged items of an extension against the existing items in the database.
+fn diff_items<T: Diffable>(extension: &Extension, staged: &[T], existing: &[T]) -> Vec<ItemDiff> {
+    let existing_by_id = existing
+        .iter()
+        .map(|item| (item.id(), item))
+        .collect::<HashMap<_, _>>();
+    let staged_ids = staged.iter().map(T::id).collect::<BTreeSet<_>>();
+
+    let mut diffs = Vec::new();
+    for item in staged {
+        match existing_by_id.get(item.id()) {
+            None => diffs.push(ItemDiff {
+                id: item.id().to_owned(),
+                change: ItemChange::Added,
+                fields: Vec::new(),
+            }),
+            Some(existing_item) => {
+                let fields = existing_item.field_changes(item);
+                if !fields.is_empty() {
+                    diffs.push(ItemDiff {
+                        id: item.id().to_owned(),
+                        change: ItemChange::Modified,
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+
+    for item in existing {
+        if item.owned_only_by(extension) && !staged_ids.contains(item.id()) {
+            diffs.push(ItemDiff {
+                id: item.id().to_owned(),
+                change: ItemChange::Removed,
+                fields: Vec::new(),
+            });
+        }
+    }
+
+    diffs.sort_by(|a, b| a.id.cmp(&b.id));
+    diffs
+}
+
+/// Describes the custom field values of an item, such as `screen_size = 4.7, color = "red"`.
+fn describe_custom_fields(custom_fields: &CustomFields) -> String {
+    custom_fields
+        .iter()
+        .map(|(id, value)| format!("{id} = {value}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Describes the variants of a device in a single line, such as `a1863 (64GB): A1863`.
+fn describe_variants(variants: &[DeviceVariant]) -> String {
+    let mut variants = variants.iter().collect::<Vec<_>>();
+    variants.sort_by(|a, b| a.id.cmp(&b.id));
+    variants
+        .into_iter()
+        .map(|v| format!("{} ({v}): {}", v.id, v.model_identifiers.join(", ")))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Joins a list of IDs into a single line, such as `iphone_8, iphone_se_2`.
+fn describe_ids<T: UniqueID>(ids: &[T]) -> String {
+    ids.iter()
+        .map(UniqueID::unnamespaced)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Records a change to a field if its old and new values differ.
+fn compare(changes: &mut Vec<FieldChange>, field: &'static str, old: &str, new: &str) {
+    if old != new {
+        changes.push(FieldChange {
+            field,
+            old: old.to_owned(),
+            new: new.to_owned(),
+        });
+    }
+}
+
+impl Diffable for DeviceManufacturer {
+    fn id(&self) -> &str {
+        self.id.unnamespaced()
+    }
+
+    fn extensions_mut(&mut self) -> &mut HashSet<ExtensionID> {
+        &mut self.extensions
+    }
+
+    fn owned_only_by(&self, extension: &Extension) -> bool {
+        self.extensions.len() == 1 && self.extensions.contains(&extension.metadata.id)
+    }
+
+    fn field_changes(&self, new: &Self) -> Vec<FieldChange> {
+        let mut changes = Vec::new();
+        compare(
+            &mut changes,
+            "display_name",
+            &self.display_name,
+            &new.display_name,
+        );
+        compare(
+            &mut changes,
+            "custom_fields",
+            &describe_custom_fields(&self.custom_fields),
+            &describe_custom_fields(&new.custom_fields),
+        );
+        changes
+    }
+}
+
+impl Diffable for DeviceCategory {
+    fn id(&self) -> &str {
+        self.id.unnamespaced()
+    }
+
+    fn extensions_mut(&mut self) -> &mut HashSet<ExtensionID> {
+        &mut self.extensions
+    }
+
+    fn owned_only_by(&self, extension: &Extension) -> bool {
+        self.extensions.len() == 1 && self.extensions.contains(&extension.metadata.id)
+    }
+
+    fn field_changes(&self, new: &Self) -> Vec<FieldChange> {
+        let mut changes = Vec::new();
+        compare(
+            &mut changes,
+            "display_name",
+            &self.display_name,
+            &new.display_name,
+        );
+        compare(
+            &mut changes,
+            "parent",
+            &describe_ids(self.parent.as_slice()),
+            &describe_ids(new.parent.as_slice()),
+        );
+        compare(
+            &mut changes,
+            "custom_fields",
+            &describe_custom_fields(&self.custom_fields),
+            &describe_custom_fields(&new.custom_fields),
+        );
+        changes
+    }
+}
+
+impl Diffable for Device {
+    fn id(&self) -> &str {
+        self.id.unnamespaced()
+    }
+
+    fn extensions_mut(&mut self) -> &mut HashSet<ExtensionID> {
+        &mut self.extensions
+    }
+
+    fn owned_only_by(&self, extension: &Extension) -> bool {
+        self.extensions.len() == 1 && self.extensions.contains(&extension.metadata.id)
+    }
+
+    fn field_changes(&self, new: &Self) -> Vec<FieldChange> {
+        let mut changes = Vec::new();
+        compare(
+            &mut changes,
+            "display_name",
+            &self.display_name,
+            &new.display_name,
+        );
+        compare(
+            &mut changes,
+            "manufacturer",
+            self.manufacturer.unnamespaced(),
+            new.manufacturer.unnamespaced(),
+        );
+        compare(
+            &mut changes,
+            "category",
+            self.category.unnamespaced(),
+            new.category.unnamespaced(),
+        );
+        compare(
+            &mut changes,
+            "primary_model_identifiers",
+            &self.primary_model_identifiers.join(", "),
+            &new.primary_model_identifiers.join(", "),
+        );
+        compare(
+            &mut changes,
+            "extended_model_identifiers",
+            &self.extended_model_identifiers.join(", "),
+            &new.extended_model_identifiers.join(", "),
+        );
+        compare(
+            &mut changes,
+            "variants",
+            &describe_variants(&self.variants),
+            &describe_variants(&new.variants),
+        );
+        compare(
+            &mut changes,
+            "custom_fields",
+            &describe_custom_fields(&self.custom_fields),
+            &describe_custom_fields(&new.custom_fields),
+        );
+        changes
+    }
+}
+
+impl Diffable for Part {
+    fn id(&self) -> &str {
+        self.id.unnamespaced()
+    }
+
+    fn extensions_mut(&mut self) -> &mut HashSet<ExtensionID> {
+        &mut self.extensions
+    }
+
+    fn owned_only_by(&self, extension: &Extension) -> bool {
+        self.extensions.len() == 1 && self.extensions.contains(&extension.metadata.id)
+    }
+
+    fn field_changes(&self, new: &Self) -> Vec<FieldChange> {
+        let mut changes = Vec::new();
+        compare(
+            &mut changes,
+            "display_name",
+            &self.display_name,
+            &new.display_name,
+        );
+        compare(&mut changes, "kind", self.kind.name(), new.kind.name());
+        compare(
+            &mut changes,
+            "supplier_skus",
+            &self.supplier_skus.join(", "),
+            &new.supplier_skus.join(", "),
+        );
+        compare(
+            &mut changes,
+            "compatible_devices",
+            &describe_ids(&self.compatible_devices),
+            &describe_ids(&new.compatible_devices),
+        );
+        changes
+    }
+}
+
+impl Diffable for RepairService {
+    fn id(&self) -> &str {
+        self.id.unnamespaced()
+    }
+
+    fn extensions_mut(&mut self) -> &mut HashSet<ExtensionID> {
+        &mut self.extensions
+    }
+
+    fn owned_only_by(&self, extension: &Extension) -> bool {
+        self.extensions.len() == 1 && self.extensions.contains(&extension.metadata.id)
+    }
+
+    fn field_changes(&self, new: &Self) -> Vec<FieldChange> {
+        let describe_kinds = |service: &RepairService| {
+            service
+                .required_part_kinds
+                .iter()
+                .map(PartKind::name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let mut changes = Vec::new();
+        compare(
+            &mut changes,
+            "display_name",
+            &self.display_name,
+            &new.display_name,
+        );
+        compare(
+            &mut changes,
+            "default_labor_minutes",
+            &self.default_labor_minutes.to_string(),
+            &new.default_labor_minutes.to_string(),
+        );
+        compare(
+            &mut changes,
+            "default_price",
+            &self.default_price.to_string(),
+            &new.default_price.to_string(),
+        );
+        compare(
+            &mut changes,
+            "required_part_kinds",
+            &describe_kinds(self),
+            &describe_kinds(new),
+        );
+        compare(
+            &mut changes,
+            "device_categories",
+            &describe_ids(&self.device_categories),
+            &describe_ids(&new.device_categories),
+        );
+        compare(
+            &mut changes,
+            "devices",
+            &describe_ids(&self.devices),
+            &describe_ids(&new.devices),
+        );
+        changes
+    }
+}
+
+impl fmt::Display for PlannedAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlannedAction::Load => write!(f, "load"),
+            PlannedAction::Reload => write!(f, "reload"),
+            PlannedAction::Skip => write!(f, "skip"),
+        }
+    }
+}
+
+impl fmt::Display for ExtensionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.old_version {
+            Some(old_version) => writeln!(
+                f,
+                "Extension '{}' ({old_version} -> {}): {}",
+                self.id, self.new_version, self.action
+            )?,
+            None => writeln!(
+                f,
+                "Extension '{}' ({}): {}",
+                self.id, self.new_version, self.action
+            )?,
+        }
+
+        if self.is_empty() {
+            return writeln!(f, "  (no changes)");
+        }
+
+        for (kind, items) in [
+            ("device manufacturer", &self.device_manufacturers),
+            ("device category", &self.device_categories),
+            ("device", &self.devices),
+            ("part", &self.parts),
+            ("repair service", &self.repair_services),
+        ] {
+            for item in items {
+                let symbol = match item.change {
+                    ItemChange::Added => '+',
+                    ItemChange::Removed => '-',
+                    ItemChange::Modified => '~',
+                };
+                writeln!(f, "  {symbol} {kind} '{}'", item.id)?;
+                for field in &item.fields {
+                    writeln!(
+                        f,
+                        "      {}: \"{}\" -> \"{}\"",
+                        field.field, field.old, field.new
+                    )?;
+                }
+            }
+        }
+
+        for identifier in &self.added_model_identifiers {
+            writeln!(f, "  + model identifier '{identifier}'")?;
+        }
+        for identifier in &self.removed_model_identifiers {
+            writeln!(f, "  - model identifier '{identifier}'")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::{BTreeMap, HashSet};
+
+use semver::VersionReq;
+
+use super::manager::{
+    CustomFieldToml, DeviceCategoryToml, DeviceManufacturerToml, DeviceToml, ExtensionMetadataToml,
+    InventoryExtensionToml, PartToml, RepairServiceToml,
+};
+use super::versions::CURRENT_FORMAT_VERSION;
+use super::{ExtensionID, Metadata};
+use crate::database::Database;
+use crate::models::common::{
+    CustomFieldDefinition, Dependencies, Device, DeviceCategory, DeviceManufacturer, Part,
+    RepairService, UniqueID,
+};
+
+/// Exports the contents of the database as a TOML extension with the given metadata.
+/// If an extension ID is given, only the items and custom fields which belong to that extension
+/// are exported, along with that extension's dependencies.
+/// Items are exported as their extensions provide them, so items of disabled extensions are
+/// included and overrides are not applied.
+pub async fn export(
+    db: &Database,
+    owner: Option<&ExtensionID>,
+    metadata: &Metadata,
+) -> anyhow::Result<String> {
+    // * Several extensions can declare the same field, which only needs to be exported once.
+    let mut custom_fields = Vec::new();
+    for (extension, field) in db.list_custom_fields().await? {
+        let owned = owner.is_none_or(|owner| *owner == extension);
+        if owned && !custom_fields.contains(&field) {
+            custom_fields.push(field);
+        }
+    }
+
+    // * The items of an extension can refer to the items of its dependencies, so the export only
+    // * passes validation on its own if it declares them too.
+    let dependencies = match owner {
+        Some(owner) => db
+            .list_extension_dependencies()
+            .await?
+            .remove(owner)
+            .unwrap_or_default(),
+        None => Dependencies::new(),
+    };
+
+    to_toml(
+        metadata,
+        owner,
+        &dependencies,
+        custom_fields,
+        db.list_base_device_manufacturers().await?,
+        db.list_base_device_categories().await?,
+        db.list_base_devices().await?,
+        db.list_base_parts().await?,
+        db.list_base_repair_services().await?,
+    )
+}
+
+/// Writes the given items as a TOML extension with the given metadata and dependencies, in the
+/// current format version.
+/// Items and custom fields are sorted by ID so the output is the same regardless of the order they
+/// were read in.
+// * Each kind of item is passed separately, matching how they are listed from the database.
+#[allow(clippy::too_many_arguments)]
+pub fn to_toml(
+    metadata: &Metadata,
+    owner: Option<&ExtensionID>,
+    dependencies: &Dependencies,
+    mut custom_fields: Vec<CustomFieldDefinition>,
+    mut device_manufacturers: Vec<DeviceManufacturer>,
+    mut device_categories: Vec<DeviceCategory>,
+    mut devices: Vec<Device>,
+    mut parts: Vec<Part>,
+    mut repair_services: Vec<RepairService>,
+) -> anyhow::Result<String> {
+    let owned = |extensions: &HashSet<ExtensionID>| match owner {
+        Some(owner) => extensions.contains(owner),
+        None => true,
+    };
+    device_manufacturers.retain(|m| owned(&m.extensions));
+    device_categories.retain(|c| owned(&c.extensions));
+    devices.retain(|d| owned(&d.extensions));
+    parts.retain(|p| owned(&p.extensions));
+    repair_services.retain(|s| owned(&s.extensions));
+
+    device_manufacturers.sort_by(|a, b| a.id.cmp(&b.id));
+    device_categories.sort_by(|a, b| a.id.cmp(&b.id));
+    devices.sort_by(|a, b| a.id.cmp(&b.id));
+    parts.sort_by(|a, b| a.id.cmp(&b.id));
+    repair_services.sort_by(|a, b| a.id.cmp(&b.id));
+    custom_fields.sort_by(|a, b| (a.target.name(), &a.id).cmp(&(b.target.name(), &b.id)));
+
+    let custom_fields = custom_fields
+        .iter()
+        .map(CustomFieldToml::from)
+        .collect::<Vec<_>>();
+
+    let device_manufacturers = device_manufacturers
+        .into_iter()
+        .map(|m| DeviceManufacturerToml {
+            id: m.id.unnamespaced().to_owned(),
+            display_name: m.display_name,
+            custom_fields: m.custom_fields,
+        })
+        .collect::<Vec<_>>();
+    let device_categories = device_categories
+        .into_iter()
+        .map(|c| DeviceCategoryToml {
+            id: c.id.unnamespaced().to_owned(),
+            display_name: c.display_name,
+            parent: c.parent.map(|id| id.unnamespaced().to_owned()),
+            custom_fields: c.custom_fields,
+        })
+        .collect::<Vec<_>>();
+    let devices = devices
+        .into_iter()
+        .map(|mut d| {
+            d.variants.sort_by(|a, b| a.id.cmp(&b.id));
+            d
+        })
+        .map(|d| DeviceToml {
+            id: d.id.unnamespaced().to_owned(),
+            display_name: d.display_name,
+            manufacturer: d.manufacturer.unnamespaced().to_owned(),
+            category: d.category.unnamespaced().to_owned(),
+            primary_model_identifiers: d.primary_model_identifiers,
+            extended_model_identifiers: d.extended_model_identifiers,
+            variants: d.variants,
+            custom_fields: d.custom_fields,
+        })
+        .collect();
+    let parts = parts
+        .into_iter()
+        .map(|p| PartToml {
+            id: p.id.unnamespaced().to_owned(),
+            display_name: p.display_name,
+            kind: p.kind,
+            supplier_skus: p.supplier_skus,
+            compatible_devices: p
+                .compatible_devices
+                .iter()
+                .map(|id| id.unnamespaced().to_owned())
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    let repair_services = repair_services
+        .into_iter()
+        .map(|s| RepairServiceToml {
+            id: s.id.unnamespaced().to_owned(),
+            display_name: s.display_name,
+            default_labor_minutes: s.default_labor_minutes,
+            default_price: s.default_price,
+            required_part_kinds: s.required_part_kinds,
+            device_categories: s
+                .device_categories
+                .iter()
+                .map(|id| id.unnamespaced().to_owned())
+                .collect(),
+            devices: s
+                .devices
+                .iter()
+                .map(|id| id.unnamespaced().to_owned())
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    let dependencies = dependencies
+        .iter()
+        .map(|(id, requirement)| (id.unnamespaced().to_owned(), requirement.to_string()))
+        .collect::<BTreeMap<_, _>>();
+
+    let extension = InventoryExtensionToml {
+        format_version: CURRENT_FORMAT_VERSION,
+        extension: ExtensionMetadataToml {
+            id: metadata.id.unnamespaced().to_owned(),
+            display_name: metadata.display_name.clone(),
+            version: metadata.version.to_string(),
+            author: metadata.author.clone(),
+            license: metadata.license.clone(),
+            description: metadata.description.clone(),
+            homepage: metadata.homepage.clone(),
+            techtriage: metadata.techtriage.as_ref().map(VersionReq::to_string),
+            dependencies: (!dependencies.is_empty()).then_some(dependencies),
+        },
+        custom_fields: (!custom_fields.is_empty()).then_some(custom_fields),
+        device_manufacturers: (!device_manufacturers.is_empty()).then_some(device_manufacturers),
+        device_categories: (!device_categories.is_empty()).then_some(device_categories),
+        devices,
+        parts: (!parts.is_empty()).then_some(parts),
+        repair_services: (!repair_services.is_empty()).then_some(repair_services),
+        overrides: None,
+    };
+
+    Ok(toml::to_string_pretty(&extension)?)
+}
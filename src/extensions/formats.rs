@@ -0,0 +1,429 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use super::manager::{
+    CustomFieldToml, DeviceCategoryToml, DeviceManufacturerToml, DeviceToml, ExtensionMetadataToml,
+    InventoryExtensionToml, OverrideToml, PartToml, RepairServiceToml,
+};
+use super::validation::{Diagnostic, FieldPath, Location};
+use super::versions::{self, Syntax, CURRENT_FORMAT_VERSION};
+use crate::models::common::CustomFieldValue;
+
+/// A file format which extensions can be written in.
+/// Every format is parsed into the same intermediate representation, so all formats go through
+/// the same validation.
+pub(super) trait Format: Sync {
+    /// The file extensions used by files in this format, without the leading dot.
+    fn file_extensions(&self) -> &'static [&'static str];
+
+    /// Parses the source of an extension file in any supported format version.
+    /// Returns the extension upgraded to the current format version, along with the format
+    /// version of the file. Syntax errors and missing or mistyped fields are returned as a
+    /// diagnostic.
+    fn parse(&self, source: &str) -> Result<(InventoryExtensionToml, u32), Diagnostic>;
+
+    /// Finds the location of a field within the source of an extension file, if possible.
+    /// The field path must be in the format version of the file.
+    fn locate(&self, _source: &str, _field: &FieldPath) -> Option<Location> {
+        None
+    }
+
+    /// Rewrites the source of an extension file in the current format version.
+    /// Formats which cannot keep comments and formatting write the upgraded extension from scratch.
+    fn upgrade(&self, source: &str, extension: &InventoryExtensionToml) -> anyhow::Result<String>;
+}
+
+/// Every supported extension file format.
+const FORMATS: &[&dyn Format] = &[&Toml, &Json, &Yaml, &Csv];
+
+/// Gets the format of an extension file based on its file extension.
+pub(super) fn for_path(path: &Path) -> Option<&'static dyn Format> {
+    let extension = path.extension().and_then(OsStr::to_str)?;
+    FORMATS
+        .iter()
+        .copied()
+        .find(|format| format.file_extensions().contains(&extension))
+}
+
+/// Extensions written in TOML.
+struct Toml;
+
+/// Extensions written in JSON, using the same structure as the TOML format.
+struct Json;
+
+/// Extensions written in YAML, using the same structure as the TOML format.
+struct Yaml;
+
+/// Extensions written as a CSV spreadsheet with one device per row.
+///
+/// The file starts with a header of comment lines beginning with `#`, which together contain
+/// everything except the devices as a TOML fragment. The first line after the header names the
+/// columns, which are `id`, `display_name`, `manufacturer`, `category`,
+/// `primary_model_identifiers`, and `extended_model_identifiers`. Model identifiers are separated
+/// with `|`. An optional `custom_fields` column holds the device's custom field values as the
+/// inside of a TOML inline table, such as `screen_size = 4.7, water_resistant = true`. Device
+/// variants cannot be written in a row, so devices with variants need one of the other formats.
+/// For example:
+///
+/// ```text
+/// # format_version = 2
+/// #
+/// # [extension]
+/// # id = "example"
+/// # display_name = "Example"
+/// # version = "1.0.0"
+/// #
+/// # [[device_manufacturers]]
+/// # id = "apple"
+/// # display_name = "Apple"
+/// #
+/// # [[device_categories]]
+/// # id = "phone"
+/// # display_name = "Phone"
+/// id,display_name,manufacturer,category,primary_model_identifiers,extended_model_identifiers
+/// iphone_8,iPhone 8,apple,phone,A1863|A1905|A1906,iPhone10.1|iPhone10.4
+/// ```
+struct Csv;
+
+impl Syntax for Toml {
+    fn deserialize<T: DeserializeOwned>(&self, source: &str) -> Result<T, Diagnostic> {
+        toml::from_str(source).map_err(|e| {
+            let location = e
+                .span()
+                .map(|span| Location::from_offset(source, span.start));
+            Diagnostic::file_error(e.message(), location)
+        })
+    }
+}
+
+impl Format for Toml {
+    fn file_extensions(&self) -> &'static [&'static str] {
+        &["toml"]
+    }
+
+    fn parse(&self, source: &str) -> Result<(InventoryExtensionToml, u32), Diagnostic> {
+        versions::parse(self, source)
+    }
+
+    fn locate(&self, source: &str, field: &FieldPath) -> Option<Location> {
+        field.locate_in_toml(source)
+    }
+
+    fn upgrade(&self, source: &str, _extension: &InventoryExtensionToml) -> anyhow::Result<String> {
+        versions::upgrade_toml(source)
+    }
+}
+
+impl Syntax for Json {
+    fn deserialize<T: DeserializeOwned>(&self, source: &str) -> Result<T, Diagnostic> {
+        serde_json::from_str(source).map_err(|e| {
+            let location = Location {
+                line: e.line(),
+                column: e.column(),
+            };
+            // * The error message already ends with the line and column.
+            let message = e.to_string();
+            let message = match message.rsplit_once(" at line ") {
+                Some((message, _)) => message.to_owned(),
+                None => message,
+            };
+            Diagnostic::file_error(message, Some(location))
+        })
+    }
+}
+
+impl Format for Json {
+    fn file_extensions(&self) -> &'static [&'static str] {
+        &["json"]
+    }
+
+    fn parse(&self, source: &str) -> Result<(InventoryExtensionToml, u32), Diagnostic> {
+        versions::parse(self, source)
+    }
+
+    fn upgrade(&self, _source: &str, extension: &InventoryExtensionToml) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(extension)? + "\n")
+    }
+}
+
+impl Syntax for Yaml {
+    fn deserialize<T: DeserializeOwned>(&self, source: &str) -> Result<T, Diagnostic> {
+        serde_yaml::from_str(source).map_err(|e| {
+            let location = e.location().map(|location| Location {
+                line: location.line(),
+                column: location.column(),
+            });
+            Diagnostic::file_error(e.to_string(), location)
+        })
+    }
+}
+
+impl Format for Yaml {
+    fn file_extensions(&self) -> &'static [&'static str] {
+        &["yaml", "yml"]
+    }
+
+    fn parse(&self, source: &str) -> Result<(InventoryExtensionToml, u32), Diagnostic> {
+        versions::parse(self, source)
+    }
+
+    // ? Is there a YAML library which can edit documents without losing comments?
+    fn upgrade(&self, _source: &str, extension: &InventoryExtensionToml) -> anyhow::Result<String> {
+        Ok(serde_yaml::to_string(extension)?)
+    }
+}
+
+/// Everything in a CSV extension except the devices, as read from the header.
+#[derive(Debug, Deserialize)]
+struct CsvHeader {
+    format_version: u32,
+    extension: ExtensionMetadataToml,
+    custom_fields: Option<Vec<CustomFieldToml>>,
+    device_manufacturers: Option<Vec<DeviceManufacturerToml>>,
+    device_categories: Option<Vec<DeviceCategoryToml>>,
+    parts: Option<Vec<PartToml>>,
+    repair_services: Option<Vec<RepairServiceToml>>,
+    overrides: Option<Vec<OverrideToml>>,
+}
+
+/// The header of a CSV extension in format version 1.
+#[derive(Debug, Deserialize)]
+struct CsvHeaderV1 {
+    extension_id: String,
+    extension_display_name: String,
+    extension_version: String,
+    dependencies: Option<BTreeMap<String, String>>,
+    device_manufacturers: Option<Vec<DeviceManufacturerToml>>,
+    device_categories: Option<Vec<DeviceCategoryToml>>,
+}
+
+/// The TOML fragment in the header of a CSV extension.
+struct CsvHeaderSyntax<'a>(&'a CsvSource<'a>);
+
+/// A single row of a CSV extension.
+#[derive(Debug, Deserialize)]
+struct CsvDevice {
+    id: String,
+    display_name: String,
+    manufacturer: String,
+    category: String,
+    primary_model_identifiers: String,
+    extended_model_identifiers: String,
+    #[serde(default)]
+    custom_fields: String,
+}
+
+/// The custom field values of a CSV row, parsed as a TOML inline table.
+#[derive(Debug, Deserialize)]
+struct CsvCustomFields {
+    custom_fields: BTreeMap<String, CustomFieldValue>,
+}
+
+/// The header of a CSV extension, split from the rows.
+struct CsvSource<'a> {
+    /// The TOML fragment in the header, with the comment markers removed.
+    header: String,
+    /// The number of characters removed from the start of each header line.
+    header_indents: Vec<usize>,
+    /// The column names and device rows.
+    rows: &'a str,
+}
+
+impl<'a> CsvSource<'a> {
+    /// Splits the header from the rows.
+    fn split(source: &'a str) -> Self {
+        let mut header = Vec::new();
+        let mut header_indents = Vec::new();
+        let mut rows = source;
+        while let Some(comment) = rows.trim_start_matches([' ', '\t']).strip_prefix('#') {
+            let (line, rest) = comment.split_once('\n').unwrap_or((comment, ""));
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let content = line.strip_prefix(' ').unwrap_or(line);
+            header_indents.push(rows.len() - comment.len() + (line.len() - content.len()));
+            header.push(content);
+            rows = rest;
+        }
+
+        Self {
+            header: header.join("\n"),
+            header_indents,
+            rows,
+        }
+    }
+
+    /// Converts a location within the header fragment into a location within the whole file.
+    fn header_location(&self, location: Location) -> Location {
+        let indent = self
+            .header_indents
+            .get(location.line - 1)
+            .copied()
+            .unwrap_or(0);
+        Location {
+            line: location.line,
+            column: location.column + indent,
+        }
+    }
+
+    /// Creates a reader for the device rows.
+    fn reader(&self) -> csv::Reader<&'a [u8]> {
+        csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(self.rows.as_bytes())
+    }
+
+    /// Finds the location of the device row with the given index.
+    fn row_location(&self, index: usize) -> Option<Location> {
+        let record = self.reader().into_records().nth(index)?.ok()?;
+        Some(Location {
+            line: self.row_line(record.position()?.line()),
+            column: 1,
+        })
+    }
+
+    /// Converts a line within the rows into a line within the whole file.
+    fn row_line(&self, line: u64) -> usize {
+        self.header_indents.len() + line as usize
+    }
+}
+
+/// Splits a list of model identifiers separated with `|`.
+fn split_identifiers(identifiers: &str) -> Vec<String> {
+    identifiers
+        .split('|')
+        .map(str::trim)
+        .filter(|identifier| !identifier.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+impl Syntax for CsvHeaderSyntax<'_> {
+    fn deserialize<T: DeserializeOwned>(&self, header: &str) -> Result<T, Diagnostic> {
+        toml::from_str(header).map_err(|e| {
+            let location = e.span().map(|span| {
+                self.0
+                    .header_location(Location::from_offset(header, span.start))
+            });
+            Diagnostic::file_error(format!("Invalid header: {}", e.message()), location)
+        })
+    }
+}
+
+impl From<CsvHeaderV1> for CsvHeader {
+    fn from(v1: CsvHeaderV1) -> Self {
+        Self {
+            format_version: CURRENT_FORMAT_VERSION,
+            extension: ExtensionMetadataToml {
+                id: v1.extension_id,
+                display_name: v1.extension_display_name,
+                version: v1.extension_version,
+                dependencies: v1.dependencies,
+                ..Default::default()
+            },
+            custom_fields: None,
+            device_manufacturers: v1.device_manufacturers,
+            device_categories: v1.device_categories,
+            parts: None,
+            repair_services: None,
+            overrides: None,
+        }
+    }
+}
+
+impl Format for Csv {
+    fn file_extensions(&self) -> &'static [&'static str] {
+        &["csv"]
+    }
+
+    fn parse(&self, source: &str) -> Result<(InventoryExtensionToml, u32), Diagnostic> {
+        let source = CsvSource::split(source);
+        let syntax = CsvHeaderSyntax(&source);
+        let version = versions::format_version(&syntax, &source.header)?;
+        let header = match version {
+            1 => syntax.deserialize::<CsvHeaderV1>(&source.header)?.into(),
+            _ => syntax.deserialize::<CsvHeader>(&source.header)?,
+        };
+
+        let mut devices = Vec::new();
+        for row in source.reader().deserialize::<CsvDevice>() {
+            let row = row.map_err(|e| {
+                let location = e.position().map(|position| Location {
+                    line: source.row_line(position.line()),
+                    column: 1,
+                });
+                Diagnostic::file_error(format!("Invalid device row: {e}"), location)
+            })?;
+
+            let custom_fields = match row.custom_fields.is_empty() {
+                true => BTreeMap::new(),
+                false => {
+                    toml::from_str::<CsvCustomFields>(&format!(
+                        "custom_fields = {{ {} }}",
+                        row.custom_fields
+                    ))
+                    .map_err(|e| {
+                        let location = source.row_location(devices.len());
+                        Diagnostic::file_error(
+                            format!("Invalid custom fields: {}", e.message()),
+                            location,
+                        )
+                    })?
+                    .custom_fields
+                }
+            };
+
+            devices.push(DeviceToml {
+                id: row.id,
+                display_name: row.display_name,
+                manufacturer: row.manufacturer,
+                category: row.category,
+                primary_model_identifiers: split_identifiers(&row.primary_model_identifiers),
+                extended_model_identifiers: split_identifiers(&row.extended_model_identifiers),
+                variants: Vec::new(),
+                custom_fields,
+            });
+        }
+
+        let extension = InventoryExtensionToml {
+            format_version: header.format_version,
+            extension: header.extension,
+            custom_fields: header.custom_fields,
+            device_manufacturers: header.device_manufacturers,
+            device_categories: header.device_categories,
+            devices,
+            parts: header.parts,
+            repair_services: header.repair_services,
+            overrides: header.overrides,
+        };
+
+        Ok((extension, version))
+    }
+
+    fn locate(&self, source: &str, field: &FieldPath) -> Option<Location> {
+        let source = CsvSource::split(source);
+        match field.device_index() {
+            // * Devices are located by their row, since the columns are not named in each row.
+            Some(index) => source.row_location(index),
+            None => field
+                .locate_in_toml(&source.header)
+                .map(|location| source.header_location(location)),
+        }
+    }
+
+    fn upgrade(&self, source: &str, _extension: &InventoryExtensionToml) -> anyhow::Result<String> {
+        let source = CsvSource::split(source);
+        let header = versions::upgrade_toml(&source.header)?
+            .lines()
+            .map(|line| match line.is_empty() {
+                true => "#".to_owned(),
+                false => format!("# {line}"),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Ok(format!("{header}\n{}", source.rows))
+    }
+}
@@ -0,0 +1,234 @@
+use sha2::{Digest, Sha256};
+
+use super::Extension;
+use crate::models::common::{
+    CustomFieldType, CustomFields, DeviceVariant, OverrideTarget, UniqueID,
+};
+
+/// Feeds values into a hasher in an unambiguous form.
+struct ContentHasher(Sha256);
+
+impl ContentHasher {
+    /// Adds a string, prefixed with its length so adjacent values cannot run together.
+    fn string(&mut self, value: &str) {
+        self.0.update((value.len() as u64).to_le_bytes());
+        self.0.update(value.as_bytes());
+    }
+
+    /// Adds a list of strings, prefixed with the number of items.
+    fn strings(&mut self, values: &[String]) {
+        self.0.update((values.len() as u64).to_le_bytes());
+        for value in values {
+            self.string(value);
+        }
+    }
+
+    /// Adds the variants of a device, if it has any, so the hashes of extensions without variants
+    /// are unchanged. The order of the variants does not change the hash.
+    fn variants(&mut self, variants: &[DeviceVariant]) {
+        if variants.is_empty() {
+            return;
+        }
+        self.string("variants");
+        let mut variants = variants.iter().collect::<Vec<_>>();
+        variants.sort_by(|a, b| a.id.cmp(&b.id));
+        self.0.update((variants.len() as u64).to_le_bytes());
+        for variant in variants {
+            self.string(&variant.id);
+            for (name, value) in [
+                ("capacity", &variant.capacity),
+                ("color", &variant.color),
+                ("carrier", &variant.carrier),
+                ("region", &variant.region),
+            ] {
+                if let Some(value) = value {
+                    self.string(name);
+                    self.string(value);
+                }
+            }
+            self.strings(&variant.model_identifiers);
+        }
+    }
+
+    /// Adds the custom field values of an item, if it has any, so the hashes of extensions
+    /// without custom fields are unchanged.
+    fn custom_fields(&mut self, custom_fields: &CustomFields) {
+        if custom_fields.is_empty() {
+            return;
+        }
+        self.string("custom_fields");
+        self.0.update((custom_fields.len() as u64).to_le_bytes());
+        for (id, value) in custom_fields {
+            self.string(id);
+            self.string(&value.to_string());
+        }
+    }
+}
+
+impl Extension {
+    /// Computes a hash of the extension's contents, used to detect when an extension has changed
+    /// without its version being bumped.
+    /// The version is not included, and neither is the order in which manufacturers, categories,
+    /// and devices are listed, since neither changes what is loaded into the database.
+    pub fn content_hash(&self) -> String {
+        let mut hasher = ContentHasher(Sha256::new());
+        hasher.string(&self.metadata.display_name);
+
+        // * Manifest fields are only hashed when present, so extensions without them keep the
+        // * hashes they had before the fields were introduced.
+        let metadata = &self.metadata;
+        for (name, value) in [
+            ("author", metadata.author.clone()),
+            ("license", metadata.license.clone()),
+            ("description", metadata.description.clone()),
+            ("homepage", metadata.homepage.clone()),
+            (
+                "techtriage",
+                metadata.techtriage.as_ref().map(|r| r.to_string()),
+            ),
+        ] {
+            if let Some(value) = value {
+                hasher.string(name);
+                hasher.string(&value);
+            }
+        }
+
+        // * Dependencies are already sorted by ID.
+        hasher.string("dependencies");
+        for (id, requirement) in &self.dependencies {
+            hasher.string(id.unnamespaced());
+            hasher.string(&requirement.to_string());
+        }
+
+        hasher.string("device_manufacturers");
+        let mut manufacturers = self.device_manufacturers.iter().collect::<Vec<_>>();
+        manufacturers.sort_by(|a, b| a.id.cmp(&b.id));
+        for manufacturer in manufacturers {
+            hasher.string(manufacturer.id.unnamespaced());
+            hasher.string(&manufacturer.display_name);
+            hasher.custom_fields(&manufacturer.custom_fields);
+        }
+
+        hasher.string("device_categories");
+        let mut categories = self.device_categories.iter().collect::<Vec<_>>();
+        categories.sort_by(|a, b| a.id.cmp(&b.id));
+        for category in categories {
+            hasher.string(category.id.unnamespaced());
+            hasher.string(&category.display_name);
+            // * Parents are only hashed when present, like variants.
+            if let Some(parent) = &category.parent {
+                hasher.string("parent");
+                hasher.string(parent.unnamespaced());
+            }
+            hasher.custom_fields(&category.custom_fields);
+        }
+
+        hasher.string("devices");
+        let mut devices = self.devices.iter().collect::<Vec<_>>();
+        devices.sort_by(|a, b| a.id.cmp(&b.id));
+        for device in devices {
+            hasher.string(device.id.unnamespaced());
+            hasher.string(&device.display_name);
+            hasher.string(device.manufacturer.unnamespaced());
+            hasher.string(device.category.unnamespaced());
+            hasher.strings(&device.primary_model_identifiers);
+            hasher.strings(&device.extended_model_identifiers);
+            hasher.variants(&device.variants);
+            hasher.custom_fields(&device.custom_fields);
+        }
+
+        // * Parts are only hashed when present, like custom field declarations.
+        if !self.parts.is_empty() {
+            hasher.string("parts");
+            let mut parts = self.parts.iter().collect::<Vec<_>>();
+            parts.sort_by(|a, b| a.id.cmp(&b.id));
+            for part in parts {
+                hasher.string(part.id.unnamespaced());
+                hasher.string(&part.display_name);
+                hasher.string(part.kind.name());
+                hasher.strings(&part.supplier_skus);
+                hasher
+                    .0
+                    .update((part.compatible_devices.len() as u64).to_le_bytes());
+                for device in &part.compatible_devices {
+                    hasher.string(device.unnamespaced());
+                }
+            }
+        }
+
+        // * Repair services are only hashed when present, like parts.
+        if !self.repair_services.is_empty() {
+            hasher.string("repair_services");
+            let mut services = self.repair_services.iter().collect::<Vec<_>>();
+            services.sort_by(|a, b| a.id.cmp(&b.id));
+            for service in services {
+                hasher.string(service.id.unnamespaced());
+                hasher.string(&service.display_name);
+                hasher.string(&service.default_labor_minutes.to_string());
+                hasher.string(&service.default_price.to_string());
+                hasher
+                    .0
+                    .update((service.required_part_kinds.len() as u64).to_le_bytes());
+                for kind in &service.required_part_kinds {
+                    hasher.string(kind.name());
+                }
+                hasher
+                    .0
+                    .update((service.device_categories.len() as u64).to_le_bytes());
+                for category in &service.device_categories {
+                    hasher.string(category.unnamespaced());
+                }
+                hasher
+                    .0
+                    .update((service.devices.len() as u64).to_le_bytes());
+                for device in &service.devices {
+                    hasher.string(device.unnamespaced());
+                }
+            }
+        }
+
+        // * Custom field declarations are only hashed when present, like overrides.
+        if !self.custom_fields.is_empty() {
+            hasher.string("custom_field_declarations");
+            let mut fields = self.custom_fields.iter().collect::<Vec<_>>();
+            fields.sort_by_key(|field| (field.target.name(), &field.id));
+            for field in fields {
+                hasher.string(field.target.name());
+                hasher.string(&field.id);
+                hasher.string(&field.display_name);
+                hasher.string(field.field_type.name());
+                if let CustomFieldType::Enum(values) = &field.field_type {
+                    hasher.strings(values);
+                }
+                hasher.string(&field.required.to_string());
+            }
+        }
+
+        // * Overrides are applied in order, so their order is part of the contents. They are only
+        // * hashed when present, so the hashes of base extensions are unchanged.
+        if !self.overrides.is_empty() {
+            hasher.string("overrides");
+            for o in &self.overrides {
+                let (kind, id) = match &o.target {
+                    OverrideTarget::DeviceManufacturer(id) => {
+                        ("device_manufacturer", id.unnamespaced())
+                    }
+                    OverrideTarget::DeviceCategory(id) => ("device_category", id.unnamespaced()),
+                    OverrideTarget::Device(id) => ("device", id.unnamespaced()),
+                };
+                hasher.string(kind);
+                hasher.string(id);
+                hasher.string(o.operation.name());
+                match o.operation.field_value() {
+                    Some((field, value)) => {
+                        hasher.string(field.name());
+                        hasher.string(value);
+                    }
+                    None => hasher.string(""),
+                }
+            }
+        }
+
+        format!("{:x}", hasher.0.finalize())
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::{HashMap, HashSet};
+
+use clap::ValueEnum;
+
+use super::validation::{Diagnostic, FieldPath};
+use super::Extension;
+use crate::models::common::{Device, DeviceUniqueID, DeviceVariant, UniqueID};
+
+/// How a model identifier collision should be treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CollisionSeverity {
+    /// Refuse to load the extension.
+    Error,
+    /// Load the extension, but log a warning.
+    Warning,
+    /// Load the extension silently.
+    Ignore,
+}
+
+/// The severity of each kind of model identifier collision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollisionSeverities {
+    /// The same identifier is listed more than once for a single device.
+    pub within_device: CollisionSeverity,
+    /// Two devices in the same extension share an identifier.
+    pub across_devices: CollisionSeverity,
+    /// A device shares an identifier with a device from another extension in the database.
+    pub with_database: CollisionSeverity,
+}
+
+impl Default for CollisionSeverities {
+    fn default() -> Self {
+        // * A repeated identifier within one device is harmless for lookups, but an identifier
+        // * shared by two devices makes lookups ambiguous.
+        CollisionSeverities {
+            within_device: CollisionSeverity::Warning,
+            across_devices: CollisionSeverity::Error,
+            with_database: CollisionSeverity::Error,
+        }
+    }
+}
+
+impl CollisionSeverity {
+    /// Creates a diagnostic with this severity, or nothing if the collision is ignored.
+    fn diagnostic(self, field: FieldPath, message: String) -> Option<Diagnostic> {
+        match self {
+            CollisionSeverity::Error => Some(Diagnostic::error(field, message)),
+            CollisionSeverity::Warning => Some(Diagnostic::warning(field, message)),
+            CollisionSeverity::Ignore => None,
+        }
+    }
+}
+
+/// The model identifiers of a single device, including those of its variants, along with the
+/// path to each within the device at the given path.
+fn identifiers<'a>(
+    device: &FieldPath,
+    primary: &'a [String],
+    extended: &'a [String],
+    variants: &'a [DeviceVariant],
+) -> Vec<(FieldPath, &'a String)> {
+    let mut identifiers = Vec::new();
+    for (list, values) in [
+        ("primary_model_identifiers", primary),
+        ("extended_model_identifiers", extended),
+    ] {
+        for (i, identifier) in values.iter().enumerate() {
+            identifiers.push((device.clone().then(list).index(i), identifier));
+        }
+    }
+    for (i, variant) in variants.iter().enumerate() {
+        for (j, identifier) in variant.model_identifiers.iter().enumerate() {
+            let field = device.clone().then("variants").index(i);
+            identifiers.push((field.then("model_identifiers").index(j), identifier));
+        }
+    }
+
+    identifiers
+}
+
+/// Finds model identifiers which are listed more than once in a device or which are shared by
+/// multiple devices within the same extension.
+/// Each device is given as its ID, its primary and extended model identifiers, and its variants.
+pub(super) fn check_extension<'a>(
+    devices: impl IntoIterator<Item = (&'a str, &'a [String], &'a [String], &'a [DeviceVariant])>,
+    severities: &CollisionSeverities,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut owners = HashMap::<&str, &str>::new();
+    for (i, (device_id, primary, extended, variants)) in devices.into_iter().enumerate() {
+        let mut seen = HashSet::new();
+        let device = FieldPath::element("devices", i);
+        for (field, identifier) in identifiers(&device, primary, extended, variants) {
+            if !seen.insert(identifier.as_str()) {
+                diagnostics.extend(severities.within_device.diagnostic(
+                    field,
+                    format!(
+                        "Model identifier '{identifier}' is listed more than once for device \
+                        '{device_id}'"
+                    ),
+                ));
+                continue;
+            }
+
+            match owners.get(identifier.as_str()) {
+                Some(owner) => diagnostics.extend(severities.across_devices.diagnostic(
+                    field,
+                    format!(
+                        "Model identifier '{identifier}' of device '{device_id}' is already used by \
+                        device '{owner}'"
+                    ),
+                )),
+                None => {
+                    owners.insert(identifier, device_id);
+                }
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Finds model identifiers of an extension's devices which are already used by different devices
+/// in the database.
+/// Devices which only belong to the extension itself are ignored, since they will be replaced if
+/// the extension is reloaded.
+pub fn check_database(
+    extension: &Extension,
+    existing_devices: &[Device],
+    severity: CollisionSeverity,
+) -> Vec<Diagnostic> {
+    let extension_id = &extension.metadata.id;
+    let mut owners = HashMap::<&str, &DeviceUniqueID>::new();
+    for device in existing_devices {
+        let owned_by_extension =
+            device.extensions.len() == 1 && device.extensions.contains(extension_id);
+        if owned_by_extension {
+            continue;
+        }
+
+        for identifier in device.model_identifiers() {
+            owners.insert(identifier, &device.id);
+        }
+    }
+
+    let mut diagnostics = Vec::new();
+    for (i, device) in extension.devices.iter().enumerate() {
+        for (field, identifier) in identifiers(
+            &FieldPath::element("devices", i),
+            &device.primary_model_identifiers,
+            &device.extended_model_identifiers,
+            &device.variants,
+        ) {
+            // * A device with the same ID is the same device, and will be merged.
+            let Some(owner) = owners
+                .get(identifier.as_str())
+                .filter(|o| **o != &device.id)
+            else {
+                continue;
+            };
+
+            diagnostics.extend(severity.diagnostic(
+                field,
+                format!(
+                    "Model identifier '{identifier}' of device '{}' is already used by device '{}' \
+                    in the database",
+                    device.id.unnamespaced(),
+                    owner.unnamespaced()
+                ),
+            ));
+        }
+    }
+
+    diagnostics
+}
@@ -1,111 +1,504 @@
-use std::collections::HashSet;
-use std::ffi::OsStr;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::DirEntry;
 use std::path::Path;
-use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-use log::{info, warn};
-use semver::Version;
-use serde::Deserialize;
+use log::{error, info, warn};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
 
-use super::conflicts::LoadConflict;
+use super::bundles::{self, TrustedKeys, UnsignedPolicy};
+use super::conflicts::{ConflictKind, LoadConflict, LoadFailure, LoadFailureReason};
+use super::custom_fields;
+use super::dependencies::{self, DependencyError};
+use super::diff::{DatabaseSnapshot, ExtensionDiff, PlannedAction};
+use super::formats;
+use super::identifiers::{self, CollisionSeverities};
+use super::merging::{FieldConflict, MergeConflictError, MergePolicy, Merger};
+use super::orphans::{self, DirectoryScan, OrphanPolicy};
+use super::overrides;
+use super::report::{ExtensionOutcome, LoadReport, RecordCounts};
+use super::validation::{self, Diagnostic, ValidationReport};
+use super::versions;
 use super::{ExtensionID, Metadata};
 use crate::database::Database;
 use crate::models::common::{
-    Device, DeviceCategory, DeviceCategoryUniqueID, DeviceManufacturer, DeviceManufacturerUniqueID,
-    DeviceUniqueID, UniqueID,
+    CustomFieldDefinition, CustomFieldValue, Device, DeviceCategory, DeviceCategoryUniqueID,
+    DeviceManufacturer, DeviceManufacturerUniqueID, DeviceUniqueID, DeviceVariant, Override, Part,
+    PartKind, PartUniqueID, RepairService, RepairServiceUniqueID, UniqueID,
 };
 
+/// The directory which extension files are loaded from.
+pub const EXTENSIONS_DIRECTORY: &str = "./extensions";
+
 /// An extension of the database inventory system.
 #[derive(Debug, Clone)]
 pub struct InventoryExtension {
     pub metadata: Metadata,
+    pub dependencies: BTreeMap<ExtensionID, VersionReq>,
+    /// Fields which the extension adds to manufacturers, categories, or devices.
+    pub custom_fields: Vec<CustomFieldDefinition>,
     pub device_manufacturers: Vec<DeviceManufacturer>,
     pub device_categories: Vec<DeviceCategory>,
     pub devices: Vec<Device>,
+    /// Replacement parts, and the devices each of them fits.
+    pub parts: Vec<Part>,
+    /// Repairs which can be performed on the extension's devices, or on devices from others.
+    pub repair_services: Vec<RepairService>,
+    /// Changes to items provided by other extensions.
+    /// Extensions with overrides cannot provide any items of their own.
+    pub overrides: Vec<Override>,
 }
 
-/// An inventory extension as read from a TOML file.
+/// An inventory extension as read from an extension file.
+/// Although every format is read into this type, it mirrors the structure of the TOML format.
 /// Some types are not compatible with the database, so this type must be converted into an
 /// [`InventoryExtension`] before calling [`Database::load_extension`].
-#[derive(Debug, Deserialize)]
-struct InventoryExtensionToml {
-    extension_id: String,
-    extension_display_name: String,
-    extension_version: String,
-    device_manufacturers: Option<Vec<DeviceManufacturerToml>>,
-    device_categories: Option<Vec<DeviceCategoryToml>>,
-    devices: Vec<DeviceToml>,
+/// This is also the type which extensions are written as when exported.
+/// It always has the structure of the current format version; files in older format versions are
+/// upgraded into it when they are parsed.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub(super) struct InventoryExtensionToml {
+    pub(super) format_version: u32,
+    pub(super) extension: ExtensionMetadataToml,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) custom_fields: Option<Vec<CustomFieldToml>>,
+    pub(super) device_manufacturers: Option<Vec<DeviceManufacturerToml>>,
+    pub(super) device_categories: Option<Vec<DeviceCategoryToml>>,
+    #[serde(default)]
+    pub(super) devices: Vec<DeviceToml>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) parts: Option<Vec<PartToml>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) repair_services: Option<Vec<RepairServiceToml>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) overrides: Option<Vec<OverrideToml>>,
+}
+
+/// The metadata and dependencies of an extension as read from an extension file.
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+pub(super) struct ExtensionMetadataToml {
+    pub(super) id: String,
+    pub(super) display_name: String,
+    pub(super) version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) license: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) homepage: Option<String>,
+    /// The version requirement which the running TechTriage version must match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) techtriage: Option<String>,
+    pub(super) dependencies: Option<BTreeMap<String, String>>,
 }
 
-/// A device manufacturer as read from a TOML extension.
+/// A device manufacturer as read from an extension file.
 /// This must be converted into a [`DeviceManufacturer`] before adding it to the database.
-#[derive(Debug, Deserialize)]
-struct DeviceManufacturerToml {
-    id: String,
-    display_name: String,
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub(super) struct DeviceManufacturerToml {
+    pub(super) id: String,
+    pub(super) display_name: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(super) custom_fields: BTreeMap<String, CustomFieldValue>,
 }
 
-/// A category of device as read from a TOML extension.
+/// A category of device as read from an extension file.
 /// This must be converted into a [`DeviceCategory`] before adding it to the database.
-#[derive(Debug, Deserialize)]
-struct DeviceCategoryToml {
-    id: String,
-    display_name: String,
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub(super) struct DeviceCategoryToml {
+    pub(super) id: String,
+    pub(super) display_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) parent: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(super) custom_fields: BTreeMap<String, CustomFieldValue>,
 }
 
-/// A device and its metadata as read from a TOML extension.
+/// A device and its metadata as read from an extension file.
 /// This must be converted into a [`Device`] before adding it to the database.
-#[derive(Debug, Deserialize)]
-struct DeviceToml {
-    id: String,
-    display_name: String,
-    manufacturer: String,
-    category: String,
-    primary_model_identifiers: Vec<String>,
-    extended_model_identifiers: Vec<String>,
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub(super) struct DeviceToml {
+    pub(super) id: String,
+    pub(super) display_name: String,
+    pub(super) manufacturer: String,
+    pub(super) category: String,
+    pub(super) primary_model_identifiers: Vec<String>,
+    pub(super) extended_model_identifiers: Vec<String>,
+    // * Devices without variants only list their model identifiers above.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) variants: Vec<DeviceVariant>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(super) custom_fields: BTreeMap<String, CustomFieldValue>,
+}
+
+/// A part as read from an extension file.
+/// This must be converted into a [`Part`] before adding it to the database.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub(super) struct PartToml {
+    pub(super) id: String,
+    pub(super) display_name: String,
+    pub(super) kind: PartKind,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) supplier_skus: Vec<String>,
+    pub(super) compatible_devices: Vec<String>,
+}
+
+/// A repair service as read from an extension file.
+/// This must be converted into a [`RepairService`] before adding it to the database.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub(super) struct RepairServiceToml {
+    pub(super) id: String,
+    pub(super) display_name: String,
+    pub(super) default_labor_minutes: u32,
+    /// In the smallest unit of the shop's currency, such as cents.
+    pub(super) default_price: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) required_part_kinds: Vec<PartKind>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) device_categories: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) devices: Vec<String>,
+}
+
+/// The declaration of a custom field as read from an extension file.
+/// This must be converted into a [`CustomFieldDefinition`] before adding it to the database.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub(super) struct CustomFieldToml {
+    pub(super) id: String,
+    pub(super) target: CustomFieldTargetToml,
+    pub(super) display_name: String,
+    #[serde(rename = "type")]
+    pub(super) field_type: CustomFieldTypeToml,
+    /// The allowed values of an enum field.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) values: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(super) required: bool,
+}
+
+/// The kind of item which a custom field is added to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum CustomFieldTargetToml {
+    DeviceManufacturer,
+    DeviceCategory,
+    Device,
+}
+
+/// The type of value which a custom field holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum CustomFieldTypeToml {
+    String,
+    Number,
+    Bool,
+    Enum,
+    List,
+}
+
+/// An override of an item provided by another extension, as read from an extension file.
+/// This must be converted into an [`Override`] before adding it to the database.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub(super) struct OverrideToml {
+    pub(super) target: OverrideTargetToml,
+    pub(super) id: String,
+    pub(super) operation: OverrideOperationToml,
+    /// The field to change. Removing without a field hides the whole item.
+    pub(super) field: Option<String>,
+    pub(super) value: Option<String>,
+}
+
+/// The kind of item which an override applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum OverrideTargetToml {
+    DeviceManufacturer,
+    DeviceCategory,
+    Device,
+}
+
+/// The change made by an override.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum OverrideOperationToml {
+    Add,
+    Remove,
+    Replace,
 }
 
 /// Manages the parsing and loading of extensions into the database.
 pub struct ExtensionManager {
     staged_extensions: Vec<InventoryExtension>,
-    auto_reload: bool,
+    config: ExtensionManagerConfig,
+    /// Set when the staged extensions were read from the whole extensions directory.
+    pub(super) directory_scan: Option<DirectoryScan>,
+    /// The keys which extension bundles must be signed with.
+    pub(super) trusted_keys: TrustedKeys,
+}
+
+/// Configuration for validating and loading extensions.
+#[derive(Debug, Clone, Default)]
+pub struct ExtensionManagerConfig {
+    /// Reload all conflicting extensions, even if their version has not changed.
+    pub auto_reload: bool,
+    /// Reload extensions whose contents changed without a version bump.
+    pub reload_on_drift: bool,
+    /// Compute what loading the staged extensions would change, without writing anything.
+    pub dry_run: bool,
+    /// Replace loaded extensions with older versions of themselves.
+    pub allow_downgrade: bool,
+    /// What to do with loaded extensions whose files have been removed.
+    pub orphans: OrphanPolicy,
+    /// What to do with extension files which are not part of a signed bundle.
+    pub unsigned_extensions: UnsignedPolicy,
+    /// How to reconcile items which several extensions define with different metadata.
+    pub merge_policy: MergePolicy,
+    /// The extensions in order of priority, highest first, for [`MergePolicy::Priority`].
+    pub extension_priority: Vec<ExtensionID>,
+    /// How each kind of model identifier collision is treated.
+    pub identifier_collisions: CollisionSeverities,
 }
 
 impl ExtensionManager {
     /// Loads all extensions from the default location (the extensions folder).
-    pub fn new(auto_reload: bool) -> anyhow::Result<Self> {
-        let mut manager = Self::base_with_context(auto_reload);
-        for extension_file in std::fs::read_dir("./extensions")?.flatten() {
+    /// Extension files which fail validation or signature verification are reported and skipped.
+    pub fn new(config: ExtensionManagerConfig) -> anyhow::Result<Self> {
+        let mut manager = Self::base_with_context(config);
+        manager.trusted_keys = TrustedKeys::load(Path::new(bundles::TRUSTED_KEYS_DIRECTORY))?;
+        let mut scan = DirectoryScan::default();
+        for extension_file in std::fs::read_dir(EXTENSIONS_DIRECTORY)?.flatten() {
             if Self::is_extension(&extension_file) {
                 info!(
                     "Located extension file: {}",
                     extension_file.path().display()
                 );
-                manager.stage_extension(Self::parse_extension(&extension_file.path())?)?;
+                match manager.read_extension(&extension_file.path()) {
+                    Ok((extension, report)) => {
+                        report.log();
+                        let id = extension.metadata.id.clone();
+                        if let Err(e) = manager.stage_extension(extension) {
+                            error!("Skipping extension '{}': {e}", id.unnamespaced());
+                            scan.unstaged.insert(id);
+                        }
+                    }
+                    Err(report) => {
+                        report.log();
+                        error!(
+                            "Skipping invalid extension file: {}",
+                            extension_file.path().display()
+                        );
+                        scan.invalid_files += 1;
+                    }
+                }
             }
         }
 
+        manager.directory_scan = Some(scan);
         Ok(manager)
     }
 
     /// Creates a manager with no staged extensions.
-    pub fn base_with_context(auto_reload: bool) -> Self {
+    pub fn base_with_context(config: ExtensionManagerConfig) -> Self {
         Self {
             staged_extensions: Vec::new(),
-            auto_reload,
+            config,
+            directory_scan: None,
+            trusted_keys: TrustedKeys::default(),
+        }
+    }
+
+    /// Reads an extension file or signed bundle into an extension which can be staged.
+    /// Bundles are only accepted if they are signed by a trusted key, and plain extension files
+    /// are handled according to the unsigned extension policy.
+    pub fn read_extension(
+        &self,
+        path: &Path,
+    ) -> Result<(InventoryExtension, ValidationReport), ValidationReport> {
+        if bundles::is_bundle_path(path) {
+            return self.read_bundle(path);
+        }
+
+        let (extension, mut report) = self.parse_extension(path)?;
+        match self.config.unsigned_extensions {
+            UnsignedPolicy::Allow => {}
+            UnsignedPolicy::Warn => report.push(Diagnostic::file_warning(
+                "Extension is not signed; it should be distributed as a signed bundle",
+            )),
+            UnsignedPolicy::Reject => {
+                report.push(Diagnostic::file_error(
+                    "Extension is not signed, and unsigned extensions are not allowed",
+                    None,
+                ));
+                return Err(report);
+            }
+        }
+
+        Ok((extension, report))
+    }
+
+    /// Verifies the signature of a bundle before parsing the extension file inside it.
+    fn read_bundle(
+        &self,
+        path: &Path,
+    ) -> Result<(InventoryExtension, ValidationReport), ValidationReport> {
+        match bundles::verify(path, &self.trusted_keys) {
+            Ok(verified) => {
+                info!(
+                    "Verified extension bundle {}, signed by '{}'.",
+                    path.display(),
+                    verified.signer
+                );
+                self.parse_extension_source(&verified.extension_file, &verified.source)
+            }
+            Err(e) => {
+                let mut report = ValidationReport::new(path);
+                report.push(Diagnostic::file_error(
+                    format!("Invalid extension bundle: {e:#}"),
+                    None,
+                ));
+                Err(report)
+            }
+        }
+    }
+
+    /// Parses an extension file into an extension which can be added to the database by the
+    /// manager. TOML, JSON, YAML, and CSV files are supported.
+    /// On success, the returned report contains any warnings found during validation.
+    /// On failure, the returned report contains at least one error.
+    pub fn parse_extension(
+        &self,
+        filename: &Path,
+    ) -> Result<(InventoryExtension, ValidationReport), ValidationReport> {
+        match std::fs::read_to_string(filename) {
+            Ok(source) => self.parse_extension_source(filename, &source),
+            Err(e) => {
+                let mut report = ValidationReport::new(filename);
+                report.push(Diagnostic::file_error(
+                    format!("Failed to read extension file: {e}"),
+                    None,
+                ));
+                Err(report)
+            }
+        }
+    }
+
+    /// Parses and validates the source of an extension file.
+    /// The format is chosen based on the file extension of the filename, which is otherwise only
+    /// used for reporting.
+    pub(super) fn parse_extension_source(
+        &self,
+        filename: &Path,
+        source: &str,
+    ) -> Result<(InventoryExtension, ValidationReport), ValidationReport> {
+        let mut report = ValidationReport::new(filename);
+        let Some(format) = formats::for_path(filename) else {
+            report.push(Diagnostic::file_error(
+                "Unsupported extension file format",
+                None,
+            ));
+            return Err(report);
+        };
+
+        let (extension_toml, format_version) = match format.parse(source) {
+            Ok(parsed) => parsed,
+            Err(diagnostic) => {
+                report.push(diagnostic);
+                return Err(report);
+            }
+        };
+
+        report.extend_located(
+            validation::validate(&extension_toml, &self.config.identifier_collisions),
+            |field| format.locate(source, &versions::field_in_version(field, format_version)),
+        );
+        if report.has_errors() {
+            return Err(report);
+        }
+
+        match InventoryExtension::try_from(extension_toml) {
+            Ok(extension) => Ok((extension, report)),
+            Err(e) => {
+                report.push(Diagnostic::file_error(e.to_string(), None));
+                Err(report)
+            }
         }
     }
 
-    /// Parses a TOML file into an extension which can be added to the database by the manager.
-    fn parse_extension(filename: &Path) -> anyhow::Result<InventoryExtension> {
-        let toml = std::fs::read_to_string(filename)?;
-        let extension_toml: InventoryExtensionToml = toml::from_str(&toml)?;
-        Ok(InventoryExtension::from(extension_toml))
+    /// Rewrites an extension file in the current format version, keeping comments and formatting
+    /// where the file format allows it. Returns `None` if the file is already up to date.
+    /// The file is not validated, since upgrading does not change its contents.
+    pub fn upgrade_extension(&self, filename: &Path) -> Result<Option<String>, ValidationReport> {
+        let mut report = ValidationReport::new(filename);
+        let Some(format) = formats::for_path(filename) else {
+            report.push(Diagnostic::file_error(
+                "Unsupported extension file format",
+                None,
+            ));
+            return Err(report);
+        };
+        let source = match std::fs::read_to_string(filename) {
+            Ok(source) => source,
+            Err(e) => {
+                report.push(Diagnostic::file_error(
+                    format!("Failed to read extension file: {e}"),
+                    None,
+                ));
+                return Err(report);
+            }
+        };
+
+        let (extension_toml, format_version) = match format.parse(&source) {
+            Ok(parsed) => parsed,
+            Err(diagnostic) => {
+                report.push(diagnostic);
+                return Err(report);
+            }
+        };
+        if format_version == versions::CURRENT_FORMAT_VERSION {
+            return Ok(None);
+        }
+
+        let upgraded = match format.upgrade(&source, &extension_toml) {
+            Ok(upgraded) => upgraded,
+            Err(e) => {
+                report.push(Diagnostic::file_error(
+                    format!("Failed to upgrade extension file: {e}"),
+                    None,
+                ));
+                return Err(report);
+            }
+        };
+
+        // * The upgraded file is parsed again to make sure nothing was lost along the way.
+        match format.parse(&upgraded) {
+            Ok((upgraded_toml, _)) if upgraded_toml == extension_toml => Ok(Some(upgraded)),
+            _ => {
+                report.push(Diagnostic::file_error(
+                    "The upgraded file does not match the original",
+                    None,
+                ));
+                Err(report)
+            }
+        }
     }
 
     /// Stages an extension.
+    /// Fails if the extension does not support the running version of TechTriage.
     pub fn stage_extension(&mut self, extension: InventoryExtension) -> anyhow::Result<()> {
+        if let Some(requirement) = &extension.metadata.techtriage {
+            let version = Version::parse(env!("CARGO_PKG_VERSION"))?;
+            if !requirement.matches(&version) {
+                anyhow::bail!(
+                    "Extension '{}' requires TechTriage {requirement}, but this is version \
+                    {version}",
+                    extension.metadata.id.unnamespaced()
+                );
+            }
+        }
+
         info!(
             "Staging extension '{}'.",
             extension.metadata.id.unnamespaced()
@@ -116,63 +509,538 @@ impl ExtensionManager {
     }
 
     /// Adds all extensions from the manager into the database, handling any conflicts.
-    pub async fn load_extensions(self, db: &Database) -> anyhow::Result<Vec<LoadConflict>> {
+    /// Extensions are loaded after the extensions they depend on. Extensions which cannot be
+    /// loaded are skipped and reported in the result.
+    /// In dry-run mode, the database is not modified, and the result instead contains a diff of
+    /// what would change for each extension.
+    /// If the staged extensions came from the extensions directory, loaded extensions without a
+    /// file are reported as orphans and handled according to the configured policy.
+    /// The report records the outcome of every extension, along with how many records it
+    /// affected and how long it took.
+    pub async fn load_extensions(mut self, db: &Database) -> anyhow::Result<LoadReport> {
         info!("Loading staged inventory extensions into database...");
+        let started = Instant::now();
+
+        let mut loaded_extensions = db.list_extensions().await?.into_all();
+        let staged_ids = self
+            .staged_extensions
+            .iter()
+            .map(|extension| extension.metadata.id.clone())
+            .chain(
+                self.directory_scan
+                    .iter()
+                    .flat_map(|scan| scan.unstaged.clone()),
+            )
+            .collect();
+        let orphans = orphans::find(&loaded_extensions, &staged_ids);
+        let orphan_metadata = loaded_extensions
+            .iter()
+            .filter(|metadata| orphans.contains(&metadata.id))
+            .cloned()
+            .collect::<Vec<_>>();
+        // * Staged extensions replace the loaded versions of themselves, so their dependencies are
+        // * the ones which will be needed.
+        let mut dependencies = db.list_extension_dependencies().await?;
+        for extension in &self.staged_extensions {
+            dependencies.insert(
+                extension.metadata.id.clone(),
+                extension.dependencies.clone(),
+            );
+        }
+        let orphan_dependents = orphans::find_dependents(&orphans, &dependencies);
+        let loaded_hashes = db.list_extension_hashes().await?;
+        let mut snapshot = match self.config.dry_run {
+            true => Some(DatabaseSnapshot::read(db).await?),
+            false => None,
+        };
+        let mut result = LoadReport {
+            dry_run: self.config.dry_run,
+            ..Default::default()
+        };
+
+        // * Override extensions are loaded after base extensions, so the items they target are
+        // * already in the database. Dependencies are still loaded first either way.
+        let (base_extensions, override_extensions): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.staged_extensions)
+                .into_iter()
+                .partition(|extension| !extension.is_override());
+        let (sorted_extensions, dependency_failures) = dependencies::sort(
+            base_extensions
+                .into_iter()
+                .chain(override_extensions)
+                .collect(),
+            &loaded_extensions,
+        );
+        let mut failed_extensions = HashSet::new();
+        for (extension, error) in dependency_failures {
+            error!(
+                "Skipping extension '{}': {error}",
+                extension.metadata.id.unnamespaced()
+            );
+            failed_extensions.insert(extension.metadata.id.clone());
+            result.record(
+                &extension.metadata,
+                ExtensionOutcome::Failed {
+                    error: error.to_string(),
+                },
+                RecordCounts::skipped(&extension),
+                Duration::ZERO,
+            );
+            result.failures.push(LoadFailure {
+                id: extension.metadata.id,
+                reason: LoadFailureReason::Dependency(error),
+            });
+        }
 
-        let mut loaded_extensions = db.list_extensions().await?;
-        let mut conflicts = Vec::new();
-        for staged_extension in self.staged_extensions.into_iter() {
-            let staged_extension_metadata = &staged_extension.metadata;
+        for staged_extension in sorted_extensions {
+            let extension_started = Instant::now();
+            // * The extension is moved into the database when it is loaded, so its metadata is
+            // * copied for the report.
+            let metadata = staged_extension.metadata.clone();
+            let staged_extension_metadata = &metadata;
             let staged_extension_id = staged_extension_metadata.id.unnamespaced().to_owned();
+            let skipped_records = RecordCounts::skipped(&staged_extension);
+            let fail = |result: &mut LoadReport, reason: LoadFailureReason| {
+                result.record(
+                    staged_extension_metadata,
+                    ExtensionOutcome::Failed {
+                        error: reason.to_string(),
+                    },
+                    skipped_records,
+                    extension_started.elapsed(),
+                );
+                result.failures.push(LoadFailure {
+                    id: staged_extension_metadata.id.clone(),
+                    reason,
+                });
+            };
 
-            let Some(conflict) = LoadConflict::new(&staged_extension, &mut loaded_extensions)
-            else {
+            // * Dependencies are always loaded first, but they can still fail to load.
+            if let Some(dependency) = staged_extension
+                .dependencies
+                .keys()
+                .find(|dependency| failed_extensions.contains(*dependency))
+            {
+                let error = DependencyError::Unavailable {
+                    dependency: dependency.clone(),
+                };
+                error!("Skipping extension '{}': {error}", staged_extension_id);
+                failed_extensions.insert(staged_extension_metadata.id.clone());
+                fail(&mut result, LoadFailureReason::Dependency(error));
+                continue;
+            }
+
+            let diagnostics = self
+                .validate_against_database(db, &staged_extension, snapshot.as_ref())
+                .await?;
+            if diagnostics.iter().any(Diagnostic::is_error) {
+                error!(
+                    "Skipping extension '{}' because it is not compatible with the database.",
+                    staged_extension_id
+                );
+                failed_extensions.insert(staged_extension_metadata.id.clone());
+                fail(&mut result, LoadFailureReason::Invalid(diagnostics));
+                continue;
+            }
+
+            let conflict =
+                LoadConflict::new(&staged_extension, &mut loaded_extensions, &loaded_hashes);
+
+            // * Downgrades are refused even when force-reloading, since they are usually caused
+            // * by an old copy of an extension file rather than an intentional rollback.
+            let refused_downgrade = conflict.as_ref().is_some_and(|conflict| {
+                conflict.kind == ConflictKind::Downgrade && !self.config.allow_downgrade
+            });
+            if let (true, Some(conflict)) = (refused_downgrade, &conflict) {
+                error!(
+                    "Skipping extension '{}' because version {} is older than the loaded \
+                    version {}. Use --allow-downgrade to load it anyway.",
+                    staged_extension_id, conflict.new_version, conflict.old_version
+                );
+                // * The newer version stays loaded, so extensions which depend on this one can
+                // * still be loaded against it.
+                fail(
+                    &mut result,
+                    LoadFailureReason::Downgrade {
+                        loaded: conflict.old_version.clone(),
+                        staged: conflict.new_version.clone(),
+                    },
+                );
+            }
+
+            let extension_id = staged_extension_metadata.id.clone();
+            let merger = Merger::new(&self.config, &extension_id);
+            if let Some(snapshot) = &mut snapshot {
+                let action = match &conflict {
+                    None => PlannedAction::Load,
+                    Some(conflict) if conflict.should_reload(&self.config) => PlannedAction::Reload,
+                    Some(_) => PlannedAction::Skip,
+                };
+                let diff = ExtensionDiff::new(
+                    &staged_extension,
+                    conflict.as_ref().map(|conflict| &conflict.old_version),
+                    action,
+                    snapshot,
+                );
+                if !refused_downgrade {
+                    // * The extension is applied to the snapshot instead of the database, so the
+                    // * extensions after it are validated and merged as if it had been loaded.
+                    let merged = match action {
+                        PlannedAction::Skip => Ok(RecordCounts::default()),
+                        _ => Self::record_merge(
+                            snapshot
+                                .apply(&staged_extension, &merger)
+                                .map(|conflicts| (RecordCounts::default(), conflicts))
+                                .map_err(Into::into),
+                            &extension_id,
+                            &mut result,
+                        )?,
+                    };
+                    let outcome = match (&conflict, action) {
+                        (Some(conflict), PlannedAction::Reload) => ExtensionOutcome::Reloaded {
+                            previous_version: conflict.old_version.to_string(),
+                        },
+                        (Some(conflict), PlannedAction::Skip) => ExtensionOutcome::Skipped {
+                            conflict: conflict.kind,
+                        },
+                        _ => ExtensionOutcome::Loaded,
+                    };
+                    match merged {
+                        Ok(records) => result.record(
+                            staged_extension_metadata,
+                            outcome,
+                            records,
+                            extension_started.elapsed(),
+                        ),
+                        Err(reason) => {
+                            failed_extensions.insert(extension_id);
+                            fail(&mut result, reason);
+                        }
+                    }
+                }
+                result.diffs.push(diff);
+                result.conflicts.extend(conflict);
+                continue;
+            }
+
+            let Some(conflict) = conflict else {
                 info!("Loading extension '{}'...", staged_extension_id);
-                db.load_extension(staged_extension).await?;
-                info!("Successfully loaded extension '{}'.", staged_extension_id);
+                let loaded = db.load_extension(staged_extension, &merger).await;
+                match Self::record_merge(loaded, &extension_id, &mut result)? {
+                    Ok(records) => {
+                        info!("Successfully loaded extension '{}'.", staged_extension_id);
+                        result.record(
+                            staged_extension_metadata,
+                            ExtensionOutcome::Loaded,
+                            records,
+                            extension_started.elapsed(),
+                        );
+                    }
+                    Err(reason) => {
+                        failed_extensions.insert(extension_id);
+                        fail(&mut result, reason);
+                    }
+                }
                 continue;
             };
 
-            if self.auto_reload {
-                warn!("Force-reloading extension '{}'...", staged_extension_id);
-                db.reload_extension(staged_extension).await?;
-                info!("Successfully reloaded extension '{}'.", staged_extension_id);
-            } else if conflict.should_reload() {
-                info!("Reloading extension '{}'...", staged_extension_id);
-                db.reload_extension(staged_extension).await?;
-                info!("Successfully reloaded extension '{}'.", staged_extension_id);
+            if refused_downgrade {
+                result.conflicts.push(conflict);
+                continue;
+            }
+
+            if conflict.should_reload(&self.config) {
+                match conflict.kind {
+                    ConflictKind::Unchanged if self.config.auto_reload => {
+                        warn!("Force-reloading extension '{}'...", staged_extension_id)
+                    }
+                    ConflictKind::ContentDrift => warn!(
+                        "Contents of extension '{}' changed without a version bump, reloading...",
+                        staged_extension_id
+                    ),
+                    ConflictKind::Downgrade => warn!(
+                        "Downgrading extension '{}' from version {} to {}...",
+                        staged_extension_id, conflict.old_version, conflict.new_version
+                    ),
+                    _ => info!(
+                        "Reloading extension '{}' (version {} -> {})...",
+                        staged_extension_id, conflict.old_version, conflict.new_version
+                    ),
+                }
+                let reloaded = db.reload_extension(staged_extension, &merger).await;
+                match Self::record_merge(reloaded, &extension_id, &mut result)? {
+                    Ok(records) => {
+                        info!("Successfully reloaded extension '{}'.", staged_extension_id);
+                        result.record(
+                            staged_extension_metadata,
+                            ExtensionOutcome::Reloaded {
+                                previous_version: conflict.old_version.to_string(),
+                            },
+                            records,
+                            extension_started.elapsed(),
+                        );
+                    }
+                    Err(reason) => {
+                        failed_extensions.insert(extension_id);
+                        fail(&mut result, reason);
+                    }
+                }
             } else {
-                info!(
-                    "Skipping extension '{}' because its version has not changed.",
-                    staged_extension_id
+                match conflict.kind {
+                    ConflictKind::ContentDrift => warn!(
+                        "Skipping extension '{}' because its version has not changed, even though \
+                        its contents have. Bump its version or use --reload-on-drift to reload it.",
+                        staged_extension_id
+                    ),
+                    _ => info!(
+                        "Skipping extension '{}' because it has not changed.",
+                        staged_extension_id
+                    ),
+                }
+                result.record(
+                    staged_extension_metadata,
+                    ExtensionOutcome::Skipped {
+                        conflict: conflict.kind,
+                    },
+                    skipped_records,
+                    extension_started.elapsed(),
+                );
+            }
+
+            result.conflicts.push(conflict);
+        }
+
+        if let Some(scan) = self.directory_scan.take() {
+            self.handle_orphans(db, scan, &orphan_metadata, &orphan_dependents, &mut result)
+                .await?;
+            result.orphans = orphans;
+        }
+
+        result.duration = started.elapsed();
+        Ok(result)
+    }
+
+    /// Records the metadata conflicts found while loading an extension.
+    /// Returns the records the extension affected if it was loaded, or the reason it failed if
+    /// the merge policy refused its conflicts. Any other error is returned.
+    fn record_merge(
+        loaded: anyhow::Result<(RecordCounts, Vec<FieldConflict>)>,
+        extension_id: &ExtensionID,
+        result: &mut LoadReport,
+    ) -> anyhow::Result<Result<RecordCounts, LoadFailureReason>> {
+        let (records, conflicts) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                let MergeConflictError(conflicts) = e.downcast::<MergeConflictError>()?;
+                for conflict in &conflicts {
+                    error!("{conflict}");
+                }
+                error!(
+                    "Skipping extension '{}' because its metadata conflicts with existing \
+                    records. Use --merge-policy to choose how conflicts are resolved.",
+                    extension_id.unnamespaced()
                 );
+                return Ok(Err(LoadFailureReason::MergeConflict(conflicts)));
             }
+        };
 
-            conflicts.push(conflict);
+        for conflict in &conflicts {
+            warn!("{conflict}");
         }
+        result.merge_conflicts.extend(conflicts);
+        Ok(Ok(records))
+    }
 
-        Ok(conflicts)
+    /// Applies the orphan policy to loaded extensions whose files have been removed, recording
+    /// whether each one was unloaded. Orphans which other extensions depend on are never unloaded.
+    async fn handle_orphans(
+        &self,
+        db: &Database,
+        scan: DirectoryScan,
+        orphans: &[Metadata],
+        dependents: &HashMap<ExtensionID, ExtensionID>,
+        result: &mut LoadReport,
+    ) -> anyhow::Result<()> {
+        for metadata in orphans {
+            let started = Instant::now();
+            let orphan = &metadata.id;
+            let orphan_id = orphan.unnamespaced();
+            let mut unloaded = false;
+            match self.config.orphans {
+                OrphanPolicy::Keep => info!(
+                    "Keeping extension '{}', which no longer has an extension file.",
+                    orphan_id
+                ),
+                OrphanPolicy::Warn => warn!(
+                    "Extension '{}' is loaded, but no longer has an extension file. Use \
+                    --orphans unload to unload it.",
+                    orphan_id
+                ),
+                OrphanPolicy::Unload if dependents.contains_key(orphan) => warn!(
+                    "Not unloading extension '{}', which no longer has an extension file, \
+                    because extension '{}' depends on it.",
+                    orphan_id,
+                    dependents[orphan].unnamespaced()
+                ),
+                OrphanPolicy::Unload if self.config.dry_run => info!(
+                    "Extension '{}' no longer has an extension file and would be unloaded.",
+                    orphan_id
+                ),
+                // * An invalid file might be the one which defines the extension, so unloading
+                // * it could throw away an extension which is only temporarily broken.
+                OrphanPolicy::Unload if scan.invalid_files > 0 => warn!(
+                    "Not unloading extension '{}', which no longer has an extension file, \
+                    because {} extension file(s) could not be read.",
+                    orphan_id, scan.invalid_files
+                ),
+                OrphanPolicy::Unload => {
+                    info!(
+                        "Unloading extension '{}', which no longer has an extension file...",
+                        orphan_id
+                    );
+                    db.unload_extension(orphan).await?;
+                    info!("Successfully unloaded extension '{}'.", orphan_id);
+                    unloaded = true;
+                }
+            }
+            result.record(
+                metadata,
+                ExtensionOutcome::Orphaned { unloaded },
+                RecordCounts::default(),
+                started.elapsed(),
+            );
+        }
+
+        Ok(())
     }
 
-    /// Checks whether a given filesystem object is a valid extension.
-    fn is_extension(object: &DirEntry) -> bool {
+    /// Checks an extension against the current contents of the database, logging any problems.
+    /// This includes references to manufacturers, categories, and devices which do not exist, parent
+    /// categories which would form a cycle, model identifiers which are already used by other
+    /// devices, and overrides of unknown items.
+    /// In a dry run, the extension is checked against the snapshot instead, which includes what
+    /// the staged extensions before it would have loaded.
+    /// Returns the diagnostics, which prevent the extension from loading if any are errors.
+    async fn validate_against_database(
+        &self,
+        db: &Database,
+        extension: &InventoryExtension,
+        snapshot: Option<&DatabaseSnapshot>,
+    ) -> anyhow::Result<Vec<Diagnostic>> {
+        let mut existing_manufacturers = db
+            .list_device_manufacturers()
+            .await?
+            .into_iter()
+            .map(|m| m.id)
+            .collect::<HashSet<_>>();
+        let mut existing_categories = db
+            .list_device_categories()
+            .await?
+            .into_iter()
+            .map(|c| c.id)
+            .collect::<HashSet<_>>();
+        // * Categories hidden by disabled extensions keep their parents, so they can still form a
+        // * cycle once those extensions are enabled again.
+        // * Devices hidden by overrides or disabled extensions still exist, so parts and overrides
+        // * can refer to them, and their identifiers must not be reused.
+        let (base_categories, existing_devices, custom_fields) = match snapshot {
+            Some(snapshot) => {
+                existing_manufacturers.extend(
+                    snapshot
+                        .staged_items(&snapshot.device_manufacturers)
+                        .map(|m| m.id.clone()),
+                );
+                existing_categories.extend(
+                    snapshot
+                        .staged_items(&snapshot.device_categories)
+                        .map(|c| c.id.clone()),
+                );
+                (
+                    snapshot.device_categories.clone(),
+                    snapshot.devices.clone(),
+                    snapshot.custom_fields.clone(),
+                )
+            }
+            None => (
+                db.list_base_device_categories().await?,
+                db.list_base_devices().await?,
+                db.list_custom_fields().await?,
+            ),
+        };
+        let base_devices = existing_devices.iter().map(|d| d.id.clone()).collect();
+
+        let mut diagnostics = validation::check_references(
+            extension,
+            &existing_manufacturers,
+            &existing_categories,
+            &base_devices,
+        );
+        diagnostics.extend(validation::check_category_hierarchy(
+            extension,
+            &base_categories,
+            &Merger::new(&self.config, &extension.metadata.id),
+        ));
+        if extension.is_override() {
+            diagnostics.extend(overrides::check_targets(
+                extension,
+                &existing_manufacturers,
+                &existing_categories,
+                &base_devices,
+            ));
+        }
+        diagnostics.extend(identifiers::check_database(
+            extension,
+            &existing_devices,
+            self.config.identifier_collisions.with_database,
+        ));
+        diagnostics.extend(custom_fields::check_against_database(
+            extension,
+            &custom_fields,
+        ));
+        for diagnostic in &diagnostics {
+            match diagnostic.is_error() {
+                true => error!(
+                    "Extension '{}': {diagnostic}",
+                    extension.metadata.id.unnamespaced()
+                ),
+                false => warn!(
+                    "Extension '{}': {diagnostic}",
+                    extension.metadata.id.unnamespaced()
+                ),
+            }
+        }
+
+        Ok(diagnostics)
+    }
+
+    /// Checks whether a given filesystem object is a valid extension file or bundle.
+    pub(super) fn is_extension(object: &DirEntry) -> bool {
         let (path, filetype) = (object.path(), object.file_type());
         if let Ok(filetype) = filetype {
-            if filetype.is_file() && path.extension() == Some(OsStr::new("toml")) {
+            if filetype.is_file() && Self::is_extension_path(&path) {
+                return true;
+            }
+            if filetype.is_dir() && bundles::is_bundle_path(&path) {
                 return true;
             }
         }
 
         false
     }
+
+    /// Checks whether a path has the file extension of a supported extension file format.
+    pub(super) fn is_extension_path(path: &Path) -> bool {
+        formats::for_path(path).is_some()
+    }
 }
 
-// TODO: Remove unwraps
-// * Inner types here ([`DeviceManufacturer`], [`DeviceCategory`], [`Device`]) must be
-// * converted with context provided by the [`ExtensionToml`] itself, so they cannot be converted
-// * directly.
-impl From<InventoryExtensionToml> for InventoryExtension {
-    fn from(toml: InventoryExtensionToml) -> Self {
+// * Inner types here ([`DeviceManufacturer`], [`DeviceCategory`], [`Device`], [`Part`],
+// * [`RepairService`]) must be converted with context provided by the [`ExtensionToml`] itself, so
+// * they cannot be converted directly.
+impl TryFrom<InventoryExtensionToml> for InventoryExtension {
+    type Error = anyhow::Error;
+    fn try_from(toml: InventoryExtensionToml) -> Result<Self, Self::Error> {
+        let extension_id = ExtensionID::new(&toml.extension.id);
         let device_manufacturers = toml
             .device_manufacturers
             .unwrap_or_default()
@@ -180,7 +1048,8 @@ impl From<InventoryExtensionToml> for InventoryExtension {
             .map(|m| DeviceManufacturer {
                 id: DeviceManufacturerUniqueID::new(&m.id),
                 display_name: m.display_name,
-                extensions: HashSet::from([ExtensionID::new(&toml.extension_id)]),
+                extensions: HashSet::from([extension_id.clone()]),
+                custom_fields: m.custom_fields,
             })
             .collect();
 
@@ -191,7 +1060,9 @@ impl From<InventoryExtensionToml> for InventoryExtension {
             .map(|c| DeviceCategory {
                 id: DeviceCategoryUniqueID::new(&c.id),
                 display_name: c.display_name,
-                extensions: HashSet::from([ExtensionID::new(&toml.extension_id)]),
+                parent: c.parent.as_deref().map(DeviceCategoryUniqueID::new),
+                extensions: HashSet::from([extension_id.clone()]),
+                custom_fields: c.custom_fields,
             })
             .collect();
 
@@ -204,21 +1075,97 @@ impl From<InventoryExtensionToml> for InventoryExtension {
                 display_name: d.display_name,
                 manufacturer: DeviceManufacturerUniqueID::new(&d.manufacturer),
                 category: DeviceCategoryUniqueID::new(&d.category),
-                extensions: HashSet::from([ExtensionID::new(&toml.extension_id)]),
+                extensions: HashSet::from([extension_id.clone()]),
                 primary_model_identifiers: d.primary_model_identifiers,
                 extended_model_identifiers: d.extended_model_identifiers,
+                variants: d.variants,
+                custom_fields: d.custom_fields,
             })
             .collect();
 
-        InventoryExtension {
+        let parts = toml
+            .parts
+            .unwrap_or_default()
+            .into_iter()
+            .map(|p| Part {
+                id: PartUniqueID::new(&p.id),
+                display_name: p.display_name,
+                kind: p.kind,
+                supplier_skus: p.supplier_skus,
+                compatible_devices: p
+                    .compatible_devices
+                    .iter()
+                    .map(DeviceUniqueID::new)
+                    .collect(),
+                extensions: HashSet::from([extension_id.clone()]),
+            })
+            .collect();
+
+        let repair_services = toml
+            .repair_services
+            .unwrap_or_default()
+            .into_iter()
+            .map(|s| RepairService {
+                id: RepairServiceUniqueID::new(&s.id),
+                display_name: s.display_name,
+                default_labor_minutes: s.default_labor_minutes,
+                default_price: s.default_price,
+                required_part_kinds: s.required_part_kinds,
+                device_categories: s
+                    .device_categories
+                    .iter()
+                    .map(DeviceCategoryUniqueID::new)
+                    .collect(),
+                devices: s.devices.iter().map(DeviceUniqueID::new).collect(),
+                extensions: HashSet::from([extension_id.clone()]),
+            })
+            .collect();
+
+        let custom_fields = toml
+            .custom_fields
+            .unwrap_or_default()
+            .into_iter()
+            .map(CustomFieldDefinition::try_from)
+            .collect::<anyhow::Result<_>>()?;
+
+        let overrides = toml
+            .overrides
+            .unwrap_or_default()
+            .into_iter()
+            .map(Override::try_from)
+            .collect::<anyhow::Result<_>>()?;
+
+        let dependencies = toml
+            .extension
+            .dependencies
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(id, requirement)| Ok((ExtensionID::new(id), VersionReq::parse(&requirement)?)))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(InventoryExtension {
             metadata: Metadata {
-                id: ExtensionID::new(&toml.extension_id),
-                display_name: toml.extension_display_name,
-                version: Version::from_str(&toml.extension_version).unwrap(),
+                id: extension_id,
+                display_name: toml.extension.display_name,
+                version: Version::parse(&toml.extension.version)?,
+                author: toml.extension.author,
+                license: toml.extension.license,
+                description: toml.extension.description,
+                homepage: toml.extension.homepage,
+                techtriage: toml
+                    .extension
+                    .techtriage
+                    .map(|requirement| VersionReq::parse(&requirement))
+                    .transpose()?,
             },
+            dependencies,
+            custom_fields,
             device_manufacturers,
             device_categories,
             devices,
-        }
+            parts,
+            repair_services,
+            overrides,
+        })
     }
 }
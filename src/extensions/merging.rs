@@ -0,0 +1,401 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use super::manager::ExtensionManagerConfig;
+use super::ExtensionID;
+use crate::models::common::{
+    CustomFields, Device, DeviceCategory, DeviceManufacturer, DeviceVariant, Part, RepairService,
+    UniqueID,
+};
+
+/// How to reconcile an item which is defined by more than one extension with different metadata.
+/// Lists such as model identifiers, supplier SKUs, and the devices a part or service applies to,
+/// and custom fields and variants which only one extension defines, are always combined, so this
+/// only applies to the other fields.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum MergePolicy {
+    /// The extension which is loaded last overrides the existing metadata.
+    #[default]
+    LastWins,
+    /// The existing metadata is kept, and the extension which is loaded last is overridden.
+    FirstWins,
+    /// The extension which is listed first in the extension priority order wins.
+    /// Extensions which are not listed have the lowest priority, and ties keep the existing
+    /// metadata.
+    Priority,
+    /// Extensions with conflicting metadata fail to load.
+    Error,
+}
+
+/// How a single conflicting field was resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    /// The metadata in the database was kept, overriding the staged extension.
+    KeptExisting,
+    /// The staged extension overrode the metadata in the database.
+    UsedStaged,
+    /// The staged extension was refused.
+    Refused,
+}
+
+/// A field of an item which two extensions define with different values.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldConflict {
+    pub item: &'static str,
+    pub id: String,
+    pub field: &'static str,
+    /// The value in the database before the staged extension was loaded.
+    pub existing: String,
+    /// The extension which provided the value in the database.
+    /// If several extensions provide the item, this is the one with the highest priority.
+    pub existing_extension: String,
+    pub staged: String,
+    pub staged_extension: String,
+    pub resolution: Resolution,
+}
+
+/// Error returned when an extension cannot be loaded under the [`MergePolicy::Error`] policy.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MergeConflictError(pub Vec<FieldConflict>);
+
+/// Reconciles items from a staged extension with the existing records in the database.
+pub struct Merger<'a> {
+    policy: MergePolicy,
+    /// The extensions in order of priority, highest first.
+    priority: &'a [ExtensionID],
+    /// The extension being loaded.
+    extension: &'a ExtensionID,
+}
+
+/// The conflicts found while merging a single item.
+struct ItemMerge {
+    item: &'static str,
+    id: String,
+    existing_extension: Option<ExtensionID>,
+    staged_extension: ExtensionID,
+    resolution: Resolution,
+    conflicts: Vec<FieldConflict>,
+}
+
+impl<'a> Merger<'a> {
+    /// Creates a merger for loading the given extension with the configured policy.
+    pub fn new(config: &'a ExtensionManagerConfig, extension: &'a ExtensionID) -> Self {
+        Self {
+            policy: config.merge_policy,
+            priority: &config.extension_priority,
+            extension,
+        }
+    }
+
+    /// Merges the existing record of a device manufacturer into the staged one.
+    pub fn device_manufacturer(
+        &self,
+        staged: &mut DeviceManufacturer,
+        existing: DeviceManufacturer,
+    ) -> Vec<FieldConflict> {
+        let mut merge = self.start(
+            "device manufacturer",
+            staged.id.unnamespaced(),
+            &existing.extensions,
+        );
+        merge.field(
+            "display_name",
+            &mut staged.display_name,
+            existing.display_name.clone(),
+            String::clone,
+        );
+        merge.custom_fields(&mut staged.custom_fields, &existing.custom_fields);
+
+        if merge.existing_extension.is_some() {
+            staged.merge(existing);
+        }
+        merge.conflicts
+    }
+
+    /// Merges the existing record of a device category into the staged one.
+    pub fn device_category(
+        &self,
+        staged: &mut DeviceCategory,
+        existing: DeviceCategory,
+    ) -> Vec<FieldConflict> {
+        let mut merge = self.start(
+            "device category",
+            staged.id.unnamespaced(),
+            &existing.extensions,
+        );
+        merge.field(
+            "display_name",
+            &mut staged.display_name,
+            existing.display_name.clone(),
+            String::clone,
+        );
+        merge.field(
+            "parent",
+            &mut staged.parent,
+            existing.parent.clone(),
+            |id| {
+                id.as_ref()
+                    .map_or_else(String::new, |id| id.unnamespaced().to_owned())
+            },
+        );
+        merge.custom_fields(&mut staged.custom_fields, &existing.custom_fields);
+
+        if merge.existing_extension.is_some() {
+            staged.merge(existing);
+        }
+        merge.conflicts
+    }
+
+    /// Merges the existing record of a device into the staged one.
+    pub fn device(&self, staged: &mut Device, existing: Device) -> Vec<FieldConflict> {
+        let mut merge = self.start("device", staged.id.unnamespaced(), &existing.extensions);
+        merge.field(
+            "display_name",
+            &mut staged.display_name,
+            existing.display_name.clone(),
+            String::clone,
+        );
+        merge.field(
+            "manufacturer",
+            &mut staged.manufacturer,
+            existing.manufacturer.clone(),
+            |id| id.unnamespaced().to_owned(),
+        );
+        merge.field(
+            "category",
+            &mut staged.category,
+            existing.category.clone(),
+            |id| id.unnamespaced().to_owned(),
+        );
+        merge.variants(&mut staged.variants, &existing.variants);
+        merge.custom_fields(&mut staged.custom_fields, &existing.custom_fields);
+
+        if merge.existing_extension.is_some() {
+            staged.merge(existing);
+        }
+        merge.conflicts
+    }
+
+    /// Merges the existing record of a part into the staged one.
+    pub fn part(&self, staged: &mut Part, existing: Part) -> Vec<FieldConflict> {
+        let mut merge = self.start("part", staged.id.unnamespaced(), &existing.extensions);
+        merge.field(
+            "display_name",
+            &mut staged.display_name,
+            existing.display_name.clone(),
+            String::clone,
+        );
+        merge.field("kind", &mut staged.kind, existing.kind, |kind| {
+            kind.name().to_owned()
+        });
+
+        if merge.existing_extension.is_some() {
+            staged.merge(existing);
+        }
+        merge.conflicts
+    }
+
+    /// Merges the existing record of a repair service into the staged one.
+    pub fn repair_service(
+        &self,
+        staged: &mut RepairService,
+        existing: RepairService,
+    ) -> Vec<FieldConflict> {
+        let mut merge = self.start(
+            "repair service",
+            staged.id.unnamespaced(),
+            &existing.extensions,
+        );
+        merge.field(
+            "display_name",
+            &mut staged.display_name,
+            existing.display_name.clone(),
+            String::clone,
+        );
+        merge.field(
+            "default_labor_minutes",
+            &mut staged.default_labor_minutes,
+            existing.default_labor_minutes,
+            u32::to_string,
+        );
+        merge.field(
+            "default_price",
+            &mut staged.default_price,
+            existing.default_price,
+            u64::to_string,
+        );
+
+        if merge.existing_extension.is_some() {
+            staged.merge(existing);
+        }
+        merge.conflicts
+    }
+
+    /// Decides how conflicts with an existing record are resolved, based on the extensions which
+    /// already provide it.
+    // * The staged extension may already provide the record if it is being reloaded. Its own
+    // * contents are replaced rather than merged, so it is not treated as an existing provider.
+    fn start(&self, item: &'static str, id: &str, existing: &HashSet<ExtensionID>) -> ItemMerge {
+        let existing_extension = existing
+            .iter()
+            .filter(|extension| *extension != self.extension)
+            .min_by_key(|extension| (self.rank(extension), extension.unnamespaced()))
+            .cloned();
+
+        let resolution = match self.policy {
+            MergePolicy::LastWins => Resolution::UsedStaged,
+            MergePolicy::FirstWins => Resolution::KeptExisting,
+            MergePolicy::Priority => match &existing_extension {
+                Some(existing) if self.rank(self.extension) < self.rank(existing) => {
+                    Resolution::UsedStaged
+                }
+                _ => Resolution::KeptExisting,
+            },
+            MergePolicy::Error => Resolution::Refused,
+        };
+
+        ItemMerge {
+            item,
+            id: id.to_owned(),
+            existing_extension,
+            staged_extension: self.extension.clone(),
+            resolution,
+            conflicts: Vec::new(),
+        }
+    }
+
+    /// Gets the position of an extension in the priority order, where lower is higher priority.
+    fn rank(&self, extension: &ExtensionID) -> usize {
+        self.priority
+            .iter()
+            .position(|e| e == extension)
+            .unwrap_or(self.priority.len())
+    }
+}
+
+impl ItemMerge {
+    /// Reconciles a single field, recording a conflict if the values differ.
+    fn field<T: PartialEq>(
+        &mut self,
+        field: &'static str,
+        staged: &mut T,
+        existing: T,
+        display: impl Fn(&T) -> String,
+    ) {
+        let Some(existing_extension) = &self.existing_extension else {
+            return;
+        };
+        if *staged == existing {
+            return;
+        }
+
+        self.conflicts.push(FieldConflict {
+            item: self.item,
+            id: self.id.clone(),
+            field,
+            existing: display(&existing),
+            existing_extension: existing_extension.unnamespaced().to_owned(),
+            staged: display(staged),
+            staged_extension: self.staged_extension.unnamespaced().to_owned(),
+            resolution: self.resolution,
+        });
+        if self.resolution == Resolution::KeptExisting {
+            *staged = existing;
+        }
+    }
+
+    /// Reconciles each variant which both the staged and existing device define.
+    /// Variants which only one of them has are combined when the devices are merged.
+    fn variants(&mut self, staged: &mut [DeviceVariant], existing: &[DeviceVariant]) {
+        for existing_variant in existing {
+            if let Some(staged_variant) = staged.iter_mut().find(|v| v.id == existing_variant.id) {
+                self.field(
+                    "variants",
+                    staged_variant,
+                    existing_variant.clone(),
+                    |variant| {
+                        format!(
+                            "{} = {variant} ({})",
+                            variant.id,
+                            variant.model_identifiers.join(", ")
+                        )
+                    },
+                );
+            }
+        }
+    }
+
+    /// Reconciles each custom field which both the staged and existing item have a value for.
+    /// Fields which only one of them has are combined when the items are merged.
+    fn custom_fields(&mut self, staged: &mut CustomFields, existing: &CustomFields) {
+        for (id, existing_value) in existing {
+            if let Some(staged_value) = staged.get_mut(id) {
+                self.field(
+                    "custom_fields",
+                    staged_value,
+                    existing_value.clone(),
+                    |value| format!("{id} = {value}"),
+                );
+            }
+        }
+    }
+}
+
+impl fmt::Display for FieldConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (kept, kept_extension, overridden, overridden_extension) = match self.resolution {
+            Resolution::KeptExisting => (
+                &self.existing,
+                &self.existing_extension,
+                &self.staged,
+                &self.staged_extension,
+            ),
+            Resolution::UsedStaged => (
+                &self.staged,
+                &self.staged_extension,
+                &self.existing,
+                &self.existing_extension,
+            ),
+            Resolution::Refused => {
+                return write!(
+                    f,
+                    "{} '{}' has {} \"{}\" from extension '{}', but \"{}\" from extension '{}'",
+                    self.item,
+                    self.id,
+                    self.field,
+                    self.existing,
+                    self.existing_extension,
+                    self.staged,
+                    self.staged_extension
+                );
+            }
+        };
+
+        write!(
+            f,
+            "{} '{}': {} \"{overridden}\" from extension '{overridden_extension}' was overridden \
+            by \"{kept}\" from extension '{kept_extension}'",
+            self.item, self.id, self.field
+        )
+    }
+}
+
+impl fmt::Display for MergeConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Metadata conflicts with existing records: {}",
+            self.0
+                .iter()
+                .map(FieldConflict::to_string)
+                .collect::<Vec<_>>()
+                .join("; ")
+        )
+    }
+}
+
+impl std::error::Error for MergeConflictError {}
@@ -1,9 +1,37 @@
+mod bundles;
 mod conflicts;
+mod custom_fields;
+mod dependencies;
+mod diff;
+mod export;
+mod formats;
+mod hashing;
+mod identifiers;
 mod manager;
+mod merging;
+mod orphans;
+mod overrides;
+mod report;
 #[cfg(test)]
 mod tests;
+mod validation;
+mod versions;
+mod watcher;
 
-pub use manager::{ExtensionManager, InventoryExtension};
+pub use bundles::{parse_signing_key, sign as sign_bundle, UnsignedPolicy};
+pub use conflicts::ConflictKind;
+pub use custom_fields::is_valid_id as is_valid_custom_field_id;
+pub use export::export;
+pub use identifiers::{CollisionSeverities, CollisionSeverity};
+pub use manager::{
+    ExtensionManager, ExtensionManagerConfig, InventoryExtension, EXTENSIONS_DIRECTORY,
+};
+pub use merging::{FieldConflict, MergeConflictError, MergePolicy, Merger, Resolution};
+pub use orphans::OrphanPolicy;
+pub use overrides::apply as apply_overrides;
+pub use report::{LoadReport, RecordCounts};
+pub use validation::{Severity, ValidationReport};
+pub use watcher::ExtensionWatcher;
 
 use self::manager::InventoryExtension as Extension;
 use crate::models::common::{
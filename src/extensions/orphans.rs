@@ -0,0 +1,68 @@
+use std::collections::{HashMap, HashSet};
+
+use clap::ValueEnum;
+
+use super::{ExtensionID, Metadata};
+use crate::models::common::Dependencies;
+
+/// What to do with loaded extensions whose files are no longer in the extensions directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OrphanPolicy {
+    /// Leave the extension loaded.
+    Keep,
+    /// Leave the extension loaded, but log a warning.
+    #[default]
+    Warn,
+    /// Unload the extension and its contents.
+    Unload,
+}
+
+/// The outcome of reading every file in the extensions directory.
+/// Orphans can only be detected when the staged extensions came from a full scan, since otherwise
+/// every extension which was not staged would look orphaned.
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryScan {
+    /// The number of extension files which could not be parsed.
+    /// The IDs of these extensions are unknown, so they might not really be orphaned.
+    pub invalid_files: usize,
+    /// Extensions which were read but could not be staged, such as extensions which do not
+    /// support the running version. Their files still exist, so they are not orphaned.
+    pub unstaged: HashSet<ExtensionID>,
+}
+
+/// Finds the loaded extensions which were not staged.
+pub fn find(loaded: &[Metadata], staged: &HashSet<ExtensionID>) -> Vec<ExtensionID> {
+    loaded
+        .iter()
+        .filter(|metadata| !staged.contains(&metadata.id))
+        .map(|metadata| metadata.id.clone())
+        .collect()
+}
+
+/// Finds the orphans which must stay loaded because other extensions depend on them, along with
+/// an extension which depends on each one.
+/// Orphans which are only needed by other orphans can be unloaded along with them, but an orphan
+/// which stays loaded keeps its own dependencies loaded as well.
+pub fn find_dependents(
+    orphans: &[ExtensionID],
+    dependencies: &HashMap<ExtensionID, Dependencies>,
+) -> HashMap<ExtensionID, ExtensionID> {
+    let mut kept = HashMap::new();
+    loop {
+        let mut changed = false;
+        for (dependent, requirements) in dependencies {
+            if orphans.contains(dependent) && !kept.contains_key(dependent) {
+                continue;
+            }
+            for dependency in requirements.keys() {
+                if orphans.contains(dependency) && !kept.contains_key(dependency) {
+                    kept.insert(dependency.clone(), dependent.clone());
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return kept;
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+
+use super::manager::{
+    InventoryExtensionToml, OverrideOperationToml, OverrideTargetToml, OverrideToml,
+};
+use super::validation::{Diagnostic, FieldPath};
+use super::Extension;
+use crate::models::common::{
+    Device, DeviceCategory, DeviceCategoryUniqueID, DeviceManufacturer, DeviceManufacturerUniqueID,
+    DeviceUniqueID, Override, OverrideField, OverrideOperation, OverrideTarget, UniqueID,
+};
+
+/// An item which overrides can be applied to.
+pub trait Overridable: Sized {
+    /// Checks whether the override applies to this item.
+    fn is_target(&self, target: &OverrideTarget) -> bool;
+
+    /// Applies a single override to this item.
+    /// Returns `None` if the override hides the item.
+    fn apply(self, operation: &OverrideOperation) -> Option<Self>;
+}
+
+/// Applies overrides to a list of items, in order.
+/// Overrides which target items that are not in the list are ignored.
+pub fn apply<T: Overridable>(items: Vec<T>, overrides: &[Override]) -> Vec<T> {
+    items
+        .into_iter()
+        .filter_map(|mut item| {
+            for o in overrides {
+                if item.is_target(&o.target) {
+                    item = item.apply(&o.operation)?;
+                }
+            }
+            Some(item)
+        })
+        .collect()
+}
+
+impl Overridable for DeviceManufacturer {
+    fn is_target(&self, target: &OverrideTarget) -> bool {
+        matches!(target, OverrideTarget::DeviceManufacturer(id) if *id == self.id)
+    }
+
+    fn apply(mut self, operation: &OverrideOperation) -> Option<Self> {
+        if let OverrideOperation::Replace {
+            field: OverrideField::DisplayName,
+            value,
+        } = operation
+        {
+            self.display_name = value.clone();
+        }
+        Some(self)
+    }
+}
+
+impl Overridable for DeviceCategory {
+    fn is_target(&self, target: &OverrideTarget) -> bool {
+        matches!(target, OverrideTarget::DeviceCategory(id) if *id == self.id)
+    }
+
+    fn apply(mut self, operation: &OverrideOperation) -> Option<Self> {
+        if let OverrideOperation::Replace {
+            field: OverrideField::DisplayName,
+            value,
+        } = operation
+        {
+            self.display_name = value.clone();
+        }
+        Some(self)
+    }
+}
+
+impl Overridable for Device {
+    fn is_target(&self, target: &OverrideTarget) -> bool {
+        matches!(target, OverrideTarget::Device(id) if *id == self.id)
+    }
+
+    fn apply(mut self, operation: &OverrideOperation) -> Option<Self> {
+        match operation {
+            OverrideOperation::Hide => return None,
+            OverrideOperation::Replace { field, value } => match field {
+                OverrideField::DisplayName => self.display_name = value.clone(),
+                OverrideField::Manufacturer => {
+                    self.manufacturer = DeviceManufacturerUniqueID::new(value)
+                }
+                OverrideField::Category => self.category = DeviceCategoryUniqueID::new(value),
+                // * Lists cannot be replaced, which is enforced when overrides are parsed.
+                _ => {}
+            },
+            OverrideOperation::Add { field, value } => {
+                let identifiers = self.identifiers_mut(*field);
+                if !identifiers.contains(value) {
+                    identifiers.push(value.clone());
+                }
+            }
+            OverrideOperation::Remove { field, value } => {
+                self.identifiers_mut(*field).retain(|v| v != value);
+            }
+        }
+        Some(self)
+    }
+}
+
+impl Device {
+    /// Gets the list of model identifiers which an override field refers to.
+    fn identifiers_mut(&mut self, field: OverrideField) -> &mut Vec<String> {
+        match field {
+            OverrideField::PrimaryModelIdentifiers => &mut self.primary_model_identifiers,
+            _ => &mut self.extended_model_identifiers,
+        }
+    }
+}
+
+/// Interprets the operation of an override as read from an extension file.
+/// Returns a message describing the problem if the combination of target, operation, field, and
+/// value is not supported.
+fn parse_operation(
+    target: OverrideTargetToml,
+    operation: OverrideOperationToml,
+    field: Option<&str>,
+    value: Option<&str>,
+) -> Result<OverrideOperation, String> {
+    let is_device = target == OverrideTargetToml::Device;
+    let field = match field {
+        Some(name) => Some(
+            OverrideField::from_name(name)
+                .ok_or_else(|| format!("Unknown override field '{name}'"))?,
+        ),
+        None => None,
+    };
+
+    let (field, value) = match (operation, field, value) {
+        (OverrideOperationToml::Remove, None, None) if is_device => {
+            return Ok(OverrideOperation::Hide)
+        }
+        (OverrideOperationToml::Remove, None, None) => {
+            return Err("Only devices can be hidden".to_owned())
+        }
+        (_, None, _) => return Err("Override must have a field".to_owned()),
+        (_, Some(_), None) => return Err("Override must have a value".to_owned()),
+        (_, Some(field), Some(value)) => (field, value.to_owned()),
+    };
+
+    if !is_device && field != OverrideField::DisplayName {
+        return Err(format!(
+            "Field '{}' can only be overridden on devices",
+            field.name()
+        ));
+    }
+    match (operation, field.is_list()) {
+        (OverrideOperationToml::Replace, true) => Err(format!(
+            "Field '{}' is a list, so values must be added or removed instead",
+            field.name()
+        )),
+        (OverrideOperationToml::Add | OverrideOperationToml::Remove, false) => Err(format!(
+            "Field '{}' is not a list, so it must be replaced instead",
+            field.name()
+        )),
+        (OverrideOperationToml::Add, true) => Ok(OverrideOperation::Add { field, value }),
+        (OverrideOperationToml::Remove, true) => Ok(OverrideOperation::Remove { field, value }),
+        (OverrideOperationToml::Replace, false) => Ok(OverrideOperation::Replace { field, value }),
+    }
+}
+
+/// Checks the overrides in a parsed extension file.
+/// Extensions with overrides cannot provide any items of their own, so that each extension is
+/// clearly either a base extension or an override extension.
+pub(super) fn validate(extension: &InventoryExtensionToml) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let Some(overrides) = &extension.overrides else {
+        return diagnostics;
+    };
+
+    let has_items = !extension.devices.is_empty()
+        || extension
+            .device_manufacturers
+            .as_ref()
+            .is_some_and(|m| !m.is_empty())
+        || extension
+            .device_categories
+            .as_ref()
+            .is_some_and(|c| !c.is_empty())
+        || extension.parts.as_ref().is_some_and(|p| !p.is_empty())
+        || extension
+            .repair_services
+            .as_ref()
+            .is_some_and(|s| !s.is_empty());
+    if has_items {
+        diagnostics.push(Diagnostic::error(
+            FieldPath::key("overrides"),
+            "Extensions with overrides cannot also define device manufacturers, categories, \
+            devices, parts, or repair services",
+        ));
+    }
+
+    for (i, o) in overrides.iter().enumerate() {
+        let field = FieldPath::element("overrides", i);
+        if o.id.trim().is_empty() {
+            diagnostics.push(Diagnostic::error(
+                field.clone().then("id"),
+                "Override target ID must not be empty",
+            ));
+        }
+        if let Err(message) = parse_operation(
+            o.target,
+            o.operation,
+            o.field.as_deref(),
+            o.value.as_deref(),
+        ) {
+            diagnostics.push(Diagnostic::error(field.then("operation"), message));
+        }
+    }
+
+    diagnostics
+}
+
+/// Checks that every item targeted by the extension's overrides exists among the given records
+/// already in the database, along with any manufacturer or category which a device is moved to.
+/// Missing targets are only warned about, since the override takes effect once they are loaded.
+pub fn check_targets(
+    extension: &Extension,
+    existing_manufacturers: &HashSet<DeviceManufacturerUniqueID>,
+    existing_categories: &HashSet<DeviceCategoryUniqueID>,
+    existing_devices: &HashSet<DeviceUniqueID>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (i, o) in extension.overrides.iter().enumerate() {
+        let field = FieldPath::element("overrides", i);
+        let (kind, id, exists) = match &o.target {
+            OverrideTarget::DeviceManufacturer(id) => (
+                "device manufacturer",
+                id.unnamespaced(),
+                existing_manufacturers.contains(id),
+            ),
+            OverrideTarget::DeviceCategory(id) => (
+                "device category",
+                id.unnamespaced(),
+                existing_categories.contains(id),
+            ),
+            OverrideTarget::Device(id) => {
+                ("device", id.unnamespaced(), existing_devices.contains(id))
+            }
+        };
+        if !exists {
+            diagnostics.push(Diagnostic::warning(
+                field.clone().then("id"),
+                format!("Override targets unknown {kind} '{id}'"),
+            ));
+        }
+
+        match &o.operation {
+            OverrideOperation::Replace {
+                field: OverrideField::Manufacturer,
+                value,
+            } if !existing_manufacturers.contains(&DeviceManufacturerUniqueID::new(value)) => {
+                diagnostics.push(Diagnostic::error(
+                    field.then("value"),
+                    format!("Override references unknown device manufacturer '{value}'"),
+                ));
+            }
+            OverrideOperation::Replace {
+                field: OverrideField::Category,
+                value,
+            } if !existing_categories.contains(&DeviceCategoryUniqueID::new(value)) => {
+                diagnostics.push(Diagnostic::error(
+                    field.then("value"),
+                    format!("Override references unknown device category '{value}'"),
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    diagnostics
+}
+
+impl TryFrom<OverrideToml> for Override {
+    type Error = anyhow::Error;
+    fn try_from(toml: OverrideToml) -> Result<Self, Self::Error> {
+        let operation = parse_operation(
+            toml.target,
+            toml.operation,
+            toml.field.as_deref(),
+            toml.value.as_deref(),
+        )
+        .map_err(|message| anyhow!(message))?;
+        let target = match toml.target {
+            OverrideTargetToml::DeviceManufacturer => {
+                OverrideTarget::DeviceManufacturer(DeviceManufacturerUniqueID::new(toml.id))
+            }
+            OverrideTargetToml::DeviceCategory => {
+                OverrideTarget::DeviceCategory(DeviceCategoryUniqueID::new(toml.id))
+            }
+            OverrideTargetToml::Device => OverrideTarget::Device(DeviceUniqueID::new(toml.id)),
+        };
+
+        Ok(Override { target, operation })
+    }
+}
+
+impl Extension {
+    /// Checks whether this is an override extension, which patches items provided by other
+    /// extensions instead of providing its own.
+    pub fn is_override(&self) -> bool {
+        !self.overrides.is_empty()
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt;
+use std::ops::AddAssign;
+use std::time::Duration;
+
+use log::{error, info};
+use serde::{Serialize, Serializer};
+
+use super::conflicts::{ConflictKind, LoadConflict, LoadFailure};
+use super::diff::ExtensionDiff;
+use super::merging::FieldConflict;
+use super::{Extension, ExtensionID, Metadata};
+use crate::models::common::UniqueID;
+
+/// The outcome of loading all staged extensions into the database.
+// * Conflicts, failures, and orphans are serialized through the outcome of each extension.
+#[derive(Debug, Default, Serialize)]
+pub struct LoadReport {
+    /// Whether the database was left unchanged, in which case the outcomes are what would have
+    /// happened.
+    pub dry_run: bool,
+    /// What happened to each staged or orphaned extension, in the order they were handled.
+    pub extensions: Vec<ExtensionReport>,
+    #[serde(skip)]
+    pub conflicts: Vec<LoadConflict>,
+    #[serde(skip)]
+    pub failures: Vec<LoadFailure>,
+    /// A preview of the changes to each extension, only populated in dry-run mode.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub diffs: Vec<ExtensionDiff>,
+    /// Loaded extensions whose files are no longer in the extensions directory.
+    #[serde(skip)]
+    pub orphans: Vec<ExtensionID>,
+    /// Every field which was overridden while merging loaded extensions with existing records.
+    pub merge_conflicts: Vec<FieldConflict>,
+    /// How long loading took in total.
+    #[serde(rename = "duration_ms", serialize_with = "serialize_millis")]
+    pub duration: Duration,
+}
+
+/// What happened to a single extension while loading.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExtensionReport {
+    pub id: String,
+    /// The version of the staged extension, or the loaded version for orphaned extensions.
+    pub version: String,
+    #[serde(flatten)]
+    pub outcome: ExtensionOutcome,
+    /// The records the extension created or merged, or the records which were skipped because
+    /// the extension was not loaded. Records are not counted in dry-run mode.
+    pub records: RecordCounts,
+    /// How long it took to handle the extension.
+    #[serde(rename = "duration_ms", serialize_with = "serialize_millis")]
+    pub duration: Duration,
+}
+
+/// How loading a single extension ended.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum ExtensionOutcome {
+    /// The extension was not loaded before, and has been loaded.
+    Loaded,
+    /// A different version or revision of the extension was loaded, and has been replaced.
+    Reloaded { previous_version: String },
+    /// The extension is already loaded, and was left as it is.
+    Skipped { conflict: ConflictKind },
+    /// The extension could not be loaded.
+    Failed { error: String },
+    /// The extension is loaded, but no longer has an extension file.
+    Orphaned { unloaded: bool },
+}
+
+/// The number of records in each table which an extension affected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RecordCounts {
+    pub device_manufacturers: TableCounts,
+    pub device_categories: TableCounts,
+    pub devices: TableCounts,
+    pub overrides: TableCounts,
+    pub custom_fields: TableCounts,
+    pub parts: TableCounts,
+    pub repair_services: TableCounts,
+}
+
+/// The number of records in one table which an extension affected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TableCounts {
+    /// Records which did not exist, or only belonged to the extension itself.
+    pub created: usize,
+    /// Existing records from other extensions which the extension's records were merged into.
+    pub merged: usize,
+    /// Records which were not written because the extension was skipped or failed to load.
+    pub skipped: usize,
+}
+
+impl LoadReport {
+    /// Records what happened to a staged extension.
+    pub fn record(
+        &mut self,
+        metadata: &Metadata,
+        outcome: ExtensionOutcome,
+        records: RecordCounts,
+        duration: Duration,
+    ) {
+        self.extensions.push(ExtensionReport {
+            id: metadata.id.unnamespaced().to_owned(),
+            version: metadata.version.to_string(),
+            outcome,
+            records,
+            duration,
+        });
+    }
+
+    /// Logs a summary with one line per extension, followed by the totals.
+    pub fn log_summary(&self) {
+        let mut totals = [0; 5];
+        let mut records = TableCounts::default();
+        for extension in &self.extensions {
+            let index = match extension.outcome {
+                ExtensionOutcome::Loaded => 0,
+                ExtensionOutcome::Reloaded { .. } => 1,
+                ExtensionOutcome::Skipped { .. } => 2,
+                ExtensionOutcome::Failed { .. } => 3,
+                ExtensionOutcome::Orphaned { .. } => 4,
+            };
+            totals[index] += 1;
+            records += extension.records.total();
+
+            match extension.outcome {
+                ExtensionOutcome::Failed { .. } => error!("{extension}"),
+                _ => info!("{extension}"),
+            }
+        }
+
+        let [loaded, reloaded, skipped, failed, orphaned] = totals;
+        info!(
+            "{}{loaded} loaded, {reloaded} reloaded, {skipped} skipped, {failed} failed, and \
+            {orphaned} orphaned extension(s) in {} ms. {records}.",
+            match self.dry_run {
+                true => "Dry run: ",
+                false => "",
+            },
+            self.duration.as_millis(),
+        );
+    }
+}
+
+impl RecordCounts {
+    /// Counts every record of an extension which was not loaded as skipped.
+    pub fn skipped(extension: &Extension) -> Self {
+        let skipped = |skipped| TableCounts {
+            skipped,
+            ..Default::default()
+        };
+        RecordCounts {
+            device_manufacturers: skipped(extension.device_manufacturers.len()),
+            device_categories: skipped(extension.device_categories.len()),
+            devices: skipped(extension.devices.len()),
+            overrides: skipped(extension.overrides.len()),
+            custom_fields: skipped(extension.custom_fields.len()),
+            parts: skipped(extension.parts.len()),
+            repair_services: skipped(extension.repair_services.len()),
+        }
+    }
+
+    /// Adds up the counts of every table.
+    pub fn total(&self) -> TableCounts {
+        let mut total = self.device_manufacturers;
+        total += self.device_categories;
+        total += self.devices;
+        total += self.overrides;
+        total += self.custom_fields;
+        total += self.parts;
+        total += self.repair_services;
+        total
+    }
+}
+
+impl TableCounts {
+    /// Counts a record which was written to the table.
+    pub fn record(&mut self, merged: bool) {
+        match merged {
+            true => self.merged += 1,
+            false => self.created += 1,
+        }
+    }
+}
+
+impl AddAssign for TableCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.created += other.created;
+        self.merged += other.merged;
+        self.skipped += other.skipped;
+    }
+}
+
+impl fmt::Display for TableCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} record(s) created, {} merged, {} skipped",
+            self.created, self.merged, self.skipped
+        )
+    }
+}
+
+impl fmt::Display for ExtensionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Extension '{}' v{} {} in {} ms, {}.",
+            self.id,
+            self.version,
+            self.outcome,
+            self.duration.as_millis(),
+            self.records.total()
+        )
+    }
+}
+
+impl fmt::Display for ExtensionOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtensionOutcome::Loaded => write!(f, "loaded"),
+            ExtensionOutcome::Reloaded { previous_version } => {
+                write!(f, "reloaded over v{previous_version}")
+            }
+            ExtensionOutcome::Skipped { conflict } => write!(f, "skipped ({conflict})"),
+            ExtensionOutcome::Failed { error } => write!(f, "failed ({error})"),
+            ExtensionOutcome::Orphaned { unloaded: true } => write!(f, "orphaned and unloaded"),
+            ExtensionOutcome::Orphaned { unloaded: false } => write!(f, "orphaned"),
+        }
+    }
+}
+
+/// Serializes a duration as a number of milliseconds, which is easier to use in scripts than
+/// separate seconds and nanoseconds.
+fn serialize_millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
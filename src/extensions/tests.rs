@@ -1,9 +1,37 @@
-use semver::Version;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::time::Duration;
 
-use super::conflicts::LoadConflict;
-use super::{Extension, ExtensionID, ExtensionManager as Manager, Metadata};
+use ed25519_dalek::SigningKey;
+use semver::{Version, VersionReq};
+
+use super::bundles::{self, TrustedKeys, UnsignedPolicy};
+use super::conflicts::{ConflictKind, LoadConflict, LoadFailureReason};
+use super::custom_fields;
+use super::dependencies::{self, DependencyError};
+use super::diff::{DatabaseSnapshot, ExtensionDiff, ItemChange, PlannedAction};
+use super::export;
+use super::formats;
+use super::identifiers;
+use super::manager::InventoryExtensionToml;
+use super::merging::{MergeConflictError, MergePolicy, Merger, Resolution};
+use super::orphans::{self, DirectoryScan, OrphanPolicy};
+use super::overrides;
+use super::report::{ExtensionOutcome, LoadReport, RecordCounts};
+use super::validation::{self, Location, Severity};
+use super::versions;
+use super::{
+    CollisionSeverities, CollisionSeverity, Extension, ExtensionID, ExtensionManager as Manager,
+    ExtensionManagerConfig, ExtensionWatcher, Metadata,
+};
 use crate::database::Database;
-use crate::models::common::{Device, DeviceCategory, DeviceManufacturer, UniqueID};
+use crate::models::common::{
+    withdraw_contribution, CategoryTree, Combined, CustomFieldDefinition, CustomFieldTarget,
+    CustomFieldType, CustomFieldValue, CustomFields, Device, DeviceCategory,
+    DeviceCategoryUniqueID, DeviceManufacturer, DeviceUniqueID, Override, OverrideField,
+    OverrideOperation, OverrideTarget, Part, PartKind, PartUniqueID, PriceList, RepairService,
+    UniqueID,
+};
 
 /// Tests that an extension will be loaded normally if it does not conflict with an existing
 /// extension, regardless of whether the auto-reload flag is set.
@@ -27,8 +55,41 @@ async fn load_new_extension() {
     db.teardown().await;
 }
 
-/// Tests that a conflicting extension which has the same version as an existing extension will be
-/// skipped if the auto-reload flag is not set.
+/// Tests that a conflicting extension which is identical to an existing extension will be skipped if
+/// the auto-reload flag is not set.
+#[tokio::test]
+async fn skip_unchanged() {
+    let db = Database::connect_with_name("skip_unchanged").await;
+    db.setup_tables().await.unwrap();
+
+    let extension = Extension::test_single(1, 1);
+
+    // Check that the extension can be loaded without conflicts
+    load_and_check_no_conflicts(&db, false, &extension, true, false).await;
+
+    // Attempt to load the same extension again
+    let manager = Manager::with_extensions(false, [extension.clone()]);
+    let load_report = manager.load_extensions(&db).await.unwrap();
+    // Make sure the conflict was identified as unchanged
+    let load_conflicts = load_report.conflicts;
+    assert_eq!(load_conflicts.len(), 1);
+    assert_eq!(load_conflicts[0], LoadConflict::unchanged(&extension));
+    // Make sure the report counts all of the extension's records as skipped
+    assert_eq!(
+        load_report.extensions[0].outcome,
+        ExtensionOutcome::Skipped {
+            conflict: ConflictKind::Unchanged
+        }
+    );
+    assert_eq!(load_report.extensions[0].records.total().skipped, 3);
+
+    db.contains(&extension, true).await;
+
+    db.teardown().await;
+}
+
+/// Tests that a conflicting extension which has the same version as an existing extension, but
+/// different contents, will be skipped if neither the auto-reload nor reload-on-drift flags are set.
 #[tokio::test]
 async fn skip_duplicate() {
     let db = Database::connect_with_name("skip_duplicate").await;
@@ -42,12 +103,12 @@ async fn skip_duplicate() {
 
     // Attempt to load the second extension into the database
     let manager = Manager::with_extensions(false, [skipped_extension.clone()]);
-    let load_conflicts = manager.load_extensions(&db).await.unwrap();
+    let load_conflicts = manager.load_extensions(&db).await.unwrap().conflicts;
     // Make sure the conflicts were correctly identified
     assert_eq!(load_conflicts.len(), 1);
     assert_eq!(
         load_conflicts[0],
-        LoadConflict::already_loaded(original_extension.metadata.id.clone())
+        LoadConflict::content_drift(&original_extension, &skipped_extension)
     );
 
     // Make sure that the original extension was not reloaded
@@ -56,11 +117,44 @@ async fn skip_duplicate() {
     db.teardown().await;
 }
 
-/// Tests that a conflicting extension which has a different version than an existing extension will
-/// be reloaded if the auto-reload flag is not set.
+/// Tests that a conflicting extension whose contents changed without a version bump will be
+/// reloaded if the reload-on-drift flag is set.
+#[tokio::test]
+async fn reload_on_drift() {
+    let db = Database::connect_with_name("reload_on_drift").await;
+    db.setup_tables().await.unwrap();
+
+    // Create two extensions with the same metadata, but different contents
+    let (original_extension, drifted_extension) = Extension::test_pair_same_metadata();
+
+    // Check that the original extension can be loaded without conflicts
+    load_and_check_no_conflicts(&db, false, &original_extension, true, false).await;
+
+    // Load the drifted extension with the reload-on-drift flag set
+    let mut manager = Manager::base_with_context(ExtensionManagerConfig {
+        reload_on_drift: true,
+        ..Default::default()
+    });
+    manager.stage_extension(drifted_extension.clone()).unwrap();
+    let load_conflicts = manager.load_extensions(&db).await.unwrap().conflicts;
+    // Make sure the conflict was identified as drift
+    assert_eq!(load_conflicts.len(), 1);
+    assert_eq!(
+        load_conflicts[0],
+        LoadConflict::content_drift(&original_extension, &drifted_extension)
+    );
+
+    // Make sure that the drifted extension replaced the original extension
+    db.contains(&drifted_extension, true).await;
+
+    db.teardown().await;
+}
+
+/// Tests that a conflicting extension which is newer than an existing extension will be reloaded if
+/// the auto-reload flag is not set.
 #[tokio::test]
-async fn version_change() {
-    let db = Database::connect_with_name("version_change").await;
+async fn upgrade() {
+    let db = Database::connect_with_name("upgrade").await;
     db.setup_tables().await.unwrap();
 
     // Create two extensions with different versions and contents
@@ -71,12 +165,12 @@ async fn version_change() {
 
     // Load the updated extension into the database, which should replace the original extension
     let manager = Manager::with_extensions(false, [updated_extension.clone()]);
-    let load_conflicts = manager.load_extensions(&db).await.unwrap();
+    let load_conflicts = manager.load_extensions(&db).await.unwrap().conflicts;
     // Make sure the conflicts were correctly identified
     assert_eq!(load_conflicts.len(), 1);
     assert_eq!(
         load_conflicts[0],
-        LoadConflict::version_change(original_extension.metadata.id)
+        LoadConflict::upgrade(&original_extension, &updated_extension)
     );
 
     // Make sure that the original extension was reloaded
@@ -85,6 +179,60 @@ async fn version_change() {
     db.teardown().await;
 }
 
+/// Tests that a conflicting extension which is older than an existing extension will be skipped,
+/// even if the auto-reload flag is set, unless downgrades are allowed.
+#[tokio::test]
+async fn downgrade() {
+    let db = Database::connect_with_name("downgrade").await;
+    db.setup_tables().await.unwrap();
+
+    // Create two extensions with different versions and contents
+    let (older_extension, newer_extension) = Extension::test_pair_different_metadata();
+
+    // Check that the newer extension can be loaded without conflicts
+    load_and_check_no_conflicts(&db, false, &newer_extension, true, false).await;
+
+    // Attempt to load the older extension with the auto-reload flag set, along with an extension
+    // which depends on it
+    let mut dependent_extension = Extension::test_single(2, 3);
+    dependent_extension.depend_on(&older_extension, ">=1.0.0");
+    let manager =
+        Manager::with_extensions(true, [older_extension.clone(), dependent_extension.clone()]);
+    let load_result = manager.load_extensions(&db).await.unwrap();
+    // Make sure the downgrade was identified and refused
+    assert_eq!(
+        load_result.conflicts,
+        [LoadConflict::downgrade(&newer_extension, &older_extension)]
+    );
+    assert_eq!(load_result.failures.len(), 1);
+    assert_eq!(
+        load_result.failures[0].reason,
+        LoadFailureReason::Downgrade {
+            loaded: newer_extension.metadata.version.clone(),
+            staged: older_extension.metadata.version.clone(),
+        }
+    );
+    // Make sure that the newer extension was not replaced, and still satisfies the dependent
+    db.contains(&newer_extension, false).await;
+    db.contains(&dependent_extension, false).await;
+    db.unload_extension(&dependent_extension.metadata.id)
+        .await
+        .unwrap();
+
+    // Load the older extension again with downgrades allowed
+    let mut manager = Manager::base_with_context(ExtensionManagerConfig {
+        allow_downgrade: true,
+        ..Default::default()
+    });
+    manager.stage_extension(older_extension.clone()).unwrap();
+    let load_result = manager.load_extensions(&db).await.unwrap();
+    assert!(load_result.failures.is_empty());
+    // Make sure that the newer extension was replaced
+    db.contains(&older_extension, true).await;
+
+    db.teardown().await;
+}
+
 /// Tests that an extension which conflicts with an existing extension will be reloaded
 /// automatically if the auto-reload flag is set.
 #[tokio::test]
@@ -100,12 +248,12 @@ async fn auto_reload() {
 
     // Load the second extension into the database, which should replace the original extension
     let manager = Manager::with_extensions(true, [reloaded_extension.clone()]);
-    let load_conflicts = manager.load_extensions(&db).await.unwrap();
+    let load_conflicts = manager.load_extensions(&db).await.unwrap().conflicts;
     // Make sure the conflicts were correctly identified
     assert_eq!(load_conflicts.len(), 1);
     assert_eq!(
         load_conflicts[0],
-        LoadConflict::already_loaded(original_extension.metadata.id)
+        LoadConflict::content_drift(&original_extension, &reloaded_extension)
     );
 
     // Make sure the original extension was unloaded and the new version was loaded
@@ -123,12 +271,12 @@ async fn auto_reload() {
 
     // Load the second extension into the database, which should replace the original extension
     let manager = Manager::with_extensions(true, [reloaded_extension.clone()]);
-    let load_conflicts = manager.load_extensions(&db).await.unwrap();
+    let load_conflicts = manager.load_extensions(&db).await.unwrap().conflicts;
     // Make sure the conflicts were correctly identified
     assert_eq!(load_conflicts.len(), 1);
     assert_eq!(
         load_conflicts[0],
-        LoadConflict::version_change(original_extension.metadata.id)
+        LoadConflict::upgrade(&original_extension, &reloaded_extension)
     );
 
     // Make sured that the original extension was unloaded and the new version was loaded
@@ -137,6 +285,249 @@ async fn auto_reload() {
     db.teardown().await;
 }
 
+/// Tests that a dry run reports the changes an extension would make without modifying the database.
+#[tokio::test]
+async fn dry_run() {
+    let db = Database::connect_with_name("dry_run").await;
+    db.setup_tables().await.unwrap();
+
+    // Create two extensions with different versions and contents
+    let (original_extension, updated_extension) = Extension::test_pair_different_metadata();
+
+    // Check that the original extension can be loaded without conflicts
+    load_and_check_no_conflicts(&db, false, &original_extension, true, false).await;
+
+    // Preview loading the updated extension
+    let mut manager = Manager::base_with_context(ExtensionManagerConfig {
+        dry_run: true,
+        ..Default::default()
+    });
+    manager.stage_extension(updated_extension.clone()).unwrap();
+    let load_result = manager.load_extensions(&db).await.unwrap();
+    // Make sure the diff describes a reload
+    assert_eq!(load_result.diffs.len(), 1);
+    assert_eq!(load_result.diffs[0].action, PlannedAction::Reload);
+    assert!(!load_result.diffs[0].is_empty());
+
+    // Make sure that the original extension was not reloaded
+    db.contains(&original_extension, true).await;
+
+    // Preview loading an extension along with others which use its items, and which collide or
+    // conflict with them
+    let dependency = Extension::test_single(2, 2);
+    let mut dependent = Extension::test_single(3, 3);
+    dependent.devices[0].manufacturer = dependency.device_manufacturers[0].id.clone();
+    let mut colliding = Extension::test_single(4, 2);
+    colliding.devices[0].id = DeviceUniqueID::new("colliding");
+    let mut conflicting = Extension::test_single(5, 2);
+    conflicting.device_manufacturers[0].display_name = "Renamed Manufacturer".to_owned();
+    for extension in [&mut dependent, &mut colliding, &mut conflicting] {
+        extension.depend_on(&dependency, ">=1.0.0");
+    }
+    let mut manager = Manager::base_with_context(ExtensionManagerConfig {
+        dry_run: true,
+        merge_policy: MergePolicy::Error,
+        ..Default::default()
+    });
+    for extension in [&dependency, &dependent, &colliding, &conflicting] {
+        manager.stage_extension(extension.clone()).unwrap();
+    }
+    let load_result = manager.load_extensions(&db).await.unwrap();
+    // Make sure the dependent is checked against the items its dependency would load
+    let diffs = load_result
+        .diffs
+        .iter()
+        .map(|diff| (diff.id.as_str(), diff.action))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(diffs["test_2"], PlannedAction::Load);
+    assert_eq!(diffs["test_3"], PlannedAction::Load);
+    // Make sure the collision and the refused conflict with the staged dependency are predicted
+    assert_eq!(load_result.failures.len(), 2);
+    for failure in &load_result.failures {
+        match &failure.reason {
+            LoadFailureReason::Invalid(_) => assert_eq!(failure.id, colliding.metadata.id),
+            LoadFailureReason::MergeConflict(conflicts) => {
+                assert_eq!(failure.id, conflicting.metadata.id);
+                assert_eq!(conflicts[0].field, "display_name");
+            }
+            reason => panic!("Unexpected failure: {reason}"),
+        }
+    }
+
+    // Make sure that nothing was loaded
+    db.contains(&original_extension, true).await;
+
+    db.teardown().await;
+}
+
+/// Tests that loaded extensions which were not found in the extensions directory are reported as
+/// orphans, and only unloaded if requested and every extension file could be read.
+#[tokio::test]
+async fn orphans() {
+    let db = Database::connect_with_name("orphans").await;
+    db.setup_tables().await.unwrap();
+
+    // Load two extensions with different contents
+    let (extension, orphaned_extension) =
+        (Extension::test_single(1, 1), Extension::test_single(2, 2));
+    load_and_check_no_conflicts(&db, false, &extension, false, false).await;
+    load_and_check_no_conflicts(&db, false, &orphaned_extension, false, false).await;
+
+    // Stage only the first extension, as if the file of the second had been removed
+    let orphan_manager = |scan: DirectoryScan| {
+        let mut manager = Manager::base_with_context(ExtensionManagerConfig {
+            orphans: OrphanPolicy::Unload,
+            ..Default::default()
+        });
+        manager.directory_scan = Some(scan);
+        manager.stage_extension(extension.clone()).unwrap();
+        manager
+    };
+
+    // Make sure the orphan is kept while another extension file could not be read
+    let load_result = orphan_manager(DirectoryScan {
+        invalid_files: 1,
+        ..Default::default()
+    })
+    .load_extensions(&db)
+    .await
+    .unwrap();
+    assert_eq!(load_result.orphans.len(), 1);
+    assert_eq!(load_result.orphans[0], orphaned_extension.metadata.id);
+    db.contains(&orphaned_extension, false).await;
+
+    // Make sure an extension whose file exists but could not be staged is not an orphan
+    let load_result = orphan_manager(DirectoryScan {
+        unstaged: HashSet::from([orphaned_extension.metadata.id.clone()]),
+        ..Default::default()
+    })
+    .load_extensions(&db)
+    .await
+    .unwrap();
+    assert!(load_result.orphans.is_empty());
+    db.contains(&orphaned_extension, false).await;
+
+    // Make sure the orphan is kept while a staged extension depends on it
+    let mut dependent_extension = Extension::test_single(3, 3);
+    dependent_extension.depend_on(&orphaned_extension, ">=1.0.0");
+    let mut manager = orphan_manager(DirectoryScan::default());
+    manager
+        .stage_extension(dependent_extension.clone())
+        .unwrap();
+    let load_result = manager.load_extensions(&db).await.unwrap();
+    assert_eq!(
+        load_result.orphans,
+        std::slice::from_ref(&orphaned_extension.metadata.id)
+    );
+    db.contains(&orphaned_extension, false).await;
+    db.contains(&dependent_extension, false).await;
+
+    // Make sure the orphan is kept while a loaded extension depends on it
+    let load_result = orphan_manager(DirectoryScan {
+        unstaged: HashSet::from([dependent_extension.metadata.id.clone()]),
+        ..Default::default()
+    })
+    .load_extensions(&db)
+    .await
+    .unwrap();
+    assert_eq!(
+        load_result.orphans,
+        std::slice::from_ref(&orphaned_extension.metadata.id)
+    );
+    db.contains(&orphaned_extension, false).await;
+    db.unload_extension(&dependent_extension.metadata.id)
+        .await
+        .unwrap();
+
+    // Make sure the orphan is unloaded once every extension file could be read
+    let load_result = orphan_manager(DirectoryScan::default())
+        .load_extensions(&db)
+        .await
+        .unwrap();
+    assert_eq!(load_result.orphans, [orphaned_extension.metadata.id]);
+    db.contains(&extension, true).await;
+
+    db.teardown().await;
+}
+
+/// Tests that the watcher loads, replaces, and unloads extensions as their files change, and keeps
+/// the loaded extension when a change cannot be loaded or other extensions depend on it.
+#[tokio::test]
+async fn watch_extensions() {
+    let db = Database::connect_with_name("watch_extensions").await;
+    db.setup_tables().await.unwrap();
+
+    let directory = tempfile::tempdir().unwrap();
+    let mut watcher = ExtensionWatcher::new(directory.path(), Default::default()).unwrap();
+    let path = watcher.directory.join("test.toml");
+    let write = |extension: &Extension| std::fs::write(&path, extension.export()).unwrap();
+
+    // Make sure a created file is loaded
+    let extension_1 = Extension::test_single(1, 1);
+    write(&extension_1);
+    watcher.handle_change(&db, &path).await.unwrap();
+    db.contains(&extension_1, true).await;
+
+    // Make sure the old extension is kept if the file's new extension cannot be staged or loaded
+    let mut incompatible = Extension::test_single(2, 2);
+    incompatible.metadata.techtriage = Some(VersionReq::parse("<0.0.1").unwrap());
+    write(&incompatible);
+    assert!(watcher.handle_change(&db, &path).await.is_err());
+    db.contains(&extension_1, true).await;
+    let mut invalid = Extension::test_single(2, 2);
+    invalid.device_manufacturers.clear();
+    write(&invalid);
+    watcher.handle_change(&db, &path).await.unwrap();
+    db.contains(&extension_1, true).await;
+
+    // Make sure a modified file replaces its old extension once the new one is loaded
+    let extension_2 = Extension::test_single(2, 2);
+    write(&extension_2);
+    watcher.handle_change(&db, &path).await.unwrap();
+    db.contains(&extension_2, true).await;
+
+    // Make sure a deleted file does not unload an extension which another extension depends on
+    let mut dependent = Extension::test_single(3, 3);
+    dependent.depend_on(&extension_2, "^1.0.0");
+    load_and_check_no_conflicts(&db, false, &dependent, true, false).await;
+    std::fs::remove_file(&path).unwrap();
+    watcher.handle_change(&db, &path).await.unwrap();
+    db.contains(&extension_2, true).await;
+    db.unload_extension(&dependent.metadata.id).await.unwrap();
+    write(&extension_2);
+    watcher.handle_change(&db, &path).await.unwrap();
+
+    // Make sure a deleted file unloads its extension
+    std::fs::remove_file(&path).unwrap();
+    watcher.handle_change(&db, &path).await.unwrap();
+    assert!(db.list_extensions().await.unwrap().into_all().is_empty());
+
+    db.teardown().await;
+}
+
+/// Tests that the watcher handles changes to extension files and to the contents of bundles, and
+/// ignores everything else in the directory.
+#[test]
+fn watched_paths() {
+    let directory = tempfile::tempdir().unwrap();
+    let watcher = ExtensionWatcher::new(directory.path(), Default::default()).unwrap();
+    let root = &watcher.directory;
+
+    let file = root.join("test.toml");
+    assert_eq!(watcher.extension_entry(&file), Some(file));
+    let bundle = root.join("test.bundle");
+    assert_eq!(
+        watcher.extension_entry(&bundle.join(bundles::MANIFEST_FILE)),
+        Some(bundle.clone())
+    );
+    assert_eq!(watcher.extension_entry(&bundle), Some(bundle));
+    assert_eq!(watcher.extension_entry(&root.join("notes.txt")), None);
+    assert_eq!(
+        watcher.extension_entry(&root.join("backup/test.toml")),
+        None
+    );
+}
+
 /// Tests that extensions are unloaded correctly.
 #[tokio::test]
 async fn unload_extension() {
@@ -161,118 +552,2470 @@ async fn unload_extension() {
     db.teardown().await;
 }
 
-/// Tests that an extension can be loaded without generating any conflicts.
-/// This test is meant to be a shortcut used by other tests, rather than a standalone test.
-async fn load_and_check_no_conflicts(
-    db: &Database,
-    auto_reload: bool,
-    extension: &Extension,
-    only_extension: bool,
-    remove_after: bool,
-) {
-    // Load the extension into the database
-    let manager = Manager::with_extensions(auto_reload, [extension.clone()]);
-    let load_conflicts = manager.load_extensions(db).await.unwrap();
-    // Make sure there were no conflicts
-    assert!(load_conflicts.is_empty());
-    // Make sure the extension was loaded correctly
-    // * The additional check for exclusivity is not entirely necessary, but it is included to
-    // * provide some extra certainty of the result.
-    db.contains(extension, only_extension).await;
+/// Tests that disabling an extension hides the items only it provides without removing them, and
+/// that re-enabling it restores them.
+#[tokio::test]
+async fn disable_extension() {
+    let db = Database::connect_with_name("disable_extension").await;
+    db.setup_tables().await.unwrap();
 
-    // Remove the extension if requested
-    if remove_after {
-        db.unload_extension(&extension.metadata.id).await.unwrap();
+    // Create two extensions with different names but the same contents, and one with its own
+    let (extension_1, extension_2) = Extension::test_pair_same_contents();
+    let extension_3 = Extension::test_single(3, 3);
+    for extension in [&extension_1, &extension_2, &extension_3] {
+        load_and_check_no_conflicts(&db, false, extension, false, false).await;
     }
-}
 
-impl Extension {
-    /// Creates a basic extension with no contents for testing purposes.
-    /// Can be modified to test different scenarios.
-    fn test(num: u32) -> Self {
-        Self {
-            metadata: Metadata {
-                id: ExtensionID::new(format!("test_{num}")),
-                display_name: format!("Test Extension {num}"),
-                version: Version::new(1, 0, 0),
-            },
-            device_manufacturers: Vec::new(),
-            device_categories: Vec::new(),
-            devices: Vec::new(),
-        }
+    // Disable the first and third extensions
+    for extension in [&extension_1, &extension_3] {
+        assert!(db
+            .set_extension_enabled(&extension.metadata.id, false)
+            .await
+            .unwrap());
     }
+    assert!(!db
+        .set_extension_enabled(&ExtensionID::new("missing"), false)
+        .await
+        .unwrap());
 
-    /// Creates a single basic extension with contents.
-    /// Can be modified to test different scenarios.
-    fn test_single(extension_num: u32, contents_num: u32) -> Self {
-        // Create an empty extension.
-        let mut extension = Self::test(extension_num);
+    // Make sure items shared with an enabled extension are still listed, but the rest are hidden
+    let devices = db.list_devices().await.unwrap();
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].id, extension_2.devices[0].id);
+    assert_eq!(db.list_base_devices().await.unwrap().len(), 2);
+    assert_eq!(db.list_extensions().await.unwrap().base.len(), 3);
 
-        // Populate the extension with one device manufacturer, device category, and device.
-        let device_manufacturer = DeviceManufacturer::test(contents_num, &extension.metadata.id);
-        let device_category = DeviceCategory::test(contents_num, &extension.metadata.id);
-        let device = Device::test(
-            contents_num,
-            &extension.metadata.id,
-            &device_manufacturer.id,
-            &device_category.id,
-        );
+    // Make sure the hidden items can still be exported, and their identifiers still collide
+    let exported = export::export(&db, Some(&extension_3.metadata.id), &extension_3.metadata)
+        .await
+        .unwrap();
+    assert!(exported.contains(extension_3.devices[0].id.unnamespaced()));
+    let mut colliding = Extension::test_single(4, 4);
+    colliding.devices[0].primary_model_identifiers =
+        extension_3.devices[0].primary_model_identifiers.clone();
+    let load_result = Manager::with_extensions(false, [colliding])
+        .load_extensions(&db)
+        .await
+        .unwrap();
+    assert_eq!(load_result.failures.len(), 1);
 
-        extension.device_manufacturers.push(device_manufacturer);
-        extension.device_categories.push(device_category);
-        extension.devices.push(device);
+    // Make sure re-enabling the extension restores its contents
+    db.set_extension_enabled(&extension_3.metadata.id, true)
+        .await
+        .unwrap();
+    db.contains(&extension_3, false).await;
 
-        extension
-    }
+    db.teardown().await;
+}
 
-    /// Creates two basic extensions with the same metadata and different contents.
-    fn test_pair_same_metadata() -> (Self, Self) {
-        (Self::test_single(1, 1), Self::test_single(1, 2))
-    }
+/// Tests that reloading or unloading an extension removes the values it no longer gives to items
+/// which other extensions also provide, while keeping the values which those extensions give them.
+#[tokio::test]
+async fn reload_shared_items() {
+    let db = Database::connect_with_name("reload_shared_items").await;
+    db.setup_tables().await.unwrap();
 
-    /// Creates two basic extensions with the same ID, a different version, and different contents.
-    fn test_pair_different_metadata() -> (Self, Self) {
-        let extension_1 = Self::test_single(1, 1);
-        let mut extension_2 = Self::test_single(1, 2);
-        extension_2.metadata.version = Version::new(1, 0, 1);
+    // Load two extensions which provide the same device, each with an identifier of its own
+    let (mut extension, mut other_extension) = Extension::test_pair_same_contents();
+    extension.devices[0]
+        .extended_model_identifiers
+        .push("Old".to_owned());
+    other_extension.devices[0]
+        .extended_model_identifiers
+        .push("Other".to_owned());
+    load_and_check_no_conflicts(&db, false, &extension, false, false).await;
+    load_and_check_no_conflicts(&db, false, &other_extension, false, false).await;
 
-        (extension_1, extension_2)
-    }
+    // Reload the first extension with a different identifier of its own
+    let mut updated_extension = extension.clone();
+    updated_extension.metadata.version = Version::new(1, 0, 1);
+    updated_extension.devices[0].extended_model_identifiers[1] = "New".to_owned();
+    let load_result = Manager::with_extensions(false, [updated_extension.clone()])
+        .load_extensions(&db)
+        .await
+        .unwrap();
+    assert!(load_result.failures.is_empty());
 
-    /// Creates two basic extensions with a different ID, the same version, and the same contents.
-    fn test_pair_same_contents() -> (Self, Self) {
-        (Self::test_single(1, 1), Self::test_single(2, 1))
-    }
+    // Make sure only the identifier it dropped was removed
+    let devices = db.list_devices().await.unwrap();
+    assert_eq!(devices.len(), 1);
+    assert_eq!(
+        devices[0].extended_model_identifiers,
+        ["test_1_extended", "New", "Other"]
+    );
+
+    // Make sure only the identifiers of the other extension are kept once it is the only provider
+    db.unload_extension(&updated_extension.metadata.id)
+        .await
+        .unwrap();
+    db.contains(&other_extension, true).await;
+    let devices = db.list_devices().await.unwrap();
+    assert_eq!(
+        devices[0].extended_model_identifiers,
+        ["test_1_extended", "Other"]
+    );
+
+    // Make sure the same happens when a reload no longer lists the shared device
+    load_and_check_no_conflicts(&db, false, &updated_extension, false, false).await;
+    let mut dropped_extension = updated_extension.clone();
+    dropped_extension.metadata.version = Version::new(1, 0, 2);
+    dropped_extension.devices.clear();
+    let load_result = Manager::with_extensions(false, [dropped_extension])
+        .load_extensions(&db)
+        .await
+        .unwrap();
+    assert!(load_result.failures.is_empty());
+    let devices = db.list_devices().await.unwrap();
+    assert_eq!(
+        devices[0].extended_model_identifiers,
+        ["test_1_extended", "Other"]
+    );
+
+    db.teardown().await;
 }
 
-impl Manager {
-    /// Creates a manager for the provided extensions.
-    fn with_extensions(auto_reload: bool, extensions: impl IntoIterator<Item = Extension>) -> Self {
-        let mut manager = Self::base_with_context(auto_reload);
-        for extension in extensions {
-            manager.stage_extension(extension).unwrap();
+/// Tests that override extensions are listed separately from base extensions, that their overrides
+/// survive the base extension being reloaded, and that they are not exported with its items, while
+/// their own exports keep their dependencies.
+#[tokio::test]
+async fn overrides_survive_reload() {
+    let db = Database::connect_with_name("overrides_survive_reload").await;
+    db.setup_tables().await.unwrap();
+
+    // Load a base extension, then an extension which renames its device
+    let base = Extension::test_single(1, 1);
+    let device_id = base.devices[0].id.clone();
+    let mut local = Extension::test(2);
+    local.depend_on(&base, "^1.0.0");
+    local.overrides.push(Override {
+        target: OverrideTarget::Device(device_id.clone()),
+        operation: OverrideOperation::Replace {
+            field: OverrideField::DisplayName,
+            value: "Renamed Device".to_owned(),
+        },
+    });
+    load_and_check_no_conflicts(&db, false, &base, true, false).await;
+    let load_result = Manager::with_extensions(false, [local.clone()])
+        .load_extensions(&db)
+        .await
+        .unwrap();
+    assert!(load_result.failures.is_empty());
+
+    // Make sure the extensions are listed separately
+    let loaded = db.list_extensions().await.unwrap();
+    assert_eq!(loaded.base, std::slice::from_ref(&base.metadata));
+    assert_eq!(loaded.overrides, std::slice::from_ref(&local.metadata));
+
+    // Reload a newer version of the base extension
+    let mut upgraded = base.clone();
+    upgraded.metadata.version = Version::new(1, 0, 1);
+    upgraded.devices[0]
+        .extended_model_identifiers
+        .push("Newer".to_owned());
+    let load_result = Manager::with_extensions(false, [upgraded.clone()])
+        .load_extensions(&db)
+        .await
+        .unwrap();
+    assert!(load_result.failures.is_empty());
+    assert_eq!(
+        load_result.extensions[0].outcome,
+        ExtensionOutcome::Reloaded {
+            previous_version: "1.0.0".to_owned()
         }
+    );
 
-        manager
-    }
+    // Make sure the override still patches the reloaded device, and is still listed on its own
+    let devices = db.list_devices().await.unwrap();
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].display_name, "Renamed Device");
+    assert!(devices[0]
+        .extended_model_identifiers
+        .contains(&"Newer".to_owned()));
+    let loaded = db.list_extensions().await.unwrap();
+    assert_eq!(loaded.base, std::slice::from_ref(&upgraded.metadata));
+    assert_eq!(loaded.overrides, std::slice::from_ref(&local.metadata));
+
+    // Make sure exporting the base extension does not include the override
+    let exported = export::export(&db, Some(&base.metadata.id), &upgraded.metadata)
+        .await
+        .unwrap();
+    assert!(!exported.contains("Renamed Device"));
+    assert!(exported.contains(&base.devices[0].display_name));
+
+    // Make sure exporting the override extension keeps its dependency on the base extension
+    let exported = export::export(&db, Some(&local.metadata.id), &local.metadata)
+        .await
+        .unwrap();
+    let exported = toml::from_str::<InventoryExtensionToml>(&exported).unwrap();
+    assert_eq!(
+        exported.extension.dependencies,
+        Some(BTreeMap::from([(
+            base.metadata.id.unnamespaced().to_owned(),
+            "^1.0.0".to_owned()
+        )]))
+    );
+
+    db.teardown().await;
 }
 
-impl LoadConflict {
-    /// Creates a conflict indicating that the given extension is already loaded but its version has
-    /// not changed.
-    fn already_loaded(id: ExtensionID) -> Self {
-        Self {
-            id,
-            same_version: true,
-        }
+/// Tests that an extension which fails partway through loading or reloading leaves the database
+/// exactly as it was, including the previous version of a reloaded extension.
+#[tokio::test]
+async fn failed_load_is_rolled_back() {
+    let db = Database::connect_with_name("failed_load_is_rolled_back").await;
+    db.setup_tables().await.unwrap();
+
+    /// Reads everything a failed load could have changed.
+    async fn snapshot(
+        db: &Database,
+    ) -> (
+        Vec<Metadata>,
+        Vec<Device>,
+        Vec<(ExtensionID, CustomFieldDefinition)>,
+    ) {
+        let mut devices = db.list_base_devices().await.unwrap();
+        devices.sort_by(|a, b| a.id.cmp(&b.id));
+        (
+            db.list_extensions().await.unwrap().into_all(),
+            devices,
+            db.list_custom_fields().await.unwrap(),
+        )
     }
 
-    /// Creates a conflict indicating that the given extension is already loaded but its version has
-    /// changed.
-    fn version_change(id: ExtensionID) -> Self {
-        Self {
-            id,
-            same_version: false,
-        }
+    let extension = Extension::test_single(1, 1);
+    load_and_check_no_conflicts(&db, false, &extension, true, false).await;
+    let before = snapshot(&db).await;
+
+    // Give the last device a value which the schema rejects, so only the final statement fails
+    let with_invalid_last_device = |mut failing: Extension| {
+        failing.custom_fields.push(CustomFieldDefinition {
+            id: "grade".to_owned(),
+            target: CustomFieldTarget::Device,
+            display_name: "Grade".to_owned(),
+            field_type: CustomFieldType::Enum(vec!["a".to_owned()]),
+            required: false,
+        });
+        let mut device = Device::test(
+            9,
+            &failing.metadata.id,
+            &failing.device_manufacturers[0].id,
+            &failing.device_categories[0].id,
+        );
+        device
+            .custom_fields
+            .insert("grade".to_owned(), CustomFieldValue::String("z".to_owned()));
+        failing.devices.push(device);
+        failing
+    };
+    let config = ExtensionManagerConfig::default();
+
+    // Make sure a failed load writes nothing
+    let failing = with_invalid_last_device(Extension::test_single(2, 2));
+    let failing_id = failing.metadata.id.clone();
+    let merger = Merger::new(&config, &failing_id);
+    assert!(db.load_extension(failing, &merger).await.is_err());
+    assert_eq!(snapshot(&db).await, before);
+
+    // Make sure a failed reload keeps the previous version and its contents
+    let mut upgraded = with_invalid_last_device(Extension::test_single(1, 2));
+    upgraded.metadata.version = Version::new(1, 0, 1);
+    let merger = Merger::new(&config, &extension.metadata.id);
+    assert!(db.reload_extension(upgraded, &merger).await.is_err());
+    assert_eq!(snapshot(&db).await, before);
+    db.contains(&extension, true).await;
+
+    // Make sure a merge conflict refused on the last device writes nothing either
+    let mut conflicting = Extension::test_single(3, 3);
+    let mut device = extension.devices[0].clone();
+    device.display_name = "Renamed Device".to_owned();
+    device.extensions = HashSet::from([conflicting.metadata.id.clone()]);
+    conflicting.devices.push(device);
+    let config = ExtensionManagerConfig {
+        merge_policy: MergePolicy::Error,
+        ..Default::default()
+    };
+    let conflicting_id = conflicting.metadata.id.clone();
+    let merger = Merger::new(&config, &conflicting_id);
+    let error = db.load_extension(conflicting, &merger).await.unwrap_err();
+    assert!(error.downcast_ref::<MergeConflictError>().is_some());
+    assert_eq!(snapshot(&db).await, before);
+
+    db.teardown().await;
+}
+
+/// Tests that parts can be looked up by the devices they fit and the other way around, and that
+/// they are merged and unloaded like devices.
+#[tokio::test]
+async fn compatible_parts() {
+    let db = Database::connect_with_name("compatible_parts").await;
+    db.setup_tables().await.unwrap();
+
+    // Create two extensions with the same devices, which both provide the same part
+    let (mut extension_1, mut extension_2) = Extension::test_pair_same_contents();
+    for extension in [&mut extension_1, &mut extension_2] {
+        let part = Part::test(1, &extension.metadata.id, &extension.devices[0].id);
+        extension.parts.push(part);
+    }
+    load_and_check_no_conflicts(&db, false, &extension_1, true, false).await;
+    load_and_check_no_conflicts(&db, false, &extension_2, false, false).await;
+
+    // Make sure the part is found from its device, and the device from its part
+    let device = &extension_1.devices[0];
+    let part = &extension_1.parts[0];
+    let parts = db.list_compatible_parts(&device.id).await.unwrap();
+    assert_eq!(parts.len(), 1);
+    assert_eq!(parts[0].extensions.len(), 2);
+    let devices = db.list_compatible_devices(&part.id).await.unwrap();
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].id, device.id);
+    assert!(db
+        .list_compatible_devices(&PartUniqueID::new("missing"))
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Make sure the part is kept while another extension still provides it
+    db.unload_extension(&extension_1.metadata.id).await.unwrap();
+    db.contains(&extension_2, true).await;
+    db.unload_extension(&extension_2.metadata.id).await.unwrap();
+    assert!(db.list_parts().await.unwrap().is_empty());
+
+    db.teardown().await;
+}
+
+/// Tests that a device's price list includes the repair services which apply to it, and is not
+/// built for devices which do not exist.
+#[tokio::test]
+async fn price_list() {
+    let db = Database::connect_with_name("price_list").await;
+    db.setup_tables().await.unwrap();
+
+    let mut extension = Extension::test_single(1, 1);
+    let device = extension.devices[0].clone();
+    let service = RepairService::test(1, &extension.metadata.id, &device.category);
+    extension.repair_services.push(service.clone());
+    extension
+        .parts
+        .push(Part::test(1, &extension.metadata.id, &device.id));
+    load_and_check_no_conflicts(&db, false, &extension, true, false).await;
+
+    let price_list = db.price_list(&device.id).await.unwrap().unwrap();
+    assert_eq!(price_list.entries.len(), 1);
+    assert_eq!(price_list.entries[0].service, service);
+    assert_eq!(price_list.entries[0].parts.len(), 1);
+    assert!(db
+        .price_list(&DeviceUniqueID::new("missing"))
+        .await
+        .unwrap()
+        .is_none());
+
+    db.teardown().await;
+}
+
+/// Tests that the devices in a category's subtree can be listed, and that repair services apply to
+/// the devices in the categories below theirs.
+#[tokio::test]
+async fn category_subtree() {
+    let db = Database::connect_with_name("category_subtree").await;
+    db.setup_tables().await.unwrap();
+
+    let mut extension = Extension::test_single(1, 1);
+    let parent = DeviceCategory::test(2, &extension.metadata.id);
+    extension.device_categories[0].parent = Some(parent.id.clone());
+    let service = RepairService::test(1, &extension.metadata.id, &parent.id);
+    extension.device_categories.push(parent.clone());
+    extension.repair_services.push(service.clone());
+    load_and_check_no_conflicts(&db, false, &extension, true, false).await;
+
+    let device = &extension.devices[0];
+    for category in [&parent.id, &device.category] {
+        let devices = db.list_devices_in_category(category).await.unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].id, device.id);
+    }
+    let price_list = db.price_list(&device.id).await.unwrap().unwrap();
+    assert_eq!(price_list.entries.len(), 1);
+    assert_eq!(price_list.entries[0].service, service);
+
+    db.teardown().await;
+}
+
+/// Tests that the extensions shipped with the server parse without any errors.
+#[test]
+fn bundled_extensions_are_valid() {
+    for extension_file in std::fs::read_dir("./extensions").unwrap().flatten() {
+        if let Err(report) = Manager::base_with_context(Default::default()).parse_extension_source(
+            &extension_file.path(),
+            &std::fs::read_to_string(extension_file.path()).unwrap(),
+        ) {
+            panic!("{report}");
+        }
+    }
+}
+
+/// Tests that the same extension is read identically from every supported format.
+#[test]
+fn extension_formats() {
+    let toml = r#"
+        extension_id = "formats"
+        extension_display_name = "Formats"
+        extension_version = "1.0.0"
+
+        [[device_manufacturers]]
+        id = "apple"
+        display_name = "Apple"
+
+        [[device_categories]]
+        id = "phone"
+        display_name = "Phone"
+
+        [[devices]]
+        id = "iphone_8"
+        display_name = "iPhone 8"
+        manufacturer = "apple"
+        category = "phone"
+        primary_model_identifiers = ["A1863", "A1905"]
+        extended_model_identifiers = []
+    "#;
+    let json = r#"{
+        "extension_id": "formats",
+        "extension_display_name": "Formats",
+        "extension_version": "1.0.0",
+        "device_manufacturers": [{ "id": "apple", "display_name": "Apple" }],
+        "device_categories": [{ "id": "phone", "display_name": "Phone" }],
+        "devices": [{
+            "id": "iphone_8",
+            "display_name": "iPhone 8",
+            "manufacturer": "apple",
+            "category": "phone",
+            "primary_model_identifiers": ["A1863", "A1905"],
+            "extended_model_identifiers": []
+        }]
+    }"#;
+    let yaml = "
+extension_id: formats
+extension_display_name: Formats
+extension_version: 1.0.0
+device_manufacturers:
+  - id: apple
+    display_name: Apple
+device_categories:
+  - id: phone
+    display_name: Phone
+devices:
+  - id: iphone_8
+    display_name: iPhone 8
+    manufacturer: apple
+    category: phone
+    primary_model_identifiers: [A1863, A1905]
+    extended_model_identifiers: []
+";
+    let csv = r#"# extension_id = "formats"
+# extension_display_name = "Formats"
+# extension_version = "1.0.0"
+#
+# [[device_manufacturers]]
+# id = "apple"
+# display_name = "Apple"
+#
+# [[device_categories]]
+# id = "phone"
+# display_name = "Phone"
+id,display_name,manufacturer,category,primary_model_identifiers,extended_model_identifiers
+iphone_8,iPhone 8,apple,phone,A1863|A1905,
+"#;
+
+    let manager = Manager::base_with_context(Default::default());
+    let parse = |filename: &str, source: &str| {
+        manager
+            .parse_extension_source(Path::new(filename), source)
+            .unwrap_or_else(|report| panic!("{report}"))
+            .0
+    };
+    let expected = parse("test.toml", toml);
+    for (filename, source) in [("test.json", json), ("test.yaml", yaml), ("test.csv", csv)] {
+        let extension = parse(filename, source);
+        assert_eq!(extension.metadata, expected.metadata);
+        assert_eq!(extension.content_hash(), expected.content_hash());
+    }
+
+    // Make sure problems in CSV files are located by their row
+    let report = manager
+        .parse_extension_source(
+            Path::new("test.csv"),
+            &csv.replace("iphone_8,iPhone 8,apple", "iphone_8,iPhone 8,google"),
+        )
+        .unwrap()
+        .1;
+    assert_eq!(report.diagnostics.len(), 1);
+    assert_eq!(
+        report.diagnostics[0].location,
+        Some(Location {
+            line: 13,
+            column: 1
+        })
+    );
+
+    // Make sure unsupported files are rejected
+    let report = manager
+        .parse_extension_source(Path::new("test.txt"), toml)
+        .unwrap_err();
+    assert!(report.has_errors());
+}
+
+/// Tests that exported extensions are sorted, only contain the requested extension's items, and
+/// round-trip through the parser without changes.
+#[test]
+fn export_round_trip() {
+    let mut extension = Extension::test_single(1, 1);
+    extension.depend_on(&Extension::test(4), ">=1.2.0");
+    let mut reordered_extension = extension.clone();
+    for other in [Extension::test_single(2, 3), Extension::test_single(2, 2)] {
+        reordered_extension
+            .device_manufacturers
+            .extend(other.device_manufacturers);
+        reordered_extension
+            .device_categories
+            .extend(other.device_categories);
+        reordered_extension.devices.extend(other.devices);
+    }
+    reordered_extension.devices.reverse();
+
+    let export = |extension: &Extension, owner: Option<&ExtensionID>| {
+        export::to_toml(
+            &extension.metadata,
+            owner,
+            &extension.dependencies,
+            extension.custom_fields.clone(),
+            extension.device_manufacturers.clone(),
+            extension.device_categories.clone(),
+            extension.devices.clone(),
+            extension.parts.clone(),
+            extension.repair_services.clone(),
+        )
+        .unwrap()
+    };
+
+    // Make sure filtering by extension only exports that extension's items
+    let exported = export(&reordered_extension, Some(&extension.metadata.id));
+    assert_eq!(exported, export(&extension, None));
+
+    // Make sure the export parses into the same extension and exports identically again
+    let exported = export(&reordered_extension, None);
+    let (parsed_extension, report) = Manager::base_with_context(Default::default())
+        .parse_extension_source(Path::new("export.toml"), &exported)
+        .unwrap();
+    assert!(report.diagnostics.is_empty());
+    assert_eq!(parsed_extension.metadata, reordered_extension.metadata);
+    assert_eq!(
+        parsed_extension.dependencies,
+        reordered_extension.dependencies
+    );
+    assert_eq!(
+        parsed_extension.content_hash(),
+        reordered_extension.content_hash()
+    );
+    assert_eq!(export(&parsed_extension, None), exported);
+
+    // Make sure items are sorted by ID
+    let device_ids = parsed_extension
+        .devices
+        .iter()
+        .map(|d| d.id.unnamespaced())
+        .collect::<Vec<_>>();
+    assert_eq!(device_ids, ["test_1", "test_2", "test_3"]);
+}
+
+/// Tests that files in older format versions are upgraded to the current format version, both in
+/// memory and by rewriting them with their comments intact.
+#[test]
+fn format_versions() {
+    let v1 = r#"# An extension in format version 1.
+extension_id = "versions"
+extension_display_name = "Versions"
+# The version of the extension, not the format.
+extension_version = "1.0.0"
+
+# Other extensions which must be loaded first.
+[dependencies]
+base = "^1.0"
+
+[[devices]]
+id = "iphone_8"
+display_name = "iPhone 8"
+manufacturer = "apple"
+category = "phone"
+primary_model_identifiers = ["A1863"]
+extended_model_identifiers = []
+"#;
+    let v2 = r#"# An extension in format version 1.
+format_version = 2
+
+[extension]
+id = "versions"
+display_name = "Versions"
+# The version of the extension, not the format.
+version = "1.0.0"
+
+# Other extensions which must be loaded first.
+[extension.dependencies]
+base = "^1.0"
+
+[[devices]]
+id = "iphone_8"
+display_name = "iPhone 8"
+manufacturer = "apple"
+category = "phone"
+primary_model_identifiers = ["A1863"]
+extended_model_identifiers = []
+"#;
+
+    // Make sure both versions are read into the same extension
+    let format = formats::for_path(Path::new("test.toml")).unwrap();
+    let (v1_toml, v1_version) = format.parse(v1).unwrap();
+    let (v2_toml, v2_version) = format.parse(v2).unwrap();
+    assert_eq!(
+        (v1_version, v2_version),
+        (1, versions::CURRENT_FORMAT_VERSION)
+    );
+    assert_eq!(v1_toml, v2_toml);
+
+    // Make sure upgrading keeps comments and produces the current version
+    assert_eq!(format.upgrade(v1, &v1_toml).unwrap(), v2);
+
+    // Make sure CSV headers are upgraded in the same way
+    let csv_format = formats::for_path(Path::new("test.csv")).unwrap();
+    let csv = "# extension_id = \"versions\"\n\
+        # extension_display_name = \"Versions\"\n\
+        # extension_version = \"1.0.0\"\n\
+        id,display_name,manufacturer,category,primary_model_identifiers,extended_model_identifiers\n\
+        iphone_8,iPhone 8,apple,phone,A1863,\n";
+    let (csv_toml, _) = csv_format.parse(csv).unwrap();
+    let upgraded_csv = csv_format.upgrade(csv, &csv_toml).unwrap();
+    assert!(upgraded_csv.starts_with("# format_version = 2\n#\n# [extension]\n"));
+    assert_eq!(
+        csv_format.parse(&upgraded_csv).unwrap(),
+        (csv_toml, versions::CURRENT_FORMAT_VERSION)
+    );
+
+    // Make sure problems in older files are located in the original file
+    let manager = Manager::base_with_context(Default::default());
+    let report = manager
+        .parse_extension_source(Path::new("test.toml"), &v1.replace("\"1.0.0\"", "\"one\""))
+        .unwrap_err();
+    assert_eq!(
+        report.diagnostics[0].location,
+        Some(Location {
+            line: 5,
+            column: 21
+        })
+    );
+
+    // Make sure newer format versions are rejected
+    let report = manager
+        .parse_extension_source(
+            Path::new("test.toml"),
+            &v2.replace("format_version = 2", "format_version = 3"),
+        )
+        .unwrap_err();
+    assert!(report.has_errors());
+}
+
+/// Tests that bundles are only read if they are signed by a trusted key and unmodified, and that
+/// unsigned extension files are handled according to the configured policy.
+#[test]
+fn signed_bundles() {
+    let extension = Extension::test_single(1, 1);
+    let source = export::to_toml(
+        &extension.metadata,
+        None,
+        &extension.dependencies,
+        Vec::new(),
+        extension.device_manufacturers.clone(),
+        extension.device_categories.clone(),
+        extension.devices.clone(),
+        Vec::new(),
+        Vec::new(),
+    )
+    .unwrap();
+
+    let directory = tempfile::tempdir().unwrap();
+    let bundle = directory.path().join("test.bundle");
+    std::fs::create_dir_all(&bundle).unwrap();
+    std::fs::write(bundle.join("test.toml"), &source).unwrap();
+
+    let signing_key = SigningKey::from_bytes(&[1; 32]);
+    let other_key = SigningKey::from_bytes(&[2; 32]);
+    assert_eq!(
+        bundles::sign(&bundle, None, &signing_key).unwrap(),
+        "test.toml"
+    );
+
+    // Make sure a bundle signed by a trusted key is read
+    let mut manager = Manager::base_with_context(Default::default());
+    manager.trusted_keys =
+        TrustedKeys::from_keys([("test".to_owned(), signing_key.verifying_key())]);
+    let (read_extension, _) = manager.read_extension(&bundle).unwrap();
+    assert_eq!(read_extension.metadata, extension.metadata);
+
+    // Make sure verifying a bundle returns the same contents which were hashed
+    let verified = bundles::verify(&bundle, &manager.trusted_keys).unwrap();
+    assert_eq!(verified.source, source);
+    assert_eq!(verified.signer, "test");
+
+    // Make sure an already-signed bundle can be signed again without naming its extension file
+    assert_eq!(
+        bundles::sign(&bundle, None, &signing_key).unwrap(),
+        "test.toml"
+    );
+    assert!(manager.read_extension(&bundle).is_ok());
+
+    // Make sure a bundle signed by an untrusted key is rejected
+    let mut untrusted_manager = Manager::base_with_context(Default::default());
+    untrusted_manager.trusted_keys =
+        TrustedKeys::from_keys([("other".to_owned(), other_key.verifying_key())]);
+    assert!(untrusted_manager.read_extension(&bundle).is_err());
+
+    // Make sure a bundle whose extension file was modified after signing is rejected
+    std::fs::write(bundle.join("test.toml"), source.replace("Test", "Tampered")).unwrap();
+    assert!(manager.read_extension(&bundle).is_err());
+
+    // Make sure unsigned extension files are rejected if the policy requires it
+    let unsigned_file = directory.path().join("unsigned.toml");
+    std::fs::write(&unsigned_file, &source).unwrap();
+    assert!(manager.read_extension(&unsigned_file).is_ok());
+    let rejecting_manager = Manager::base_with_context(ExtensionManagerConfig {
+        unsigned_extensions: UnsignedPolicy::Reject,
+        ..Default::default()
+    });
+    assert!(rejecting_manager.read_extension(&unsigned_file).is_err());
+}
+
+/// Tests that validation problems are reported with their location instead of causing a panic.
+#[test]
+fn invalid_extension_diagnostics() {
+    let toml = "\
+extension_id = 'test'
+extension_display_name = ''
+extension_version = 'one'
+
+[[device_manufacturers]]
+id = 'test'
+display_name = 'Test'
+
+[[devices]]
+id = 'device'
+display_name = 'Device'
+manufacturer = 'test'
+category = 'missing'
+primary_model_identifiers = []
+extended_model_identifiers = []
+
+[[devices]]
+id = 'device'
+display_name = 'Device'
+manufacturer = 'test'
+category = 'missing'
+primary_model_identifiers = []
+extended_model_identifiers = []
+";
+
+    let report = Manager::base_with_context(Default::default())
+        .parse_extension_source(Path::new("test.toml"), toml)
+        .unwrap_err();
+    let diagnostics = report
+        .diagnostics
+        .iter()
+        .map(|d| (d.severity, d.location.map(|l| l.line)))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        diagnostics,
+        [
+            // Empty extension display name
+            (Severity::Error, Some(2)),
+            // Invalid extension version
+            (Severity::Error, Some(3)),
+            // Unknown category in the first device
+            (Severity::Warning, Some(13)),
+            // Duplicate device ID
+            (Severity::Error, Some(18)),
+            // Unknown category in the second device
+            (Severity::Warning, Some(21)),
+        ]
+    );
+}
+
+/// Tests that syntax errors are reported instead of causing a panic.
+#[test]
+fn syntax_error_diagnostics() {
+    let toml = "extension_id = 'test'\nextension_display_name = 'Test\n";
+
+    let report = Manager::base_with_context(Default::default())
+        .parse_extension_source(Path::new("test.toml"), toml)
+        .unwrap_err();
+    assert_eq!(report.diagnostics.len(), 1);
+    assert_eq!(
+        report.diagnostics[0].location,
+        Some(Location {
+            line: 2,
+            column: 31
+        })
+    );
+}
+
+/// Tests that model identifier collisions within an extension are reported with the configured
+/// severity.
+#[test]
+fn identifier_collisions() {
+    let toml = "\
+extension_id = 'test'
+extension_display_name = 'Test'
+extension_version = '1.0.0'
+
+[[device_manufacturers]]
+id = 'test'
+display_name = 'Test'
+
+[[device_categories]]
+id = 'test'
+display_name = 'Test'
+
+[[devices]]
+id = 'device_1'
+display_name = 'Device 1'
+manufacturer = 'test'
+category = 'test'
+primary_model_identifiers = ['A', 'B', 'A']
+extended_model_identifiers = []
+
+[[devices]]
+id = 'device_2'
+display_name = 'Device 2'
+manufacturer = 'test'
+category = 'test'
+primary_model_identifiers = ['C']
+extended_model_identifiers = ['B']
+";
+
+    // With the default severities, the shared identifier prevents the extension from loading
+    let report = Manager::base_with_context(Default::default())
+        .parse_extension_source(Path::new("test.toml"), toml)
+        .unwrap_err();
+    let diagnostics = report
+        .diagnostics
+        .iter()
+        .map(|d| (d.severity, d.field.as_ref().unwrap().to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        diagnostics,
+        [
+            (
+                Severity::Warning,
+                "devices[0].primary_model_identifiers[2]".to_owned()
+            ),
+            (
+                Severity::Error,
+                "devices[1].extended_model_identifiers[0]".to_owned()
+            ),
+        ]
+    );
+
+    // Ignoring all collisions allows the extension to load without any diagnostics
+    let manager = Manager::base_with_context(ExtensionManagerConfig {
+        identifier_collisions: CollisionSeverities {
+            within_device: CollisionSeverity::Ignore,
+            across_devices: CollisionSeverity::Ignore,
+            with_database: CollisionSeverity::Ignore,
+        },
+        ..Default::default()
+    });
+    let (_, report) = manager
+        .parse_extension_source(Path::new("test.toml"), toml)
+        .unwrap();
+    assert!(report.diagnostics.is_empty());
+}
+
+/// Tests that staged extensions are sorted so that dependencies are loaded first.
+#[test]
+fn dependency_order() {
+    let base = Extension::test(1);
+    let mut vendor = Extension::test(2);
+    vendor.depend_on(&base, "^1.0");
+    let mut local = Extension::test(3);
+    local.depend_on(&vendor, "=1.0.0");
+    local.depend_on(&base, "*");
+
+    let (sorted, failed) = dependencies::sort(vec![local, vendor, base], &[]);
+    assert!(failed.is_empty());
+    let sorted = sorted
+        .iter()
+        .map(|e| e.metadata.id.unnamespaced())
+        .collect::<Vec<_>>();
+    assert_eq!(sorted, ["test_1", "test_2", "test_3"]);
+
+    // A dependency which is already loaded in the database does not need to be staged
+    let base = Extension::test(1);
+    let mut vendor = Extension::test(2);
+    vendor.depend_on(&base, "^1.0");
+    let (sorted, failed) = dependencies::sort(vec![vendor], &[base.metadata]);
+    assert!(failed.is_empty());
+    assert_eq!(sorted.len(), 1);
+}
+
+/// Tests that missing, incompatible, and cyclic dependencies are rejected.
+#[test]
+fn dependency_errors() {
+    let base = Extension::test(1);
+    let mut incompatible = Extension::test(2);
+    incompatible.depend_on(&base, "^2.0");
+    let mut missing = Extension::test(3);
+    missing.depend_on(&Extension::test(4), "*");
+    let mut cycle_1 = Extension::test(5);
+    let mut cycle_2 = Extension::test(6);
+    cycle_1.depend_on(&cycle_2, "*");
+    cycle_2.depend_on(&cycle_1, "*");
+
+    let (sorted, failed) =
+        dependencies::sort(vec![base, incompatible, missing, cycle_1, cycle_2], &[]);
+    assert_eq!(sorted.len(), 1);
+    let failed = failed
+        .into_iter()
+        .map(|(e, error)| (e.metadata.id.unnamespaced().to_owned(), error))
+        .collect::<BTreeMap<_, _>>();
+    assert_eq!(
+        failed["test_2"],
+        DependencyError::Incompatible {
+            dependency: ExtensionID::new("test_1"),
+            requirement: VersionReq::parse("^2.0").unwrap(),
+            found: Version::new(1, 0, 0),
+        }
+    );
+    assert_eq!(
+        failed["test_3"],
+        DependencyError::Missing {
+            dependency: ExtensionID::new("test_4"),
+            requirement: VersionReq::STAR,
+        }
+    );
+    assert_eq!(
+        failed["test_6"],
+        DependencyError::Cycle(vec![
+            ExtensionID::new("test_5"),
+            ExtensionID::new("test_6"),
+            ExtensionID::new("test_5"),
+        ])
+    );
+    assert_eq!(
+        failed["test_5"],
+        DependencyError::Unavailable {
+            dependency: ExtensionID::new("test_6")
+        }
+    );
+}
+
+/// Tests that conflicts are classified by comparing versions, and content hashes when the versions
+/// are the same.
+#[test]
+fn conflict_kinds() {
+    let (extension, drifted_extension) = Extension::test_pair_same_metadata();
+    let (_, newer_extension) = Extension::test_pair_different_metadata();
+    let hashes = HashMap::from([(extension.metadata.id.clone(), extension.content_hash())]);
+
+    fn classify(
+        loaded: &Extension,
+        staged: &Extension,
+        hashes: &HashMap<ExtensionID, String>,
+    ) -> ConflictKind {
+        LoadConflict::new(staged, &mut vec![loaded.metadata.clone()], hashes)
+            .unwrap()
+            .kind
+    }
+
+    assert_eq!(
+        classify(&extension, &extension, &hashes),
+        ConflictKind::Unchanged
+    );
+    assert_eq!(
+        classify(&extension, &drifted_extension, &hashes),
+        ConflictKind::ContentDrift
+    );
+    // * Extensions loaded without a hash cannot be confirmed to be unchanged.
+    assert_eq!(
+        classify(&extension, &extension, &HashMap::new()),
+        ConflictKind::ContentDrift
+    );
+    assert_eq!(
+        classify(&extension, &newer_extension, &hashes),
+        ConflictKind::Upgrade
+    );
+    assert_eq!(
+        classify(&newer_extension, &extension, &hashes),
+        ConflictKind::Downgrade
+    );
+
+    // An extension which is not loaded does not conflict
+    let other_extension = Extension::test(2);
+    assert!(LoadConflict::new(
+        &other_extension,
+        &mut vec![extension.metadata.clone()],
+        &hashes
+    )
+    .is_none());
+}
+
+/// Tests that conflicting metadata is resolved according to the merge policy, that model
+/// identifiers are always combined, and that every overridden field is reported.
+#[test]
+fn merge_policies() {
+    let (first_id, second_id) = (ExtensionID::new("first"), ExtensionID::new("second"));
+    let existing = DeviceManufacturer::test(1, &first_id);
+    let mut staged = DeviceManufacturer::test(1, &second_id);
+    staged.display_name = "Renamed Manufacturer".to_owned();
+
+    let merge = |policy: MergePolicy, priority: &[&ExtensionID]| {
+        let config = ExtensionManagerConfig {
+            merge_policy: policy,
+            extension_priority: priority.iter().map(|&id| id.clone()).collect(),
+            ..Default::default()
+        };
+        let mut merged = staged.clone();
+        let conflicts =
+            Merger::new(&config, &second_id).device_manufacturer(&mut merged, existing.clone());
+        (merged, conflicts)
+    };
+
+    // Make sure the extension loaded last wins by default, and the override is reported
+    let (merged, conflicts) = merge(MergePolicy::LastWins, &[]);
+    assert_eq!(merged.display_name, staged.display_name);
+    assert_eq!(
+        merged.extensions,
+        HashSet::from([first_id.clone(), second_id.clone()])
+    );
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].field, "display_name");
+    assert_eq!(conflicts[0].existing_extension, "first");
+    assert_eq!(conflicts[0].resolution, Resolution::UsedStaged);
+
+    let (merged, conflicts) = merge(MergePolicy::FirstWins, &[]);
+    assert_eq!(merged.display_name, existing.display_name);
+    assert_eq!(conflicts[0].resolution, Resolution::KeptExisting);
+
+    // Make sure priority follows the given order, and unlisted extensions keep existing metadata
+    let (merged, _) = merge(MergePolicy::Priority, &[&second_id, &first_id]);
+    assert_eq!(merged.display_name, staged.display_name);
+    let (merged, _) = merge(MergePolicy::Priority, &[&first_id, &second_id]);
+    assert_eq!(merged.display_name, existing.display_name);
+    let (merged, _) = merge(MergePolicy::Priority, &[]);
+    assert_eq!(merged.display_name, existing.display_name);
+
+    let (_, conflicts) = merge(MergePolicy::Error, &[]);
+    assert_eq!(conflicts[0].resolution, Resolution::Refused);
+
+    // Make sure an extension does not conflict with its own records when it is reloaded
+    let config = ExtensionManagerConfig {
+        merge_policy: MergePolicy::Error,
+        ..Default::default()
+    };
+    let mut reloaded = staged.clone();
+    let conflicts = Merger::new(&config, &second_id)
+        .device_manufacturer(&mut reloaded, DeviceManufacturer::test(1, &second_id));
+    assert!(conflicts.is_empty());
+    assert_eq!(reloaded, staged);
+
+    // Make sure model identifiers are combined without duplicates
+    let manufacturer_id = existing.id.clone();
+    let category_id = DeviceCategory::test(1, &first_id).id;
+    let existing_device = Device::test(1, &first_id, &manufacturer_id, &category_id);
+    let mut staged_device = Device::test(1, &second_id, &manufacturer_id, &category_id);
+    staged_device
+        .primary_model_identifiers
+        .push("Extra".to_owned());
+    let conflicts = Merger::new(&config, &second_id).device(&mut staged_device, existing_device);
+    assert!(conflicts.is_empty());
+    assert_eq!(
+        staged_device.primary_model_identifiers,
+        ["test_1_primary", "Extra"]
+    );
+    assert_eq!(
+        staged_device.extended_model_identifiers,
+        ["test_1_extended"]
+    );
+}
+
+/// Tests that override extensions are parsed and validated, and that their overrides patch items
+/// from other extensions in order.
+#[test]
+fn override_extensions() {
+    let toml = "\
+format_version = 2
+
+[extension]
+id = 'local'
+display_name = 'Local Overrides'
+version = '1.0.0'
+
+[[overrides]]
+target = 'device'
+id = 'test_1'
+operation = 'replace'
+field = 'display_name'
+value = 'Renamed Device'
+
+[[overrides]]
+target = 'device'
+id = 'test_1'
+operation = 'add'
+field = 'primary_model_identifiers'
+value = 'Extra'
+
+[[overrides]]
+target = 'device'
+id = 'test_1'
+operation = 'remove'
+field = 'extended_model_identifiers'
+value = 'test_1_extended'
+
+[[overrides]]
+target = 'device_manufacturer'
+id = 'test_1'
+operation = 'replace'
+field = 'display_name'
+value = 'Renamed Manufacturer'
+
+[[overrides]]
+target = 'device'
+id = 'test_2'
+operation = 'remove'
+";
+
+    let manager = Manager::base_with_context(Default::default());
+    let (extension, _) = manager
+        .parse_extension_source(Path::new("local.toml"), toml)
+        .unwrap();
+    assert!(extension.is_override());
+    assert_eq!(extension.overrides.len(), 5);
+
+    // Make sure overrides are applied to the items they target, and hidden devices are removed
+    let base_id = ExtensionID::new("base");
+    let manufacturer = DeviceManufacturer::test(1, &base_id);
+    let category = DeviceCategory::test(1, &base_id);
+    let devices = (1..=3)
+        .map(|num| Device::test(num, &base_id, &manufacturer.id, &category.id))
+        .collect::<Vec<_>>();
+
+    let devices = overrides::apply(devices, &extension.overrides);
+    assert_eq!(
+        devices
+            .iter()
+            .map(|d| d.id.unnamespaced())
+            .collect::<Vec<_>>(),
+        ["test_1", "test_3"]
+    );
+    assert_eq!(devices[0].display_name, "Renamed Device");
+    assert_eq!(
+        devices[0].primary_model_identifiers,
+        ["test_1_primary", "Extra"]
+    );
+    assert!(devices[0].extended_model_identifiers.is_empty());
+    assert_eq!(
+        devices[1],
+        Device::test(3, &base_id, &manufacturer.id, &category.id)
+    );
+
+    let manufacturers = overrides::apply(vec![manufacturer], &extension.overrides);
+    assert_eq!(manufacturers[0].display_name, "Renamed Manufacturer");
+    assert_eq!(
+        overrides::apply(vec![category.clone()], &extension.overrides),
+        [category]
+    );
+
+    // Make sure unsupported overrides and overrides mixed with items are reported
+    let invalid = "\
+format_version = 2
+
+[extension]
+id = 'local'
+display_name = 'Local Overrides'
+version = '1.0.0'
+
+[[device_categories]]
+id = 'category'
+display_name = 'Category'
+
+[[overrides]]
+target = 'device_category'
+id = 'test_1'
+operation = 'remove'
+
+[[overrides]]
+target = 'device'
+id = 'test_1'
+operation = 'replace'
+field = 'primary_model_identifiers'
+value = 'Extra'
+";
+    let report = manager
+        .parse_extension_source(Path::new("local.toml"), invalid)
+        .unwrap_err();
+    let diagnostics = report
+        .diagnostics
+        .iter()
+        .map(|d| (d.severity, d.field.as_ref().map(ToString::to_string)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        diagnostics,
+        [
+            (Severity::Error, Some("overrides".to_owned())),
+            (Severity::Error, Some("overrides[0].operation".to_owned())),
+            (Severity::Error, Some("overrides[1].operation".to_owned())),
+        ]
+    );
+}
+
+/// Tests that only loaded extensions which were not staged are considered orphans.
+#[test]
+fn find_orphans() {
+    let loaded = [Extension::test(1).metadata, Extension::test(2).metadata];
+    let staged = HashSet::from([ExtensionID::new("test_1"), ExtensionID::new("test_3")]);
+
+    assert_eq!(
+        orphans::find(&loaded, &staged),
+        [ExtensionID::new("test_2")]
+    );
+}
+
+/// Tests that orphans are kept while extensions which are not unloaded depend on them, directly or
+/// through another orphan which is kept.
+#[test]
+fn find_orphan_dependents() {
+    let id = |name: &str| ExtensionID::new(name);
+    let requires = |names: &[&str]| {
+        names
+            .iter()
+            .map(|name| (id(name), VersionReq::STAR))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let orphans = [id("a"), id("b"), id("c"), id("x")];
+    let dependencies = HashMap::from([
+        (id("d"), requires(&["a"])),
+        (id("a"), requires(&["b"])),
+        (id("x"), requires(&["c"])),
+    ]);
+
+    assert_eq!(
+        orphans::find_dependents(&orphans, &dependencies),
+        HashMap::from([(id("a"), id("d")), (id("b"), id("a"))])
+    );
+}
+
+/// Tests that the content hash of an extension ignores its version and the order of its contents,
+/// but not the contents themselves.
+#[test]
+fn content_hash() {
+    let (extension, drifted_extension) = Extension::test_pair_same_metadata();
+    assert_ne!(extension.content_hash(), drifted_extension.content_hash());
+
+    let mut bumped_extension = extension.clone();
+    bumped_extension.metadata.version = Version::new(2, 0, 0);
+    assert_eq!(extension.content_hash(), bumped_extension.content_hash());
+
+    let mut combined_extension = extension.clone();
+    combined_extension
+        .devices
+        .extend(drifted_extension.devices.clone());
+    let mut reordered_extension = combined_extension.clone();
+    reordered_extension.devices.reverse();
+    assert_eq!(
+        combined_extension.content_hash(),
+        reordered_extension.content_hash()
+    );
+
+    let mut edited_extension = extension.clone();
+    edited_extension.devices[0]
+        .extended_model_identifiers
+        .push("Extra".to_owned());
+    assert_ne!(extension.content_hash(), edited_extension.content_hash());
+}
+
+/// Tests that applying extensions to a snapshot merges and replaces their items as loading them
+/// would, and leaves the snapshot unchanged if the merge policy refuses a conflict.
+#[test]
+fn snapshot_apply() {
+    let config = ExtensionManagerConfig {
+        merge_policy: MergePolicy::Error,
+        ..Default::default()
+    };
+    let (mut original_extension, updated_extension) = Extension::test_pair_different_metadata();
+    original_extension.devices[0]
+        .extended_model_identifiers
+        .push("Original".to_owned());
+    let mut snapshot = DatabaseSnapshot::default();
+    let merger = Merger::new(&config, &original_extension.metadata.id);
+    snapshot.apply(&original_extension, &merger).unwrap();
+    assert_eq!(snapshot.devices, original_extension.devices);
+    assert!(snapshot.staged.contains(&original_extension.metadata.id));
+
+    // Make sure an extension with the same items is merged with them
+    let mut shared_extension = Extension::test_single(2, 1);
+    shared_extension.devices[0]
+        .extended_model_identifiers
+        .push("Shared".to_owned());
+    let shared_id = shared_extension.metadata.id.clone();
+    snapshot
+        .apply(&shared_extension, &Merger::new(&config, &shared_id))
+        .unwrap();
+    assert_eq!(snapshot.devices.len(), 1);
+    assert_eq!(snapshot.devices[0].extensions.len(), 2);
+    assert_eq!(snapshot.devices[0].model_identifiers().count(), 4);
+
+    // Make sure a refused conflict leaves the snapshot unchanged
+    let mut conflicting_extension = Extension::test_single(3, 1);
+    conflicting_extension.devices[0].display_name = "Renamed Device".to_owned();
+    let conflicting_id = conflicting_extension.metadata.id.clone();
+    let before = snapshot.clone();
+    let MergeConflictError(conflicts) = snapshot
+        .apply(
+            &conflicting_extension,
+            &Merger::new(&config, &conflicting_id),
+        )
+        .unwrap_err();
+    assert_eq!(conflicts[0].field, "display_name");
+    assert_eq!(snapshot.devices, before.devices);
+    assert_eq!(snapshot.staged, before.staged);
+
+    // Make sure reloading an extension replaces the items only it provides
+    snapshot.apply(&updated_extension, &merger).unwrap();
+    let mut device_ids = snapshot
+        .devices
+        .iter()
+        .map(|device| device.id.unnamespaced())
+        .collect::<Vec<_>>();
+    device_ids.sort();
+    assert_eq!(device_ids, ["test_1", "test_2"]);
+    assert_eq!(
+        snapshot.devices[0].extensions,
+        HashSet::from([shared_id.clone()])
+    );
+    // Make sure the identifier only the reloaded extension gave the shared device was removed
+    assert_eq!(
+        snapshot.devices[0].extended_model_identifiers,
+        ["test_1_extended", "Shared"]
+    );
+
+    // Make sure nothing is withdrawn if the contribution of another provider is unknown
+    let mut device = before.devices[0].clone();
+    let mut contributions = before.contributions.clone();
+    contributions
+        .get_mut(&device.key())
+        .unwrap()
+        .remove(&shared_id);
+    withdraw_contribution(&mut device, &original_extension.metadata.id, &contributions);
+    assert_eq!(device.model_identifiers().count(), 4);
+}
+
+/// Tests that diffs identify added, removed, and modified items and model identifiers.
+#[test]
+fn extension_diff() {
+    let (original_extension, updated_extension) = Extension::test_pair_different_metadata();
+    let snapshot = DatabaseSnapshot {
+        device_manufacturers: original_extension.device_manufacturers.clone(),
+        device_categories: original_extension.device_categories.clone(),
+        devices: original_extension.devices.clone(),
+        parts: Vec::new(),
+        repair_services: Vec::new(),
+        ..Default::default()
+    };
+
+    // Replacing every item should remove the old items and add the new ones
+    let diff = ExtensionDiff::new(
+        &updated_extension,
+        Some(&original_extension.metadata.version),
+        PlannedAction::Reload,
+        &snapshot,
+    );
+    assert_eq!(diff.old_version.as_deref(), Some("1.0.0"));
+    assert_eq!(diff.new_version, "1.0.1");
+    for items in [
+        &diff.device_manufacturers,
+        &diff.device_categories,
+        &diff.devices,
+    ] {
+        let changes = items
+            .iter()
+            .map(|item| (item.id.as_str(), item.change))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [
+                ("test_1", ItemChange::Removed),
+                ("test_2", ItemChange::Added)
+            ]
+        );
+    }
+    assert_eq!(
+        diff.added_model_identifiers.iter().collect::<Vec<_>>(),
+        ["test_2_extended", "test_2_primary"]
+    );
+    assert_eq!(
+        diff.removed_model_identifiers.iter().collect::<Vec<_>>(),
+        ["test_1_extended", "test_1_primary"]
+    );
+
+    // Changing a field should only modify the item
+    let mut modified_extension = original_extension.clone();
+    modified_extension.devices[0].display_name = "Renamed Device".to_owned();
+    let diff = ExtensionDiff::new(
+        &modified_extension,
+        Some(&original_extension.metadata.version),
+        PlannedAction::Skip,
+        &snapshot,
+    );
+    assert!(diff.device_manufacturers.is_empty());
+    assert_eq!(diff.devices.len(), 1);
+    assert_eq!(diff.devices[0].change, ItemChange::Modified);
+    assert_eq!(diff.devices[0].fields[0].field, "display_name");
+    assert_eq!(diff.devices[0].fields[0].new, "Renamed Device");
+
+    // An identical extension should produce an empty diff
+    let diff = ExtensionDiff::new(
+        &original_extension,
+        Some(&original_extension.metadata.version),
+        PlannedAction::Skip,
+        &snapshot,
+    );
+    assert!(diff.is_empty());
+}
+
+/// Tests that custom fields are declared and checked against their types, both within an
+/// extension and against the fields declared by loaded extensions.
+#[test]
+fn custom_fields() {
+    let toml = r#"
+format_version = 2
+
+[extension]
+id = "fields"
+display_name = "Fields"
+version = "1.0.0"
+
+[[custom_fields]]
+id = "screen_size"
+target = "device"
+display_name = "Screen Size"
+type = "number"
+required = true
+
+[[custom_fields]]
+id = "water_resistance"
+target = "device"
+display_name = "Water Resistance"
+type = "enum"
+values = ["none", "ip67", "ip68"]
+
+[[custom_fields]]
+id = "founded"
+target = "device_manufacturer"
+display_name = "Founded"
+type = "number"
+
+[[device_manufacturers]]
+id = "apple"
+display_name = "Apple"
+custom_fields = { founded = 1976 }
+
+[[device_categories]]
+id = "phone"
+display_name = "Phone"
+
+[[devices]]
+id = "iphone_8"
+display_name = "iPhone 8"
+manufacturer = "apple"
+category = "phone"
+primary_model_identifiers = ["A1863"]
+extended_model_identifiers = []
+custom_fields = { screen_size = 4.7, water_resistance = "ip67" }
+"#;
+
+    let manager = Manager::base_with_context(Default::default());
+    let parse =
+        |filename: &str, source: &str| manager.parse_extension_source(Path::new(filename), source);
+    let (extension, report) = parse("test.toml", toml).unwrap_or_else(|report| panic!("{report}"));
+    assert!(report.diagnostics.is_empty());
+    assert_eq!(extension.custom_fields.len(), 3);
+    assert_eq!(
+        extension.custom_fields[1].field_type,
+        CustomFieldType::Enum(vec![
+            "none".to_owned(),
+            "ip67".to_owned(),
+            "ip68".to_owned()
+        ])
+    );
+    assert_eq!(
+        extension.devices[0].custom_fields.get("screen_size"),
+        Some(&CustomFieldValue::Number(4.7))
+    );
+
+    // Make sure values are checked against their declarations
+    for (from, to) in [
+        ("screen_size = 4.7", "screen_size = 'large'"),
+        ("\"ip67\" }", "\"ip69\" }"),
+        ("screen_size = 4.7, ", ""),
+        ("id = \"founded\"", "id = \"Founded\""),
+        (
+            "type = \"number\"\n\n[[device",
+            "type = \"number\"\nvalues = [\"1976\"]\n\n[[device",
+        ),
+    ] {
+        assert!(toml.contains(from));
+        let report = parse("test.toml", &toml.replace(from, to)).unwrap_err();
+        assert!(report.has_errors(), "{from} -> {to}");
+    }
+
+    // Make sure the CSV column holds the same values
+    let header = toml
+        .split("[[devices]]")
+        .next()
+        .unwrap()
+        .lines()
+        .map(|line| format!("# {line}\n"))
+        .collect::<String>();
+    let csv = format!(
+        "{header}id,display_name,manufacturer,category,primary_model_identifiers,\
+        extended_model_identifiers,custom_fields\n\
+        iphone_8,iPhone 8,apple,phone,A1863,,\"screen_size = 4.7, water_resistance = 'ip67'\"\n"
+    );
+    let (csv_extension, _) = parse("test.csv", &csv).unwrap_or_else(|report| panic!("{report}"));
+    assert_eq!(csv_extension.content_hash(), extension.content_hash());
+
+    // Make sure exported extensions keep their declarations and values
+    let exported = export::to_toml(
+        &extension.metadata,
+        None,
+        &extension.dependencies,
+        extension.custom_fields.clone(),
+        extension.device_manufacturers.clone(),
+        extension.device_categories.clone(),
+        extension.devices.clone(),
+        Vec::new(),
+        Vec::new(),
+    )
+    .unwrap();
+    let (exported_extension, _) =
+        parse("export.toml", &exported).unwrap_or_else(|report| panic!("{report}"));
+    assert_eq!(exported_extension.content_hash(), extension.content_hash());
+
+    // Make sure fields shared with loaded extensions must agree on their type
+    let other_id = ExtensionID::new("other");
+    let declaration = |id: &str, field_type| CustomFieldDefinition {
+        id: id.to_owned(),
+        target: CustomFieldTarget::Device,
+        display_name: id.to_owned(),
+        field_type,
+        required: false,
+    };
+    let existing = [
+        (
+            other_id.clone(),
+            declaration("screen_size", CustomFieldType::String),
+        ),
+        (
+            other_id.clone(),
+            declaration("refurbished", CustomFieldType::Bool),
+        ),
+    ];
+    let diagnostics = custom_fields::check_against_database(&extension, &existing);
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].message.contains("'other'"));
+
+    // Make sure values may use fields declared by loaded extensions, but not undeclared fields
+    let mut extended = extension.clone();
+    extended.devices[0]
+        .custom_fields
+        .insert("refurbished".to_owned(), CustomFieldValue::Bool(true));
+    assert_eq!(
+        custom_fields::check_against_database(&extended, &existing[1..]).len(),
+        0
+    );
+    let values = &mut extended.devices[0].custom_fields;
+    values.insert("refurbished".to_owned(), CustomFieldValue::Number(1.0));
+    values.insert(
+        "battery".to_owned(),
+        CustomFieldValue::String("A1821".to_owned()),
+    );
+    assert_eq!(
+        custom_fields::check_against_database(&extended, &existing[1..]).len(),
+        2
+    );
+
+    // Make sure custom fields are combined when merging, and differing values are conflicts
+    let (first_id, second_id) = (ExtensionID::new("first"), ExtensionID::new("second"));
+    let mut existing_manufacturer = DeviceManufacturer::test(1, &first_id);
+    let mut staged_manufacturer = DeviceManufacturer::test(1, &second_id);
+    existing_manufacturer.custom_fields = CustomFields::from([
+        ("founded".to_owned(), CustomFieldValue::Number(1976.0)),
+        (
+            "country".to_owned(),
+            CustomFieldValue::String("US".to_owned()),
+        ),
+    ]);
+    staged_manufacturer.custom_fields =
+        CustomFields::from([("founded".to_owned(), CustomFieldValue::Number(1977.0))]);
+    let config = ExtensionManagerConfig::default();
+    let conflicts = Merger::new(&config, &second_id)
+        .device_manufacturer(&mut staged_manufacturer, existing_manufacturer);
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].field, "custom_fields");
+    assert_eq!(conflicts[0].staged, "founded = 1977");
+    assert_eq!(staged_manufacturer.custom_fields.len(), 2);
+}
+
+/// Tests that device variants are read with their attributes, checked for identifier collisions,
+/// and found by their own model identifiers.
+#[test]
+fn device_variants() {
+    let toml = r#"
+format_version = 2
+
+[extension]
+id = "variants"
+display_name = "Variants"
+version = "1.0.0"
+
+[[device_manufacturers]]
+id = "apple"
+display_name = "Apple"
+
+[[device_categories]]
+id = "phone"
+display_name = "Phone"
+
+[[devices]]
+id = "iphone_4"
+display_name = "iPhone 4"
+manufacturer = "apple"
+category = "phone"
+primary_model_identifiers = ["A1332"]
+extended_model_identifiers = []
+
+[[devices.variants]]
+id = "mc603"
+capacity = "16GB"
+color = "Black"
+carrier = "AT&T"
+region = "US"
+model_identifiers = ["MC603LL/A"]
+
+[[devices.variants]]
+id = "mc605"
+capacity = "32GB"
+color = "Black"
+carrier = "AT&T"
+region = "US"
+model_identifiers = ["MC605LL/A"]
+"#;
+
+    let parse = |source: &str| {
+        Manager::base_with_context(Default::default())
+            .parse_extension_source(Path::new("test.toml"), source)
+    };
+    let (extension, report) = parse(toml).unwrap_or_else(|report| panic!("{report}"));
+    assert!(report.diagnostics.is_empty());
+    let device = &extension.devices[0];
+    assert_eq!(device.variants.len(), 2);
+    assert_eq!(device.variants[1].to_string(), "32GB, Black, AT&T, US");
+
+    // Make sure identifiers are matched to their variant, or to the device itself
+    let variant = device.match_identifier("MC605LL/A").unwrap().unwrap();
+    assert_eq!(variant.id, "mc605");
+    assert_eq!(device.match_identifier("A1332"), Some(None));
+    assert_eq!(device.match_identifier("MC604LL/A"), None);
+    assert_eq!(device.model_identifiers().count(), 3);
+
+    // Make sure duplicate variants and identifiers shared with a variant are reported
+    let report = parse(&toml.replace("id = \"mc605\"", "id = \"mc603\"")).unwrap_err();
+    assert!(report.has_errors());
+    let (_, report) = parse(&toml.replace("[\"MC605LL/A\"]", "[\"A1332\"]")).unwrap();
+    assert_eq!(report.diagnostics.len(), 1);
+    assert_eq!(report.diagnostics[0].severity, Severity::Warning);
+
+    // Make sure variant identifiers collide with other devices in the database
+    let mut other_device = Device::test(
+        1,
+        &ExtensionID::new("other"),
+        &device.manufacturer,
+        &device.category,
+    );
+    other_device.primary_model_identifiers = vec!["MC603LL/A".to_owned()];
+    let diagnostics =
+        identifiers::check_database(&extension, &[other_device], CollisionSeverity::Error);
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].message.contains("MC603LL/A"));
+
+    // Make sure the order of variants does not change the hash, but their attributes do
+    let mut reordered = extension.clone();
+    reordered.devices[0].variants.reverse();
+    assert_eq!(reordered.content_hash(), extension.content_hash());
+    reordered.devices[0].variants[0].color = Some("White".to_owned());
+    assert_ne!(reordered.content_hash(), extension.content_hash());
+
+    // Make sure variants survive an export
+    let exported = export::to_toml(
+        &extension.metadata,
+        None,
+        &extension.dependencies,
+        Vec::new(),
+        extension.device_manufacturers.clone(),
+        extension.device_categories.clone(),
+        reordered.devices.clone(),
+        Vec::new(),
+        Vec::new(),
+    )
+    .unwrap();
+    let (exported_extension, _) = parse(&exported).unwrap_or_else(|report| panic!("{report}"));
+    assert_eq!(exported_extension.devices[0].variants[0].id, "mc603");
+    assert_eq!(exported_extension.content_hash(), reordered.content_hash());
+
+    // Make sure variants are combined when merging, and differing variants are conflicts
+    let second_id = ExtensionID::new("second");
+    let mut staged = device.clone();
+    staged.extensions = HashSet::from([second_id.clone()]);
+    staged.variants.truncate(1);
+    staged.variants[0].color = Some("White".to_owned());
+    let config = ExtensionManagerConfig::default();
+    let conflicts = Merger::new(&config, &second_id).device(&mut staged, device.clone());
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].field, "variants");
+    assert_eq!(staged.variants.len(), 2);
+    assert_eq!(staged.variants[0].color.as_deref(), Some("White"));
+}
+
+/// Tests that the optional manifest fields are read, validated, and exported, and that extensions
+/// which do not support the running version of TechTriage cannot be staged.
+#[test]
+fn extension_manifest() {
+    let toml = r#"
+format_version = 2
+
+[extension]
+id = "manifest"
+display_name = "Manifest"
+version = "1.0.0"
+author = "Example Repair Co."
+license = "MIT"
+description = "Devices repaired by Example Repair Co."
+homepage = "https://example.com/extensions"
+techtriage = "*"
+"#;
+
+    let mut manager = Manager::base_with_context(Default::default());
+    let parse = |source: &str| {
+        Manager::base_with_context(Default::default())
+            .parse_extension_source(Path::new("test.toml"), source)
+    };
+    let (extension, report) = parse(toml).unwrap_or_else(|report| panic!("{report}"));
+    assert!(report.diagnostics.is_empty());
+    assert_eq!(
+        extension.metadata.author.as_deref(),
+        Some("Example Repair Co.")
+    );
+    assert_eq!(extension.metadata.license.as_deref(), Some("MIT"));
+    assert_eq!(
+        extension.metadata.homepage.as_deref(),
+        Some("https://example.com/extensions")
+    );
+    assert_eq!(extension.metadata.techtriage, Some(VersionReq::STAR));
+
+    // Make sure the manifest is part of the content hash, so editing it counts as a change
+    let mut edited = extension.clone();
+    edited.metadata.license = Some("Apache-2.0".to_owned());
+    assert_ne!(edited.content_hash(), extension.content_hash());
+
+    // Make sure the manifest survives an export
+    let exported = export::to_toml(
+        &extension.metadata,
+        None,
+        &extension.dependencies,
+        Vec::new(),
+        Vec::new(),
+        Vec::new(),
+        Vec::new(),
+        Vec::new(),
+        Vec::new(),
+    )
+    .unwrap();
+    let (exported_extension, _) = parse(&exported).unwrap_or_else(|report| panic!("{report}"));
+    assert_eq!(exported_extension.metadata, extension.metadata);
+
+    // Make sure invalid manifest fields are reported
+    for (from, to) in [
+        ("https://example.com/extensions", "example.com"),
+        ("techtriage = \"*\"", "techtriage = \"latest\""),
+        ("license = \"MIT\"", "license = \"\""),
+    ] {
+        let report = parse(&toml.replace(from, to)).unwrap_err();
+        assert!(report.has_errors(), "{from} -> {to}");
+    }
+
+    // Make sure the version requirement is checked against the running version
+    manager.stage_extension(extension.clone()).unwrap();
+    let mut incompatible = extension;
+    incompatible.metadata.techtriage = Some(VersionReq::parse(">=999.0.0").unwrap());
+    let error = manager.stage_extension(incompatible).unwrap_err();
+    assert!(error.to_string().contains(env!("CARGO_PKG_VERSION")));
+}
+
+/// Tests that load reports count the records of each extension and serialize every outcome.
+#[test]
+fn load_report() {
+    let extension = Extension::test_single(1, 1);
+    let mut report = LoadReport::default();
+    report.record(
+        &extension.metadata,
+        ExtensionOutcome::Reloaded {
+            previous_version: "0.9.0".to_owned(),
+        },
+        RecordCounts::default(),
+        Duration::from_millis(12),
+    );
+    report.record(
+        &extension.metadata,
+        ExtensionOutcome::Failed {
+            error: "Dependency 'base' is not available".to_owned(),
+        },
+        RecordCounts::skipped(&extension),
+        Duration::ZERO,
+    );
+
+    // Make sure skipped records are counted per table
+    let skipped = &report.extensions[1].records;
+    assert_eq!(skipped.devices.skipped, 1);
+    assert_eq!(skipped.total().skipped, 3);
+    assert_eq!(skipped.total().created, 0);
+
+    // Make sure the outcome is flattened into each extension, and durations are in milliseconds
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["extensions"][0]["id"], "test_1");
+    assert_eq!(json["extensions"][0]["outcome"], "reloaded");
+    assert_eq!(json["extensions"][0]["previous_version"], "0.9.0");
+    assert_eq!(json["extensions"][0]["duration_ms"], 12.0);
+    assert_eq!(json["extensions"][1]["outcome"], "failed");
+    assert_eq!(json["extensions"][1]["records"]["devices"]["skipped"], 1);
+    assert!(json.get("diffs").is_none());
+
+    assert_eq!(
+        report.extensions[0].to_string(),
+        "Extension 'test_1' v1.0.0 reloaded over v0.9.0 in 12 ms, 0 record(s) created, 0 \
+        merged, 0 skipped."
+    );
+}
+
+/// Tests that parts are read, validated, hashed, exported, merged, and diffed, and that references
+/// to devices which do not exist are caught at load time.
+#[test]
+fn parts() {
+    let toml = r#"
+format_version = 2
+
+[extension]
+id = "parts"
+display_name = "Parts"
+version = "1.0.0"
+
+[[device_manufacturers]]
+id = "apple"
+display_name = "Apple"
+
+[[device_categories]]
+id = "phone"
+display_name = "Phone"
+
+[[devices]]
+id = "iphone_8"
+display_name = "iPhone 8"
+manufacturer = "apple"
+category = "phone"
+primary_model_identifiers = ["A1863"]
+extended_model_identifiers = []
+
+[[parts]]
+id = "iphone_8_screen"
+display_name = "iPhone 8 Screen"
+kind = "screen"
+supplier_skus = ["IFX-8-LCD"]
+compatible_devices = ["iphone_8"]
+
+[[parts]]
+id = "iphone_8_battery"
+display_name = "iPhone 8 Battery"
+kind = "battery"
+compatible_devices = ["iphone_8", "iphone_se_2"]
+"#;
+
+    let parse = |source: &str| {
+        Manager::base_with_context(Default::default())
+            .parse_extension_source(Path::new("test.toml"), source)
+    };
+    let (extension, report) = parse(toml).unwrap_or_else(|report| panic!("{report}"));
+    assert_eq!(extension.parts.len(), 2);
+    assert_eq!(extension.parts[0].kind, PartKind::Screen);
+    assert!(extension.parts[1].is_compatible_with(&DeviceUniqueID::new("iphone_se_2")));
+
+    // Make sure devices from outside of the extension are flagged, and caught at load time
+    assert_eq!(report.diagnostics.len(), 1);
+    assert_eq!(report.diagnostics[0].severity, Severity::Warning);
+    let existing_devices = HashSet::from([DeviceUniqueID::new("iphone_se_2")]);
+    let references = |devices| {
+        validation::check_references(&extension, &HashSet::new(), &HashSet::new(), devices)
+    };
+    assert!(references(&existing_devices).is_empty());
+    let diagnostics = references(&HashSet::new());
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].message.contains("iphone_se_2"));
+
+    // Make sure duplicate parts and unknown kinds are errors
+    let report = parse(&toml.replace("\"iphone_8_battery\"", "\"iphone_8_screen\"")).unwrap_err();
+    assert!(report.has_errors());
+    assert!(parse(&toml.replace("\"battery\"", "\"antenna\"")).is_err());
+
+    // Make sure parts change the hash, and survive an export
+    let mut without_parts = extension.clone();
+    without_parts.parts.clear();
+    assert_ne!(without_parts.content_hash(), extension.content_hash());
+    let exported = export::to_toml(
+        &extension.metadata,
+        None,
+        &extension.dependencies,
+        Vec::new(),
+        extension.device_manufacturers.clone(),
+        extension.device_categories.clone(),
+        extension.devices.clone(),
+        extension.parts.clone(),
+        Vec::new(),
+    )
+    .unwrap();
+    let (exported_extension, _) = parse(&exported).unwrap_or_else(|report| panic!("{report}"));
+    assert_eq!(
+        exported_extension.parts[0].id.unnamespaced(),
+        "iphone_8_battery"
+    );
+    assert_eq!(exported_extension.content_hash(), {
+        let mut sorted = extension.clone();
+        sorted.parts.reverse();
+        sorted.content_hash()
+    });
+
+    // Make sure compatible devices and SKUs are combined when merging, and kinds conflict
+    let second_id = ExtensionID::new("second");
+    let existing = Part::test(1, &extension.metadata.id, &extension.devices[0].id);
+    let mut staged = Part::test(1, &second_id, &DeviceUniqueID::new("iphone_se_2"));
+    staged.kind = PartKind::Battery;
+    let config = ExtensionManagerConfig::default();
+    let conflicts = Merger::new(&config, &second_id).part(&mut staged, existing.clone());
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].field, "kind");
+    assert_eq!(staged.compatible_devices.len(), 2);
+    assert_eq!(staged.supplier_skus.len(), 1);
+    assert_eq!(staged.extensions.len(), 2);
+
+    // Make sure changes to parts are previewed
+    let mut snapshot_part = extension.parts[0].clone();
+    snapshot_part.kind = PartKind::Camera;
+    let snapshot = DatabaseSnapshot {
+        device_manufacturers: extension.device_manufacturers.clone(),
+        device_categories: extension.device_categories.clone(),
+        devices: extension.devices.clone(),
+        parts: vec![snapshot_part],
+        repair_services: Vec::new(),
+        ..Default::default()
+    };
+    let diff = ExtensionDiff::new(&extension, None, PlannedAction::Reload, &snapshot);
+    assert_eq!(diff.parts.len(), 2);
+    assert_eq!(diff.parts[0].change, ItemChange::Added);
+    assert_eq!(diff.parts[1].change, ItemChange::Modified);
+    assert_eq!(diff.parts[1].fields[0].field, "kind");
+
+    // Make sure parts are counted as skipped records
+    assert_eq!(RecordCounts::skipped(&extension).parts.skipped, 2);
+}
+
+/// Tests that repair services are read, validated, merged, and exported, and that price lists only
+/// include the services which apply to a device.
+#[test]
+fn repair_services() {
+    let toml = r#"
+format_version = 2
+
+[extension]
+id = "services"
+display_name = "Services"
+version = "1.0.0"
+
+[[device_manufacturers]]
+id = "apple"
+display_name = "Apple"
+
+[[device_categories]]
+id = "phone"
+display_name = "Phone"
+
+[[device_categories]]
+id = "tablet"
+display_name = "Tablet"
+
+[[devices]]
+id = "iphone_8"
+display_name = "iPhone 8"
+manufacturer = "apple"
+category = "phone"
+primary_model_identifiers = ["A1863"]
+extended_model_identifiers = []
+
+[[devices]]
+id = "ipad_air"
+display_name = "iPad Air"
+manufacturer = "apple"
+category = "tablet"
+primary_model_identifiers = ["A1474"]
+extended_model_identifiers = []
+
+[[parts]]
+id = "iphone_8_screen"
+display_name = "iPhone 8 Screen"
+kind = "screen"
+compatible_devices = ["iphone_8"]
+
+[[repair_services]]
+id = "phone_screen_replacement"
+display_name = "Phone Screen Replacement"
+default_labor_minutes = 45
+default_price = 12999
+required_part_kinds = ["screen"]
+device_categories = ["phone"]
+
+[[repair_services]]
+id = "ipad_air_battery_replacement"
+display_name = "iPad Air Battery Replacement"
+default_labor_minutes = 90
+default_price = 9900
+required_part_kinds = ["battery"]
+devices = ["ipad_air"]
+"#;
+
+    let parse = |source: &str| {
+        Manager::base_with_context(Default::default())
+            .parse_extension_source(Path::new("test.toml"), source)
+    };
+    let (extension, report) = parse(toml).unwrap_or_else(|report| panic!("{report}"));
+    assert!(report.diagnostics.is_empty());
+    assert_eq!(extension.repair_services.len(), 2);
+    assert_eq!(extension.repair_services[0].default_price, 12999);
+
+    // Make sure services are scoped to their categories and devices
+    let (iphone, ipad) = (&extension.devices[0], &extension.devices[1]);
+    let categories = CategoryTree::new(&extension.device_categories);
+    let price_list = PriceList::new(
+        iphone.clone(),
+        extension.repair_services.clone(),
+        &extension.parts,
+        &categories,
+    );
+    assert_eq!(price_list.entries.len(), 1);
+    assert_eq!(price_list.entries[0].parts.len(), 1);
+    assert!(price_list.entries[0].missing_part_kinds().is_empty());
+    let price_list = PriceList::new(
+        ipad.clone(),
+        extension.repair_services.clone(),
+        &extension.parts,
+        &categories,
+    );
+    assert_eq!(price_list.entries.len(), 1);
+    assert_eq!(
+        price_list.entries[0].missing_part_kinds(),
+        [PartKind::Battery]
+    );
+
+    // Make sure services without a scope, and unknown categories and devices, are reported
+    let unscoped = toml.replace("devices = [\"ipad_air\"]", "");
+    assert!(parse(&unscoped).unwrap_err().has_errors());
+    let diagnostics = validation::check_references(
+        &extension,
+        &HashSet::new(),
+        &HashSet::new(),
+        &HashSet::new(),
+    );
+    assert!(diagnostics.is_empty());
+    let mut unknown = extension.clone();
+    unknown.device_categories.clear();
+    let diagnostics =
+        validation::check_references(&unknown, &HashSet::new(), &HashSet::new(), &HashSet::new());
+    assert_eq!(diagnostics.len(), 3);
+    assert!(diagnostics[2].message.contains("phone_screen_replacement"));
+
+    // Make sure differing prices are conflicts, and scopes are combined when merging
+    let second_id = ExtensionID::new("second");
+    let existing = extension.repair_services[0].clone();
+    let mut staged = RepairService::test(1, &second_id, &ipad.category);
+    staged.id = existing.id.clone();
+    staged.display_name = existing.display_name.clone();
+    staged.default_labor_minutes = existing.default_labor_minutes;
+    let config = ExtensionManagerConfig::default();
+    let conflicts = Merger::new(&config, &second_id).repair_service(&mut staged, existing);
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].field, "default_price");
+    assert_eq!(staged.default_price, 4999);
+    assert_eq!(staged.device_categories.len(), 2);
+
+    // Make sure services change the hash, and survive an export
+    let mut cheaper = extension.clone();
+    cheaper.repair_services[0].default_price -= 1000;
+    assert_ne!(cheaper.content_hash(), extension.content_hash());
+    let exported = export::to_toml(
+        &extension.metadata,
+        None,
+        &extension.dependencies,
+        Vec::new(),
+        extension.device_manufacturers.clone(),
+        extension.device_categories.clone(),
+        extension.devices.clone(),
+        extension.parts.clone(),
+        extension.repair_services.clone(),
+    )
+    .unwrap();
+    let (exported_extension, _) = parse(&exported).unwrap_or_else(|report| panic!("{report}"));
+    assert_eq!(
+        exported_extension.repair_services[0].id.unnamespaced(),
+        "ipad_air_battery_replacement"
+    );
+    assert_eq!(exported_extension.content_hash(), extension.content_hash());
+}
+
+/// Tests that device categories can be nested, that cycles in the hierarchy are rejected, and that
+/// repair services are inherited by the categories below the ones they apply to.
+#[test]
+fn category_hierarchy() {
+    let toml = r#"
+format_version = 2
+
+[extension]
+id = "hierarchy"
+display_name = "Hierarchy"
+version = "1.0.0"
+
+[[device_manufacturers]]
+id = "apple"
+display_name = "Apple"
+
+[[device_categories]]
+id = "phone"
+display_name = "Phone"
+
+[[device_categories]]
+id = "smartphone"
+display_name = "Smartphone"
+parent = "phone"
+
+[[devices]]
+id = "iphone_8"
+display_name = "iPhone 8"
+manufacturer = "apple"
+category = "smartphone"
+primary_model_identifiers = ["A1863"]
+extended_model_identifiers = []
+
+[[repair_services]]
+id = "phone_screen_replacement"
+display_name = "Phone Screen Replacement"
+default_labor_minutes = 45
+default_price = 12999
+device_categories = ["phone"]
+"#;
+
+    let parse = |source: &str| {
+        Manager::base_with_context(Default::default())
+            .parse_extension_source(Path::new("test.toml"), source)
+    };
+    let (extension, report) = parse(toml).unwrap_or_else(|report| panic!("{report}"));
+    assert!(report.diagnostics.is_empty());
+    let (phone, smartphone) = (
+        &extension.device_categories[0].id,
+        &extension.device_categories[1].id,
+    );
+    assert_eq!(extension.device_categories[1].parent.as_ref(), Some(phone));
+
+    // Make sure the tree is walked upwards, and services are inherited
+    let categories = CategoryTree::new(&extension.device_categories);
+    assert_eq!(categories.ancestors(smartphone), [smartphone, phone]);
+    assert!(categories.is_within(smartphone, phone));
+    assert!(!categories.is_within(phone, smartphone));
+    let iphone = &extension.devices[0];
+    let service = &extension.repair_services[0];
+    assert!(service.applies_to(iphone, &categories));
+    assert!(!service.applies_to(iphone, &CategoryTree::default()));
+
+    // Make sure cycles are reported once, and unknown parents are flagged
+    let cycle = toml.replace(
+        "display_name = \"Phone\"",
+        "display_name = \"Phone\"\nparent = \"smartphone\"",
+    );
+    let report = parse(&cycle).unwrap_err();
+    assert_eq!(report.count(Severity::Error), 1);
+    assert!(report.diagnostics[0]
+        .message
+        .contains("phone -> smartphone -> phone"));
+    let own_parent = toml.replace("parent = \"phone\"", "parent = \"smartphone\"");
+    assert!(parse(&own_parent).unwrap_err().has_errors());
+    let unknown_parent = toml.replace("parent = \"phone\"", "parent = \"gadget\"");
+    let (_, report) = parse(&unknown_parent).unwrap_or_else(|report| panic!("{report}"));
+    assert_eq!(report.count(Severity::Warning), 1);
+
+    // Make sure parents are checked against the database at load time
+    let mut orphan = extension.clone();
+    orphan.device_categories.remove(0);
+    orphan.repair_services.clear();
+    let diagnostics =
+        validation::check_references(&orphan, &HashSet::new(), &HashSet::new(), &HashSet::new());
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].message.contains("unknown parent category"));
+    let config = ExtensionManagerConfig::default();
+    let merger = Merger::new(&config, &extension.metadata.id);
+    assert!(validation::check_category_hierarchy(&extension, &[], &merger).is_empty());
+    let mut existing = DeviceCategory::test(1, &ExtensionID::new("other"));
+    existing.id = DeviceCategoryUniqueID::new("gadget");
+    existing.parent = Some(smartphone.clone());
+    let mut nested = extension.clone();
+    nested.device_categories[0].parent = Some(existing.id.clone());
+    let diagnostics = validation::check_category_hierarchy(&nested, &[existing], &merger);
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0]
+        .message
+        .contains("gadget -> smartphone -> phone -> gadget"));
+
+    // Make sure a cycle is found if the merge policy keeps an existing parent which the extension
+    // removes
+    let mut existing = extension.device_categories[0].clone();
+    existing.extensions = HashSet::from([ExtensionID::new("other")]);
+    existing.parent = Some(smartphone.clone());
+    let first_wins = ExtensionManagerConfig {
+        merge_policy: MergePolicy::FirstWins,
+        ..Default::default()
+    };
+    let diagnostics = validation::check_category_hierarchy(
+        &extension,
+        std::slice::from_ref(&existing),
+        &Merger::new(&first_wins, &extension.metadata.id),
+    );
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0]
+        .message
+        .contains("phone -> smartphone -> phone"));
+    assert!(validation::check_category_hierarchy(&extension, &[existing], &merger).is_empty());
+
+    // Make sure differing parents are conflicts
+    let second_id = ExtensionID::new("second");
+    let mut staged = extension.device_categories[1].clone();
+    staged.parent = None;
+    staged.extensions = HashSet::from([second_id.clone()]);
+    let conflicts = Merger::new(&config, &second_id)
+        .device_category(&mut staged, extension.device_categories[1].clone());
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].field, "parent");
+
+    // Make sure parents change the hash, and survive an export
+    let mut flat = extension.clone();
+    flat.device_categories[1].parent = None;
+    assert_ne!(flat.content_hash(), extension.content_hash());
+    let exported = export::to_toml(
+        &extension.metadata,
+        None,
+        &extension.dependencies,
+        Vec::new(),
+        extension.device_manufacturers.clone(),
+        extension.device_categories.clone(),
+        extension.devices.clone(),
+        extension.parts.clone(),
+        extension.repair_services.clone(),
+    )
+    .unwrap();
+    let (exported_extension, _) = parse(&exported).unwrap_or_else(|report| panic!("{report}"));
+    assert_eq!(exported_extension.content_hash(), extension.content_hash());
+}
+
+/// Tests that an extension can be loaded without generating any conflicts.
+/// This test is meant to be a shortcut used by other tests, rather than a standalone test.
+async fn load_and_check_no_conflicts(
+    db: &Database,
+    auto_reload: bool,
+    extension: &Extension,
+    only_extension: bool,
+    remove_after: bool,
+) {
+    // Load the extension into the database
+    let manager = Manager::with_extensions(auto_reload, [extension.clone()]);
+    let load_result = manager.load_extensions(db).await.unwrap();
+    // Make sure there were no conflicts or failures
+    assert!(load_result.conflicts.is_empty());
+    assert!(load_result.failures.is_empty());
+    // Make sure the report shows the extension as loaded with every record written
+    assert_eq!(load_result.extensions.len(), 1);
+    assert_eq!(load_result.extensions[0].outcome, ExtensionOutcome::Loaded);
+    let records = load_result.extensions[0].records.total();
+    assert_eq!(
+        records.created + records.merged,
+        RecordCounts::skipped(extension).total().skipped
+    );
+    // Make sure the extension was loaded correctly
+    // * The additional check for exclusivity is not entirely necessary, but it is included to
+    // * provide some extra certainty of the result.
+    db.contains(extension, only_extension).await;
+
+    // Remove the extension if requested
+    if remove_after {
+        db.unload_extension(&extension.metadata.id).await.unwrap();
+    }
+}
+
+impl Extension {
+    /// Creates a basic extension with no contents for testing purposes.
+    /// Can be modified to test different scenarios.
+    fn test(num: u32) -> Self {
+        Self {
+            metadata: Metadata {
+                id: ExtensionID::new(format!("test_{num}")),
+                display_name: format!("Test Extension {num}"),
+                version: Version::new(1, 0, 0),
+                author: None,
+                license: None,
+                description: None,
+                homepage: None,
+                techtriage: None,
+            },
+            dependencies: BTreeMap::new(),
+            custom_fields: Vec::new(),
+            device_manufacturers: Vec::new(),
+            device_categories: Vec::new(),
+            devices: Vec::new(),
+            parts: Vec::new(),
+            repair_services: Vec::new(),
+            overrides: Vec::new(),
+        }
+    }
+
+    /// Creates a single basic extension with contents.
+    /// Can be modified to test different scenarios.
+    fn test_single(extension_num: u32, contents_num: u32) -> Self {
+        // Create an empty extension.
+        let mut extension = Self::test(extension_num);
+
+        // Populate the extension with one device manufacturer, device category, and device.
+        let device_manufacturer = DeviceManufacturer::test(contents_num, &extension.metadata.id);
+        let device_category = DeviceCategory::test(contents_num, &extension.metadata.id);
+        let device = Device::test(
+            contents_num,
+            &extension.metadata.id,
+            &device_manufacturer.id,
+            &device_category.id,
+        );
+
+        extension.device_manufacturers.push(device_manufacturer);
+        extension.device_categories.push(device_category);
+        extension.devices.push(device);
+
+        extension
+    }
+
+    /// Writes the extension as it would be exported, in the current TOML format.
+    fn export(&self) -> String {
+        export::to_toml(
+            &self.metadata,
+            None,
+            &self.dependencies,
+            self.custom_fields.clone(),
+            self.device_manufacturers.clone(),
+            self.device_categories.clone(),
+            self.devices.clone(),
+            self.parts.clone(),
+            self.repair_services.clone(),
+        )
+        .unwrap()
+    }
+
+    /// Adds a dependency on another extension with the given version requirement.
+    fn depend_on(&mut self, other: &Extension, requirement: &str) {
+        self.dependencies.insert(
+            other.metadata.id.clone(),
+            VersionReq::parse(requirement).unwrap(),
+        );
+    }
+
+    /// Creates two basic extensions with the same metadata and different contents.
+    fn test_pair_same_metadata() -> (Self, Self) {
+        (Self::test_single(1, 1), Self::test_single(1, 2))
+    }
+
+    /// Creates two basic extensions with the same ID, a different version, and different contents.
+    fn test_pair_different_metadata() -> (Self, Self) {
+        let extension_1 = Self::test_single(1, 1);
+        let mut extension_2 = Self::test_single(1, 2);
+        extension_2.metadata.version = Version::new(1, 0, 1);
+
+        (extension_1, extension_2)
+    }
+
+    /// Creates two basic extensions with a different ID, the same version, and the same contents.
+    fn test_pair_same_contents() -> (Self, Self) {
+        (Self::test_single(1, 1), Self::test_single(2, 1))
+    }
+}
+
+impl Manager {
+    /// Creates a manager for the provided extensions.
+    fn with_extensions(auto_reload: bool, extensions: impl IntoIterator<Item = Extension>) -> Self {
+        let mut manager = Self::base_with_context(ExtensionManagerConfig {
+            auto_reload,
+            ..Default::default()
+        });
+        for extension in extensions {
+            manager.stage_extension(extension).unwrap();
+        }
+
+        manager
+    }
+}
+
+impl LoadConflict {
+    /// Creates a conflict between a loaded and a staged extension.
+    fn test(kind: ConflictKind, loaded: &Extension, staged: &Extension) -> Self {
+        Self {
+            id: loaded.metadata.id.clone(),
+            kind,
+            old_version: loaded.metadata.version.clone(),
+            new_version: staged.metadata.version.clone(),
+        }
+    }
+
+    /// Creates a conflict indicating that the given extension is already loaded and has not
+    /// changed.
+    fn unchanged(extension: &Extension) -> Self {
+        Self::test(ConflictKind::Unchanged, extension, extension)
+    }
+
+    /// Creates a conflict indicating that the given extension is already loaded and its contents
+    /// have changed, but its version has not.
+    fn content_drift(loaded: &Extension, staged: &Extension) -> Self {
+        Self::test(ConflictKind::ContentDrift, loaded, staged)
+    }
+
+    /// Creates a conflict indicating that the given extension is already loaded with an older
+    /// version.
+    fn upgrade(loaded: &Extension, staged: &Extension) -> Self {
+        Self::test(ConflictKind::Upgrade, loaded, staged)
+    }
+
+    /// Creates a conflict indicating that the given extension is already loaded with a newer
+    /// version.
+    fn downgrade(loaded: &Extension, staged: &Extension) -> Self {
+        Self::test(ConflictKind::Downgrade, loaded, staged)
     }
 }
@@ -0,0 +1,898 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+
+use log::{error, warn};
+use semver::{Version, VersionReq};
+use serde::{Serialize, Serializer};
+
+use super::custom_fields;
+use super::identifiers::{self, CollisionSeverities};
+use super::manager::InventoryExtensionToml;
+use super::merging::Merger;
+use super::overrides;
+use super::Extension;
+use crate::models::common::{
+    DeviceCategory, DeviceCategoryUniqueID, DeviceManufacturerUniqueID, DeviceUniqueID, UniqueID,
+};
+
+/// How serious a problem found in an extension is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The extension cannot be loaded.
+    Error,
+    /// The extension can be loaded, but probably does not do what its author intended.
+    Warning,
+}
+
+/// A position within an extension file. Both the line and column start at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// One step of a [`FieldPath`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// The path to a field within an extension, such as `devices[3].manufacturer`.
+/// Used to locate a diagnostic within the source file after validation has finished.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldPath(pub(super) Vec<FieldSegment>);
+
+/// A single problem found while parsing or validating an extension.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub field: Option<FieldPath>,
+    pub location: Option<Location>,
+}
+
+/// All of the problems found in a single extension file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationReport {
+    pub path: PathBuf,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Location {
+    /// Converts a byte offset into a line and column within the given source.
+    pub fn from_offset(source: &str, offset: usize) -> Self {
+        let offset = offset.min(source.len());
+        let preceding = &source[..offset];
+        let line = preceding.matches('\n').count() + 1;
+        let line_start = preceding.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let column = preceding[line_start..].chars().count() + 1;
+
+        Self { line, column }
+    }
+}
+
+impl FieldPath {
+    /// Creates a path to a top-level field.
+    pub fn key(key: impl Into<String>) -> Self {
+        Self(vec![FieldSegment::Key(key.into())])
+    }
+
+    /// Creates a path to an element of a top-level array.
+    pub fn element(key: impl Into<String>, index: usize) -> Self {
+        Self::key(key).index(index)
+    }
+
+    /// Extends the path with a table key.
+    pub fn then(mut self, key: impl Into<String>) -> Self {
+        self.0.push(FieldSegment::Key(key.into()));
+        self
+    }
+
+    /// Extends the path with an array index.
+    pub fn index(mut self, index: usize) -> Self {
+        self.0.push(FieldSegment::Index(index));
+        self
+    }
+
+    /// Gets the index of the device which the field belongs to, if any.
+    pub fn device_index(&self) -> Option<usize> {
+        match self.0.as_slice() {
+            [FieldSegment::Key(key), FieldSegment::Index(index), ..] if key == "devices" => {
+                Some(*index)
+            }
+            _ => None,
+        }
+    }
+
+    /// Finds the location of the field within a TOML document.
+    /// If the field itself is missing, the location of its closest existing parent is used.
+    pub fn locate_in_toml(&self, source: &str) -> Option<Location> {
+        let document = toml_edit::ImDocument::parse(source).ok()?;
+        let mut item = document.as_item();
+        let mut span = None;
+        for segment in &self.0 {
+            let next = match segment {
+                FieldSegment::Key(key) => item.get(key.as_str()),
+                FieldSegment::Index(index) => item.get(*index),
+            };
+            let Some(next) = next else {
+                break;
+            };
+
+            item = next;
+            span = item.span().or(span);
+        }
+
+        span.map(|span| Location::from_offset(source, span.start))
+    }
+}
+
+impl Diagnostic {
+    /// Creates an error which prevents the extension from being loaded.
+    pub fn error(field: FieldPath, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            field: Some(field),
+            location: None,
+        }
+    }
+
+    /// Creates a warning which does not prevent the extension from being loaded.
+    pub fn warning(field: FieldPath, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(field, message)
+        }
+    }
+
+    /// Creates a warning which is not tied to a particular field.
+    pub fn file_warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::file_error(message, None)
+        }
+    }
+
+    /// Creates an error which is not tied to a particular field, such as a syntax error.
+    pub fn file_error(message: impl Into<String>, location: Option<Location>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            field: None,
+            location,
+        }
+    }
+
+    /// Checks whether this diagnostic prevents the extension from being loaded.
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl ValidationReport {
+    /// Creates an empty report for the given file.
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Adds a diagnostic to the report.
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Adds validation diagnostics to the report, locating each one with the given function.
+    pub fn extend_located(
+        &mut self,
+        diagnostics: Vec<Diagnostic>,
+        locate: impl Fn(&FieldPath) -> Option<Location>,
+    ) {
+        for mut diagnostic in diagnostics {
+            if diagnostic.location.is_none() {
+                diagnostic.location = diagnostic.field.as_ref().and_then(&locate);
+            }
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    /// Checks whether the report contains any errors.
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+
+    /// Counts the diagnostics in the report with the given severity.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    }
+
+    /// Logs every diagnostic in the report at the appropriate level.
+    pub fn log(&self) {
+        for diagnostic in &self.diagnostics {
+            match diagnostic.severity {
+                Severity::Error => error!("{}", self.describe(diagnostic)),
+                Severity::Warning => warn!("{}", self.describe(diagnostic)),
+            }
+        }
+    }
+
+    /// Formats a diagnostic with the file path and location it refers to.
+    pub fn describe(&self, diagnostic: &Diagnostic) -> String {
+        match diagnostic.location {
+            Some(Location { line, column }) => {
+                format!("{}:{line}:{column}: {diagnostic}", self.path.display())
+            }
+            None => format!("{}: {diagnostic}", self.path.display()),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+        }
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                FieldSegment::Key(key) if i == 0 => write!(f, "{key}")?,
+                FieldSegment::Key(key) => write!(f, ".{key}")?,
+                FieldSegment::Index(index) => write!(f, "[{index}]")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)?;
+        if let Some(field) = &self.field {
+            write!(f, " (at `{field}`)")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Extension file {} has {} error(s)",
+            self.path.display(),
+            self.count(Severity::Error)
+        )?;
+        for diagnostic in &self.diagnostics {
+            write!(f, "\n  {}", self.describe(diagnostic))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationReport {}
+
+// * Field paths are serialized in their display form so they are readable in JSON output.
+impl Serialize for FieldPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Checks a parsed extension file for problems which the TOML parser cannot catch by itself.
+pub(super) fn validate(
+    extension: &InventoryExtensionToml,
+    identifier_collisions: &CollisionSeverities,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    let metadata = &extension.extension;
+    check_not_empty(
+        &mut diagnostics,
+        &metadata.id,
+        FieldPath::key("extension").then("id"),
+        "Extension ID",
+    );
+    check_not_empty(
+        &mut diagnostics,
+        &metadata.display_name,
+        FieldPath::key("extension").then("display_name"),
+        "Extension display name",
+    );
+    if let Err(e) = Version::parse(&metadata.version) {
+        diagnostics.push(Diagnostic::error(
+            FieldPath::key("extension").then("version"),
+            format!(
+                "Extension version '{}' is not a valid semantic version: {e}",
+                metadata.version
+            ),
+        ));
+    }
+
+    for (value, field, name) in [
+        (&metadata.author, "author", "Extension author"),
+        (&metadata.license, "license", "Extension license"),
+        (
+            &metadata.description,
+            "description",
+            "Extension description",
+        ),
+    ] {
+        if let Some(value) = value {
+            check_not_empty(
+                &mut diagnostics,
+                value,
+                FieldPath::key("extension").then(field),
+                name,
+            );
+        }
+    }
+    if let Some(homepage) = &metadata.homepage {
+        if !homepage.starts_with("https://") && !homepage.starts_with("http://") {
+            diagnostics.push(Diagnostic::error(
+                FieldPath::key("extension").then("homepage"),
+                format!("Extension homepage '{homepage}' must be an HTTP or HTTPS URL"),
+            ));
+        }
+    }
+    if let Some(requirement) = &metadata.techtriage {
+        if let Err(e) = VersionReq::parse(requirement) {
+            diagnostics.push(Diagnostic::error(
+                FieldPath::key("extension").then("techtriage"),
+                format!("TechTriage version requirement '{requirement}' is not valid: {e}"),
+            ));
+        }
+    }
+
+    for (id, requirement) in metadata.dependencies.iter().flatten() {
+        let field = FieldPath::key("extension").then("dependencies").then(id);
+        if id == &metadata.id {
+            diagnostics.push(Diagnostic::error(
+                field.clone(),
+                "Extension cannot depend on itself",
+            ));
+        }
+        if let Err(e) = VersionReq::parse(requirement) {
+            diagnostics.push(Diagnostic::error(
+                field,
+                format!(
+                    "Version requirement '{requirement}' of dependency '{id}' is not valid: {e}"
+                ),
+            ));
+        }
+    }
+
+    let mut manufacturers = HashMap::new();
+    for (i, manufacturer) in extension.device_manufacturers.iter().flatten().enumerate() {
+        let field = FieldPath::element("device_manufacturers", i);
+        check_not_empty(
+            &mut diagnostics,
+            &manufacturer.id,
+            field.clone().then("id"),
+            "Device manufacturer ID",
+        );
+        check_not_empty(
+            &mut diagnostics,
+            &manufacturer.display_name,
+            field.clone().then("display_name"),
+            "Device manufacturer display name",
+        );
+        check_unique(
+            &mut diagnostics,
+            &mut manufacturers,
+            &manufacturer.id,
+            field,
+            "device manufacturer",
+        );
+    }
+
+    let mut categories = HashMap::new();
+    for (i, category) in extension.device_categories.iter().flatten().enumerate() {
+        let field = FieldPath::element("device_categories", i);
+        check_not_empty(
+            &mut diagnostics,
+            &category.id,
+            field.clone().then("id"),
+            "Device category ID",
+        );
+        check_not_empty(
+            &mut diagnostics,
+            &category.display_name,
+            field.clone().then("display_name"),
+            "Device category display name",
+        );
+        check_unique(
+            &mut diagnostics,
+            &mut categories,
+            &category.id,
+            field,
+            "device category",
+        );
+    }
+
+    // * References to records outside of this extension cannot be resolved without a database, so
+    // * they are only flagged here and checked properly at load time.
+    // * Extensions with dependencies are expected to reference records from them.
+    let has_dependencies = metadata
+        .dependencies
+        .as_ref()
+        .is_some_and(|dependencies| !dependencies.is_empty());
+
+    let mut parents = HashMap::new();
+    for (i, category) in extension.device_categories.iter().flatten().enumerate() {
+        let Some(parent) = &category.parent else {
+            continue;
+        };
+        parents.insert(category.id.as_str(), parent.as_str());
+        if !has_dependencies && !categories.contains_key(parent.as_str()) {
+            diagnostics.push(Diagnostic::warning(
+                FieldPath::element("device_categories", i).then("parent"),
+                format!(
+                    "Device category '{parent}' is not defined in this extension, so it must \
+                    already exist in the database"
+                ),
+            ));
+        }
+    }
+    for cycle in find_cycles(&parents) {
+        diagnostics.push(Diagnostic::error(
+            categories[cycle[0]].clone().then("parent"),
+            format!(
+                "Device category '{}' is its own ancestor: {}",
+                cycle[0],
+                cycle.join(" -> ")
+            ),
+        ));
+    }
+
+    let mut devices = HashMap::new();
+    for (i, device) in extension.devices.iter().enumerate() {
+        let field = FieldPath::element("devices", i);
+        check_not_empty(
+            &mut diagnostics,
+            &device.id,
+            field.clone().then("id"),
+            "Device ID",
+        );
+        check_not_empty(
+            &mut diagnostics,
+            &device.display_name,
+            field.clone().then("display_name"),
+            "Device display name",
+        );
+        check_unique(
+            &mut diagnostics,
+            &mut devices,
+            &device.id,
+            field.clone(),
+            "device",
+        );
+
+        let mut variants = HashMap::new();
+        for (j, variant) in device.variants.iter().enumerate() {
+            let variant_field = field.clone().then("variants").index(j);
+            check_not_empty(
+                &mut diagnostics,
+                &variant.id,
+                variant_field.clone().then("id"),
+                "Variant ID",
+            );
+            check_unique(
+                &mut diagnostics,
+                &mut variants,
+                &variant.id,
+                variant_field.clone(),
+                "variant",
+            );
+            if variant.model_identifiers.is_empty() {
+                diagnostics.push(Diagnostic::warning(
+                    variant_field.then("model_identifiers"),
+                    format!(
+                        "Variant '{}' of device '{}' has no model identifiers, so it cannot be \
+                        looked up",
+                        variant.id, device.id
+                    ),
+                ));
+            }
+        }
+
+        if !has_dependencies && !manufacturers.contains_key(device.manufacturer.as_str()) {
+            diagnostics.push(Diagnostic::warning(
+                field.clone().then("manufacturer"),
+                format!(
+                    "Device manufacturer '{}' is not defined in this extension, so it must \
+                    already exist in the database",
+                    device.manufacturer
+                ),
+            ));
+        }
+        if !has_dependencies && !categories.contains_key(device.category.as_str()) {
+            diagnostics.push(Diagnostic::warning(
+                field.then("category"),
+                format!(
+                    "Device category '{}' is not defined in this extension, so it must already \
+                    exist in the database",
+                    device.category
+                ),
+            ));
+        }
+    }
+
+    let mut parts = HashMap::new();
+    for (i, part) in extension.parts.iter().flatten().enumerate() {
+        let field = FieldPath::element("parts", i);
+        check_not_empty(
+            &mut diagnostics,
+            &part.id,
+            field.clone().then("id"),
+            "Part ID",
+        );
+        check_not_empty(
+            &mut diagnostics,
+            &part.display_name,
+            field.clone().then("display_name"),
+            "Part display name",
+        );
+        check_unique(
+            &mut diagnostics,
+            &mut parts,
+            &part.id,
+            field.clone(),
+            "part",
+        );
+        for (j, sku) in part.supplier_skus.iter().enumerate() {
+            check_not_empty(
+                &mut diagnostics,
+                sku,
+                field.clone().then("supplier_skus").index(j),
+                "Supplier SKU",
+            );
+        }
+
+        if part.compatible_devices.is_empty() {
+            diagnostics.push(Diagnostic::warning(
+                field.clone().then("compatible_devices"),
+                format!("Part '{}' is not compatible with any devices", part.id),
+            ));
+        }
+        // * Like the manufacturers and categories of devices, devices from other extensions are
+        // * only checked at load time.
+        for (j, device) in part.compatible_devices.iter().enumerate() {
+            if !has_dependencies && !devices.contains_key(device.as_str()) {
+                diagnostics.push(Diagnostic::warning(
+                    field.clone().then("compatible_devices").index(j),
+                    format!(
+                        "Device '{device}' is not defined in this extension, so it must already \
+                        exist in the database"
+                    ),
+                ));
+            }
+        }
+    }
+
+    let mut services = HashMap::new();
+    for (i, service) in extension.repair_services.iter().flatten().enumerate() {
+        let field = FieldPath::element("repair_services", i);
+        check_not_empty(
+            &mut diagnostics,
+            &service.id,
+            field.clone().then("id"),
+            "Repair service ID",
+        );
+        check_not_empty(
+            &mut diagnostics,
+            &service.display_name,
+            field.clone().then("display_name"),
+            "Repair service display name",
+        );
+        check_unique(
+            &mut diagnostics,
+            &mut services,
+            &service.id,
+            field.clone(),
+            "repair service",
+        );
+
+        if service.device_categories.is_empty() && service.devices.is_empty() {
+            diagnostics.push(Diagnostic::error(
+                field.clone(),
+                format!(
+                    "Repair service '{}' must apply to at least one device category or device",
+                    service.id
+                ),
+            ));
+        }
+        for (j, category) in service.device_categories.iter().enumerate() {
+            if !has_dependencies && !categories.contains_key(category.as_str()) {
+                diagnostics.push(Diagnostic::warning(
+                    field.clone().then("device_categories").index(j),
+                    format!(
+                        "Device category '{category}' is not defined in this extension, so it \
+                        must already exist in the database"
+                    ),
+                ));
+            }
+        }
+        for (j, device) in service.devices.iter().enumerate() {
+            if !has_dependencies && !devices.contains_key(device.as_str()) {
+                diagnostics.push(Diagnostic::warning(
+                    field.clone().then("devices").index(j),
+                    format!(
+                        "Device '{device}' is not defined in this extension, so it must already \
+                        exist in the database"
+                    ),
+                ));
+            }
+        }
+    }
+
+    diagnostics.extend(identifiers::check_extension(
+        extension.devices.iter().map(|d| {
+            (
+                d.id.as_str(),
+                d.primary_model_identifiers.as_slice(),
+                d.extended_model_identifiers.as_slice(),
+                d.variants.as_slice(),
+            )
+        }),
+        identifier_collisions,
+    ));
+    diagnostics.extend(overrides::validate(extension));
+    diagnostics.extend(custom_fields::validate(extension));
+
+    diagnostics
+}
+
+/// Checks that every parent of the extension's device categories, every manufacturer and category
+/// referenced by its devices, and every category and device referenced by its parts and repair
+/// services, exists, either within the extension itself or among the given records already in the
+/// database.
+pub fn check_references(
+    extension: &Extension,
+    existing_manufacturers: &HashSet<DeviceManufacturerUniqueID>,
+    existing_categories: &HashSet<DeviceCategoryUniqueID>,
+    existing_devices: &HashSet<DeviceUniqueID>,
+) -> Vec<Diagnostic> {
+    let manufacturers = extension
+        .device_manufacturers
+        .iter()
+        .map(|m| &m.id)
+        .collect::<HashSet<_>>();
+    let categories = extension
+        .device_categories
+        .iter()
+        .map(|c| &c.id)
+        .collect::<HashSet<_>>();
+
+    let mut diagnostics = Vec::new();
+    for (i, category) in extension.device_categories.iter().enumerate() {
+        let Some(parent) = &category.parent else {
+            continue;
+        };
+        if !categories.contains(parent) && !existing_categories.contains(parent) {
+            diagnostics.push(Diagnostic::error(
+                FieldPath::element("device_categories", i).then("parent"),
+                format!(
+                    "Device category '{}' references unknown parent category '{}'",
+                    category.id.unnamespaced(),
+                    parent.unnamespaced()
+                ),
+            ));
+        }
+    }
+    for (i, device) in extension.devices.iter().enumerate() {
+        let field = FieldPath::element("devices", i);
+        if !manufacturers.contains(&device.manufacturer)
+            && !existing_manufacturers.contains(&device.manufacturer)
+        {
+            diagnostics.push(Diagnostic::error(
+                field.clone().then("manufacturer"),
+                format!(
+                    "Device '{}' references unknown device manufacturer '{}'",
+                    device.id.unnamespaced(),
+                    device.manufacturer.unnamespaced()
+                ),
+            ));
+        }
+        if !categories.contains(&device.category) && !existing_categories.contains(&device.category)
+        {
+            diagnostics.push(Diagnostic::error(
+                field.then("category"),
+                format!(
+                    "Device '{}' references unknown device category '{}'",
+                    device.id.unnamespaced(),
+                    device.category.unnamespaced()
+                ),
+            ));
+        }
+    }
+
+    let devices = extension
+        .devices
+        .iter()
+        .map(|d| &d.id)
+        .collect::<HashSet<_>>();
+    for (i, part) in extension.parts.iter().enumerate() {
+        for (j, device) in part.compatible_devices.iter().enumerate() {
+            if !devices.contains(device) && !existing_devices.contains(device) {
+                diagnostics.push(Diagnostic::error(
+                    FieldPath::element("parts", i)
+                        .then("compatible_devices")
+                        .index(j),
+                    format!(
+                        "Part '{}' references unknown device '{}'",
+                        part.id.unnamespaced(),
+                        device.unnamespaced()
+                    ),
+                ));
+            }
+        }
+    }
+
+    for (i, service) in extension.repair_services.iter().enumerate() {
+        let field = FieldPath::element("repair_services", i);
+        for (j, category) in service.device_categories.iter().enumerate() {
+            if !categories.contains(category) && !existing_categories.contains(category) {
+                diagnostics.push(Diagnostic::error(
+                    field.clone().then("device_categories").index(j),
+                    format!(
+                        "Repair service '{}' references unknown device category '{}'",
+                        service.id.unnamespaced(),
+                        category.unnamespaced()
+                    ),
+                ));
+            }
+        }
+        for (j, device) in service.devices.iter().enumerate() {
+            if !devices.contains(device) && !existing_devices.contains(device) {
+                diagnostics.push(Diagnostic::error(
+                    field.clone().then("devices").index(j),
+                    format!(
+                        "Repair service '{}' references unknown device '{}'",
+                        service.id.unnamespaced(),
+                        device.unnamespaced()
+                    ),
+                ));
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Checks that the parents given to the extension's device categories do not make any category its
+/// own ancestor, once combined with the parents of the given categories already in the database.
+/// Categories which already exist are merged with the given merger first, since its policy decides
+/// which parent is kept.
+pub fn check_category_hierarchy(
+    extension: &Extension,
+    existing_categories: &[DeviceCategory],
+    merger: &Merger<'_>,
+) -> Vec<Diagnostic> {
+    let resolved_parents = extension
+        .device_categories
+        .iter()
+        .map(|category| {
+            let Some(existing) = existing_categories.iter().find(|c| c.id == category.id) else {
+                return category.parent.clone();
+            };
+            let mut merged = category.clone();
+            merger.device_category(&mut merged, existing.clone());
+            merged.parent
+        })
+        .collect::<Vec<_>>();
+
+    let mut parents = existing_categories
+        .iter()
+        .filter_map(|c| Some((&c.id, c.parent.as_ref()?)))
+        .collect::<HashMap<_, _>>();
+    let mut positions = HashMap::new();
+    for (i, (category, parent)) in extension
+        .device_categories
+        .iter()
+        .zip(&resolved_parents)
+        .enumerate()
+    {
+        positions.insert(&category.id, i);
+        match parent {
+            Some(parent) => parents.insert(&category.id, parent),
+            None => parents.remove(&category.id),
+        };
+    }
+
+    // * Cycles which do not involve this extension's categories were already in the database, so
+    // * they are not reported against it.
+    find_cycles(&parents)
+        .into_iter()
+        .filter_map(|cycle| {
+            let position = cycle.iter().filter_map(|id| positions.get(id)).min()?;
+            Some(Diagnostic::error(
+                FieldPath::element("device_categories", *position).then("parent"),
+                format!(
+                    "Device category '{}' is its own ancestor: {}",
+                    cycle[0].unnamespaced(),
+                    cycle
+                        .iter()
+                        .map(|id| id.unnamespaced())
+                        .collect::<Vec<_>>()
+                        .join(" -> ")
+                ),
+            ))
+        })
+        .collect()
+}
+
+/// Finds the cycles in a hierarchy, given the parent of each item. Each cycle is listed once,
+/// starting and ending with its smallest item, such as `a -> b -> a`.
+fn find_cycles<T: Copy + Ord + Hash>(parents: &HashMap<T, T>) -> Vec<Vec<T>> {
+    let mut starts = parents.keys().copied().collect::<Vec<_>>();
+    starts.sort();
+
+    let mut visited = HashSet::new();
+    let mut cycles = Vec::new();
+    for start in starts {
+        let mut path = Vec::new();
+        let mut current = Some(start);
+        while let Some(item) = current {
+            if let Some(position) = path.iter().position(|p| *p == item) {
+                let mut cycle = path.split_off(position);
+                let smallest = (0..cycle.len()).min_by_key(|&i| cycle[i]).unwrap_or(0);
+                cycle.rotate_left(smallest);
+                cycle.push(cycle[0]);
+                cycles.push(cycle);
+                break;
+            }
+            if !visited.insert(item) {
+                break;
+            }
+            path.push(item);
+            current = parents.get(&item).copied();
+        }
+    }
+    cycles
+}
+
+/// Adds an error to the diagnostics if the given value is empty or only whitespace.
+fn check_not_empty(diagnostics: &mut Vec<Diagnostic>, value: &str, field: FieldPath, name: &str) {
+    if value.trim().is_empty() {
+        diagnostics.push(Diagnostic::error(
+            field,
+            format!("{name} must not be empty"),
+        ));
+    }
+}
+
+/// Adds an error to the diagnostics if the given ID has already been seen.
+/// Otherwise, the ID is recorded along with the field it was first defined at.
+fn check_unique<'a>(
+    diagnostics: &mut Vec<Diagnostic>,
+    seen: &mut HashMap<&'a str, FieldPath>,
+    id: &'a str,
+    field: FieldPath,
+    kind: &str,
+) {
+    if let Some(first) = seen.get(id) {
+        diagnostics.push(Diagnostic::error(
+            field.then("id"),
+            format!("Duplicate {kind} ID '{id}' (first defined at `{first}`)"),
+        ));
+    } else {
+        seen.insert(id, field);
+    }
+}
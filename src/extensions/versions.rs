@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use toml_edit::{DocumentMut, Item, Key, Table};
+
+use super::manager::{
+    DeviceCategoryToml, DeviceManufacturerToml, DeviceToml, ExtensionMetadataToml,
+    InventoryExtensionToml,
+};
+use super::validation::{Diagnostic, FieldPath, FieldSegment};
+
+/// The newest version of the extension file format, which is used when writing extensions.
+/// Files without a `format_version` key were written before the format was versioned, and are
+/// treated as version 1.
+///
+/// Version history:
+/// 1. The extension's metadata and dependencies are top-level keys prefixed with `extension_`.
+/// 2. The extension's metadata and dependencies are grouped in an `[extension]` table.
+///    Override extensions list `[[overrides]]` instead of devices.
+pub const CURRENT_FORMAT_VERSION: u32 = 2;
+
+/// The steps which upgrade a TOML document from each format version to the next, starting from
+/// version 1.
+const UPGRADES: &[fn(&mut DocumentMut)] = &[upgrade_v1];
+
+/// A syntax which extension files can be deserialized from, such as TOML or JSON.
+pub(super) trait Syntax {
+    /// Deserializes the source of an extension file into any type.
+    /// Syntax errors and missing or mistyped fields are returned as a diagnostic.
+    fn deserialize<T: DeserializeOwned>(&self, source: &str) -> Result<T, Diagnostic>;
+}
+
+/// The format version of an extension file, which is read before the rest of the file so the
+/// matching parser can be used.
+#[derive(Debug, Deserialize)]
+struct FormatVersion {
+    format_version: Option<u32>,
+}
+
+/// An extension file in format version 1.
+#[derive(Debug, Deserialize)]
+pub(super) struct InventoryExtensionTomlV1 {
+    pub(super) extension_id: String,
+    pub(super) extension_display_name: String,
+    pub(super) extension_version: String,
+    pub(super) dependencies: Option<BTreeMap<String, String>>,
+    pub(super) device_manufacturers: Option<Vec<DeviceManufacturerToml>>,
+    pub(super) device_categories: Option<Vec<DeviceCategoryToml>>,
+    pub(super) devices: Vec<DeviceToml>,
+}
+
+impl From<InventoryExtensionTomlV1> for InventoryExtensionToml {
+    fn from(v1: InventoryExtensionTomlV1) -> Self {
+        Self {
+            format_version: CURRENT_FORMAT_VERSION,
+            extension: ExtensionMetadataToml {
+                id: v1.extension_id,
+                display_name: v1.extension_display_name,
+                version: v1.extension_version,
+                dependencies: v1.dependencies,
+                ..Default::default()
+            },
+            custom_fields: None,
+            device_manufacturers: v1.device_manufacturers,
+            device_categories: v1.device_categories,
+            devices: v1.devices,
+            parts: None,
+            repair_services: None,
+            overrides: None,
+        }
+    }
+}
+
+/// Reads the format version of an extension file, making sure it is supported.
+pub(super) fn format_version(syntax: &impl Syntax, source: &str) -> Result<u32, Diagnostic> {
+    let version = syntax
+        .deserialize::<FormatVersion>(source)?
+        .format_version
+        .unwrap_or(1);
+    if version == 0 || version > CURRENT_FORMAT_VERSION {
+        return Err(Diagnostic::file_error(
+            format!(
+                "Unsupported format version {version}; the newest supported version is \
+                {CURRENT_FORMAT_VERSION}"
+            ),
+            None,
+        ));
+    }
+
+    Ok(version)
+}
+
+/// Parses an extension file in any supported format version, upgrading it to the current format
+/// version. Returns the extension along with the format version of the file.
+pub(super) fn parse(
+    syntax: &impl Syntax,
+    source: &str,
+) -> Result<(InventoryExtensionToml, u32), Diagnostic> {
+    let version = format_version(syntax, source)?;
+    let extension = match version {
+        1 => syntax
+            .deserialize::<InventoryExtensionTomlV1>(source)?
+            .into(),
+        _ => syntax.deserialize(source)?,
+    };
+
+    Ok((extension, version))
+}
+
+/// Converts the path of a field in the current format version into the path of the same field
+/// in an older format version, so it can be located in the original file.
+pub(super) fn field_in_version(field: &FieldPath, version: u32) -> FieldPath {
+    if version != 1 {
+        return field.clone();
+    }
+
+    match field.0.as_slice() {
+        [FieldSegment::Key(table), FieldSegment::Key(key), rest @ ..] if table == "extension" => {
+            let key = match key.as_str() {
+                "dependencies" => "dependencies".to_owned(),
+                key => format!("extension_{key}"),
+            };
+            let mut segments = vec![FieldSegment::Key(key)];
+            segments.extend_from_slice(rest);
+            FieldPath(segments)
+        }
+        _ => field.clone(),
+    }
+}
+
+/// Rewrites a TOML document in the current format version.
+/// Everything is edited in place, so comments and formatting are kept.
+pub(super) fn upgrade_toml(source: &str) -> anyhow::Result<String> {
+    let mut document = source.parse::<DocumentMut>()?;
+    let version = match document.get("format_version") {
+        Some(version) => match version.as_integer() {
+            Some(version) => version,
+            None => bail!("The format version must be an integer"),
+        },
+        None => 1,
+    };
+    if version < 1 || version > CURRENT_FORMAT_VERSION as i64 {
+        bail!("Unsupported format version {version}");
+    }
+
+    for upgrade in &UPGRADES[version as usize - 1..] {
+        upgrade(&mut document);
+    }
+
+    Ok(document.to_string())
+}
+
+/// Moves the top-level metadata keys of format version 1 into the `[extension]` table.
+fn upgrade_v1(document: &mut DocumentMut) {
+    let mut extension = Table::new();
+    // * Comments at the start of the file belong to the first key, but should stay at the start.
+    let first_key = document.iter().next().map(|(key, _)| key.to_owned());
+    let mut file_header = None;
+    for (old, new) in [
+        ("extension_id", "id"),
+        ("extension_display_name", "display_name"),
+        ("extension_version", "version"),
+        ("dependencies", "dependencies"),
+    ] {
+        let Some((old_key, item)) = document.remove_entry(old) else {
+            continue;
+        };
+
+        let mut key = Key::new(new);
+        match item {
+            // * Tables keep their own comments, and are written as `[extension.dependencies]`.
+            Item::Table(mut table) => {
+                table.set_position(0);
+                extension.insert_formatted(&key, Item::Table(table));
+            }
+            item => {
+                let mut decor = old_key.leaf_decor().clone();
+                if first_key.as_deref() == Some(old) {
+                    file_header = decor.prefix().cloned();
+                    decor.clear();
+                }
+                *key.leaf_decor_mut() = decor;
+                extension.insert_formatted(&key, item);
+            }
+        }
+    }
+    extension.set_position(0);
+
+    let mut key = Key::new("format_version");
+    if let Some(file_header) = file_header {
+        key.leaf_decor_mut().set_prefix(file_header);
+    }
+    document.insert_formatted(&key, toml_edit::value(2));
+    document.insert("extension", Item::Table(extension));
+}
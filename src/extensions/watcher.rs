@@ -0,0 +1,228 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use notify_debouncer_mini::notify::RecursiveMode;
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult};
+use tokio::sync::mpsc;
+
+use super::bundles::{self, TrustedKeys};
+use super::manager::{ExtensionManager, ExtensionManagerConfig};
+use super::orphans;
+use super::ExtensionID;
+use crate::database::Database;
+use crate::models::common::UniqueID;
+
+/// How long to wait for a burst of filesystem events to finish before reloading.
+/// Editors often write a file several times when saving it.
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Watches the extensions directory and loads, reloads, or unloads extensions as their files are
+/// created, modified, or deleted.
+pub struct ExtensionWatcher {
+    pub(super) directory: PathBuf,
+    config: ExtensionManagerConfig,
+    /// The keys which extension bundles must be signed with.
+    pub(super) trusted_keys: TrustedKeys,
+    /// The ID of the extension defined by each known extension file or bundle.
+    /// Needed to unload an extension once its file has been deleted and can no longer be read.
+    known_files: HashMap<PathBuf, ExtensionID>,
+}
+
+impl ExtensionWatcher {
+    /// Creates a watcher for the given directory, recording the extension defined by each of the
+    /// files and bundles which are currently in it.
+    pub fn new(directory: &Path, config: ExtensionManagerConfig) -> anyhow::Result<Self> {
+        let mut watcher = Self {
+            directory: directory.canonicalize()?,
+            config,
+            trusted_keys: TrustedKeys::load(Path::new(bundles::TRUSTED_KEYS_DIRECTORY))?,
+            known_files: HashMap::new(),
+        };
+
+        let manager = watcher.manager();
+        for extension_file in std::fs::read_dir(&watcher.directory)?.flatten() {
+            if !ExtensionManager::is_extension(&extension_file) {
+                continue;
+            }
+
+            let path = extension_file.path();
+            if let Ok((extension, _)) = manager.read_extension(&path) {
+                watcher.known_files.insert(path, extension.metadata.id);
+            }
+        }
+
+        Ok(watcher)
+    }
+
+    /// Creates a manager which can read the extension files and bundles in the directory.
+    fn manager(&self) -> ExtensionManager {
+        let mut manager = ExtensionManager::base_with_context(self.config.clone());
+        manager.trusted_keys = self.trusted_keys.clone();
+        manager
+    }
+
+    /// Finds the extension file or bundle in the watched directory which a changed path belongs
+    /// to. Changes to anything else, such as other subdirectories, are ignored.
+    pub(super) fn extension_entry(&self, path: &Path) -> Option<PathBuf> {
+        let mut components = path.strip_prefix(&self.directory).ok()?.components();
+        let entry = self.directory.join(components.next()?);
+        let in_directory = components.next().is_none();
+        match (in_directory, bundles::is_bundle_path(&entry)) {
+            (_, true) => Some(entry),
+            (true, false) if ExtensionManager::is_extension_path(&entry) => Some(entry),
+            _ => None,
+        }
+    }
+
+    /// Watches the directory until the watcher fails, handling each batch of changes as it
+    /// arrives. Failures to load individual extensions are logged rather than returned.
+    pub async fn run(mut self, db: &Database) -> anyhow::Result<()> {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut debouncer = new_debouncer(DEBOUNCE_TIMEOUT, move |result: DebounceEventResult| {
+            // * The receiver is only dropped when the watcher stops, so this cannot be handled.
+            let _ = sender.send(result);
+        })?;
+        // * Bundles are directories, so the files inside them need to be watched too.
+        debouncer
+            .watcher()
+            .watch(&self.directory, RecursiveMode::Recursive)?;
+
+        info!(
+            "Watching {} for extension changes...",
+            self.directory.display()
+        );
+
+        while let Some(result) = receiver.recv().await {
+            match result {
+                Ok(events) => {
+                    // * A path can appear more than once in a batch, and several files of a
+                    // * bundle can change at once, but each only needs to be handled once since
+                    // * its current state is checked directly.
+                    let paths = events
+                        .into_iter()
+                        .filter_map(|event| self.extension_entry(&event.path))
+                        .collect::<BTreeSet<_>>();
+                    for path in paths {
+                        if let Err(e) = self.handle_change(db, &path).await {
+                            error!("Failed to handle change to {}: {e}", path.display());
+                        }
+                    }
+                }
+                Err(e) => warn!("Error while watching extensions directory: {e}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Loads, reloads, or unloads the extension defined by a single changed file or bundle.
+    pub(super) async fn handle_change(&mut self, db: &Database, path: &Path) -> anyhow::Result<()> {
+        if !path.exists() {
+            let Some(extension_id) = self.known_files.remove(path) else {
+                debug!("Ignoring removal of unknown file {}.", path.display());
+                return Ok(());
+            };
+
+            if let Some(dependent) = Self::dependent(db, &extension_id).await? {
+                warn!(
+                    "Extension file {} was deleted, but extension '{}' is kept loaded because extension '{}' depends on it.",
+                    path.display(),
+                    extension_id.unnamespaced(),
+                    dependent.unnamespaced()
+                );
+                return Ok(());
+            }
+
+            info!(
+                "Extension file {} was deleted, unloading extension '{}'...",
+                path.display(),
+                extension_id.unnamespaced()
+            );
+            db.unload_extension(&extension_id).await?;
+            info!(
+                "Successfully unloaded extension '{}'.",
+                extension_id.unnamespaced()
+            );
+            return Ok(());
+        }
+
+        match self.known_files.contains_key(path) {
+            true => info!("Extension file {} was modified.", path.display()),
+            false => info!("Extension file {} was created.", path.display()),
+        }
+
+        let mut manager = self.manager();
+        let extension = match manager.read_extension(path) {
+            Ok((extension, report)) => {
+                report.log();
+                extension
+            }
+            Err(report) => {
+                report.log();
+                error!(
+                    "Ignoring change to invalid extension file {}; the loaded extension is unchanged.",
+                    path.display()
+                );
+                return Ok(());
+            }
+        };
+
+        // * Going through the manager applies the usual conflict handling, so an unchanged
+        // * version is skipped unless auto-reload is enabled.
+        let extension_id = extension.metadata.id.clone();
+        manager.stage_extension(extension)?;
+        let result = manager.load_extensions(db).await?;
+        result.log_summary();
+        if !result.failures.is_empty() {
+            error!(
+                "Failed to load extension '{}' from {}; the loaded extension is unchanged.",
+                extension_id.unnamespaced(),
+                path.display()
+            );
+            return Ok(());
+        }
+
+        // * If the file now defines a different extension, the old one no longer has a file.
+        // * It is only unloaded once the new one has loaded, so a broken change loses nothing.
+        if let Some(previous_id) = self
+            .known_files
+            .insert(path.to_owned(), extension_id.clone())
+        {
+            if previous_id == extension_id {
+                return Ok(());
+            }
+            if let Some(dependent) = Self::dependent(db, &previous_id).await? {
+                warn!(
+                    "Extension file {} no longer defines extension '{}', but it is kept loaded because extension '{}' depends on it.",
+                    path.display(),
+                    previous_id.unnamespaced(),
+                    dependent.unnamespaced()
+                );
+            } else {
+                info!(
+                    "Extension file {} no longer defines extension '{}', unloading it...",
+                    path.display(),
+                    previous_id.unnamespaced()
+                );
+                db.unload_extension(&previous_id).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds a loaded extension which depends on the given extension, which would break if it
+    /// were unloaded.
+    async fn dependent(
+        db: &Database,
+        extension_id: &ExtensionID,
+    ) -> anyhow::Result<Option<ExtensionID>> {
+        let dependencies = db.list_extension_dependencies().await?;
+        Ok(
+            orphans::find_dependents(std::slice::from_ref(extension_id), &dependencies)
+                .remove(extension_id),
+        )
+    }
+}
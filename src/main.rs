@@ -1,15 +1,23 @@
+mod commands;
 mod database;
 mod extensions;
 mod models;
+mod plugins;
 
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use log::info;
 use simplelog::{ColorChoice, Config, LevelFilter, TermLogger, TerminalMode, WriteLogger};
 
+use commands::OutputFormat;
 use database::Database;
-use extensions::ExtensionManager;
+use extensions::{
+    CollisionSeverities, CollisionSeverity, ExtensionManager, ExtensionManagerConfig,
+    ExtensionWatcher, MergePolicy, OrphanPolicy, UnsignedPolicy, EXTENSIONS_DIRECTORY,
+};
+use models::common::{InventoryExtensionUniqueID as ExtensionID, UniqueID};
+use plugins::{PluginLimits, PluginManager, PluginManagerConfig};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -17,19 +25,69 @@ async fn main() -> anyhow::Result<()> {
 
     let verbose = *args.get_one::<bool>("verbose").unwrap();
     let log_file = args.get_one::<std::path::PathBuf>("log file");
-    let auto_reload = *args.get_one::<bool>("auto reload").unwrap();
+    let config = get_manager_config(&args);
+    let watch = *args.get_one::<bool>("watch").unwrap();
+    let format = *args.get_one::<OutputFormat>("format").unwrap();
 
     start_logger(verbose, log_file).unwrap();
 
     info!("TechTriage v{}", env!("CARGO_PKG_VERSION"));
+
+    if let Some(("extension", extension_args)) = args.subcommand() {
+        let code = commands::extension(extension_args, config).await?;
+        std::process::exit(code);
+    }
+
+    if let Some(("device", device_args)) = args.subcommand() {
+        let code = commands::device(device_args).await?;
+        std::process::exit(code);
+    }
+
+    if let Some(("part", part_args)) = args.subcommand() {
+        let code = commands::part(part_args).await?;
+        std::process::exit(code);
+    }
+
+    if let Some(("category", category_args)) = args.subcommand() {
+        let code = commands::category(category_args).await?;
+        std::process::exit(code);
+    }
+
+    if let Some(("plugin", plugin_args)) = args.subcommand() {
+        let code = commands::plugin(plugin_args, get_plugin_config(&args, config)).await?;
+        std::process::exit(code);
+    }
+
     info!("Starting server...");
 
     let db = Database::connect().await;
 
-    db.setup_tables().await?;
+    // * A dry run must not write anything, including the schema.
+    if !config.dry_run {
+        db.setup_tables().await?;
+    }
 
-    let manager = ExtensionManager::new(auto_reload)?;
-    manager.load_extensions(&db).await?;
+    let manager = ExtensionManager::new(config.clone())?;
+    let result = manager.load_extensions(&db).await?;
+
+    if config.dry_run {
+        result.log_summary();
+        stop(commands::dry_run(&result, format)?);
+    }
+    commands::load_report(&result, format)?;
+
+    // * Plugins see the catalog as it is after the extensions are loaded.
+    let plugin_manager = PluginManager::new(get_plugin_config(&args, config.clone()));
+    let (plugin_host, _) = plugin_manager.load_plugins(&db).await?;
+    info!("Loaded {} plugin(s).", plugin_host.plugins().count());
+
+    if watch {
+        let watcher = ExtensionWatcher::new(Path::new(EXTENSIONS_DIRECTORY), config)?;
+        tokio::select! {
+            result = watcher.run(&db) => result?,
+            _ = tokio::signal::ctrl_c() => info!("Received interrupt signal."),
+        }
+    }
 
     stop(0);
 }
@@ -39,6 +97,278 @@ fn get_args() -> clap::ArgMatches {
     use clap::{value_parser, Arg, ArgAction, Command};
     Command::new("techtriage")
         .bin_name("techtriage")
+        .subcommand(
+            Command::new("extension")
+                .about("Tools for working with inventory extensions.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("lint")
+                        .about(
+                            "Parse and validate extension files without connecting to the \
+                            database. Exits with a non-zero code if any file has errors.",
+                        )
+                        .arg(
+                            Arg::new("files")
+                                .required(true)
+                                .num_args(1..)
+                                .value_parser(value_parser!(PathBuf))
+                                .help("The extension files to check."),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .value_parser(value_parser!(OutputFormat))
+                                .default_value("text")
+                                .help("The format to print diagnostics in."),
+                        ),
+                )
+                .subcommand(
+                    Command::new("export")
+                        .about(
+                            "Export the contents of the database as a TOML extension, sorted by \
+                            ID so the output can be versioned.",
+                        )
+                        .arg(
+                            Arg::new("extension")
+                                .long("extension")
+                                .help("Only export items which belong to this extension."),
+                        )
+                        .arg(
+                            Arg::new("id")
+                                .long("id")
+                                .help("The ID of the exported extension."),
+                        )
+                        .arg(
+                            Arg::new("name")
+                                .long("name")
+                                .help("The display name of the exported extension."),
+                        )
+                        .arg(
+                            Arg::new("version")
+                                .long("version")
+                                .value_parser(|v: &str| semver::Version::parse(v))
+                                .help("The version of the exported extension."),
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .short('o')
+                                .long("output")
+                                .value_parser(value_parser!(PathBuf))
+                                .help("Write the extension to a file instead of stdout."),
+                        ),
+                )
+                .subcommand(
+                    Command::new("enable")
+                        .about("Enable loaded extensions which were disabled.")
+                        .arg(
+                            Arg::new("extensions")
+                                .required(true)
+                                .num_args(1..)
+                                .help("The IDs of the extensions to enable."),
+                        ),
+                )
+                .subcommand(
+                    Command::new("disable")
+                        .about(
+                            "Disable loaded extensions, hiding their contents without removing \
+                            them from the database.",
+                        )
+                        .arg(
+                            Arg::new("extensions")
+                                .required(true)
+                                .num_args(1..)
+                                .help("The IDs of the extensions to disable."),
+                        ),
+                )
+                .subcommand(
+                    Command::new("status")
+                        .about("List the loaded extensions and whether each one is enabled.")
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .value_parser(value_parser!(OutputFormat))
+                                .default_value("text")
+                                .help("The format to print the extensions in."),
+                        ),
+                )
+                .subcommand(
+                    Command::new("upgrade")
+                        .about(
+                            "Rewrite extension files in the newest format version. Comments are \
+                            kept in TOML and CSV files.",
+                        )
+                        .arg(
+                            Arg::new("files")
+                                .required(true)
+                                .num_args(1..)
+                                .value_parser(value_parser!(PathBuf))
+                                .help("The extension files to upgrade."),
+                        ),
+                )
+                .subcommand(
+                    Command::new("sign")
+                        .about(
+                            "Sign an extension bundle with a hex-encoded ed25519 secret key, and \
+                            print the matching public key.",
+                        )
+                        .arg(
+                            Arg::new("bundle")
+                                .required(true)
+                                .value_parser(value_parser!(PathBuf))
+                                .help("The bundle directory to sign."),
+                        )
+                        .arg(
+                            Arg::new("key")
+                                .long("key")
+                                .required(true)
+                                .value_parser(value_parser!(PathBuf))
+                                .help("The file containing the secret key."),
+                        )
+                        .arg(
+                            Arg::new("extension file")
+                                .long("extension-file")
+                                .help(
+                                    "The extension file within the bundle to sign. Only needed if \
+                                    the bundle contains more than one.",
+                                ),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("device")
+                .about("Tools for looking up devices in the database.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("lookup")
+                        .about(
+                            "Find the device and variant which a model identifier refers to. \
+                            Exits with a non-zero code if no device has the identifier.",
+                        )
+                        .arg(
+                            Arg::new("identifier")
+                                .required(true)
+                                .help("The model identifier to look up, such as A1863."),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .value_parser(value_parser!(OutputFormat))
+                                .default_value("text")
+                                .help("The format to print the device in."),
+                        ),
+                )
+                .subcommand(
+                    Command::new("parts")
+                        .about(
+                            "List the parts which fit a device. Exits with a non-zero code if no \
+                            parts fit it.",
+                        )
+                        .arg(
+                            Arg::new("device")
+                                .required(true)
+                                .help("The ID of the device, such as iphone_8."),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .value_parser(value_parser!(OutputFormat))
+                                .default_value("text")
+                                .help("The format to print the parts in."),
+                        ),
+                )
+                .subcommand(
+                    Command::new("prices")
+                        .about(
+                            "Print the price list of a device, with every repair service which \
+                            applies to it. Exits with a non-zero code if the device does not exist.",
+                        )
+                        .arg(
+                            Arg::new("device")
+                                .required(true)
+                                .help("The ID of the device, such as iphone_8."),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .value_parser(value_parser!(OutputFormat))
+                                .default_value("text")
+                                .help("The format to print the price list in."),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("part")
+                .about("Tools for looking up parts in the database.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("devices")
+                        .about(
+                            "List the devices which a part fits. Exits with a non-zero code if \
+                            the part fits no devices.",
+                        )
+                        .arg(
+                            Arg::new("part")
+                                .required(true)
+                                .help("The ID of the part, such as iphone_8_screen."),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .value_parser(value_parser!(OutputFormat))
+                                .default_value("text")
+                                .help("The format to print the devices in."),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("category")
+                .about("Tools for looking up device categories in the database.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("devices")
+                        .about(
+                            "List the devices in a category, including those in the categories \
+                            below it. Exits with a non-zero code if there are no such devices.",
+                        )
+                        .arg(
+                            Arg::new("category")
+                                .required(true)
+                                .help("The ID of the category, such as phone."),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .value_parser(value_parser!(OutputFormat))
+                                .default_value("text")
+                                .help("The format to print the devices in."),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("plugin")
+                .about("Tools for working with WebAssembly plugins.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("list").about("Load every plugin and list the hooks it registers."),
+                )
+                .subcommand(
+                    Command::new("run")
+                        .about(
+                            "Load every plugin and run a hook, printing each plugin's result as \
+                            JSON. Exits with a non-zero code if any plugin fails.",
+                        )
+                        .arg(
+                            Arg::new("hook")
+                                .required(true)
+                                .help("The name of the hook to run."),
+                        )
+                        .arg(
+                            Arg::new("input")
+                                .long("input")
+                                .help("A JSON value passed to the hook. Defaults to null."),
+                        ),
+                ),
+        )
         .arg(
             Arg::new("verbose")
                 .short('v')
@@ -62,9 +392,189 @@ fn get_args() -> clap::ArgMatches {
                     changed. This is useful for development and testing of extensions.",
                 ),
         )
+        .arg(
+            Arg::new("reload on drift")
+                .long("reload-on-drift")
+                .action(ArgAction::SetTrue)
+                .help(
+                    "Reload extensions whose contents have changed even though their version has \
+                    not. By default, these extensions are skipped with a warning.",
+                ),
+        )
+        .arg(
+            Arg::new("allow downgrade")
+                .long("allow-downgrade")
+                .action(ArgAction::SetTrue)
+                .help(
+                    "Allow extensions to be replaced by older versions of themselves. By default, \
+                    older versions are skipped with an error.",
+                ),
+        )
+        .arg(
+            Arg::new("orphans")
+                .long("orphans")
+                .value_parser(value_parser!(OrphanPolicy))
+                .default_value("warn")
+                .help(
+                    "What to do with loaded extensions whose files have been removed from the \
+                    extensions directory.",
+                ),
+        )
+        .arg(
+            Arg::new("unsigned extensions")
+                .long("unsigned-extensions")
+                .value_parser(value_parser!(UnsignedPolicy))
+                .default_value("allow")
+                .help(
+                    "What to do with extension files which are not part of a signed bundle. \
+                    Bundles are always checked against the keys in the trusted keys directory.",
+                ),
+        )
+        .arg(
+            Arg::new("merge policy")
+                .long("merge-policy")
+                .value_parser(value_parser!(MergePolicy))
+                .default_value("last-wins")
+                .help(
+                    "How to reconcile items which several extensions define with different \
+                    metadata. Model identifiers are always combined.",
+                ),
+        )
+        .arg(
+            Arg::new("extension priority")
+                .long("extension-priority")
+                .value_delimiter(',')
+                .help(
+                    "A comma-separated list of extension IDs in order of priority, highest first, \
+                    used by the priority merge policy.",
+                ),
+        )
+        .arg(
+            Arg::new("dry run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("watch")
+                .help(
+                    "Print what loading the extensions would add, remove, or modify in the \
+                    database, then exit without writing anything.",
+                ),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_parser(value_parser!(OutputFormat))
+                .default_value("text")
+                .help("The format to print the load report or dry run diff in."),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .action(ArgAction::SetTrue)
+                .help(
+                    "Keep running after startup and load, reload, or unload extensions whenever \
+                    files in the extensions directory are created, modified, or deleted.",
+                ),
+        )
+        .arg(
+            Arg::new("plugin memory")
+                .long("plugin-memory")
+                .global(true)
+                .value_parser(value_parser!(usize))
+                .help(
+                    "The most linear memory, in bytes, which any plugin may use. Defaults to 16 \
+                    MiB.",
+                ),
+        )
+        .arg(
+            Arg::new("plugin fuel")
+                .long("plugin-fuel")
+                .global(true)
+                .value_parser(value_parser!(u64))
+                .help(
+                    "The most fuel which any plugin may use per call, which roughly counts \
+                    instructions. Defaults to 10,000,000.",
+                ),
+        )
+        .arg(
+            Arg::new("within device collisions")
+                .long("within-device-collisions")
+                .global(true)
+                .value_parser(value_parser!(CollisionSeverity))
+                .help("How to treat a model identifier listed more than once for one device."),
+        )
+        .arg(
+            Arg::new("across device collisions")
+                .long("across-device-collisions")
+                .global(true)
+                .value_parser(value_parser!(CollisionSeverity))
+                .help("How to treat a model identifier shared by two devices in one extension."),
+        )
+        .arg(
+            Arg::new("database collisions")
+                .long("database-collisions")
+                .global(true)
+                .value_parser(value_parser!(CollisionSeverity))
+                .help(
+                    "How to treat a model identifier already used by a device from another \
+                    extension in the database.",
+                ),
+        )
         .get_matches()
 }
 
+/// Builds the extension manager configuration from the provided CLI arguments.
+fn get_manager_config(args: &clap::ArgMatches) -> ExtensionManagerConfig {
+    let defaults = CollisionSeverities::default();
+    let severity = |name: &str, default: CollisionSeverity| {
+        args.get_one::<CollisionSeverity>(name)
+            .copied()
+            .unwrap_or(default)
+    };
+
+    ExtensionManagerConfig {
+        auto_reload: *args.get_one::<bool>("auto reload").unwrap(),
+        reload_on_drift: *args.get_one::<bool>("reload on drift").unwrap(),
+        dry_run: *args.get_one::<bool>("dry run").unwrap(),
+        allow_downgrade: *args.get_one::<bool>("allow downgrade").unwrap(),
+        orphans: *args.get_one::<OrphanPolicy>("orphans").unwrap(),
+        unsigned_extensions: *args
+            .get_one::<UnsignedPolicy>("unsigned extensions")
+            .unwrap(),
+        merge_policy: *args.get_one::<MergePolicy>("merge policy").unwrap(),
+        extension_priority: args
+            .get_many::<String>("extension priority")
+            .unwrap_or_default()
+            .map(ExtensionID::new)
+            .collect(),
+        identifier_collisions: CollisionSeverities {
+            within_device: severity("within device collisions", defaults.within_device),
+            across_devices: severity("across device collisions", defaults.across_devices),
+            with_database: severity("database collisions", defaults.with_database),
+        },
+    }
+}
+
+/// Builds the plugin manager configuration from the provided CLI arguments.
+fn get_plugin_config(
+    args: &clap::ArgMatches,
+    extensions: ExtensionManagerConfig,
+) -> PluginManagerConfig {
+    let defaults = PluginLimits::default();
+    PluginManagerConfig {
+        extensions,
+        max_limits: PluginLimits {
+            memory: args
+                .get_one::<usize>("plugin memory")
+                .copied()
+                .unwrap_or(defaults.memory),
+            fuel: args
+                .get_one::<u64>("plugin fuel")
+                .copied()
+                .unwrap_or(defaults.fuel),
+        },
+    }
+}
+
 /// Initializes either a terminal or file logger, depending on the provided configuration.
 fn start_logger(verbose: bool, path: Option<&PathBuf>) -> anyhow::Result<()> {
     match path {
@@ -3,7 +3,7 @@ use std::hash::Hash;
 
 use crate::database::{
     DEVICE_CATEGORY_TABLE_NAME, DEVICE_MANUFACTURER_TABLE_NAME, DEVICE_TABLE_NAME,
-    EXTENSION_TABLE_NAME,
+    EXTENSION_TABLE_NAME, PART_TABLE_NAME, PLUGIN_TABLE_NAME, REPAIR_SERVICE_TABLE_NAME,
 };
 
 /// A trait for ID types which are used as "primary keys" (unique string identifiers) in the
@@ -33,6 +33,18 @@ pub struct DeviceCategoryUniqueID(String);
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct DeviceUniqueID(String);
 
+/// An unnamespaced unique part ID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PartUniqueID(String);
+
+/// An unnamespaced unique repair service ID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RepairServiceUniqueID(String);
+
+/// An unnamespaced unique plugin ID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PluginUniqueID(String);
+
 impl UniqueID for InventoryExtensionUniqueID {
     const TABLE_NAME: &'static str = EXTENSION_TABLE_NAME;
     fn new(id: impl Into<String>) -> Self {
@@ -76,3 +88,36 @@ impl UniqueID for DeviceUniqueID {
         &self.0
     }
 }
+
+impl UniqueID for PartUniqueID {
+    const TABLE_NAME: &'static str = PART_TABLE_NAME;
+    fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    fn unnamespaced(&self) -> &str {
+        &self.0
+    }
+}
+
+impl UniqueID for RepairServiceUniqueID {
+    const TABLE_NAME: &'static str = REPAIR_SERVICE_TABLE_NAME;
+    fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    fn unnamespaced(&self) -> &str {
+        &self.0
+    }
+}
+
+impl UniqueID for PluginUniqueID {
+    const TABLE_NAME: &'static str = PLUGIN_TABLE_NAME;
+    fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    fn unnamespaced(&self) -> &str {
+        &self.0
+    }
+}
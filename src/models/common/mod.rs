@@ -2,12 +2,14 @@ mod ids;
 
 pub use ids::{
     DeviceCategoryUniqueID, DeviceManufacturerUniqueID, DeviceUniqueID, InventoryExtensionUniqueID,
-    UniqueID,
+    PartUniqueID, PluginUniqueID, RepairServiceUniqueID, UniqueID,
 };
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 
-use semver::Version;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
 
 /// The metadata of an inventory extension.
 /// This does not include the extension contents, such as devices or manufacturers.
@@ -18,14 +20,44 @@ pub struct InventoryExtensionMetadata {
     pub id: InventoryExtensionUniqueID,
     pub display_name: String,
     pub version: Version,
+    /// The person or organization which publishes the extension.
+    pub author: Option<String>,
+    /// The license the extension is distributed under, preferably as an SPDX expression.
+    pub license: Option<String>,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    /// The versions of TechTriage which the extension works with.
+    pub techtriage: Option<VersionReq>,
+}
+
+/// The extensions which an extension depends on, along with the versions of each which it requires.
+pub type Dependencies = BTreeMap<InventoryExtensionUniqueID, VersionReq>;
+
+/// The metadata of a WebAssembly plugin.
+/// This does not include the plugin's module, which is only read from the plugins directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluginMetadata {
+    pub id: PluginUniqueID,
+    pub display_name: String,
+    pub version: Version,
+}
+
+/// The extensions which are currently loaded in the database, listed separately by kind.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadedExtensions {
+    /// Extensions which provide device manufacturers, categories, and devices.
+    pub base: Vec<InventoryExtensionMetadata>,
+    /// Extensions which patch items provided by other extensions.
+    pub overrides: Vec<InventoryExtensionMetadata>,
 }
 
 /// A device manufacturer.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct DeviceManufacturer {
     pub id: DeviceManufacturerUniqueID,
     pub display_name: String,
     pub extensions: HashSet<InventoryExtensionUniqueID>,
+    pub custom_fields: CustomFields,
 }
 
 /// A category of device, such as a phone, tablet, or gaming console.
@@ -33,7 +65,16 @@ pub struct DeviceManufacturer {
 pub struct DeviceCategory {
     pub id: DeviceCategoryUniqueID,
     pub display_name: String,
+    /// The broader category which this one is a kind of, such as phone for smartphone.
+    pub parent: Option<DeviceCategoryUniqueID>,
     pub extensions: HashSet<InventoryExtensionUniqueID>,
+    pub custom_fields: CustomFields,
+}
+
+/// The device categories in the catalog, arranged by their parent categories.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CategoryTree {
+    parents: HashMap<DeviceCategoryUniqueID, DeviceCategoryUniqueID>,
 }
 
 /// A device and all of its relevant metadata, such as its make and model.
@@ -46,29 +87,813 @@ pub struct Device {
     pub extensions: HashSet<InventoryExtensionUniqueID>,
     pub primary_model_identifiers: Vec<String>,
     pub extended_model_identifiers: Vec<String>,
+    /// Specific configurations of the device, each with their own model identifiers.
+    pub variants: Vec<DeviceVariant>,
+    pub custom_fields: CustomFields,
+}
+
+/// A specific configuration of a device, such as one storage capacity and color sold by one
+/// carrier, along with the model identifiers which refer to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceVariant {
+    /// The ID of the variant, which is unique within its device.
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub carrier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    pub model_identifiers: Vec<String>,
+}
+
+/// A device found by one of its model identifiers, along with the variant the identifier belongs
+/// to, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdentifierMatch {
+    pub device: Device,
+    pub variant: Option<DeviceVariant>,
+}
+
+/// A replacement part, such as a screen or battery, along with the devices it fits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Part {
+    pub id: PartUniqueID,
+    pub display_name: String,
+    pub kind: PartKind,
+    /// The SKUs which suppliers sell the part under.
+    pub supplier_skus: Vec<String>,
+    /// The devices which the part fits. A part can fit many devices, and a device can take many
+    /// parts.
+    pub compatible_devices: Vec<DeviceUniqueID>,
+    pub extensions: HashSet<InventoryExtensionUniqueID>,
+}
+
+/// The kind of component which a part replaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartKind {
+    Screen,
+    Battery,
+    Camera,
+    Speaker,
+    Microphone,
+    ChargingPort,
+    Button,
+    Housing,
+    Board,
+    Other,
+}
+
+/// A repair which a shop can offer, such as a screen or battery replacement.
+/// Services apply to every device in their device categories, including the categories below
+/// them, and to their devices.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepairService {
+    pub id: RepairServiceUniqueID,
+    pub display_name: String,
+    /// How long the repair usually takes, in minutes.
+    pub default_labor_minutes: u32,
+    /// The usual price of the repair, in the smallest unit of the shop's currency, such as cents.
+    pub default_price: u64,
+    /// The kinds of part which the repair uses, such as a screen.
+    pub required_part_kinds: Vec<PartKind>,
+    pub device_categories: Vec<DeviceCategoryUniqueID>,
+    pub devices: Vec<DeviceUniqueID>,
+    pub extensions: HashSet<InventoryExtensionUniqueID>,
+}
+
+/// The repair services which apply to a device, along with their prices.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceList {
+    pub device: Device,
+    /// The applicable services, sorted by ID.
+    pub entries: Vec<PriceListEntry>,
+}
+
+/// A single repair service in a [`PriceList`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceListEntry {
+    pub service: RepairService,
+    /// The parts of the required kinds which fit the device.
+    pub parts: Vec<Part>,
+}
+
+/// The values of an item's custom fields, by field ID.
+pub type CustomFields = BTreeMap<String, CustomFieldValue>;
+
+/// A field which an extension adds to one kind of item, such as the screen size of a device.
+/// Any extension can give values to a field once it has been declared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomFieldDefinition {
+    pub id: String,
+    pub target: CustomFieldTarget,
+    pub display_name: String,
+    pub field_type: CustomFieldType,
+    /// Whether every item of the target kind in the declaring extension must have a value.
+    pub required: bool,
+}
+
+/// The kind of item which a custom field is added to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CustomFieldTarget {
+    DeviceManufacturer,
+    DeviceCategory,
+    Device,
+}
+
+/// The type of value which a custom field holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CustomFieldType {
+    String,
+    Number,
+    Bool,
+    /// A string which must be one of the given values.
+    Enum(Vec<String>),
+    /// A list of strings.
+    List,
+}
+
+/// The value of a custom field.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum CustomFieldValue {
+    Bool(bool),
+    Number(f64),
+    String(String),
+    List(Vec<String>),
+}
+
+/// A change which an override extension makes to an item provided by other extensions.
+/// Overrides are stored separately from the items they target and applied whenever the items are
+/// read, so they survive the targeted extensions being reloaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Override {
+    pub target: OverrideTarget,
+    pub operation: OverrideOperation,
+}
+
+/// The item which an override applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OverrideTarget {
+    DeviceManufacturer(DeviceManufacturerUniqueID),
+    DeviceCategory(DeviceCategoryUniqueID),
+    Device(DeviceUniqueID),
+}
+
+/// The change made by an override.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OverrideOperation {
+    /// Adds a value to a list, such as a model identifier.
+    Add { field: OverrideField, value: String },
+    /// Removes a value from a list.
+    Remove { field: OverrideField, value: String },
+    /// Replaces the value of a field.
+    Replace { field: OverrideField, value: String },
+    /// Hides the item entirely.
+    Hide,
+}
+
+/// A field which can be changed by an override.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverrideField {
+    DisplayName,
+    Manufacturer,
+    Category,
+    PrimaryModelIdentifiers,
+    ExtendedModelIdentifiers,
+}
+
+impl OverrideField {
+    pub const ALL: [OverrideField; 5] = [
+        OverrideField::DisplayName,
+        OverrideField::Manufacturer,
+        OverrideField::Category,
+        OverrideField::PrimaryModelIdentifiers,
+        OverrideField::ExtendedModelIdentifiers,
+    ];
+
+    /// Gets the name of the field, as written in extension files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OverrideField::DisplayName => "display_name",
+            OverrideField::Manufacturer => "manufacturer",
+            OverrideField::Category => "category",
+            OverrideField::PrimaryModelIdentifiers => "primary_model_identifiers",
+            OverrideField::ExtendedModelIdentifiers => "extended_model_identifiers",
+        }
+    }
+
+    /// Gets a field from its name, as written in extension files.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|field| field.name() == name)
+    }
+
+    /// Checks whether the field is a list, which values can be added to and removed from.
+    pub fn is_list(&self) -> bool {
+        matches!(
+            self,
+            OverrideField::PrimaryModelIdentifiers | OverrideField::ExtendedModelIdentifiers
+        )
+    }
+}
+
+impl OverrideOperation {
+    /// Gets the name of the operation, as written in extension files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OverrideOperation::Add { .. } => "add",
+            OverrideOperation::Remove { .. } | OverrideOperation::Hide => "remove",
+            OverrideOperation::Replace { .. } => "replace",
+        }
+    }
+
+    /// Gets the field and value which the operation changes, if it does not hide the whole item.
+    pub fn field_value(&self) -> Option<(OverrideField, &str)> {
+        match self {
+            OverrideOperation::Add { field, value }
+            | OverrideOperation::Remove { field, value }
+            | OverrideOperation::Replace { field, value } => Some((*field, value)),
+            OverrideOperation::Hide => None,
+        }
+    }
+}
+
+impl CustomFieldTarget {
+    pub const ALL: [CustomFieldTarget; 3] = [
+        CustomFieldTarget::DeviceManufacturer,
+        CustomFieldTarget::DeviceCategory,
+        CustomFieldTarget::Device,
+    ];
+
+    /// Gets the name of the target, as written in extension files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            CustomFieldTarget::DeviceManufacturer => "device_manufacturer",
+            CustomFieldTarget::DeviceCategory => "device_category",
+            CustomFieldTarget::Device => "device",
+        }
+    }
+
+    /// Gets a target from its name, as written in extension files.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|target| target.name() == name)
+    }
+}
+
+impl CustomFieldType {
+    /// Gets the name of the type, as written in extension files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            CustomFieldType::String => "string",
+            CustomFieldType::Number => "number",
+            CustomFieldType::Bool => "bool",
+            CustomFieldType::Enum(_) => "enum",
+            CustomFieldType::List => "list",
+        }
+    }
+
+    /// Checks whether a value can be stored in a field of this type.
+    pub fn accepts(&self, value: &CustomFieldValue) -> bool {
+        match (self, value) {
+            (CustomFieldType::String, CustomFieldValue::String(_)) => true,
+            (CustomFieldType::Number, CustomFieldValue::Number(number)) => number.is_finite(),
+            (CustomFieldType::Bool, CustomFieldValue::Bool(_)) => true,
+            (CustomFieldType::Enum(values), CustomFieldValue::String(value)) => {
+                values.contains(value)
+            }
+            (CustomFieldType::List, CustomFieldValue::List(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for CustomFieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CustomFieldType::Enum(values) => write!(f, "enum of {}", values.join(", ")),
+            _ => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+impl fmt::Display for CustomFieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CustomFieldValue::Bool(value) => write!(f, "{value}"),
+            CustomFieldValue::Number(value) => write!(f, "{value}"),
+            CustomFieldValue::String(value) => write!(f, "\"{value}\""),
+            CustomFieldValue::List(values) => write!(f, "[{}]", values.join(", ")),
+        }
+    }
+}
+
+impl LoadedExtensions {
+    /// Combines the extensions of every kind into a single list.
+    pub fn into_all(self) -> Vec<InventoryExtensionMetadata> {
+        let mut extensions = self.base;
+        extensions.extend(self.overrides);
+        extensions
+    }
+}
+
+/// The values which one extension gave to the combined lists of an item, by field.
+pub type Contribution = BTreeMap<String, BTreeSet<String>>;
+
+/// The contributions to every item, by the key of the item and then by extension.
+pub type Contributions = HashMap<String, HashMap<InventoryExtensionUniqueID, Contribution>>;
+
+/// An item which several extensions can provide, whose lists are combined from all of them.
+/// The contribution of each extension is recorded, so the values it no longer provides can be
+/// removed when it is reloaded.
+pub trait Combined {
+    /// Gets the ID of the item, namespaced by its table, which contributions are recorded under.
+    fn key(&self) -> String;
+    /// Gets the extensions which provide the item.
+    fn providers(&self) -> &HashSet<InventoryExtensionUniqueID>;
+    /// Lists the values of each combined list of the item.
+    fn contribution(&self) -> Contribution;
+    /// Removes the given values from each combined list of the item.
+    fn withdraw(&mut self, values: &Contribution);
 }
 
-// TODO: Reconcile differences in metadata between existing records
+/// Removes the values which an extension contributed to an item, except those which another
+/// provider of the item contributed as well. Does nothing if the extension does not provide it.
+// * An extension which was loaded before contributions were recorded could provide any of the
+// * values, so nothing is removed unless the contribution of every provider is known.
+pub fn withdraw_contribution<T: Combined>(
+    item: &mut T,
+    extension: &InventoryExtensionUniqueID,
+    contributions: &Contributions,
+) {
+    if !item.providers().contains(extension) {
+        return;
+    }
+    let Some(contributions) = contributions.get(&item.key()) else {
+        return;
+    };
+    let Some(previous) = contributions.get(extension) else {
+        return;
+    };
+
+    let mut withdrawn = previous.clone();
+    for provider in item.providers() {
+        if provider == extension {
+            continue;
+        }
+        let Some(kept) = contributions.get(provider) else {
+            return;
+        };
+        for (field, values) in &mut withdrawn {
+            if let Some(kept_values) = kept.get(field) {
+                values.retain(|value| !kept_values.contains(value));
+            }
+        }
+    }
+    item.withdraw(&withdrawn);
+}
+
+// * Differences in other metadata are reconciled by the extension manager's merge policy.
 impl DeviceManufacturer {
-    /// Merges the extensions field of another device manufacturer into this one.
+    /// Merges the extensions and custom fields of another device manufacturer into this one.
     /// Does not check whether the two device manufacturers share the same ID and other metadata.
     pub fn merge(&mut self, other: DeviceManufacturer) {
         self.extensions.extend(other.extensions);
+        merge_custom_fields(&mut self.custom_fields, other.custom_fields);
     }
 }
 
 impl DeviceCategory {
-    /// Merges the extensions field of another device category into this one.
+    /// Merges the extensions and custom fields of another device category into this one.
     /// Does not check whether the two device categories share the same ID and other metadata.
     pub fn merge(&mut self, other: DeviceCategory) {
         self.extensions.extend(other.extensions);
+        merge_custom_fields(&mut self.custom_fields, other.custom_fields);
+    }
+}
+
+impl CategoryTree {
+    /// Arranges the given categories by their parents.
+    pub fn new<'a>(categories: impl IntoIterator<Item = &'a DeviceCategory>) -> Self {
+        Self {
+            parents: categories
+                .into_iter()
+                .filter_map(|c| Some((c.id.clone(), c.parent.clone()?)))
+                .collect(),
+        }
+    }
+
+    /// Lists a category followed by each of its ancestors, nearest first.
+    // * Cycles are rejected when extensions are loaded, but the walk still stops at one so a
+    // * corrupted database cannot cause an infinite loop.
+    pub fn ancestors<'a>(
+        &'a self,
+        category: &'a DeviceCategoryUniqueID,
+    ) -> Vec<&'a DeviceCategoryUniqueID> {
+        let mut ancestors = vec![category];
+        let mut current = category;
+        while let Some(parent) = self.parents.get(current) {
+            if ancestors.contains(&parent) {
+                break;
+            }
+            ancestors.push(parent);
+            current = parent;
+        }
+        ancestors
+    }
+
+    /// Checks whether a category is the given ancestor, or is somewhere below it.
+    pub fn is_within(
+        &self,
+        category: &DeviceCategoryUniqueID,
+        ancestor: &DeviceCategoryUniqueID,
+    ) -> bool {
+        self.ancestors(category).contains(&ancestor)
     }
 }
 
 impl Device {
-    /// Merges the extensions field of another device into this one.
+    /// Merges the extensions, model identifiers, variants, and custom fields of another device
+    /// into this one. Model identifiers and variants which this device does not have are added
+    /// after its own.
     /// Does not check whether the two devices share the same ID and other metadata.
     pub fn merge(&mut self, other: Device) {
         self.extensions.extend(other.extensions);
+        merge_custom_fields(&mut self.custom_fields, other.custom_fields);
+        for variant in other.variants {
+            if !self.variants.iter().any(|v| v.id == variant.id) {
+                self.variants.push(variant);
+            }
+        }
+        for (identifiers, other_identifiers) in [
+            (
+                &mut self.primary_model_identifiers,
+                other.primary_model_identifiers,
+            ),
+            (
+                &mut self.extended_model_identifiers,
+                other.extended_model_identifiers,
+            ),
+        ] {
+            for identifier in other_identifiers {
+                if !identifiers.contains(&identifier) {
+                    identifiers.push(identifier);
+                }
+            }
+        }
+    }
+
+    /// Lists every model identifier of the device, including those of its variants.
+    pub fn model_identifiers(&self) -> impl Iterator<Item = &String> {
+        self.primary_model_identifiers
+            .iter()
+            .chain(&self.extended_model_identifiers)
+            .chain(self.variants.iter().flat_map(|v| &v.model_identifiers))
+    }
+
+    /// Checks whether a model identifier refers to this device, and if so, which variant it
+    /// refers to. Identifiers from the device's own lists do not refer to a variant.
+    pub fn match_identifier(&self, identifier: &str) -> Option<Option<&DeviceVariant>> {
+        if let Some(variant) = self
+            .variants
+            .iter()
+            .find(|v| v.model_identifiers.iter().any(|i| i == identifier))
+        {
+            return Some(Some(variant));
+        }
+
+        self.primary_model_identifiers
+            .iter()
+            .chain(&self.extended_model_identifiers)
+            .any(|i| i == identifier)
+            .then_some(None)
+    }
+}
+
+impl Part {
+    /// Merges the extensions, supplier SKUs, and compatible devices of another part into this one.
+    /// SKUs and devices which this part does not have are added after its own.
+    /// Does not check whether the two parts share the same ID and other metadata.
+    pub fn merge(&mut self, other: Part) {
+        self.extensions.extend(other.extensions);
+        for sku in other.supplier_skus {
+            if !self.supplier_skus.contains(&sku) {
+                self.supplier_skus.push(sku);
+            }
+        }
+        for device in other.compatible_devices {
+            if !self.compatible_devices.contains(&device) {
+                self.compatible_devices.push(device);
+            }
+        }
+    }
+
+    /// Checks whether the part fits the given device.
+    pub fn is_compatible_with(&self, device: &DeviceUniqueID) -> bool {
+        self.compatible_devices.contains(device)
+    }
+}
+
+impl RepairService {
+    /// Merges the extensions, required part kinds, device categories, and devices of another
+    /// repair service into this one. Values which this service does not have are added after its
+    /// own.
+    /// Does not check whether the two services share the same ID and other metadata.
+    pub fn merge(&mut self, other: RepairService) {
+        self.extensions.extend(other.extensions);
+        for kind in other.required_part_kinds {
+            if !self.required_part_kinds.contains(&kind) {
+                self.required_part_kinds.push(kind);
+            }
+        }
+        for category in other.device_categories {
+            if !self.device_categories.contains(&category) {
+                self.device_categories.push(category);
+            }
+        }
+        for device in other.devices {
+            if !self.devices.contains(&device) {
+                self.devices.push(device);
+            }
+        }
+    }
+
+    /// Checks whether the service can be performed on the given device, either because it lists
+    /// the device or because it lists the device's category or one of its ancestors.
+    pub fn applies_to(&self, device: &Device, categories: &CategoryTree) -> bool {
+        self.devices.contains(&device.id)
+            || self
+                .device_categories
+                .iter()
+                .any(|category| categories.is_within(&device.category, category))
+    }
+}
+
+impl PriceList {
+    /// Builds the price list of a device from every repair service and part in the catalog.
+    pub fn new(
+        device: Device,
+        services: Vec<RepairService>,
+        parts: &[Part],
+        categories: &CategoryTree,
+    ) -> Self {
+        let mut entries = services
+            .into_iter()
+            .filter(|service| service.applies_to(&device, categories))
+            .map(|service| PriceListEntry {
+                parts: parts
+                    .iter()
+                    .filter(|part| {
+                        part.is_compatible_with(&device.id)
+                            && service.required_part_kinds.contains(&part.kind)
+                    })
+                    .cloned()
+                    .collect(),
+                service,
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.service.id.cmp(&b.service.id));
+
+        Self { device, entries }
+    }
+}
+
+impl PriceListEntry {
+    /// Lists the required part kinds which no part in the catalog provides for the device.
+    pub fn missing_part_kinds(&self) -> Vec<PartKind> {
+        self.service
+            .required_part_kinds
+            .iter()
+            .copied()
+            .filter(|kind| !self.parts.iter().any(|part| part.kind == *kind))
+            .collect()
+    }
+}
+
+impl PartKind {
+    /// Gets the name of the kind, as written in extension files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PartKind::Screen => "screen",
+            PartKind::Battery => "battery",
+            PartKind::Camera => "camera",
+            PartKind::Speaker => "speaker",
+            PartKind::Microphone => "microphone",
+            PartKind::ChargingPort => "charging_port",
+            PartKind::Button => "button",
+            PartKind::Housing => "housing",
+            PartKind::Board => "board",
+            PartKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for DeviceVariant {
+    /// Describes the variant by its attributes, such as `64GB, Space Gray, AT&T`, or by its ID if
+    /// it has none.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let attributes = [&self.capacity, &self.color, &self.carrier, &self.region]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        match attributes.is_empty() {
+            true => write!(f, "{}", self.id),
+            false => write!(f, "{}", attributes.join(", ")),
+        }
+    }
+}
+
+impl Combined for DeviceManufacturer {
+    fn key(&self) -> String {
+        self.id.namespaced()
+    }
+
+    fn providers(&self) -> &HashSet<InventoryExtensionUniqueID> {
+        &self.extensions
+    }
+
+    fn contribution(&self) -> Contribution {
+        let mut contribution = Contribution::new();
+        contribute(
+            &mut contribution,
+            "custom_fields",
+            self.custom_fields.keys(),
+        );
+        contribution
+    }
+
+    fn withdraw(&mut self, values: &Contribution) {
+        self.custom_fields
+            .retain(|id, _| !is_withdrawn(values, "custom_fields", id));
+    }
+}
+
+impl Combined for DeviceCategory {
+    fn key(&self) -> String {
+        self.id.namespaced()
+    }
+
+    fn providers(&self) -> &HashSet<InventoryExtensionUniqueID> {
+        &self.extensions
+    }
+
+    fn contribution(&self) -> Contribution {
+        let mut contribution = Contribution::new();
+        contribute(
+            &mut contribution,
+            "custom_fields",
+            self.custom_fields.keys(),
+        );
+        contribution
+    }
+
+    fn withdraw(&mut self, values: &Contribution) {
+        self.custom_fields
+            .retain(|id, _| !is_withdrawn(values, "custom_fields", id));
+    }
+}
+
+impl Combined for Device {
+    fn key(&self) -> String {
+        self.id.namespaced()
+    }
+
+    fn providers(&self) -> &HashSet<InventoryExtensionUniqueID> {
+        &self.extensions
+    }
+
+    fn contribution(&self) -> Contribution {
+        let mut contribution = Contribution::new();
+        contribute(
+            &mut contribution,
+            "primary_model_identifiers",
+            &self.primary_model_identifiers,
+        );
+        contribute(
+            &mut contribution,
+            "extended_model_identifiers",
+            &self.extended_model_identifiers,
+        );
+        contribute(
+            &mut contribution,
+            "variants",
+            self.variants.iter().map(|v| &v.id),
+        );
+        contribute(
+            &mut contribution,
+            "custom_fields",
+            self.custom_fields.keys(),
+        );
+        contribution
+    }
+
+    fn withdraw(&mut self, values: &Contribution) {
+        self.primary_model_identifiers
+            .retain(|i| !is_withdrawn(values, "primary_model_identifiers", i));
+        self.extended_model_identifiers
+            .retain(|i| !is_withdrawn(values, "extended_model_identifiers", i));
+        self.variants
+            .retain(|v| !is_withdrawn(values, "variants", &v.id));
+        self.custom_fields
+            .retain(|id, _| !is_withdrawn(values, "custom_fields", id));
+    }
+}
+
+impl Combined for Part {
+    fn key(&self) -> String {
+        self.id.namespaced()
+    }
+
+    fn providers(&self) -> &HashSet<InventoryExtensionUniqueID> {
+        &self.extensions
+    }
+
+    fn contribution(&self) -> Contribution {
+        let mut contribution = Contribution::new();
+        contribute(&mut contribution, "supplier_skus", &self.supplier_skus);
+        contribute(
+            &mut contribution,
+            "compatible_devices",
+            self.compatible_devices.iter().map(UniqueID::unnamespaced),
+        );
+        contribution
+    }
+
+    fn withdraw(&mut self, values: &Contribution) {
+        self.supplier_skus
+            .retain(|sku| !is_withdrawn(values, "supplier_skus", sku));
+        self.compatible_devices
+            .retain(|d| !is_withdrawn(values, "compatible_devices", d.unnamespaced()));
+    }
+}
+
+impl Combined for RepairService {
+    fn key(&self) -> String {
+        self.id.namespaced()
+    }
+
+    fn providers(&self) -> &HashSet<InventoryExtensionUniqueID> {
+        &self.extensions
+    }
+
+    fn contribution(&self) -> Contribution {
+        let mut contribution = Contribution::new();
+        contribute(
+            &mut contribution,
+            "required_part_kinds",
+            self.required_part_kinds.iter().map(PartKind::name),
+        );
+        contribute(
+            &mut contribution,
+            "device_categories",
+            self.device_categories.iter().map(UniqueID::unnamespaced),
+        );
+        contribute(
+            &mut contribution,
+            "devices",
+            self.devices.iter().map(UniqueID::unnamespaced),
+        );
+        contribution
+    }
+
+    fn withdraw(&mut self, values: &Contribution) {
+        self.required_part_kinds
+            .retain(|k| !is_withdrawn(values, "required_part_kinds", k.name()));
+        self.device_categories
+            .retain(|c| !is_withdrawn(values, "device_categories", c.unnamespaced()));
+        self.devices
+            .retain(|d| !is_withdrawn(values, "devices", d.unnamespaced()));
+    }
+}
+
+/// Adds the values of a combined list to a contribution, unless the list is empty.
+fn contribute(
+    contribution: &mut Contribution,
+    field: &str,
+    values: impl IntoIterator<Item = impl AsRef<str>>,
+) {
+    let values = values
+        .into_iter()
+        .map(|value| value.as_ref().to_owned())
+        .collect::<BTreeSet<_>>();
+    if !values.is_empty() {
+        contribution.insert(field.to_owned(), values);
+    }
+}
+
+/// Checks whether a value of a combined list is among the values being withdrawn.
+fn is_withdrawn(values: &Contribution, field: &str, value: &str) -> bool {
+    values
+        .get(field)
+        .is_some_and(|withdrawn| withdrawn.contains(value))
+}
+
+/// Adds the custom fields which this item does not have a value for.
+fn merge_custom_fields(custom_fields: &mut CustomFields, other: CustomFields) {
+    for (id, value) in other {
+        custom_fields.entry(id).or_insert(value);
     }
 }
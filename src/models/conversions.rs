@@ -1,29 +1,55 @@
 use std::collections::HashSet;
 
-use anyhow::anyhow;
-use semver::Version;
+use anyhow::{anyhow, bail};
+use semver::{Version, VersionReq};
 use surrealdb::sql::{Id, Thing};
 
 use super::common::{
-    Device, DeviceCategory, DeviceCategoryUniqueID, DeviceManufacturer, DeviceManufacturerUniqueID,
-    DeviceUniqueID, InventoryExtensionMetadata, InventoryExtensionUniqueID, UniqueID,
+    CustomFieldDefinition, CustomFieldTarget, CustomFieldType, Device, DeviceCategory,
+    DeviceCategoryUniqueID, DeviceManufacturer, DeviceManufacturerUniqueID, DeviceUniqueID,
+    InventoryExtensionMetadata, InventoryExtensionUniqueID, Override, OverrideField,
+    OverrideOperation, OverrideTarget, Part, PartUniqueID, PluginMetadata, PluginUniqueID,
+    RepairService, RepairServiceUniqueID, UniqueID,
 };
 use super::database::{
-    DeviceCategoryPullRecord, DeviceCategoryPushRecord, DeviceManufacturerPullRecord,
-    DeviceManufacturerPushRecord, DevicePullRecord, DevicePushRecord,
-    InventoryExtensionMetadataPullRecord, InventoryExtensionMetadataPushRecord,
+    CustomFieldPullRecord, CustomFieldPushRecord, DependencyRecord, DeviceCategoryPullRecord,
+    DeviceCategoryPushRecord, DeviceManufacturerPullRecord, DeviceManufacturerPushRecord,
+    DevicePullRecord, DevicePushRecord, InventoryExtensionMetadataPullRecord,
+    InventoryExtensionMetadataPushRecord, OverridePullRecord, OverridePushRecord, PartPullRecord,
+    PartPushRecord, PluginMetadataPullRecord, PluginMetadataPushRecord, RepairServicePullRecord,
+    RepairServicePushRecord,
 };
 use crate::database::{
     DEVICE_CATEGORY_TABLE_NAME, DEVICE_MANUFACTURER_TABLE_NAME, DEVICE_TABLE_NAME,
-    EXTENSION_TABLE_NAME,
+    EXTENSION_TABLE_NAME, PART_TABLE_NAME, PLUGIN_TABLE_NAME, REPAIR_SERVICE_TABLE_NAME,
 };
+use crate::extensions::InventoryExtension;
 
-impl<'a> From<&'a InventoryExtensionMetadata> for InventoryExtensionMetadataPushRecord<'a> {
-    fn from(extension: &'a InventoryExtensionMetadata) -> Self {
+impl<'a> From<&'a InventoryExtension> for InventoryExtensionMetadataPushRecord<'a> {
+    fn from(extension: &'a InventoryExtension) -> Self {
         InventoryExtensionMetadataPushRecord {
-            id: Thing::from(&extension.id),
-            display_name: &extension.display_name,
-            version: extension.version.to_string(),
+            id: Thing::from(&extension.metadata.id),
+            display_name: &extension.metadata.display_name,
+            version: extension.metadata.version.to_string(),
+            content_hash: extension.content_hash(),
+            enabled: true,
+            author: extension.metadata.author.as_deref(),
+            license: extension.metadata.license.as_deref(),
+            description: extension.metadata.description.as_deref(),
+            homepage: extension.metadata.homepage.as_deref(),
+            techtriage: extension
+                .metadata
+                .techtriage
+                .as_ref()
+                .map(VersionReq::to_string),
+            dependencies: extension
+                .dependencies
+                .iter()
+                .map(|(dependency, requirement)| DependencyRecord {
+                    extension: Thing::from(dependency),
+                    requirement: requirement.to_string(),
+                })
+                .collect(),
         }
     }
 }
@@ -35,6 +61,37 @@ impl TryFrom<InventoryExtensionMetadataPullRecord> for InventoryExtensionMetadat
             id: InventoryExtensionUniqueID::try_from(extension.id)?,
             display_name: extension.display_name,
             version: Version::parse(&extension.version)?,
+            author: extension.author,
+            license: extension.license,
+            description: extension.description,
+            homepage: extension.homepage,
+            techtriage: extension
+                .techtriage
+                .map(|requirement| VersionReq::parse(&requirement))
+                .transpose()?,
+        })
+    }
+}
+
+impl<'a> PluginMetadataPushRecord<'a> {
+    /// Creates a record for a plugin with the given hash of its module.
+    pub fn new(plugin: &'a PluginMetadata, content_hash: &'a str) -> Self {
+        PluginMetadataPushRecord {
+            id: Thing::from(&plugin.id),
+            display_name: &plugin.display_name,
+            version: plugin.version.to_string(),
+            content_hash,
+        }
+    }
+}
+
+impl TryFrom<PluginMetadataPullRecord> for PluginMetadata {
+    type Error = anyhow::Error;
+    fn try_from(plugin: PluginMetadataPullRecord) -> Result<Self, anyhow::Error> {
+        Ok(PluginMetadata {
+            id: PluginUniqueID::try_from(plugin.id)?,
+            display_name: plugin.display_name,
+            version: Version::parse(&plugin.version)?,
         })
     }
 }
@@ -45,6 +102,7 @@ impl<'a> From<&'a DeviceManufacturer> for DeviceManufacturerPushRecord<'a> {
             id: Thing::from(&manufacturer.id),
             display_name: &manufacturer.display_name,
             extensions: manufacturer.extensions.iter().map(Thing::from).collect(),
+            custom_fields: &manufacturer.custom_fields,
         }
     }
 }
@@ -60,6 +118,7 @@ impl TryFrom<DeviceManufacturerPullRecord> for DeviceManufacturer {
                 .into_iter()
                 .map(InventoryExtensionUniqueID::try_from)
                 .collect::<Result<HashSet<_>, _>>()?,
+            custom_fields: manufacturer.custom_fields.unwrap_or_default(),
         })
     }
 }
@@ -69,7 +128,9 @@ impl<'a> From<&'a DeviceCategory> for DeviceCategoryPushRecord<'a> {
         DeviceCategoryPushRecord {
             id: Thing::from(&category.id),
             display_name: &category.display_name,
+            parent: category.parent.as_ref().map(Thing::from),
             extensions: category.extensions.iter().map(Thing::from).collect(),
+            custom_fields: &category.custom_fields,
         }
     }
 }
@@ -80,11 +141,16 @@ impl TryFrom<DeviceCategoryPullRecord> for DeviceCategory {
         Ok(DeviceCategory {
             id: DeviceCategoryUniqueID::try_from(category.id)?,
             display_name: category.display_name,
+            parent: category
+                .parent
+                .map(DeviceCategoryUniqueID::try_from)
+                .transpose()?,
             extensions: category
                 .extensions
                 .into_iter()
                 .map(InventoryExtensionUniqueID::try_from)
                 .collect::<Result<HashSet<_>, _>>()?,
+            custom_fields: category.custom_fields.unwrap_or_default(),
         })
     }
 }
@@ -99,6 +165,8 @@ impl<'a> From<&'a Device> for DevicePushRecord<'a> {
             extensions: device.extensions.iter().map(Thing::from).collect(),
             primary_model_identifiers: &device.primary_model_identifiers,
             extended_model_identifiers: &device.extended_model_identifiers,
+            variants: &device.variants,
+            custom_fields: &device.custom_fields,
         }
     }
 }
@@ -118,10 +186,189 @@ impl TryFrom<DevicePullRecord> for Device {
                 .collect::<Result<HashSet<_>, _>>()?,
             primary_model_identifiers: device.primary_model_identifiers,
             extended_model_identifiers: device.extended_model_identifiers,
+            variants: device.variants.unwrap_or_default(),
+            custom_fields: device.custom_fields.unwrap_or_default(),
+        })
+    }
+}
+
+impl<'a> From<&'a Part> for PartPushRecord<'a> {
+    fn from(part: &'a Part) -> Self {
+        PartPushRecord {
+            id: Thing::from(&part.id),
+            display_name: &part.display_name,
+            kind: part.kind,
+            supplier_skus: &part.supplier_skus,
+            compatible_devices: part.compatible_devices.iter().map(Thing::from).collect(),
+            extensions: part.extensions.iter().map(Thing::from).collect(),
+        }
+    }
+}
+
+impl TryFrom<PartPullRecord> for Part {
+    type Error = anyhow::Error;
+    fn try_from(part: PartPullRecord) -> Result<Self, Self::Error> {
+        Ok(Part {
+            id: PartUniqueID::try_from(part.id)?,
+            display_name: part.display_name,
+            kind: part.kind,
+            supplier_skus: part.supplier_skus,
+            compatible_devices: part
+                .compatible_devices
+                .into_iter()
+                .map(DeviceUniqueID::try_from)
+                .collect::<Result<_, _>>()?,
+            extensions: part
+                .extensions
+                .into_iter()
+                .map(InventoryExtensionUniqueID::try_from)
+                .collect::<Result<HashSet<_>, _>>()?,
         })
     }
 }
 
+impl<'a> From<&'a RepairService> for RepairServicePushRecord<'a> {
+    fn from(service: &'a RepairService) -> Self {
+        RepairServicePushRecord {
+            id: Thing::from(&service.id),
+            display_name: &service.display_name,
+            default_labor_minutes: service.default_labor_minutes,
+            default_price: service.default_price,
+            required_part_kinds: &service.required_part_kinds,
+            device_categories: service.device_categories.iter().map(Thing::from).collect(),
+            devices: service.devices.iter().map(Thing::from).collect(),
+            extensions: service.extensions.iter().map(Thing::from).collect(),
+        }
+    }
+}
+
+impl TryFrom<RepairServicePullRecord> for RepairService {
+    type Error = anyhow::Error;
+    fn try_from(service: RepairServicePullRecord) -> Result<Self, Self::Error> {
+        Ok(RepairService {
+            id: RepairServiceUniqueID::try_from(service.id)?,
+            display_name: service.display_name,
+            default_labor_minutes: service.default_labor_minutes,
+            default_price: service.default_price,
+            required_part_kinds: service.required_part_kinds,
+            device_categories: service
+                .device_categories
+                .into_iter()
+                .map(DeviceCategoryUniqueID::try_from)
+                .collect::<Result<_, _>>()?,
+            devices: service
+                .devices
+                .into_iter()
+                .map(DeviceUniqueID::try_from)
+                .collect::<Result<_, _>>()?,
+            extensions: service
+                .extensions
+                .into_iter()
+                .map(InventoryExtensionUniqueID::try_from)
+                .collect::<Result<HashSet<_>, _>>()?,
+        })
+    }
+}
+
+impl<'a> CustomFieldPushRecord<'a> {
+    /// Creates a record for a custom field declared by an extension.
+    pub fn new(extension: &InventoryExtensionUniqueID, field: &'a CustomFieldDefinition) -> Self {
+        CustomFieldPushRecord {
+            extension: Thing::from(extension),
+            target: field.target.name(),
+            field: &field.id,
+            display_name: &field.display_name,
+            field_type: field.field_type.name(),
+            values: match &field.field_type {
+                CustomFieldType::Enum(values) => Some(values),
+                _ => None,
+            },
+            required: field.required,
+        }
+    }
+}
+
+impl TryFrom<CustomFieldPullRecord> for CustomFieldDefinition {
+    type Error = anyhow::Error;
+    fn try_from(field: CustomFieldPullRecord) -> Result<Self, Self::Error> {
+        let target = CustomFieldTarget::from_name(&field.target)
+            .ok_or_else(|| anyhow!("Unknown custom field target '{}'", field.target))?;
+        let field_type = match (field.field_type.as_str(), field.values) {
+            ("string", _) => CustomFieldType::String,
+            ("number", _) => CustomFieldType::Number,
+            ("bool", _) => CustomFieldType::Bool,
+            ("enum", Some(values)) => CustomFieldType::Enum(values),
+            ("list", _) => CustomFieldType::List,
+            (other, _) => bail!("Unknown custom field type '{other}'"),
+        };
+
+        Ok(CustomFieldDefinition {
+            id: field.field,
+            target,
+            display_name: field.display_name,
+            field_type,
+            required: field.required,
+        })
+    }
+}
+
+impl<'a> OverridePushRecord<'a> {
+    /// Creates a record for the override at the given position within an extension.
+    pub fn new(extension: &InventoryExtensionUniqueID, position: usize, o: &'a Override) -> Self {
+        let target = match &o.target {
+            OverrideTarget::DeviceManufacturer(id) => Thing::from(id),
+            OverrideTarget::DeviceCategory(id) => Thing::from(id),
+            OverrideTarget::Device(id) => Thing::from(id),
+        };
+        let (field, value) = match o.operation.field_value() {
+            Some((field, value)) => (Some(field.name()), Some(value)),
+            None => (None, None),
+        };
+
+        OverridePushRecord {
+            extension: Thing::from(extension),
+            position,
+            target,
+            operation: o.operation.name(),
+            field,
+            value,
+        }
+    }
+}
+
+impl TryFrom<OverridePullRecord> for Override {
+    type Error = anyhow::Error;
+    fn try_from(record: OverridePullRecord) -> Result<Self, Self::Error> {
+        let target = match record.target.tb.as_str() {
+            DEVICE_MANUFACTURER_TABLE_NAME => OverrideTarget::DeviceManufacturer(
+                DeviceManufacturerUniqueID::try_from(record.target)?,
+            ),
+            DEVICE_CATEGORY_TABLE_NAME => {
+                OverrideTarget::DeviceCategory(DeviceCategoryUniqueID::try_from(record.target)?)
+            }
+            DEVICE_TABLE_NAME => OverrideTarget::Device(DeviceUniqueID::try_from(record.target)?),
+            table => bail!("Override targets a record in unknown table '{table}'"),
+        };
+
+        let field = record
+            .field
+            .map(|name| {
+                OverrideField::from_name(&name)
+                    .ok_or_else(|| anyhow!("Unknown override field '{name}'"))
+            })
+            .transpose()?;
+        let operation = match (record.operation.as_str(), field, record.value) {
+            ("remove", None, None) => OverrideOperation::Hide,
+            ("add", Some(field), Some(value)) => OverrideOperation::Add { field, value },
+            ("remove", Some(field), Some(value)) => OverrideOperation::Remove { field, value },
+            ("replace", Some(field), Some(value)) => OverrideOperation::Replace { field, value },
+            (operation, _, _) => bail!("Invalid override operation '{operation}'"),
+        };
+
+        Ok(Override { target, operation })
+    }
+}
+
 impl From<&InventoryExtensionUniqueID> for Thing {
     fn from(id: &InventoryExtensionUniqueID) -> Self {
         Thing {
@@ -201,3 +448,63 @@ impl TryFrom<Thing> for DeviceUniqueID {
         }
     }
 }
+
+impl From<&PartUniqueID> for Thing {
+    fn from(id: &PartUniqueID) -> Self {
+        Thing {
+            tb: PART_TABLE_NAME.to_owned(),
+            id: Id::String(id.unnamespaced().to_owned()),
+        }
+    }
+}
+
+impl TryFrom<Thing> for PartUniqueID {
+    type Error = anyhow::Error;
+    fn try_from(thing: Thing) -> Result<Self, Self::Error> {
+        if let Id::String(id) = thing.id {
+            Ok(PartUniqueID::new(id))
+        } else {
+            Err(anyhow!("Non-string ID for part"))
+        }
+    }
+}
+
+impl From<&RepairServiceUniqueID> for Thing {
+    fn from(id: &RepairServiceUniqueID) -> Self {
+        Thing {
+            tb: REPAIR_SERVICE_TABLE_NAME.to_owned(),
+            id: Id::String(id.unnamespaced().to_owned()),
+        }
+    }
+}
+
+impl TryFrom<Thing> for RepairServiceUniqueID {
+    type Error = anyhow::Error;
+    fn try_from(thing: Thing) -> Result<Self, Self::Error> {
+        if let Id::String(id) = thing.id {
+            Ok(RepairServiceUniqueID::new(id))
+        } else {
+            Err(anyhow!("Non-string ID for repair service"))
+        }
+    }
+}
+
+impl From<&PluginUniqueID> for Thing {
+    fn from(id: &PluginUniqueID) -> Self {
+        Thing {
+            tb: PLUGIN_TABLE_NAME.to_owned(),
+            id: Id::String(id.unnamespaced().to_owned()),
+        }
+    }
+}
+
+impl TryFrom<Thing> for PluginUniqueID {
+    type Error = anyhow::Error;
+    fn try_from(thing: Thing) -> Result<Self, Self::Error> {
+        if let Id::String(id) = thing.id {
+            Ok(PluginUniqueID::new(id))
+        } else {
+            Err(anyhow!("Non-string ID for plugin"))
+        }
+    }
+}
@@ -1,12 +1,24 @@
+use std::collections::BTreeSet;
+
 use serde::{Deserialize, Serialize};
 use surrealdb::sql::Thing;
 
+use super::common::{CustomFields, DeviceVariant, PartKind};
+
 /// The metadata of an extension which can be added to the database.
 #[derive(Debug, Serialize)]
 pub struct InventoryExtensionMetadataPushRecord<'a> {
     pub id: Thing,
     pub display_name: &'a str,
     pub version: String,
+    pub content_hash: String,
+    pub enabled: bool,
+    pub author: Option<&'a str>,
+    pub license: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub homepage: Option<&'a str>,
+    pub techtriage: Option<String>,
+    pub dependencies: Vec<DependencyRecord>,
 }
 
 /// The metadata of an extension as read from the database.
@@ -15,6 +27,24 @@ pub struct InventoryExtensionMetadataPullRecord {
     pub id: Thing,
     pub display_name: String,
     pub version: String,
+    // * Extensions loaded before content hashes were introduced do not have one.
+    pub content_hash: Option<String>,
+    // * Extensions loaded before extensions could be disabled are enabled.
+    pub enabled: Option<bool>,
+    pub author: Option<String>,
+    pub license: Option<String>,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub techtriage: Option<String>,
+    // * Extensions loaded before dependencies were recorded do not list any.
+    pub dependencies: Option<Vec<DependencyRecord>>,
+}
+
+/// An extension which another extension depends on, along with the versions it requires.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DependencyRecord {
+    pub extension: Thing,
+    pub requirement: String,
 }
 
 /// A device manufacturer which can be added to the database.
@@ -23,6 +53,7 @@ pub struct DeviceManufacturerPushRecord<'a> {
     pub id: Thing,
     pub display_name: &'a str,
     pub extensions: Vec<Thing>,
+    pub custom_fields: &'a CustomFields,
 }
 
 /// A device manufacturer as read from the database.
@@ -31,6 +62,8 @@ pub struct DeviceManufacturerPullRecord {
     pub id: Thing,
     pub display_name: String,
     pub extensions: Vec<Thing>,
+    // * Items loaded before custom fields were introduced do not have any.
+    pub custom_fields: Option<CustomFields>,
 }
 
 /// A category of device which can be added to the database.
@@ -38,7 +71,9 @@ pub struct DeviceManufacturerPullRecord {
 pub struct DeviceCategoryPushRecord<'a> {
     pub id: Thing,
     pub display_name: &'a str,
+    pub parent: Option<Thing>,
     pub extensions: Vec<Thing>,
+    pub custom_fields: &'a CustomFields,
 }
 
 /// A category of device as read from the database.
@@ -46,7 +81,11 @@ pub struct DeviceCategoryPushRecord<'a> {
 pub struct DeviceCategoryPullRecord {
     pub id: Thing,
     pub display_name: String,
+    // * Categories loaded before categories could be nested do not have a parent.
+    pub parent: Option<Thing>,
     pub extensions: Vec<Thing>,
+    // * Items loaded before custom fields were introduced do not have any.
+    pub custom_fields: Option<CustomFields>,
 }
 
 /// A device and all of its relevant metadata, which can be added to the database.
@@ -59,6 +98,8 @@ pub struct DevicePushRecord<'a> {
     pub extensions: Vec<Thing>,
     pub primary_model_identifiers: &'a [String],
     pub extended_model_identifiers: &'a [String],
+    pub variants: &'a [DeviceVariant],
+    pub custom_fields: &'a CustomFields,
 }
 
 /// A device and all of its relevant metadata, as read from the database.
@@ -71,13 +112,145 @@ pub struct DevicePullRecord {
     pub extensions: Vec<Thing>,
     pub primary_model_identifiers: Vec<String>,
     pub extended_model_identifiers: Vec<String>,
+    // * Devices loaded before variants were introduced do not have any.
+    pub variants: Option<Vec<DeviceVariant>>,
+    // * Devices loaded before custom fields were introduced do not have any.
+    pub custom_fields: Option<CustomFields>,
 }
 
-/// A record with the bare minimum amount of structure, used to provide generic functions
-/// (particularly [`Surreal::create`](surrealdb::Surreal::create) with type parameters when the actual result is not important.
+/// A part which can be added to the database.
+#[derive(Debug, Serialize)]
+pub struct PartPushRecord<'a> {
+    pub id: Thing,
+    pub display_name: &'a str,
+    pub kind: PartKind,
+    pub supplier_skus: &'a [String],
+    pub compatible_devices: Vec<Thing>,
+    pub extensions: Vec<Thing>,
+}
+
+/// A part as read from the database.
 #[derive(Debug, Deserialize)]
-pub struct GenericPullRecord {
-    // * This has to be an attribute tag because `_id` does not map to `id`.
-    #[allow(dead_code)]
-    id: Thing,
+pub struct PartPullRecord {
+    pub id: Thing,
+    pub display_name: String,
+    pub kind: PartKind,
+    pub supplier_skus: Vec<String>,
+    pub compatible_devices: Vec<Thing>,
+    pub extensions: Vec<Thing>,
+}
+
+/// A repair service which can be added to the database.
+#[derive(Debug, Serialize)]
+pub struct RepairServicePushRecord<'a> {
+    pub id: Thing,
+    pub display_name: &'a str,
+    pub default_labor_minutes: u32,
+    pub default_price: u64,
+    pub required_part_kinds: &'a [PartKind],
+    pub device_categories: Vec<Thing>,
+    pub devices: Vec<Thing>,
+    pub extensions: Vec<Thing>,
+}
+
+/// A repair service as read from the database.
+#[derive(Debug, Deserialize)]
+pub struct RepairServicePullRecord {
+    pub id: Thing,
+    pub display_name: String,
+    pub default_labor_minutes: u32,
+    pub default_price: u64,
+    pub required_part_kinds: Vec<PartKind>,
+    pub device_categories: Vec<Thing>,
+    pub devices: Vec<Thing>,
+    pub extensions: Vec<Thing>,
+}
+
+/// A single override from an override extension, which can be added to the database.
+#[derive(Debug, Serialize)]
+pub struct OverridePushRecord<'a> {
+    pub extension: Thing,
+    /// The position of the override within its extension, since overrides are applied in order.
+    pub position: usize,
+    pub target: Thing,
+    pub operation: &'static str,
+    pub field: Option<&'static str>,
+    pub value: Option<&'a str>,
+}
+
+/// A single override from an override extension, as read from the database.
+#[derive(Debug, Deserialize)]
+pub struct OverridePullRecord {
+    pub extension: Thing,
+    pub position: usize,
+    pub target: Thing,
+    pub operation: String,
+    pub field: Option<String>,
+    pub value: Option<String>,
+}
+
+/// The declaration of a custom field, which can be added to the database.
+#[derive(Debug, Serialize)]
+pub struct CustomFieldPushRecord<'a> {
+    /// The extension which declared the field.
+    pub extension: Thing,
+    pub target: &'static str,
+    pub field: &'a str,
+    pub display_name: &'a str,
+    #[serde(rename = "type")]
+    pub field_type: &'static str,
+    /// The allowed values of an enum field.
+    pub values: Option<&'a [String]>,
+    pub required: bool,
+}
+
+/// The declaration of a custom field, as read from the database.
+#[derive(Debug, Deserialize)]
+pub struct CustomFieldPullRecord {
+    pub extension: Thing,
+    pub target: String,
+    pub field: String,
+    pub display_name: String,
+    #[serde(rename = "type")]
+    pub field_type: String,
+    pub values: Option<Vec<String>>,
+    pub required: bool,
+}
+
+/// The values which an extension gave to one combined list of an item, which can be added to the
+/// database.
+#[derive(Debug, Serialize)]
+pub struct ContributionPushRecord<'a> {
+    pub extension: Thing,
+    /// The namespaced ID of the item, such as `devices:iphone_8`.
+    pub item: String,
+    pub field: &'a str,
+    pub values: &'a BTreeSet<String>,
+}
+
+/// The values which an extension gave to one combined list of an item, as read from the database.
+#[derive(Debug, Deserialize)]
+pub struct ContributionPullRecord {
+    pub extension: Thing,
+    pub item: String,
+    pub field: String,
+    pub values: BTreeSet<String>,
+}
+
+/// The metadata of a plugin which can be recorded in the database.
+#[derive(Debug, Serialize)]
+pub struct PluginMetadataPushRecord<'a> {
+    pub id: Thing,
+    pub display_name: &'a str,
+    pub version: String,
+    pub content_hash: &'a str,
+}
+
+/// The metadata of a plugin as read from the database.
+#[derive(Debug, Deserialize)]
+pub struct PluginMetadataPullRecord {
+    pub id: Thing,
+    pub display_name: String,
+    pub version: String,
+    pub content_hash: String,
 }
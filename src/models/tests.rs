@@ -1,8 +1,9 @@
 use std::collections::HashSet;
 
 use super::common::{
-    Device, DeviceCategory, DeviceCategoryUniqueID, DeviceManufacturer, DeviceManufacturerUniqueID,
-    DeviceUniqueID, InventoryExtensionUniqueID, UniqueID,
+    CustomFields, Device, DeviceCategory, DeviceCategoryUniqueID, DeviceManufacturer,
+    DeviceManufacturerUniqueID, DeviceUniqueID, InventoryExtensionUniqueID, Part, PartKind,
+    PartUniqueID, RepairService, RepairServiceUniqueID, UniqueID,
 };
 
 impl DeviceManufacturer {
@@ -13,6 +14,7 @@ impl DeviceManufacturer {
             id: DeviceManufacturerUniqueID::new(format!("test_{num}")),
             display_name: format!("Test Device Manufacturer {num}"),
             extensions: HashSet::from([extension_id.clone()]),
+            custom_fields: CustomFields::new(),
         }
     }
 }
@@ -24,7 +26,9 @@ impl DeviceCategory {
         Self {
             id: DeviceCategoryUniqueID::new(format!("test_{num}")),
             display_name: format!("Test Device Category {num}"),
+            parent: None,
             extensions: HashSet::from([extension_id.clone()]),
+            custom_fields: CustomFields::new(),
         }
     }
 }
@@ -46,6 +50,49 @@ impl Device {
             extensions: HashSet::from([extension_id.clone()]),
             primary_model_identifiers: vec![format!("test_{num}_primary")],
             extended_model_identifiers: vec![format!("test_{num}_extended")],
+            variants: Vec::new(),
+            custom_fields: CustomFields::new(),
+        }
+    }
+}
+
+impl Part {
+    /// Creates a basic part which fits a single device, for testing purposes.
+    /// Can be modified to test different scenarios.
+    pub fn test(
+        num: u32,
+        extension_id: &InventoryExtensionUniqueID,
+        device_id: &DeviceUniqueID,
+    ) -> Self {
+        Self {
+            id: PartUniqueID::new(format!("test_{num}")),
+            display_name: format!("Test Part {num}"),
+            kind: PartKind::Screen,
+            supplier_skus: vec![format!("test_{num}_sku")],
+            compatible_devices: vec![device_id.clone()],
+            extensions: HashSet::from([extension_id.clone()]),
+        }
+    }
+}
+
+impl RepairService {
+    /// Creates a basic repair service which applies to a single device category and needs a
+    /// screen, for testing purposes.
+    /// Can be modified to test different scenarios.
+    pub fn test(
+        num: u32,
+        extension_id: &InventoryExtensionUniqueID,
+        category_id: &DeviceCategoryUniqueID,
+    ) -> Self {
+        Self {
+            id: RepairServiceUniqueID::new(format!("test_{num}")),
+            display_name: format!("Test Repair Service {num}"),
+            default_labor_minutes: 30,
+            default_price: 4999,
+            required_part_kinds: vec![PartKind::Screen],
+            device_categories: vec![category_id.clone()],
+            devices: Vec::new(),
+            extensions: HashSet::from([extension_id.clone()]),
         }
     }
 }
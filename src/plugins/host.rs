@@ -0,0 +1,422 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context};
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use wasmtime::{
+    Caller, Config, Engine, Extern, Instance, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, Trap,
+};
+
+use super::manager::{Plugin, PluginLimits};
+use crate::database::Database;
+use crate::models::common::{
+    Device, DeviceCategory, DeviceManufacturer, DeviceVariant, PluginMetadata, PluginUniqueID,
+    UniqueID,
+};
+
+/// The newest version of the host API, which plugins declare in their manifest.
+/// Each version is a separate import module, so plugins written against an older version keep
+/// working when a new version is added.
+///
+/// Version history:
+/// 1. Logging, hook registration, reading the catalog and hook input, and returning results.
+pub const HOST_API_VERSION: u32 = 1;
+
+/// The export which plugins register their hooks from.
+const INIT_EXPORT: &str = "init";
+/// The prefix of the export which is called when a hook runs, followed by the hook name.
+const HOOK_EXPORT_PREFIX: &str = "hook_";
+/// The linear memory which plugins share with the host.
+const MEMORY_EXPORT: &str = "memory";
+
+/// The contents of the database, as exposed to plugins through the host API.
+/// This is serialized as JSON, so its structure is part of the host API.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Catalog {
+    device_manufacturers: Vec<CatalogItem>,
+    device_categories: Vec<CatalogItem>,
+    devices: Vec<CatalogDevice>,
+}
+
+/// A device manufacturer or category in the catalog.
+#[derive(Debug, Clone, Serialize)]
+struct CatalogItem {
+    id: String,
+    display_name: String,
+}
+
+/// A device in the catalog.
+#[derive(Debug, Clone, Serialize)]
+struct CatalogDevice {
+    id: String,
+    display_name: String,
+    manufacturer: String,
+    category: String,
+    primary_model_identifiers: Vec<String>,
+    extended_model_identifiers: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    variants: Vec<DeviceVariant>,
+}
+
+/// The result of running a hook in a single plugin.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HookOutput {
+    pub plugin: String,
+    pub hook: String,
+    /// The JSON value the plugin returned, if any.
+    pub result: Option<Value>,
+    /// Why the hook failed, if it did.
+    pub error: Option<String>,
+}
+
+/// Runs WebAssembly plugins in a sandbox.
+/// Plugins cannot access anything outside of the host API, and each plugin has its own memory
+/// and fuel limits.
+pub struct PluginHost {
+    engine: Engine,
+    linker: Linker<PluginState>,
+    catalog: Arc<str>,
+    plugins: Vec<LoadedPlugin>,
+}
+
+/// A plugin which has been instantiated and initialized.
+struct LoadedPlugin {
+    metadata: PluginMetadata,
+    limits: PluginLimits,
+    store: Store<PluginState>,
+    instance: Instance,
+}
+
+/// The state which the host API can access while a plugin runs.
+struct PluginState {
+    id: PluginUniqueID,
+    limits: StoreLimits,
+    catalog: Arc<str>,
+    /// Hooks can only be registered while the plugin is initializing.
+    initializing: bool,
+    hooks: Vec<String>,
+    input: Vec<u8>,
+    result: Option<Value>,
+}
+
+impl Catalog {
+    /// Creates a catalog from the given items.
+    pub fn new(
+        device_manufacturers: Vec<DeviceManufacturer>,
+        device_categories: Vec<DeviceCategory>,
+        devices: Vec<Device>,
+    ) -> Self {
+        Self {
+            device_manufacturers: device_manufacturers
+                .into_iter()
+                .map(|m| CatalogItem {
+                    id: m.id.unnamespaced().to_owned(),
+                    display_name: m.display_name,
+                })
+                .collect(),
+            device_categories: device_categories
+                .into_iter()
+                .map(|c| CatalogItem {
+                    id: c.id.unnamespaced().to_owned(),
+                    display_name: c.display_name,
+                })
+                .collect(),
+            devices: devices
+                .into_iter()
+                .map(|d| CatalogDevice {
+                    id: d.id.unnamespaced().to_owned(),
+                    display_name: d.display_name,
+                    manufacturer: d.manufacturer.unnamespaced().to_owned(),
+                    category: d.category.unnamespaced().to_owned(),
+                    primary_model_identifiers: d.primary_model_identifiers,
+                    extended_model_identifiers: d.extended_model_identifiers,
+                    variants: d.variants,
+                })
+                .collect(),
+        }
+    }
+
+    /// Reads the current contents of the database, as they appear in listings.
+    pub async fn read(db: &Database) -> anyhow::Result<Self> {
+        Ok(Self::new(
+            db.list_device_manufacturers().await?,
+            db.list_device_categories().await?,
+            db.list_devices().await?,
+        ))
+    }
+}
+
+impl PluginHost {
+    /// Creates a host with no plugins, which exposes the given catalog to plugins.
+    pub fn new(catalog: &Catalog) -> anyhow::Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)?;
+
+        let mut linker = Linker::new(&engine);
+        define_host_api(&mut linker)?;
+
+        Ok(Self {
+            engine,
+            linker,
+            catalog: serde_json::to_string(catalog)?.into(),
+            plugins: Vec::new(),
+        })
+    }
+
+    /// Compiles, instantiates, and initializes a plugin, returning the hooks it registered.
+    /// If the plugin fails to load, the host is left unchanged.
+    pub fn load(&mut self, plugin: &Plugin) -> anyhow::Result<Vec<String>> {
+        if plugin.api_version > HOST_API_VERSION {
+            bail!(
+                "The plugin requires host API version {}, but this host only provides version \
+                {HOST_API_VERSION}",
+                plugin.api_version
+            );
+        }
+        let module = Module::new(&self.engine, &plugin.module).context("Invalid module")?;
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(plugin.limits.memory)
+            .instances(1)
+            .build();
+        let mut store = Store::new(
+            &self.engine,
+            PluginState {
+                id: plugin.metadata.id.clone(),
+                limits,
+                catalog: self.catalog.clone(),
+                initializing: true,
+                hooks: Vec::new(),
+                input: Vec::new(),
+                result: None,
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(plugin.limits.fuel)?;
+
+        let instance = self
+            .linker
+            .instantiate(&mut store, &module)
+            .map_err(describe_trap)?;
+        if instance.get_memory(&mut store, MEMORY_EXPORT).is_none() {
+            bail!("The plugin does not export its memory as '{MEMORY_EXPORT}'");
+        }
+        instance
+            .get_typed_func::<(), ()>(&mut store, INIT_EXPORT)?
+            .call(&mut store, ())
+            .map_err(describe_trap)?;
+        store.data_mut().initializing = false;
+
+        let hooks = store.data().hooks.clone();
+        self.plugins.push(LoadedPlugin {
+            metadata: plugin.metadata.clone(),
+            limits: plugin.limits,
+            store,
+            instance,
+        });
+        self.plugins
+            .sort_by(|a, b| a.metadata.id.cmp(&b.metadata.id));
+
+        Ok(hooks)
+    }
+
+    /// Runs a hook in every plugin which registered it, in order of plugin ID.
+    /// Each plugin's fuel is refilled first, so every call has the same budget.
+    pub fn run_hook(&mut self, hook: &str, input: &Value) -> anyhow::Result<Vec<HookOutput>> {
+        let input = serde_json::to_vec(input)?;
+        let mut outputs = Vec::new();
+        for plugin in &mut self.plugins {
+            if !plugin.store.data().hooks.iter().any(|h| h == hook) {
+                continue;
+            }
+
+            let (result, error) = match plugin.call_hook(hook, &input) {
+                Ok(result) => (result, None),
+                Err(e) => {
+                    // * Errors from host functions are wrapped in a trap, so the whole chain is
+                    // * kept to explain why the plugin stopped.
+                    error!(
+                        "Plugin '{}' failed to run hook '{hook}': {e:#}",
+                        plugin.metadata.id.unnamespaced()
+                    );
+                    (None, Some(format!("{e:#}")))
+                }
+            };
+            outputs.push(HookOutput {
+                plugin: plugin.metadata.id.unnamespaced().to_owned(),
+                hook: hook.to_owned(),
+                result,
+                error,
+            });
+        }
+
+        Ok(outputs)
+    }
+
+    /// Lists the loaded plugins, along with the hooks each one registered.
+    pub fn plugins(&self) -> impl Iterator<Item = (&PluginMetadata, &[String])> {
+        self.plugins
+            .iter()
+            .map(|plugin| (&plugin.metadata, plugin.store.data().hooks.as_slice()))
+    }
+}
+
+impl LoadedPlugin {
+    /// Calls the export for a hook with the given input, returning the result it set.
+    fn call_hook(&mut self, hook: &str, input: &[u8]) -> anyhow::Result<Option<Value>> {
+        let state = self.store.data_mut();
+        state.input = input.to_vec();
+        state.result = None;
+        self.store.set_fuel(self.limits.fuel)?;
+
+        let status = self
+            .instance
+            .get_typed_func::<(), i32>(&mut self.store, &format!("{HOOK_EXPORT_PREFIX}{hook}"))?
+            .call(&mut self.store, ())
+            .map_err(describe_trap)?;
+        if status != 0 {
+            bail!("The hook returned error code {status}");
+        }
+
+        Ok(self.store.data_mut().result.take())
+    }
+}
+
+/// Replaces errors caused by exceeding a plugin's limits with a clearer message.
+fn describe_trap(error: anyhow::Error) -> anyhow::Error {
+    match error.downcast_ref::<Trap>() {
+        Some(Trap::OutOfFuel) => anyhow!("The plugin ran out of fuel"),
+        _ => error,
+    }
+}
+
+/// Gets the memory which a plugin shares with the host.
+fn memory(caller: &mut Caller<'_, PluginState>) -> anyhow::Result<Memory> {
+    match caller.get_export(MEMORY_EXPORT) {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => bail!("The plugin does not export its memory as '{MEMORY_EXPORT}'"),
+    }
+}
+
+/// Reads a string from a plugin's memory.
+// * The length comes from the plugin, so the string is sliced out of its memory rather than
+// * allocating a buffer of that length first.
+fn read_string(caller: &mut Caller<'_, PluginState>, ptr: u32, len: u32) -> anyhow::Result<String> {
+    let memory = memory(caller)?;
+    let (start, end) = (ptr as usize, ptr as usize + len as usize);
+    let Some(bytes) = memory.data(&*caller).get(start..end) else {
+        bail!("The plugin passed a string outside of its memory");
+    };
+    Ok(String::from_utf8(bytes.to_vec())?)
+}
+
+/// Copies bytes into a plugin's memory if they fit in the given buffer.
+/// Returns the number of bytes copied, or -1 if the buffer is too small.
+fn write_bytes(
+    caller: &mut Caller<'_, PluginState>,
+    bytes: &[u8],
+    ptr: u32,
+    len: u32,
+) -> anyhow::Result<i32> {
+    if bytes.len() > len as usize {
+        return Ok(-1);
+    }
+    memory(caller)?.write(&mut *caller, ptr as usize, bytes)?;
+    length(bytes.len())
+}
+
+/// Converts the length of some bytes into the `i32` which plugins receive lengths as.
+// * A negative length would be read as an error code, so lengths which do not fit trap instead.
+pub(super) fn length(len: usize) -> anyhow::Result<i32> {
+    i32::try_from(len).map_err(|_| anyhow!("{len} bytes are too long to pass to a plugin"))
+}
+
+/// Defines every function of the current host API version.
+/// Functions which return an `i32` return a negative number when the call fails, so plugins can
+/// handle failures themselves.
+fn define_host_api(linker: &mut Linker<PluginState>) -> anyhow::Result<()> {
+    let module = format!("techtriage_v{HOST_API_VERSION}");
+
+    // * Levels follow the order of the `log` crate, starting from 1 for errors.
+    linker.func_wrap(
+        &module,
+        "log",
+        |mut caller: Caller<'_, PluginState>, level: i32, ptr: u32, len: u32| {
+            let message = read_string(&mut caller, ptr, len)?;
+            let id = caller.data().id.unnamespaced().to_owned();
+            match level {
+                1 => error!("Plugin '{id}': {message}"),
+                2 => warn!("Plugin '{id}': {message}"),
+                3 => info!("Plugin '{id}': {message}"),
+                _ => debug!("Plugin '{id}': {message}"),
+            }
+            Ok(())
+        },
+    )?;
+
+    // * Returns -1 outside of `init`, and -2 if the plugin does not export the hook.
+    linker.func_wrap(
+        &module,
+        "register_hook",
+        |mut caller: Caller<'_, PluginState>, ptr: u32, len: u32| {
+            let hook = read_string(&mut caller, ptr, len)?;
+            if !caller.data().initializing {
+                return Ok(-1);
+            }
+            let Some(Extern::Func(_)) = caller.get_export(&format!("{HOOK_EXPORT_PREFIX}{hook}"))
+            else {
+                return Ok(-2);
+            };
+
+            let hooks = &mut caller.data_mut().hooks;
+            if !hooks.contains(&hook) {
+                hooks.push(hook);
+            }
+            Ok(0)
+        },
+    )?;
+
+    linker.func_wrap(&module, "catalog_len", |caller: Caller<'_, PluginState>| {
+        length(caller.data().catalog.len())
+    })?;
+    linker.func_wrap(
+        &module,
+        "read_catalog",
+        |mut caller: Caller<'_, PluginState>, ptr: u32, len: u32| {
+            let catalog = caller.data().catalog.clone();
+            write_bytes(&mut caller, catalog.as_bytes(), ptr, len)
+        },
+    )?;
+
+    linker.func_wrap(&module, "input_len", |caller: Caller<'_, PluginState>| {
+        length(caller.data().input.len())
+    })?;
+    linker.func_wrap(
+        &module,
+        "read_input",
+        |mut caller: Caller<'_, PluginState>, ptr: u32, len: u32| {
+            let input = std::mem::take(&mut caller.data_mut().input);
+            let written = write_bytes(&mut caller, &input, ptr, len);
+            caller.data_mut().input = input;
+            written
+        },
+    )?;
+
+    // * Returns -1 if the result is not valid JSON.
+    linker.func_wrap(
+        &module,
+        "set_result",
+        |mut caller: Caller<'_, PluginState>, ptr: u32, len: u32| {
+            let result = read_string(&mut caller, ptr, len)?;
+            let Ok(result) = serde_json::from_str(&result) else {
+                return Ok(-1);
+            };
+            caller.data_mut().result = Some(result);
+            Ok(0)
+        },
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use log::{error, info, warn};
+use semver::Version;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::host::{Catalog, PluginHost, HOST_API_VERSION};
+use crate::database::Database;
+use crate::extensions::{ConflictKind, ExtensionManagerConfig};
+use crate::models::common::{PluginMetadata, PluginUniqueID, UniqueID};
+
+/// The directory which plugin manifests and modules are loaded from.
+pub const PLUGINS_DIRECTORY: &str = "./plugins";
+/// The file extension of plugin manifests.
+const MANIFEST_EXTENSION: &str = "toml";
+
+/// The resources which a single plugin may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PluginLimits {
+    /// The maximum size of the plugin's linear memory, in bytes.
+    pub memory: usize,
+    /// The fuel available to each call into the plugin, which roughly counts instructions.
+    pub fuel: u64,
+}
+
+impl Default for PluginLimits {
+    fn default() -> Self {
+        Self {
+            memory: 16 * 1024 * 1024,
+            fuel: 10_000_000,
+        }
+    }
+}
+
+/// Configuration for discovering and loading plugins.
+#[derive(Debug, Clone, Default)]
+pub struct PluginManagerConfig {
+    /// How plugins are replaced when their version or contents change. Plugins follow the same
+    /// rules as extensions, including dry runs.
+    pub extensions: ExtensionManagerConfig,
+    /// The most memory and fuel which any plugin may request in its manifest.
+    pub max_limits: PluginLimits,
+}
+
+/// A plugin manifest as read from the plugins directory.
+#[derive(Debug, Deserialize)]
+struct PluginManifestToml {
+    plugin: PluginToml,
+    limits: Option<PluginLimitsToml>,
+}
+
+/// The metadata of a plugin as read from its manifest.
+#[derive(Debug, Deserialize)]
+struct PluginToml {
+    id: String,
+    display_name: String,
+    version: String,
+    /// The version of the host API which the plugin was written against.
+    api_version: u32,
+    /// The name of the WebAssembly module, relative to the manifest.
+    module: String,
+}
+
+/// The limits which a plugin requests, as read from its manifest.
+/// Missing limits default to [`PluginLimits::default`].
+#[derive(Debug, Deserialize)]
+struct PluginLimitsToml {
+    memory: Option<usize>,
+    fuel: Option<u64>,
+}
+
+/// A plugin which has been read from the plugins directory, but not loaded.
+#[derive(Debug, Clone)]
+pub struct Plugin {
+    pub metadata: PluginMetadata,
+    pub api_version: u32,
+    pub limits: PluginLimits,
+    /// The WebAssembly module in the binary format.
+    pub module: Vec<u8>,
+    /// A hash of the manifest and the module, used to detect changes without a version bump.
+    pub content_hash: String,
+}
+
+/// How a discovered plugin differs from the version which was last loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginConflict {
+    pub id: PluginUniqueID,
+    pub kind: ConflictKind,
+    pub old_version: Version,
+    pub new_version: Version,
+}
+
+/// Indicator that the manager refused to load a plugin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginFailure {
+    /// The ID of the plugin, or the path of its manifest if it could not be read.
+    pub plugin: String,
+    pub reason: String,
+}
+
+/// The outcome of loading every discovered plugin.
+#[derive(Debug, Default)]
+pub struct PluginLoadResult {
+    pub conflicts: Vec<PluginConflict>,
+    pub failures: Vec<PluginFailure>,
+}
+
+/// Discovers plugins and loads them into a [`PluginHost`].
+pub struct PluginManager {
+    staged_plugins: Vec<Plugin>,
+    config: PluginManagerConfig,
+    /// Manifests which could not be read.
+    failures: Vec<PluginFailure>,
+}
+
+impl PluginManager {
+    /// Reads every plugin manifest in the plugins directory.
+    /// Plugins which cannot be read are reported when the plugins are loaded.
+    pub fn new(config: PluginManagerConfig) -> Self {
+        Self::from_directory(Path::new(PLUGINS_DIRECTORY), config)
+    }
+
+    /// Reads every plugin manifest in the given directory.
+    /// If the directory does not exist, there are no plugins.
+    pub fn from_directory(directory: &Path, config: PluginManagerConfig) -> Self {
+        let mut manager = Self::with_plugins(config, []);
+        let Ok(entries) = std::fs::read_dir(directory) else {
+            return manager;
+        };
+
+        let mut manifests = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.is_file() && path.extension() == Some(OsStr::new(MANIFEST_EXTENSION))
+            })
+            .collect::<Vec<_>>();
+        manifests.sort();
+
+        for manifest in manifests {
+            match manager.read_plugin(&manifest) {
+                Ok(plugin) => manager.staged_plugins.push(plugin),
+                Err(e) => {
+                    error!("Failed to read plugin {}: {e:#}", manifest.display());
+                    manager.failures.push(PluginFailure {
+                        plugin: manifest.display().to_string(),
+                        reason: format!("{e:#}"),
+                    });
+                }
+            }
+        }
+
+        manager
+    }
+
+    /// Creates a manager with the given plugins staged, without reading any files.
+    pub fn with_plugins(
+        config: PluginManagerConfig,
+        plugins: impl IntoIterator<Item = Plugin>,
+    ) -> Self {
+        Self {
+            staged_plugins: plugins.into_iter().collect(),
+            config,
+            failures: Vec::new(),
+        }
+    }
+
+    /// Reads a plugin manifest and the module it refers to, making sure the plugin is compatible
+    /// with this host.
+    pub fn read_plugin(&self, manifest: &Path) -> anyhow::Result<Plugin> {
+        let manifest_source = std::fs::read_to_string(manifest)?;
+        let toml = toml::from_str::<PluginManifestToml>(&manifest_source)?;
+
+        let module_path = module_path(manifest, &toml.plugin.module)?;
+        let module = std::fs::read(&module_path)
+            .with_context(|| format!("Failed to read {}", module_path.display()))?;
+
+        self.parse_plugin(toml, &manifest_source, module)
+    }
+
+    /// Validates a parsed manifest and combines it with its module.
+    fn parse_plugin(
+        &self,
+        toml: PluginManifestToml,
+        manifest_source: &str,
+        module: Vec<u8>,
+    ) -> anyhow::Result<Plugin> {
+        if toml.plugin.id.trim().is_empty() {
+            bail!("Plugin ID must not be empty");
+        }
+        if toml.plugin.api_version == 0 || toml.plugin.api_version > HOST_API_VERSION {
+            bail!(
+                "Unsupported host API version {}; the newest supported version is \
+                {HOST_API_VERSION}",
+                toml.plugin.api_version
+            );
+        }
+
+        let defaults = PluginLimits::default();
+        let requested = toml.limits.unwrap_or(PluginLimitsToml {
+            memory: None,
+            fuel: None,
+        });
+        let limits = PluginLimits {
+            memory: requested.memory.unwrap_or(defaults.memory),
+            fuel: requested.fuel.unwrap_or(defaults.fuel),
+        };
+        let max = self.config.max_limits;
+        if limits.memory > max.memory {
+            bail!(
+                "The plugin requests {} bytes of memory, but at most {} are allowed",
+                limits.memory,
+                max.memory
+            );
+        }
+        if limits.fuel > max.fuel {
+            bail!(
+                "The plugin requests {} fuel, but at most {} is allowed",
+                limits.fuel,
+                max.fuel
+            );
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update((manifest_source.len() as u64).to_le_bytes());
+        hasher.update(manifest_source.as_bytes());
+        hasher.update(&module);
+
+        Ok(Plugin {
+            metadata: PluginMetadata {
+                id: PluginUniqueID::new(toml.plugin.id),
+                display_name: toml.plugin.display_name,
+                version: Version::parse(&toml.plugin.version)?,
+            },
+            api_version: toml.plugin.api_version,
+            limits,
+            module,
+            content_hash: format!("{:x}", hasher.finalize()),
+        })
+    }
+
+    /// Loads every staged plugin into a new host which exposes the current catalog.
+    /// Plugins are compared with the versions which were last loaded, in the same way as
+    /// extensions. Plugins which cannot be loaded are skipped and reported in the result.
+    pub async fn load_plugins(
+        self,
+        db: &Database,
+    ) -> anyhow::Result<(PluginHost, PluginLoadResult)> {
+        let catalog = Catalog::read(db).await?;
+        let loaded_plugins = db.list_plugins().await?;
+        let loaded_hashes = db.list_plugin_hashes().await?;
+
+        let (host, result, changed) =
+            self.load_into_host(&catalog, &loaded_plugins, &loaded_hashes)?;
+        for plugin in changed {
+            db.record_plugin(&plugin.metadata, &plugin.content_hash)
+                .await?;
+        }
+
+        Ok((host, result))
+    }
+
+    /// Loads every staged plugin into a new host, without touching the database.
+    /// Returns the host, the result, and the plugins whose new version should be recorded.
+    pub fn load_into_host(
+        self,
+        catalog: &Catalog,
+        loaded_plugins: &[PluginMetadata],
+        loaded_hashes: &HashMap<PluginUniqueID, String>,
+    ) -> anyhow::Result<(PluginHost, PluginLoadResult, Vec<Plugin>)> {
+        let mut host = PluginHost::new(catalog)?;
+        let mut result = PluginLoadResult {
+            failures: self.failures,
+            ..Default::default()
+        };
+        let mut changed = Vec::new();
+        let config = &self.config.extensions;
+
+        // * Unlike extensions, plugins are discovered by scanning a directory of manifests, so
+        // * two manifests can declare the same ID. Neither is loaded, since neither is clearly
+        // * the intended one.
+        let mut counts = HashMap::new();
+        for plugin in &self.staged_plugins {
+            *counts.entry(&plugin.metadata.id).or_insert(0) += 1;
+        }
+
+        for plugin in &self.staged_plugins {
+            let id = plugin.metadata.id.unnamespaced();
+            let mut fail = |reason: String| {
+                error!("Skipping plugin '{id}': {reason}");
+                result.failures.push(PluginFailure {
+                    plugin: id.to_owned(),
+                    reason,
+                });
+            };
+
+            if counts[&plugin.metadata.id] > 1 {
+                fail("More than one plugin manifest declares this ID".to_owned());
+                continue;
+            }
+
+            let conflict = loaded_plugins
+                .iter()
+                .find(|loaded| loaded.id == plugin.metadata.id)
+                .map(|loaded| PluginConflict {
+                    id: loaded.id.clone(),
+                    kind: ConflictKind::classify(
+                        &plugin.metadata.version,
+                        &plugin.content_hash,
+                        &loaded.version,
+                        loaded_hashes.get(&loaded.id),
+                    ),
+                    old_version: loaded.version.clone(),
+                    new_version: plugin.metadata.version.clone(),
+                });
+            let record = match &conflict {
+                None => true,
+                Some(conflict) if conflict.kind.should_reload(config) => true,
+                Some(conflict) => match conflict.kind {
+                    ConflictKind::Unchanged => false,
+                    ConflictKind::Downgrade => {
+                        fail(format!(
+                            "Version {} is older than the loaded version {}. Use \
+                            --allow-downgrade to load it anyway.",
+                            conflict.new_version, conflict.old_version
+                        ));
+                        result.conflicts.push(conflict.clone());
+                        continue;
+                    }
+                    _ => {
+                        fail(
+                            "Its contents changed without a version bump. Bump its version or \
+                            use --reload-on-drift to load it."
+                                .to_owned(),
+                        );
+                        result.conflicts.push(conflict.clone());
+                        continue;
+                    }
+                },
+            };
+
+            match host.load(plugin) {
+                Ok(hooks) => {
+                    if hooks.is_empty() {
+                        warn!("Plugin '{id}' did not register any hooks.");
+                    }
+                    info!(
+                        "Loaded plugin '{id}' version {} with hooks: {}",
+                        plugin.metadata.version,
+                        hooks.join(", ")
+                    );
+                    if record && !config.dry_run {
+                        changed.push(plugin.clone());
+                    }
+                }
+                Err(e) => fail(format!("{e:#}")),
+            }
+            result.conflicts.extend(conflict);
+        }
+
+        Ok((host, result, changed))
+    }
+}
+
+/// Gets the path of a plugin's module, which must be in the same directory as its manifest.
+fn module_path(manifest: &Path, module: &str) -> anyhow::Result<PathBuf> {
+    if Path::new(module).file_name() != Some(OsStr::new(module)) {
+        bail!("The module '{module}' must be in the same directory as the manifest");
+    }
+
+    Ok(manifest.with_file_name(module))
+}
+
+impl fmt::Display for PluginFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Plugin '{}' failed to load: {}",
+            self.plugin, self.reason
+        )
+    }
+}
@@ -0,0 +1,7 @@
+mod host;
+mod manager;
+#[cfg(test)]
+mod tests;
+
+pub use host::Catalog;
+pub use manager::{PluginLimits, PluginManager, PluginManagerConfig};
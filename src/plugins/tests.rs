@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use semver::Version;
+use serde_json::json;
+
+use super::host::{self, Catalog, PluginHost, HOST_API_VERSION};
+use super::manager::Plugin;
+use super::{PluginLimits, PluginManager, PluginManagerConfig};
+use crate::extensions::{ConflictKind, ExtensionManagerConfig};
+use crate::models::common::{
+    Device, DeviceCategory, DeviceManufacturer, InventoryExtensionUniqueID, PluginMetadata,
+    PluginUniqueID, UniqueID,
+};
+
+/// A plugin which registers hooks that echo their input, return the catalog, and try to register
+/// another hook after initialization.
+const HOOKS_PLUGIN: &str = r#"
+(module
+  (import "techtriage_v1" "register_hook" (func $register_hook (param i32 i32) (result i32)))
+  (import "techtriage_v1" "input_len" (func $input_len (result i32)))
+  (import "techtriage_v1" "read_input" (func $read_input (param i32 i32) (result i32)))
+  (import "techtriage_v1" "catalog_len" (func $catalog_len (result i32)))
+  (import "techtriage_v1" "read_catalog" (func $read_catalog (param i32 i32) (result i32)))
+  (import "techtriage_v1" "set_result" (func $set_result (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "echocataloglate")
+  (func (export "init")
+    (drop (call $register_hook (i32.const 0) (i32.const 4)))
+    (drop (call $register_hook (i32.const 4) (i32.const 7)))
+    (drop (call $register_hook (i32.const 11) (i32.const 4))))
+  (func (export "hook_echo") (result i32)
+    (call $set_result
+      (i32.const 1024)
+      (call $read_input (i32.const 1024) (call $input_len))))
+  (func (export "hook_catalog") (result i32)
+    (call $set_result
+      (i32.const 1024)
+      (call $read_catalog (i32.const 1024) (call $catalog_len))))
+  (func (export "hook_late") (result i32)
+    (call $register_hook (i32.const 0) (i32.const 4))))
+"#;
+
+/// A plugin whose only hook never returns.
+const SPIN_PLUGIN: &str = r#"
+(module
+  (import "techtriage_v1" "register_hook" (func $register_hook (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "spin")
+  (func (export "init")
+    (drop (call $register_hook (i32.const 0) (i32.const 4))))
+  (func (export "hook_spin") (result i32)
+    (loop $forever (br $forever))
+    (i32.const 0)))
+"#;
+
+/// A plugin whose only hook returns a result longer than its memory.
+const OUT_OF_BOUNDS_PLUGIN: &str = r#"
+(module
+  (import "techtriage_v1" "register_hook" (func $register_hook (param i32 i32) (result i32)))
+  (import "techtriage_v1" "set_result" (func $set_result (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "overflow")
+  (func (export "init")
+    (drop (call $register_hook (i32.const 0) (i32.const 8))))
+  (func (export "hook_overflow") (result i32)
+    (call $set_result (i32.const 0) (i32.const -1))))
+"#;
+
+/// A plugin which declares four pages of memory, or 256 KiB.
+const LARGE_MEMORY_PLUGIN: &str = r#"
+(module
+  (memory (export "memory") 4)
+  (func (export "init")))
+"#;
+
+impl Plugin {
+    /// Creates a plugin from a module in the WebAssembly text format for testing purposes.
+    fn test(id: &str, version: &str, module: &str) -> Self {
+        Self {
+            metadata: PluginMetadata {
+                id: PluginUniqueID::new(id),
+                display_name: format!("Test Plugin {id}"),
+                version: Version::parse(version).unwrap(),
+            },
+            api_version: HOST_API_VERSION,
+            limits: PluginLimits::default(),
+            module: wat::parse_str(module).unwrap(),
+            content_hash: format!("{id}@{version}"),
+        }
+    }
+}
+
+/// Creates a catalog with a single device.
+fn test_catalog() -> Catalog {
+    let extension_id = InventoryExtensionUniqueID::new("test");
+    let manufacturer = DeviceManufacturer::test(1, &extension_id);
+    let category = DeviceCategory::test(1, &extension_id);
+    let device = Device::test(1, &extension_id, &manufacturer.id, &category.id);
+    Catalog::new(vec![manufacturer], vec![category], vec![device])
+}
+
+/// Tests that plugins can register hooks while initializing, read their input and the catalog,
+/// and return results to the host.
+#[test]
+fn host_api() {
+    let mut host = PluginHost::new(&test_catalog()).unwrap();
+    let hooks = host
+        .load(&Plugin::test("hooks", "1.0.0", HOOKS_PLUGIN))
+        .unwrap();
+    assert_eq!(hooks, ["echo", "catalog", "late"]);
+
+    let input = json!({ "device": "test_1", "quantity": 2 });
+    let outputs = host.run_hook("echo", &input).unwrap();
+    assert_eq!(outputs.len(), 1);
+    assert_eq!(outputs[0].plugin, "hooks");
+    assert_eq!(outputs[0].result, Some(input));
+    assert!(outputs[0].error.is_none());
+
+    let outputs = host.run_hook("catalog", &json!(null)).unwrap();
+    let catalog = outputs[0].result.as_ref().unwrap();
+    assert_eq!(catalog["devices"][0]["id"], "test_1");
+    assert_eq!(catalog["devices"][0]["manufacturer"], "test_1");
+
+    // Make sure hooks cannot be registered after initialization
+    let outputs = host.run_hook("late", &json!(null)).unwrap();
+    assert!(outputs[0].error.as_ref().unwrap().contains("-1"));
+
+    // Make sure hooks which no plugin registered are not run
+    assert!(host.run_hook("unknown", &json!(null)).unwrap().is_empty());
+}
+
+/// Tests that plugins cannot exceed their memory and fuel limits.
+#[test]
+fn plugin_limits() {
+    let mut host = PluginHost::new(&test_catalog()).unwrap();
+
+    // Make sure a plugin which never returns is stopped once it runs out of fuel
+    let mut spin_plugin = Plugin::test("spin", "1.0.0", SPIN_PLUGIN);
+    spin_plugin.limits.fuel = 100_000;
+    host.load(&spin_plugin).unwrap();
+    let outputs = host.run_hook("spin", &json!(null)).unwrap();
+    assert!(outputs[0].result.is_none());
+    assert!(outputs[0].error.as_ref().unwrap().contains("fuel"));
+
+    // Make sure the fuel is refilled for each call
+    let outputs = host.run_hook("spin", &json!(null)).unwrap();
+    assert!(outputs[0].error.as_ref().unwrap().contains("fuel"));
+
+    // Make sure strings outside of a plugin's memory are refused
+    host.load(&Plugin::test("overflow", "1.0.0", OUT_OF_BOUNDS_PLUGIN))
+        .unwrap();
+    let outputs = host.run_hook("overflow", &json!(null)).unwrap();
+    assert!(outputs[0].result.is_none());
+    assert!(outputs[0].error.as_ref().unwrap().contains("outside"));
+
+    // Make sure lengths which do not fit in an i32 are refused rather than passed as negative
+    assert_eq!(host::length(i32::MAX as usize).unwrap(), i32::MAX);
+    assert!(host::length(i32::MAX as usize + 1).is_err());
+
+    // Make sure a plugin which needs more memory than allowed is not loaded
+    let mut large_plugin = Plugin::test("large", "1.0.0", LARGE_MEMORY_PLUGIN);
+    large_plugin.limits.memory = 64 * 1024;
+    assert!(host.load(&large_plugin).is_err());
+    large_plugin.limits.memory = 256 * 1024;
+    assert!(host.load(&large_plugin).is_ok());
+    assert_eq!(host.plugins().count(), 3);
+}
+
+/// Tests that plugin manifests are checked against the host API version and the maximum limits.
+#[test]
+fn plugin_manifests() {
+    let directory = std::env::temp_dir().join("techtriage_plugin_manifests");
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(
+        directory.join("hooks.wasm"),
+        wat::parse_str(HOOKS_PLUGIN).unwrap(),
+    )
+    .unwrap();
+
+    let manifest = |api_version: u32, module: &str, limits: &str| {
+        format!(
+            "[plugin]\nid = \"hooks\"\ndisplay_name = \"Hooks\"\nversion = \"1.2.0\"\n\
+            api_version = {api_version}\nmodule = \"{module}\"\n{limits}"
+        )
+    };
+    let config = PluginManagerConfig {
+        max_limits: PluginLimits {
+            memory: 32 * 1024 * 1024,
+            fuel: 1_000_000,
+        },
+        ..Default::default()
+    };
+    let manager = PluginManager::with_plugins(config.clone(), []);
+    let read = |name: &str, contents: String| {
+        let path = directory.join(name);
+        std::fs::write(&path, contents).unwrap();
+        manager.read_plugin(&path)
+    };
+
+    // Make sure a valid manifest is read, with limits which were not given defaulting
+    let plugin = read(
+        "valid.toml",
+        manifest(1, "hooks.wasm", "[limits]\nfuel = 5000"),
+    )
+    .unwrap();
+    assert_eq!(plugin.metadata.id, PluginUniqueID::new("hooks"));
+    assert_eq!(plugin.metadata.version, Version::new(1, 2, 0));
+    assert_eq!(plugin.limits.fuel, 5000);
+    assert_eq!(plugin.limits.memory, PluginLimits::default().memory);
+    let same_plugin = manager.read_plugin(&directory.join("valid.toml")).unwrap();
+    assert_eq!(plugin.content_hash, same_plugin.content_hash);
+
+    // Make sure a plugin written against a newer host API is rejected
+    assert!(read(
+        "newer.toml",
+        manifest(HOST_API_VERSION + 1, "hooks.wasm", "")
+    )
+    .is_err());
+    assert!(read("zero.toml", manifest(0, "hooks.wasm", "")).is_err());
+
+    // Make sure a plugin cannot request more than the maximum limits
+    assert!(read(
+        "fuel.toml",
+        manifest(1, "hooks.wasm", "[limits]\nfuel = 2000000")
+    )
+    .is_err());
+    assert!(read(
+        "memory.toml",
+        manifest(1, "hooks.wasm", "[limits]\nmemory = 67108864")
+    )
+    .is_err());
+
+    // Make sure modules outside the plugin's directory cannot be loaded
+    assert!(read("escape.toml", manifest(1, "../hooks.wasm", "")).is_err());
+
+    // Make sure every manifest in the directory is read, and those which failed are reported
+    let manager = PluginManager::from_directory(&directory, config);
+    let (host, result, changed) = manager
+        .load_into_host(&test_catalog(), &[], &HashMap::new())
+        .unwrap();
+    assert_eq!(host.plugins().count(), 1);
+    assert_eq!(changed.len(), 1);
+    assert_eq!(result.failures.len(), 5);
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+/// Tests that plugins are versioned and conflict-checked the same way as extensions.
+#[test]
+fn plugin_conflicts() {
+    let plugin = Plugin::test("hooks", "1.0.0", HOOKS_PLUGIN);
+    let loaded = vec![plugin.metadata.clone()];
+    let hashes = HashMap::from([(plugin.metadata.id.clone(), plugin.content_hash.clone())]);
+
+    let load = |config: ExtensionManagerConfig, plugins: Vec<Plugin>| {
+        let config = PluginManagerConfig {
+            extensions: config,
+            ..Default::default()
+        };
+        PluginManager::with_plugins(config, plugins)
+            .load_into_host(&test_catalog(), &loaded, &hashes)
+            .unwrap()
+    };
+
+    // Make sure an unchanged plugin is loaded without being recorded again
+    let (host, result, changed) = load(Default::default(), vec![plugin.clone()]);
+    assert_eq!(host.plugins().count(), 1);
+    assert!(changed.is_empty());
+    assert_eq!(result.conflicts[0].kind, ConflictKind::Unchanged);
+
+    // Make sure an upgrade is loaded and recorded
+    let upgraded_plugin = Plugin::test("hooks", "1.1.0", HOOKS_PLUGIN);
+    let (_, result, changed) = load(Default::default(), vec![upgraded_plugin]);
+    assert_eq!(changed.len(), 1);
+    assert_eq!(result.conflicts[0].kind, ConflictKind::Upgrade);
+
+    // Make sure a downgrade is refused unless allowed
+    let downgraded_plugin = Plugin::test("hooks", "0.9.0", HOOKS_PLUGIN);
+    let (host, result, _) = load(Default::default(), vec![downgraded_plugin.clone()]);
+    assert_eq!(host.plugins().count(), 0);
+    assert_eq!(result.failures.len(), 1);
+    assert_eq!(result.conflicts[0].kind, ConflictKind::Downgrade);
+    let allow_downgrade = ExtensionManagerConfig {
+        allow_downgrade: true,
+        ..Default::default()
+    };
+    let (host, _, _) = load(allow_downgrade, vec![downgraded_plugin]);
+    assert_eq!(host.plugins().count(), 1);
+
+    // Make sure a plugin which changed without a version bump is refused unless allowed
+    let mut drifted_plugin = plugin.clone();
+    drifted_plugin.content_hash = "drifted".to_owned();
+    let (host, result, _) = load(Default::default(), vec![drifted_plugin.clone()]);
+    assert_eq!(host.plugins().count(), 0);
+    assert_eq!(result.conflicts[0].kind, ConflictKind::ContentDrift);
+    let reload_on_drift = ExtensionManagerConfig {
+        reload_on_drift: true,
+        ..Default::default()
+    };
+    let (_, _, changed) = load(reload_on_drift, vec![drifted_plugin]);
+    assert_eq!(changed.len(), 1);
+
+    // Make sure nothing is recorded in a dry run
+    let dry_run = ExtensionManagerConfig {
+        dry_run: true,
+        ..Default::default()
+    };
+    let new_plugin = Plugin::test("other", "1.0.0", HOOKS_PLUGIN);
+    let (host, _, changed) = load(dry_run, vec![new_plugin]);
+    assert_eq!(host.plugins().count(), 1);
+    assert!(changed.is_empty());
+
+    // Make sure neither of two plugins with the same ID is loaded
+    let (host, result, _) = load(Default::default(), vec![plugin.clone(), plugin]);
+    assert_eq!(host.plugins().count(), 0);
+    assert_eq!(result.failures.len(), 2);
+}